@@ -0,0 +1,104 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use web3::signing::keccak256;
+use web3::transports::Http;
+use web3::types::{Address, U256};
+use web3::Web3;
+
+use crate::rpc;
+use crate::standards::{CustomEvent, EventsConfig, TransferEvent};
+
+/// How balances are rebuilt from logs for tokens where summing transfers
+/// gives the wrong answer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Sum the transfer events; right for plain ERC-20, ERC-721 and ERC-1155
+    #[default]
+    Events,
+    /// Rebasing tokens: replay transfers of shares and convert shares to
+    /// balances at the end block with `totalSupply() / <total_shares>()`
+    Shares,
+    /// Fee-on-transfer and reflection tokens: sum the events, and every
+    /// `reconcile_every` blocks replace the sums with `balanceOf` at that block
+    Reconcile,
+}
+
+/// `[accounting.contracts."0x..."]` in `scanner.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Accounting {
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Event moving shares, e.g. `TransferShares(address,address,uint256)`
+    pub shares_event: Option<CustomEvent>,
+    /// View function returning the total number of shares, e.g. `getTotalShares()`
+    pub total_shares: Option<String>,
+    /// Blocks between on-chain balance reconciliations
+    #[serde(default = "default_reconcile_every")]
+    pub reconcile_every: u64,
+}
+
+fn default_reconcile_every() -> u64 {
+    100_000
+}
+
+/// `[accounting]` in `scanner.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccountingConfig {
+    #[serde(default)]
+    pub contracts: HashMap<String, Accounting>,
+}
+
+impl AccountingConfig {
+    /// The strategy for `contract`; `events` when it isn't listed.
+    pub fn for_contract(&self, contract: &str) -> Result<Accounting> {
+        let accounting = self
+            .contracts
+            .iter()
+            .find(|(address, _)| address.eq_ignore_ascii_case(contract))
+            .map(|(_, accounting)| accounting.clone())
+            .unwrap_or(Accounting { reconcile_every: default_reconcile_every(), ..Accounting::default() });
+        match accounting.strategy {
+            Strategy::Shares if accounting.shares_event.is_none() || accounting.total_shares.is_none() => {
+                bail!("accounting for {}: strategy = \"shares\" needs shares_event and total_shares", contract)
+            }
+            Strategy::Reconcile if accounting.reconcile_every == 0 => {
+                bail!("accounting for {}: reconcile_every must be above 0", contract)
+            }
+            _ => Ok(accounting),
+        }
+    }
+}
+
+impl Accounting {
+    /// Events to replay: the shares event for `shares`, else the contract's
+    /// configured transfer events.
+    pub fn events(&self, events: &EventsConfig, contract: &str) -> Result<Vec<TransferEvent>> {
+        match (&self.strategy, &self.shares_event) {
+            (Strategy::Shares, Some(event)) => Ok(vec![TransferEvent::custom(event).context("in shares_event")?]),
+            _ => events.events_for(contract),
+        }
+    }
+
+    /// Factor turning replayed amounts into balances at `block`, as a
+    /// numerator and denominator; 1/1 except for `shares`.
+    pub async fn conversion(&self, web3: &Web3<Http>, contract: Address, block: u64) -> Result<(U256, U256)> {
+        let (Strategy::Shares, Some(function)) = (&self.strategy, &self.total_shares) else {
+            return Ok((U256::one(), U256::one()));
+        };
+        let signature: String = function.chars().filter(|c| !c.is_whitespace()).collect();
+        let selector = keccak256(signature.as_bytes())[..4].to_vec();
+        let output = rpc::call_at(web3, contract, selector, Some(block))
+            .await
+            .with_context(|| format!("{} call failed", signature))?;
+        anyhow::ensure!(output.len() >= 32, "{} returned {} bytes", signature, output.len());
+        let total_shares = U256::from_big_endian(&output[..32]);
+        if total_shares.is_zero() {
+            bail!("{} is zero at block {}", signature, block);
+        }
+        Ok((rpc::total_supply_at(web3, contract, Some(block)).await?, total_shares))
+    }
+}
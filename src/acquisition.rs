@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use tracing::{debug, info, warn};
+use web3::types::{Address, U256};
+
+use crate::accounting::Strategy;
+use crate::config;
+use crate::http;
+use crate::multicall::Multicall;
+use crate::output;
+use crate::rpc;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::standards;
+use crate::state::{self, Acquisition};
+
+#[derive(Debug, clap::Args)]
+pub struct AcquisitionOptions {
+    /// First block to replay; use the contract's deployment block so no transfer is missed
+    #[arg(long, default_value_t = 0)]
+    pub from_block: u64,
+    /// Last block to replay (defaults to the latest block)
+    #[arg(long)]
+    pub to_block: Option<u64>,
+    /// Blocks per eth_getLogs request
+    #[arg(long, default_value_t = 10_000)]
+    pub chunk_size: u64,
+    /// Also write the replayed balance of every address at the last block to this CSV
+    #[arg(long)]
+    pub balances_output: Option<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+struct Holding {
+    balance: U256,
+    first_block: Option<u64>,
+    /// Block the current non-zero balance started at, `None` while the balance is zero
+    held_since_block: Option<u64>,
+}
+
+/// Replays Transfer logs of the scanned contract and records, for every
+/// current holder, the block it first received the token and the block its
+/// current unbroken holding started. Which events count as transfers comes
+/// from `[events]` in `scanner.toml`, and how they add up to balances from
+/// `[accounting]`.
+pub async fn run_acquisition(options: &AcquisitionOptions) -> Result<()> {
+    let rpc_url = env::var("OPTIMISM_RPC_URL").context("OPTIMISM_RPC_URL must be set to replay Transfer logs")?;
+    let web3 = web3::Web3::new(http::transport(&rpc_url)?);
+    let contract: Address = CONTRACT_ADDRESS.parse().context("invalid contract address")?;
+    let config = config::load_config()?;
+    let accounting = config.accounting.for_contract(CONTRACT_ADDRESS)?;
+    let events = accounting.events(&config.events, CONTRACT_ADDRESS)?;
+    let to_block = match options.to_block {
+        Some(block) => block,
+        None => web3.eth().block_number().await?.as_u64(),
+    };
+
+    let mut holdings: HashMap<Address, Holding> = HashMap::new();
+    // Reconciling replays a window at a time and corrects the balances on-chain after each
+    let window = match accounting.strategy {
+        Strategy::Reconcile => accounting.reconcile_every,
+        _ => u64::MAX,
+    };
+    let multicall = Multicall::from_env()?;
+    let mut start = options.from_block;
+    while start <= to_block {
+        let end = start.saturating_add(window - 1).min(to_block);
+        let range = (start, end, options.chunk_size);
+        standards::replay_transfers(&web3, contract, &events, range, |block, from, to, amount| {
+            if !from.is_zero() {
+                let holding = holdings.entry(from).or_default();
+                holding.balance = holding.balance.saturating_sub(amount);
+                if holding.balance.is_zero() {
+                    holding.held_since_block = None;
+                }
+            }
+            if !to.is_zero() && !amount.is_zero() {
+                let holding = holdings.entry(to).or_default();
+                if holding.balance.is_zero() {
+                    holding.held_since_block = Some(block);
+                }
+                holding.first_block.get_or_insert(block);
+                holding.balance += amount;
+            }
+        })
+        .await?;
+        if accounting.strategy == Strategy::Reconcile {
+            reconcile(&web3, &multicall, contract, &mut holdings, end).await?;
+        }
+        start = end + 1;
+    }
+
+    if let Some(path) = &options.balances_output {
+        let (numerator, denominator) = accounting.conversion(&web3, contract, to_block).await?;
+        let mut rows: Vec<(&Address, U256)> = holdings
+            .iter()
+            .filter(|(_, h)| !h.balance.is_zero())
+            .map(|(address, h)| {
+                // Shares times the supply can overflow for large supplies; divide first then
+                let balance = h.balance.checked_mul(numerator).map(|v| v / denominator);
+                (address, balance.unwrap_or_else(|| h.balance / denominator * numerator))
+            })
+            .collect();
+        rows.sort();
+        let mut writer = output::csv_writer(path)?;
+        writer.write_record(["address", "balance"])?;
+        for (address, balance) in rows {
+            writer.write_record([format!("{:?}", address), balance.to_string()])?;
+        }
+        output::finish_csv(writer)?;
+        info!(path = %path.display(), to_block, "Replayed balances written");
+    }
+
+    let mut state = state::load_state()?;
+    let mut block_times: HashMap<u64, DateTime<Utc>> = HashMap::new();
+    let mut acquisitions = HashMap::new();
+    let mut unmatched = 0;
+    for holder in &state.holders {
+        let found = holder
+            .parse::<Address>()
+            .ok()
+            .and_then(|address| holdings.get(&address))
+            .and_then(|h| Some((h.first_block?, h.held_since_block?)));
+        let Some((first_block, held_since_block)) = found else {
+            unmatched += 1;
+            continue;
+        };
+        let first_acquired = block_time(&web3, &mut block_times, first_block).await?;
+        let held_since = block_time(&web3, &mut block_times, held_since_block).await?;
+        acquisitions.insert(holder.clone(), Acquisition { first_block, first_acquired, held_since_block, held_since });
+    }
+    if unmatched > 0 {
+        // Usually transfers before --from-block, or a holder list newer than --to-block
+        warn!(holders = unmatched, "Holders without a replayed acquisition");
+    }
+
+    state.acquisitions = acquisitions;
+    state::save_state(&state)?;
+    state::save_holders_to_file(&state)?;
+    info!(holders = state.acquisitions.len(), to_block, "Acquisition times recorded");
+    Ok(())
+}
+
+/// Replaces replayed balances with `balanceOf` at `block`, for tokens whose
+/// transfer events don't add up to the real balances.
+async fn reconcile(
+    web3: &web3::Web3<web3::transports::Http>,
+    multicall: &Multicall,
+    contract: Address,
+    holdings: &mut HashMap<Address, Holding>,
+    block: u64,
+) -> Result<()> {
+    let addresses: Vec<Address> = holdings.keys().copied().collect();
+    let balances = multicall.balances_of(web3, contract, &addresses, Some(block)).await?;
+    let mut corrected = 0;
+    for (address, onchain) in addresses.iter().zip(balances) {
+        let (Some(onchain), Some(holding)) = (onchain, holdings.get_mut(address)) else {
+            continue;
+        };
+        if onchain == holding.balance {
+            continue;
+        }
+        corrected += 1;
+        if onchain.is_zero() {
+            holding.held_since_block = None;
+        } else if holding.balance.is_zero() {
+            // Balance the events missed, e.g. reflections; the holding started no later than here
+            holding.held_since_block = Some(block);
+            holding.first_block.get_or_insert(block);
+        }
+        holding.balance = onchain;
+    }
+    debug!(block, addresses = addresses.len(), corrected, "Reconciled replayed balances");
+    Ok(())
+}
+
+async fn block_time(
+    web3: &web3::Web3<web3::transports::Http>,
+    cache: &mut HashMap<u64, DateTime<Utc>>,
+    block: u64,
+) -> Result<DateTime<Utc>> {
+    if let Some(time) = cache.get(&block) {
+        return Ok(*time);
+    }
+    let time = rpc::block_time(web3, block).await?;
+    cache.insert(block, time);
+    Ok(time)
+}
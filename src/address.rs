@@ -0,0 +1,146 @@
+use anyhow::{anyhow, bail, Result};
+use std::env;
+use web3::signing::keccak256;
+
+/// How a chain encodes addresses. Override or extend the chain list with
+/// `CHAIN_ADDRESS_FORMATS`, e.g. `mysvm=solana,signet=bitcoin`; chains not
+/// listed are EVM chains.
+const CHAIN_FORMATS: [(&str, AddressFormat); 4] = [
+    ("solana", AddressFormat::Solana),
+    ("bitcoin", AddressFormat::Bitcoin),
+    ("bitcoin-testnet", AddressFormat::Bitcoin),
+    ("litecoin", AddressFormat::Bitcoin),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFormat {
+    /// `0x` and 40 hex digits; canonical in lowercase. Mixed case must be a valid EIP-55 checksum
+    Evm,
+    /// Base58 of a 32-byte public key; case-sensitive, so kept as given
+    Solana,
+    /// Bech32/bech32m segwit addresses, canonical in lowercase, or base58check
+    /// P2PKH/P2SH addresses, kept as given
+    Bitcoin,
+}
+
+impl AddressFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "evm" | "hex" => Ok(AddressFormat::Evm),
+            "solana" | "base58" => Ok(AddressFormat::Solana),
+            "bitcoin" | "bech32" => Ok(AddressFormat::Bitcoin),
+            other => bail!("unknown address format '{}' (use evm, solana or bitcoin)", other),
+        }
+    }
+
+    pub fn for_chain(chain: &str) -> Self {
+        let chain = chain.trim().to_lowercase();
+        let overrides = env::var("CHAIN_ADDRESS_FORMATS").unwrap_or_default();
+        let configured = overrides.split(',').find_map(|entry| {
+            let (name, format) = entry.split_once('=')?;
+            if !name.trim().eq_ignore_ascii_case(&chain) {
+                return None;
+            }
+            AddressFormat::parse(format).ok()
+        });
+        configured
+            .or_else(|| CHAIN_FORMATS.iter().find(|(name, _)| *name == chain).map(|(_, format)| *format))
+            .unwrap_or(AddressFormat::Evm)
+    }
+
+    /// Validates `raw` and returns its canonical form: the one string every
+    /// spelling of the same address maps to, used for deduplication, storage
+    /// and exports.
+    pub fn canonicalize(self, raw: &str) -> Result<String> {
+        let raw = raw.trim();
+        match self {
+            AddressFormat::Evm => canonical_evm(raw),
+            AddressFormat::Solana => {
+                let bytes = bs58::decode(raw).into_vec().map_err(|e| anyhow!("{} is not base58: {}", raw, e))?;
+                if bytes.len() != 32 {
+                    bail!("{} decodes to {} bytes, Solana addresses have 32", raw, bytes.len());
+                }
+                Ok(raw.to_string())
+            }
+            AddressFormat::Bitcoin => canonical_bitcoin(raw),
+        }
+    }
+
+    /// Canonical form for use as a key. Input that doesn't validate is kept
+    /// as given (trimmed), so it never merges with a real address.
+    pub fn key(self, raw: &str) -> String {
+        self.canonicalize(raw).unwrap_or_else(|_| raw.trim().to_string())
+    }
+}
+
+/// Validates an EVM address from a provider, webhook or imported list and
+/// returns it in the form the holder set uses: `0x` and 40 lowercase hex
+/// digits. Mixed case must be a valid EIP-55 checksum.
+pub fn normalize(raw: &str) -> Result<String> {
+    canonical_evm(raw.trim())
+}
+
+pub fn is_valid(raw: &str) -> bool {
+    normalize(raw).is_ok()
+}
+
+fn canonical_evm(raw: &str) -> Result<String> {
+    let hex_part = raw
+        .strip_prefix("0x")
+        .or_else(|| raw.strip_prefix("0X"))
+        .ok_or_else(|| anyhow!("{} does not start with 0x", raw))?;
+    if hex_part.len() != 40 || !hex_part.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("{} is not 40 hex digits", raw);
+    }
+    let lower = hex_part.to_ascii_lowercase();
+    let mixed_case =
+        hex_part.chars().any(|c| c.is_ascii_uppercase()) && hex_part.chars().any(|c| c.is_ascii_lowercase());
+    if mixed_case && hex_part != eip55(&lower) {
+        bail!("{} has an invalid EIP-55 checksum", raw);
+    }
+    Ok(format!("0x{}", lower))
+}
+
+/// EIP-55 checksummed form of an EVM address, e.g. for publishing.
+pub fn checksummed(raw: &str) -> Result<String> {
+    let canonical = canonical_evm(raw.trim())?;
+    Ok(format!("0x{}", eip55(&canonical[2..])))
+}
+
+/// EIP-55 checksum casing of a lowercase 40-digit hex address, without `0x`.
+fn eip55(lower: &str) -> String {
+    let hash = keccak256(lower.as_bytes());
+    lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+            if c.is_ascii_alphabetic() && nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn canonical_bitcoin(raw: &str) -> Result<String> {
+    let lower = raw.to_lowercase();
+    if ["bc1", "tb1", "bcrt1", "ltc1"].iter().any(|hrp| lower.starts_with(hrp)) {
+        // Bech32 is case-insensitive but must not mix cases
+        if raw != lower && raw != raw.to_uppercase() {
+            bail!("{} mixes upper and lower case", raw);
+        }
+        bech32::segwit::decode(&lower).map_err(|e| anyhow!("{} is not a valid segwit address: {}", raw, e))?;
+        return Ok(lower);
+    }
+    let payload = bs58::decode(raw)
+        .with_check(None)
+        .into_vec()
+        .map_err(|e| anyhow!("{} is not a valid base58check address: {}", raw, e))?;
+    // Version byte and a 20-byte hash: P2PKH/P2SH on mainnet and testnet, and Litecoin's
+    match payload.first() {
+        Some(0x00 | 0x05 | 0x6f | 0xc4 | 0x30 | 0x32) if payload.len() == 21 => Ok(raw.to_string()),
+        _ => bail!("{} is not a P2PKH or P2SH address", raw),
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::address;
+use crate::archive;
+use crate::overlap;
+use crate::reconcile::ADDRESS_COLUMNS;
+use crate::state::STATE_PATH;
+
+/// Named address sets live here as `<name>.txt`, one address per line.
+pub const SETS_DIR: &str = "data/sets";
+
+#[derive(Debug, clap::Args)]
+pub struct ImportOptions {
+    /// Set to merge the addresses into, created if it doesn't exist
+    #[arg(value_parser = parse_set_name)]
+    pub name: String,
+    /// Address lists: CSV with an address column, or text with one address per line
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    /// CSV column holding the addresses; found by name (address, wallet, ...) when not given
+    #[arg(long)]
+    pub column: Option<String>,
+    /// Replace the set's addresses instead of adding to them
+    #[arg(long)]
+    pub replace: bool,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum SetsCommand {
+    /// List the imported sets and their sizes
+    List,
+    /// Print a set's addresses
+    Show {
+        #[arg(value_parser = parse_set_name)]
+        name: String,
+    },
+    /// Addresses in any of the operands
+    Union(SetOperation),
+    /// Addresses in every operand
+    Intersect(SetOperation),
+    /// Addresses in the first operand and none of the others
+    Subtract(SetOperation),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SetOperation {
+    /// `scan` for the current holders, an imported set's name, an address list or state file,
+    /// or a run or snapshot id
+    #[arg(required = true, num_args = 2..)]
+    pub operands: Vec<String>,
+    /// Save the result as a named set
+    #[arg(long, value_name = "NAME", value_parser = parse_set_name)]
+    pub into: Option<String>,
+    /// Write the result to this file; without --into or --output it is printed
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+fn parse_set_name(name: &str) -> Result<String, String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("'{}' is not a valid set name (letters, digits, - and _)", name));
+    }
+    Ok(name.to_string())
+}
+
+pub fn set_path(name: &str) -> PathBuf {
+    Path::new(SETS_DIR).join(format!("{}.txt", name))
+}
+
+/// The addresses of an imported set.
+pub fn load_set(name: &str) -> Result<BTreeSet<String>> {
+    let path = set_path(name);
+    if !path.exists() {
+        bail!("no address set named '{}'; create it with `import {} <FILE>`", name, name);
+    }
+    overlap::load_holder_set(&path)
+}
+
+fn save_set(name: &str, addresses: &BTreeSet<String>, source: &str) -> Result<PathBuf> {
+    let path = set_path(name);
+    fs::create_dir_all(SETS_DIR)?;
+    let mut file = BufWriter::new(File::create(&path).with_context(|| format!("writing {}", path.display()))?);
+    writeln!(file, "# {} at {}", source, Utc::now().to_rfc3339())?;
+    write_addresses(&mut file, addresses)?;
+    file.flush()?;
+    Ok(path)
+}
+
+fn write_addresses(out: &mut impl Write, addresses: &BTreeSet<String>) -> Result<()> {
+    for address in addresses {
+        writeln!(out, "{}", address)?;
+    }
+    Ok(())
+}
+
+/// Normalized addresses of a CSV or text list and the number of entries that
+/// aren't addresses.
+fn read_list(path: &Path, column: Option<&str>) -> Result<(BTreeSet<String>, usize)> {
+    if path.extension().is_none_or(|ext| ext != "csv") {
+        let lines = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let entries = lines.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+        return Ok(normalize_all(entries));
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    let headers = reader.headers()?.clone();
+    let position = match column {
+        Some(name) => headers.iter().position(|h| h.eq_ignore_ascii_case(name)),
+        None => headers.iter().position(|h| ADDRESS_COLUMNS.iter().any(|n| h.eq_ignore_ascii_case(n))),
+    };
+    let Some(position) = position else {
+        let expected = column.map_or_else(|| ADDRESS_COLUMNS.join(", "), str::to_string);
+        bail!("{} has no address column (expected {})", path.display(), expected);
+    };
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        if let Some(value) = record?.get(position).filter(|value| !value.is_empty()) {
+            entries.push(value.to_string());
+        }
+    }
+    Ok(normalize_all(entries.iter().map(String::as_str)))
+}
+
+fn normalize_all<'a>(entries: impl Iterator<Item = &'a str>) -> (BTreeSet<String>, usize) {
+    let mut addresses = BTreeSet::new();
+    let mut invalid = 0;
+    for entry in entries {
+        match address::normalize(entry) {
+            Ok(address) => {
+                addresses.insert(address);
+            }
+            Err(_) => invalid += 1,
+        }
+    }
+    (addresses, invalid)
+}
+
+pub fn run_import(options: &ImportOptions) -> Result<()> {
+    let mut set = match set_path(&options.name).exists() && !options.replace {
+        true => load_set(&options.name)?,
+        false => BTreeSet::new(),
+    };
+    let before = set.len();
+    for file in &options.files {
+        let (addresses, invalid) = read_list(file, options.column.as_deref())?;
+        if invalid > 0 {
+            warn!(file = %file.display(), entries = invalid, "Skipped entries that aren't valid addresses");
+        }
+        let read = addresses.len();
+        let added = addresses.into_iter().filter(|address| set.insert(address.clone())).count();
+        info!(file = %file.display(), addresses = read, added, "Imported address list");
+    }
+    let sources: Vec<String> = options.files.iter().map(|file| file.display().to_string()).collect();
+    let path = save_set(&options.name, &set, &format!("imported from {}", sources.join(", ")))?;
+    println!(
+        "Set '{}': {} addresses ({} new) in {}",
+        options.name,
+        set.len(),
+        set.len() - before,
+        path.display()
+    );
+    Ok(())
+}
+
+/// Addresses of an operand: `scan`, an imported set, a file, or a run or snapshot id.
+fn resolve_operand(operand: &str) -> Result<BTreeSet<String>> {
+    if operand == "scan" {
+        return overlap::load_holder_set(Path::new(STATE_PATH));
+    }
+    if parse_set_name(operand).is_ok() && set_path(operand).exists() {
+        return load_set(operand);
+    }
+    let path = Path::new(operand);
+    if path.is_file() && path.extension().is_some_and(|ext| ext == "csv") {
+        let (addresses, invalid) = read_list(path, None)?;
+        if invalid > 0 {
+            warn!(file = %path.display(), entries = invalid, "Skipped entries that aren't valid addresses");
+        }
+        return Ok(addresses);
+    }
+    if path.is_file() {
+        return overlap::load_holder_set(path);
+    }
+    let state = archive::resolve_state(operand)
+        .with_context(|| format!("'{}' is not `scan`, an imported set, a file, or a run or snapshot id", operand))?;
+    overlap::load_holder_set(&state)
+}
+
+pub fn run_sets(command: &SetsCommand) -> Result<()> {
+    let (operation, name) = match command {
+        SetsCommand::List => {
+            let mut names: Vec<String> = match fs::read_dir(SETS_DIR) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_suffix(".txt").map(str::to_string))
+                    .collect(),
+                Err(_) => Vec::new(),
+            };
+            names.sort();
+            println!("{:<30} {:>10}", "set", "addresses");
+            for name in names {
+                println!("{:<30} {:>10}", name, load_set(&name)?.len());
+            }
+            return Ok(());
+        }
+        SetsCommand::Show { name } => {
+            let mut out = std::io::stdout().lock();
+            write_addresses(&mut out, &load_set(name)?)?;
+            return Ok(());
+        }
+        SetsCommand::Union(operation) => (operation, "union"),
+        SetsCommand::Intersect(operation) => (operation, "intersect"),
+        SetsCommand::Subtract(operation) => (operation, "subtract"),
+    };
+    let sets = operation.operands.iter().map(|operand| resolve_operand(operand)).collect::<Result<Vec<_>>>()?;
+    for (operand, set) in operation.operands.iter().zip(&sets) {
+        info!(operand = %operand, addresses = set.len(), "Loaded operand");
+    }
+    let (first, rest) = sets.split_first().expect("clap requires two operands");
+    let result: BTreeSet<String> = match name {
+        "union" => sets.iter().flatten().cloned().collect(),
+        "intersect" => first.iter().filter(|a| rest.iter().all(|set| set.contains(*a))).cloned().collect(),
+        _ => first.iter().filter(|a| rest.iter().all(|set| !set.contains(*a))).cloned().collect(),
+    };
+
+    let description = format!("{} {}", name, operation.operands.join(" "));
+    if let Some(into) = &operation.into {
+        let path = save_set(into, &result, &description)?;
+        println!("Set '{}': {} addresses in {}", into, result.len(), path.display());
+    }
+    if let Some(output) = &operation.output {
+        let mut file = BufWriter::new(File::create(output).with_context(|| format!("writing {}", output.display()))?);
+        write_addresses(&mut file, &result)?;
+        file.flush()?;
+        println!("{} addresses written to {}", result.len(), output.display());
+    }
+    if operation.into.is_none() && operation.output.is_none() {
+        write_addresses(&mut std::io::stdout().lock(), &result)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Deserializer};
+
+use crate::error::ScannerError;
+
+/// Response of the NFT API `getOwnersForContract` with `withTokenBalances=true`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnersResponse {
+    pub owners: Vec<Owner>,
+    #[serde(default)]
+    pub page_key: Option<String>,
+    #[serde(default, deserialize_with = "optional_quantity")]
+    pub total_count: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Owner {
+    pub owner_address: String,
+    #[serde(default)]
+    pub token_balances: Vec<TokenBalance>,
+}
+
+impl Owner {
+    pub fn balance(&self) -> u64 {
+        self.token_balances.iter().map(|b| b.balance).sum()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenBalance {
+    pub token_id: String,
+    #[serde(deserialize_with = "quantity")]
+    pub balance: u64,
+}
+
+/// Error envelopes seen from Alchemy: `{"error": {"code": .., "message": ..}}`,
+/// `{"error": "..."}` and `{"message": "..."}`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ErrorEnvelope {
+    Nested { error: ErrorBody },
+    Flat { message: String },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ErrorBody {
+    Detailed { message: String },
+    Text(String),
+}
+
+pub fn error_message(body: &str) -> Option<String> {
+    match serde_json::from_str::<ErrorEnvelope>(body).ok()? {
+        ErrorEnvelope::Nested { error: ErrorBody::Detailed { message } } => Some(message),
+        ErrorEnvelope::Nested { error: ErrorBody::Text(message) } => Some(message),
+        ErrorEnvelope::Flat { message } => Some(message),
+    }
+}
+
+/// Parses an owners page, turning error statuses and bodies that don't match
+/// the expected schema into `ScannerError`s.
+pub fn parse_owners_page(status: reqwest::StatusCode, body: &str) -> Result<OwnersResponse, ScannerError> {
+    if !status.is_success() {
+        return Err(ScannerError::from_status(status, None, body));
+    }
+    serde_json::from_str(body).map_err(|e| ScannerError::MalformedResponse {
+        reason: e.to_string(),
+        body: body.to_string(),
+    })
+}
+
+// Quantities arrive as JSON numbers or decimal strings depending on the endpoint
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Quantity {
+    Number(u64),
+    Text(String),
+}
+
+fn quantity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match Quantity::deserialize(deserializer)? {
+        Quantity::Number(n) => Ok(n),
+        Quantity::Text(s) => s.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+fn optional_quantity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Option::<Quantity>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Quantity::Number(n)) => Ok(Some(n)),
+        Some(Quantity::Text(s)) => s.parse().map(Some).map_err(serde::de::Error::custom),
+    }
+}
@@ -0,0 +1,504 @@
+use anyhow::{bail, ensure, Context, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::info;
+use web3::types::U256;
+
+use crate::address;
+use crate::identity::HolderIdentity;
+use crate::output;
+use crate::pricing;
+use crate::state::{self, ScanState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Amount proportional to tokens held
+    #[default]
+    Proportional,
+    /// Proportional, with no address above `cap_share` of the total (required)
+    Capped,
+    /// The same amount for every holder in a balance tier, tiers weighted by `tiers`
+    Tiered,
+    /// Amount proportional to the square root of tokens held
+    Quadratic,
+}
+
+/// Holders with at least `min_balance` tokens (and less than the next tier's
+/// minimum) get `weight` shares each.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Tier {
+    pub min_balance: u64,
+    pub weight: f64,
+}
+
+fn parse_tier(value: &str) -> Result<Tier, String> {
+    let (min, weight) = value.split_once(':').ok_or_else(|| format!("expected MIN_BALANCE:WEIGHT, got {}", value))?;
+    let min_balance = min.trim().parse().map_err(|_| format!("invalid tier balance: {}", min))?;
+    let weight: f64 = weight.trim().parse().map_err(|_| format!("invalid tier weight: {}", weight))?;
+    if !(weight >= 0.0 && weight.is_finite()) {
+        return Err(format!("tier weight must be a non-negative number, got {}", weight));
+    }
+    Ok(Tier { min_balance, weight })
+}
+
+/// How a reward budget is split across holders.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AllocationPolicy {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Largest share of the total any single address may receive; the excess is
+    /// redistributed over the remaining recipients
+    #[serde(default)]
+    pub cap_share: Option<f64>,
+    #[serde(default = "default_min_balance")]
+    pub min_balance: u64,
+    /// Smallest amount worth sending; holders whose share falls below it are
+    /// dropped and their share goes to the rest
+    #[serde(default)]
+    pub min_amount: u128,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Balance tiers for the tiered strategy
+    #[serde(default)]
+    pub tiers: Vec<Tier>,
+    /// Amounts are multiples of this many base units, e.g. 10^18 for whole
+    /// tokens; the remainder of the total that doesn't divide stays unallocated
+    #[serde(default = "default_round_to")]
+    pub round_to: u128,
+    /// Leave out holders whose balance is worth less than this many USD
+    #[serde(default)]
+    pub min_usd: Option<f64>,
+}
+
+fn default_min_balance() -> u64 {
+    1
+}
+
+fn default_round_to() -> u128 {
+    1
+}
+
+impl AllocationPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.strategy == Strategy::Capped && self.cap_share.is_none() {
+            bail!("policy '{}': the capped strategy needs a cap share", self.name);
+        }
+        if let Some(share) = self.cap_share.filter(|share| !(*share > 0.0 && *share <= 1.0)) {
+            bail!("policy '{}': cap_share must be above 0 and at most 1, got {}", self.name, share);
+        }
+        if self.strategy == Strategy::Tiered && self.tiers.is_empty() {
+            bail!("policy '{}': the tiered strategy needs at least one tier", self.name);
+        }
+        ensure!(self.round_to > 0, "policy '{}': round_to must be at least 1", self.name);
+        Ok(())
+    }
+
+    /// What the amounts add up to when anyone is eligible: `total` rounded
+    /// down to a multiple of `round_to`.
+    pub fn allocatable(&self, total: u128) -> u128 {
+        total / self.round_to.max(1) * self.round_to.max(1)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocationRow {
+    pub address: String,
+    pub balance: u64,
+    pub amount: u128,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AllocateOptions {
+    /// Total reward to distribute, in the reward token's base units
+    #[arg(long, required_unless_present = "budget_range")]
+    pub total: Option<u128>,
+    #[arg(long, value_enum, default_value = "proportional")]
+    pub strategy: Strategy,
+    /// Largest share of the total a single address may receive (e.g. 0.01)
+    #[arg(long)]
+    pub cap_share: Option<f64>,
+    #[arg(long, default_value_t = 1)]
+    pub min_balance: u64,
+    /// Drop recipients whose amount would be below this and redistribute their share
+    #[arg(long, default_value_t = 0)]
+    pub min_amount: u128,
+    /// Addresses to leave out, repeatable
+    #[arg(long)]
+    pub exclude: Vec<String>,
+    /// Tier for --strategy tiered as MIN_BALANCE:WEIGHT, repeatable (e.g. --tier 1:1 --tier 10:3 --tier 100:10)
+    #[arg(long = "tier", value_name = "MIN_BALANCE:WEIGHT", value_parser = parse_tier)]
+    pub tiers: Vec<Tier>,
+    /// Leave out holders whose balance is worth less than this many USD (needs `price`)
+    #[arg(long)]
+    pub min_usd: Option<f64>,
+    /// Round amounts down to multiples of this many base units (e.g. 1000000000000000000 for whole tokens)
+    #[arg(long, default_value_t = 1)]
+    pub round_to: u128,
+    /// Snapshot to allocate over (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Compare the policy variants in this TOML file instead of writing one allocation
+    #[arg(long, value_name = "VARIANTS_TOML", conflicts_with = "budget_range")]
+    pub what_if: Option<PathBuf>,
+    /// Instead of one allocation, export how recipients and median amount change
+    /// across budgets from MIN to MAX (log-spaced)
+    #[arg(long, value_name = "MIN:MAX", value_parser = parse_budget_range)]
+    pub budget_range: Option<(u128, u128)>,
+    /// Number of budgets sampled by --budget-range
+    #[arg(long, default_value_t = 10, requires = "budget_range")]
+    pub steps: usize,
+    #[arg(long, default_value = "data/allocation.csv")]
+    pub output: PathBuf,
+}
+
+impl AllocateOptions {
+    pub fn policy(&self) -> AllocationPolicy {
+        AllocationPolicy {
+            name: "cli".to_string(),
+            strategy: self.strategy,
+            cap_share: self.cap_share,
+            min_balance: self.min_balance,
+            min_amount: self.min_amount,
+            exclude: self.exclude.clone(),
+            tiers: self.tiers.clone(),
+            round_to: self.round_to,
+            min_usd: self.min_usd,
+        }
+    }
+}
+
+fn weight(policy: &AllocationPolicy, balance: u64) -> f64 {
+    match policy.strategy {
+        Strategy::Proportional | Strategy::Capped => balance as f64,
+        Strategy::Tiered => policy
+            .tiers
+            .iter()
+            .filter(|t| t.min_balance <= balance)
+            .max_by_key(|t| t.min_balance)
+            .map_or(0.0, |t| t.weight),
+        Strategy::Quadratic => (balance as f64).sqrt(),
+    }
+}
+
+fn parse_budget_range(value: &str) -> Result<(u128, u128), String> {
+    let (min, max) = value.split_once(':').ok_or_else(|| format!("expected MIN:MAX, got {}", value))?;
+    let min: u128 = min.trim().parse().map_err(|_| format!("invalid budget: {}", min))?;
+    let max: u128 = max.trim().parse().map_err(|_| format!("invalid budget: {}", max))?;
+    if min == 0 || min > max {
+        return Err("budget range must satisfy 0 < MIN <= MAX".to_string());
+    }
+    Ok((min, max))
+}
+
+/// Splits `total` over eligible holders. Amounts are whole multiples of
+/// `round_to`: shares are floored and the leftover units go to the largest
+/// remainders, so the rows always sum to exactly `policy.allocatable(total)`
+/// (unless nobody is eligible). Holders
+/// left with less than `min_amount` (or nothing) are dropped and the split
+/// is recomputed without them. Fails on an invalid excluded address, and
+/// when eligible holders exist but the strategy gives all of them zero weight.
+pub fn allocate(state: &ScanState, policy: &AllocationPolicy, total: u128) -> Result<Vec<AllocationRow>> {
+    let excluded: HashSet<String> = policy
+        .exclude
+        .iter()
+        .map(|a| address::normalize(a).with_context(|| format!("policy '{}': exclude list", policy.name)))
+        .collect::<Result<_>>()?;
+    let mut rows: Vec<AllocationRow> = state
+        .holders
+        .iter()
+        .filter(|h| !excluded.contains(*h))
+        .filter(|h| policy.min_usd.is_none_or(|min| state.usd_value(h).is_some_and(|usd| usd >= min)))
+        .map(|h| AllocationRow {
+            address: h.clone(),
+            balance: state.balances.get(h).copied().unwrap_or(0),
+            amount: 0,
+        })
+        .filter(|r| r.balance >= policy.min_balance && r.balance > 0)
+        .collect();
+    rows.sort_by(|a, b| a.address.cmp(&b.address));
+
+    if !rows.is_empty() && rows.iter().all(|r| weight(policy, r.balance) <= 0.0) {
+        bail!(
+            "policy '{}': all {} eligible holders have zero weight under the {:?} strategy",
+            policy.name,
+            rows.len(),
+            policy.strategy
+        );
+    }
+
+    loop {
+        distribute(&mut rows, policy, total);
+        let before = rows.len();
+        rows.retain(|r| r.amount > 0 && r.amount >= policy.min_amount);
+        if rows.len() == before {
+            return Ok(rows);
+        }
+    }
+}
+
+/// Shares are turned into 64-bit fixed-point weights so the split itself is
+/// exact integer arithmetic.
+const SHARE_SCALE: f64 = 18_446_744_073_709_551_616.0;
+
+fn distribute(rows: &mut [AllocationRow], policy: &AllocationPolicy, total: u128) {
+    if rows.is_empty() {
+        return;
+    }
+    let shares = capped_shares(
+        &rows.iter().map(|r| weight(policy, r.balance)).collect::<Vec<_>>(),
+        policy.cap_share,
+    );
+    let weights: Vec<U256> = shares.iter().map(|s| U256::from((s * SHARE_SCALE) as u128)).collect();
+    let total_weight = weights.iter().fold(U256::zero(), |sum, w| sum + w);
+    if total_weight.is_zero() {
+        rows.iter_mut().for_each(|r| r.amount = 0);
+        return;
+    }
+
+    // Largest-remainder rounding, in units of `round_to`
+    let unit = policy.round_to.max(1);
+    let units = U256::from(total / unit);
+    let mut remainders = Vec::with_capacity(rows.len());
+    for (row, weight) in rows.iter_mut().zip(&weights) {
+        let (amount, remainder) = (units * weight).div_mod(total_weight);
+        row.amount = amount.as_u128();
+        remainders.push(remainder);
+    }
+    // The floored amounts fall short by less than one unit per row
+    let assigned: u128 = rows.iter().map(|r| r.amount).sum();
+    let leftover = (total / unit - assigned) as usize;
+    let mut order: Vec<usize> = (0..rows.len()).collect();
+    order.sort_by(|&a, &b| remainders[b].cmp(&remainders[a]));
+    for index in order.into_iter().take(leftover) {
+        rows[index].amount += 1;
+    }
+    for row in rows.iter_mut() {
+        row.amount *= unit;
+    }
+}
+
+/// Checks that the amounts add up to what the policy says should be handed
+/// out, and returns the part of `total` left unallocated.
+pub fn verify(rows: &[AllocationRow], policy: &AllocationPolicy, total: u128) -> Result<u128> {
+    let sum: u128 = rows.iter().map(|r| r.amount).sum();
+    let expected = if rows.is_empty() { 0 } else { policy.allocatable(total) };
+    ensure!(sum == expected, "allocation amounts sum to {} but {} was expected", sum, expected);
+    ensure!(
+        rows.iter().all(|r| r.amount % policy.round_to.max(1) == 0),
+        "an amount is not a multiple of {}",
+        policy.round_to
+    );
+    Ok(total - sum)
+}
+
+/// Normalizes weights into shares, clamping any share above `cap` and
+/// redistributing the excess over the uncapped entries until none exceed it.
+fn capped_shares(weights: &[f64], cap: Option<f64>) -> Vec<f64> {
+    let total: f64 = weights.iter().sum();
+    let mut shares: Vec<f64> = weights.iter().map(|w| if total > 0.0 { w / total } else { 0.0 }).collect();
+    let Some(cap) = cap.filter(|c| *c > 0.0 && *c * (weights.len() as f64) >= 1.0) else {
+        // A cap too small to place the whole budget is ignored rather than leaving funds unallocated
+        return shares;
+    };
+
+    let mut capped = vec![false; shares.len()];
+    loop {
+        let newly_capped: Vec<usize> = (0..shares.len()).filter(|&i| !capped[i] && shares[i] > cap).collect();
+        if newly_capped.is_empty() {
+            return shares;
+        }
+        for &i in &newly_capped {
+            capped[i] = true;
+            shares[i] = cap;
+        }
+        let remaining = 1.0 - cap * capped.iter().filter(|c| **c).count() as f64;
+        let free_weight: f64 = (0..shares.len()).filter(|&i| !capped[i]).map(|i| weights[i]).sum();
+        for i in (0..shares.len()).filter(|&i| !capped[i]) {
+            shares[i] = if free_weight > 0.0 { weights[i] / free_weight * remaining } else { 0.0 };
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AllocationSummary {
+    pub name: String,
+    pub recipients: usize,
+    pub allocated: u128,
+    pub median: u128,
+    pub max: u128,
+    pub top10_share: f64,
+}
+
+pub fn summarize(name: &str, rows: &[AllocationRow]) -> AllocationSummary {
+    let mut amounts: Vec<u128> = rows.iter().map(|r| r.amount).collect();
+    amounts.sort_unstable_by(|a, b| b.cmp(a));
+    let allocated: u128 = amounts.iter().sum();
+    let top10: u128 = amounts.iter().take(10).sum();
+    let median = match amounts.len() {
+        0 => 0,
+        n if n % 2 == 1 => amounts[n / 2],
+        n => (amounts[n / 2 - 1] + amounts[n / 2]) / 2,
+    };
+    AllocationSummary {
+        name: name.to_string(),
+        recipients: rows.len(),
+        allocated,
+        median,
+        max: amounts.first().copied().unwrap_or(0),
+        top10_share: if allocated > 0 { top10 as f64 / allocated as f64 } else { 0.0 },
+    }
+}
+
+pub fn save_allocation(rows: &[AllocationRow], path: &Path) -> Result<()> {
+    let mut writer = output::csv_writer(path)?;
+    writer.write_record(["address", "balance", "amount"])?;
+    for row in rows {
+        writer.write_record([row.address.clone(), row.balance.to_string(), row.amount.to_string()])?;
+    }
+    output::finish_csv(writer)
+}
+
+#[derive(Debug, Deserialize)]
+struct WhatIfFile {
+    #[serde(rename = "variant")]
+    variants: Vec<AllocationPolicy>,
+}
+
+pub fn load_variants(path: &Path) -> Result<Vec<AllocationPolicy>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let file: WhatIfFile = toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+    let variants: Vec<AllocationPolicy> = file
+        .variants
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut v)| {
+            if v.name.is_empty() {
+                v.name = format!("variant-{}", i + 1);
+            }
+            v
+        })
+        .collect();
+    for variant in &variants {
+        variant.validate()?;
+    }
+    Ok(variants)
+}
+
+pub fn save_comparison(summaries: &[AllocationSummary], path: &Path) -> Result<()> {
+    let mut writer = output::csv_writer(path)?;
+    writer.write_record(["variant", "recipients", "allocated", "median_amount", "max_amount", "top10_share"])?;
+    for s in summaries {
+        writer.write_record([
+            s.name.clone(),
+            s.recipients.to_string(),
+            s.allocated.to_string(),
+            s.median.to_string(),
+            s.max.to_string(),
+            format!("{:.4}", s.top10_share),
+        ])?;
+    }
+    output::finish_csv(writer)
+}
+
+/// Budgets from `min` to `max` inclusive, evenly spaced on a log scale.
+pub fn budget_steps(min: u128, max: u128, steps: usize) -> Vec<u128> {
+    if steps <= 1 || min == max {
+        return vec![max];
+    }
+    let ratio = (max as f64 / min as f64).powf(1.0 / (steps - 1) as f64);
+    let mut budgets: Vec<u128> = (0..steps)
+        .map(|i| match i {
+            0 => min,
+            i if i == steps - 1 => max,
+            i => (min as f64 * ratio.powi(i as i32)).round() as u128,
+        })
+        .collect();
+    budgets.dedup();
+    budgets
+}
+
+pub fn save_sensitivity(curve: &[(u128, AllocationSummary)], path: &Path) -> Result<()> {
+    let mut writer = output::csv_writer(path)?;
+    writer.write_record(["budget", "recipients", "median_amount", "max_amount", "top10_share"])?;
+    for (budget, s) in curve {
+        writer.write_record([
+            budget.to_string(),
+            s.recipients.to_string(),
+            s.median.to_string(),
+            s.max.to_string(),
+            format!("{:.4}", s.top10_share),
+        ])?;
+    }
+    output::finish_csv(writer)
+}
+
+pub fn print_comparison<S: std::borrow::Borrow<AllocationSummary>>(summaries: &[S]) {
+    println!("{:<24} {:>10} {:>24} {:>24} {:>8}", "variant", "recipients", "median", "max", "top10");
+    for s in summaries.iter().map(|s| s.borrow()) {
+        println!(
+            "{:<24} {:>10} {:>24} {:>24} {:>7.2}%",
+            s.name, s.recipients, s.median, s.max, s.top10_share * 100.0
+        );
+    }
+}
+
+pub fn run_allocate(options: &AllocateOptions) -> Result<()> {
+    let state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    // One allocation per holder identity; with the cluster key the rows are cluster ids
+    let identity = HolderIdentity::from_env()?;
+    let state = identity.regroup(&state);
+
+    let policy = options.policy();
+    policy.validate()?;
+    if policy.min_usd.is_some() {
+        pricing::require_valuation(&state)?;
+    }
+
+    if let Some((min, max)) = options.budget_range {
+        let curve: Vec<(u128, AllocationSummary)> = budget_steps(min, max, options.steps)
+            .into_iter()
+            .map(|budget| allocate(&state, &policy, budget).map(|rows| (budget, summarize(&budget.to_string(), &rows))))
+            .collect::<Result<_>>()?;
+        print_comparison(&curve.iter().map(|(_, s)| s).collect::<Vec<_>>());
+        let path = Path::new("data/budget_sensitivity.csv");
+        save_sensitivity(&curve, path)?;
+        info!(budgets = curve.len(), output = %path.display(), "Budget sensitivity curve written");
+        return Ok(());
+    }
+
+    let total = options.total.context("--total is required")?;
+    if let Some(variants_path) = &options.what_if {
+        let summaries: Vec<AllocationSummary> = load_variants(variants_path)?
+            .iter()
+            .map(|policy| allocate(&state, policy, total).map(|rows| summarize(&policy.name, &rows)))
+            .collect::<Result<_>>()?;
+        print_comparison(&summaries);
+        let path = Path::new("data/what_if.csv");
+        save_comparison(&summaries, path)?;
+        info!(variants = summaries.len(), output = %path.display(), "What-if comparison written");
+        return Ok(());
+    }
+
+    let rows = allocate(&state, &policy, total)?;
+    let unallocated = verify(&rows, &policy, total)?;
+    save_allocation(&rows, &options.output)?;
+    let summary = summarize("cli", &rows);
+    println!("Recipients:  {}", summary.recipients);
+    println!("Allocated:   {}", summary.allocated);
+    println!("Unallocated: {}", unallocated);
+    println!("Verification: {} allocated + {} unallocated = {} total", summary.allocated, unallocated, total);
+    info!(
+        recipients = summary.recipients,
+        allocated = %summary.allocated,
+        output = %options.output.display(),
+        "Allocation written"
+    );
+    Ok(())
+}
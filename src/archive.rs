@@ -0,0 +1,461 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::address;
+use crate::completeness::Verdict;
+use crate::digest::{self, SnapshotSignature};
+use crate::enumerate;
+use crate::histogram::HISTOGRAM_TABLE_PATH;
+use crate::journal::{self, JournalEvent};
+use crate::output;
+use crate::report::AGE_DISTRIBUTION_PATH;
+use crate::rpc;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, ScanState, HOLDERS_PATH};
+
+pub const SNAPSHOTS_DIR: &str = "data/snapshots";
+
+// Files copied into a snapshot alongside the state when they exist
+fn snapshot_files() -> Result<Vec<String>> {
+    Ok(vec![
+        output::export_path(HOLDERS_PATH)?,
+        output::export_path(AGE_DISTRIBUTION_PATH)?,
+        output::export_path(HISTOGRAM_TABLE_PATH)?,
+        "data/provider_report.json".to_string(),
+    ])
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub id: String,
+    pub contract: String,
+    pub created_at: DateTime<Utc>,
+    pub path: PathBuf,
+    pub total_holders: u64,
+    /// Block the holders were read at, for snapshots made by `archive rescan`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block: Option<u64>,
+    /// keccak256 of the snapshot's canonical holder list; see `digest::canonical_list`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SnapshotSignature>,
+    /// Set by `archive rm` and `archive rescan`. The files stay on disk, but
+    /// time lookups, listings and retention skip the snapshot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded: Option<Superseded>,
+    /// Set when the scan stopped early, left pages in quarantine or held less than `totalSupply()`,
+    /// so holders are missing
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub incomplete: bool,
+    /// Set when the scan held more than `totalSupply()` or the provider's holder count
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inconsistent: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Superseded {
+    pub at: DateTime<Utc>,
+    /// Id of the snapshot that replaces this one, when it was re-scanned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl SnapshotEntry {
+    pub fn is_active(&self) -> bool {
+        self.superseded.is_none()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SnapshotIndex {
+    pub snapshots: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RetentionPolicy {
+    /// Keep the N most recent snapshots
+    pub keep_last: Option<usize>,
+    /// Keep the newest snapshot from each of the N most recent ISO weeks
+    pub keep_weekly: Option<usize>,
+}
+
+impl RetentionPolicy {
+    pub fn is_unbounded(&self) -> bool {
+        self.keep_last.is_none() && self.keep_weekly.is_none()
+    }
+}
+
+pub fn contract_dir(contract: &str) -> PathBuf {
+    Path::new(SNAPSHOTS_DIR).join(contract.to_lowercase())
+}
+
+fn index_path(contract: &str) -> PathBuf {
+    contract_dir(contract).join("index.json")
+}
+
+pub fn load_index(contract: &str) -> Result<SnapshotIndex> {
+    let path = index_path(contract);
+    if path.exists() {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    } else {
+        Ok(SnapshotIndex::default())
+    }
+}
+
+/// Snapshots that haven't been superseded, oldest first.
+pub fn active_snapshots(contract: &str) -> Result<Vec<SnapshotEntry>> {
+    let mut snapshots: Vec<SnapshotEntry> =
+        load_index(contract)?.snapshots.into_iter().filter(|s| s.is_active()).collect();
+    snapshots.sort_by_key(|s| s.created_at);
+    Ok(snapshots)
+}
+
+/// The latest active snapshot taken at or before `time`.
+pub fn snapshot_at(contract: &str, time: DateTime<Utc>) -> Result<Option<SnapshotEntry>> {
+    let snapshots = active_snapshots(contract)?;
+    Ok(snapshots.into_iter().rev().find(|s| s.created_at <= time))
+}
+
+/// The state file behind `reference`: a state file, a snapshot directory, the
+/// id of a run that archived a snapshot, or a snapshot id of the scanned contract.
+pub fn resolve_state(reference: &str) -> Result<PathBuf> {
+    let path = Path::new(reference);
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+    if path.is_dir() {
+        let state = path.join("state.json");
+        if !state.exists() {
+            bail!("{} has no state.json", path.display());
+        }
+        return Ok(state);
+    }
+    let state = if journal::run_ids()?.iter().any(|id| id == reference) {
+        let snapshot = journal::load_run(reference)?.into_iter().rev().find_map(|entry| match entry.event {
+            JournalEvent::Snapshot { path, .. } => Some(path),
+            _ => None,
+        });
+        match snapshot {
+            Some(dir) => dir.join("state.json"),
+            None => bail!("run {} archived no snapshot; pass the state file it left instead", reference),
+        }
+    } else {
+        match load_index(CONTRACT_ADDRESS)?.snapshots.into_iter().find(|s| s.id == reference) {
+            Some(entry) => entry.path.join("state.json"),
+            None => bail!("{} is not a state file, snapshot directory, run id or snapshot id", reference),
+        }
+    };
+    if !state.exists() {
+        bail!("the snapshot of {} is gone from {}; it was probably pruned", reference, state.display());
+    }
+    Ok(state)
+}
+
+pub fn save_index(contract: &str, index: &SnapshotIndex) -> Result<()> {
+    std::fs::create_dir_all(contract_dir(contract))?;
+    let file = File::create(index_path(contract))?;
+    serde_json::to_writer_pretty(file, index)?;
+    Ok(())
+}
+
+pub fn archive_snapshot(contract: &str, state: &ScanState) -> Result<SnapshotEntry> {
+    archive_state(contract, state, Utc::now(), None)
+}
+
+/// Archives `state` as taken at `created_at`. Only a snapshot of the current
+/// scan (`block` unset) gets copies of the report files in `data/`.
+fn archive_state(
+    contract: &str,
+    state: &ScanState,
+    created_at: DateTime<Utc>,
+    block: Option<u64>,
+) -> Result<SnapshotEntry> {
+    let mut id = created_at.format("%Y%m%dT%H%M%SZ").to_string();
+    // A re-scan can land on the second of an existing snapshot
+    let mut suffix = 1;
+    while contract_dir(contract).join(&id).exists() {
+        suffix += 1;
+        id = format!("{}-{}", created_at.format("%Y%m%dT%H%M%SZ"), suffix);
+    }
+    let dir = contract_dir(contract).join(&id);
+    std::fs::create_dir_all(&dir)?;
+
+    serde_json::to_writer_pretty(File::create(dir.join("state.json"))?, state)?;
+    let canonical = digest::canonical_list(state);
+    std::fs::write(dir.join(digest::CANONICAL_LIST), &canonical)?;
+    let hash = digest::snapshot_hash(&canonical);
+    let signature = digest::sign_from_env(&hash)?;
+    if block.is_none() {
+        for source in snapshot_files()? {
+            let source = Path::new(&source);
+            if let Some(name) = source.file_name().filter(|_| source.exists()) {
+                std::fs::copy(source, dir.join(name))?;
+            }
+        }
+    }
+
+    let verdict = state.completeness.as_ref().map(|completeness| completeness.verdict);
+    let entry = SnapshotEntry {
+        id,
+        contract: contract.to_string(),
+        created_at,
+        path: dir,
+        total_holders: state.holders.len() as u64,
+        block,
+        hash: Some(hash),
+        signature,
+        superseded: None,
+        incomplete: !state.complete() || verdict == Some(Verdict::Incomplete),
+        inconsistent: verdict == Some(Verdict::Inconsistent),
+    };
+    let mut index = load_index(contract)?;
+    index.snapshots.push(entry.clone());
+    save_index(contract, &index)?;
+    Ok(entry)
+}
+
+/// Removes active snapshots not selected by the policy and returns the ones
+/// deleted. Superseded snapshots are left for `archive rm --purge`.
+pub fn apply_retention(contract: &str, policy: RetentionPolicy) -> Result<Vec<SnapshotEntry>> {
+    let mut index = load_index(contract)?;
+    if policy.is_unbounded() {
+        return Ok(Vec::new());
+    }
+    index.snapshots.sort_by_key(|s| s.created_at);
+    let (mut superseded, active): (Vec<_>, Vec<_>) = index.snapshots.into_iter().partition(|s| !s.is_active());
+    index.snapshots = active;
+
+    let mut keep: HashSet<String> = HashSet::new();
+    if let Some(n) = policy.keep_last {
+        keep.extend(index.snapshots.iter().rev().take(n).map(|s| s.id.clone()));
+    }
+    if let Some(weeks) = policy.keep_weekly {
+        let mut seen_weeks = Vec::new();
+        for snapshot in index.snapshots.iter().rev() {
+            let week = snapshot.created_at.iso_week();
+            let week = (week.year(), week.week());
+            if seen_weeks.contains(&week) {
+                continue;
+            }
+            if seen_weeks.len() == weeks {
+                break;
+            }
+            seen_weeks.push(week);
+            keep.insert(snapshot.id.clone());
+        }
+    }
+
+    let (mut kept, removed): (Vec<_>, Vec<_>) = index
+        .snapshots
+        .into_iter()
+        .partition(|s| keep.contains(&s.id));
+    for snapshot in &removed {
+        if snapshot.path.exists() {
+            std::fs::remove_dir_all(&snapshot.path)?;
+        }
+    }
+    kept.append(&mut superseded);
+    kept.sort_by_key(|s| s.created_at);
+    save_index(contract, &SnapshotIndex { snapshots: kept })?;
+    Ok(removed)
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum ArchiveCommand {
+    /// List a contract's archived snapshots
+    List {
+        /// Contract address (defaults to the scanned contract)
+        contract: Option<String>,
+        /// Include superseded snapshots
+        #[arg(long)]
+        all: bool,
+    },
+    /// Mark a contract's snapshots as superseded, or delete them with --purge
+    Rm {
+        contract: String,
+        /// Only this snapshot (repeatable); without it every snapshot of the contract
+        #[arg(long = "snapshot", value_name = "ID")]
+        snapshots: Vec<String>,
+        /// Recorded with the snapshot, e.g. "provider returned stale owners"
+        #[arg(long)]
+        reason: Option<String>,
+        /// Delete the files and index entries of snapshots that are already superseded
+        #[arg(long)]
+        purge: bool,
+    },
+    /// Rebuild a snapshot at a block through ownerOf and archive it in place of bad ones
+    Rescan {
+        contract: String,
+        /// Block to read owners at; older blocks need an archive node
+        #[arg(long)]
+        at_block: u64,
+        /// Token ids to query as START:END, for contracts that aren't Enumerable
+        #[arg(long, value_name = "START:END", value_parser = enumerate::parse_id_range)]
+        id_range: Option<(u64, u64)>,
+        /// Snapshot the re-scan replaces (repeatable); defaults to earlier re-scans at the same block
+        #[arg(long = "replaces", value_name = "ID")]
+        replaces: Vec<String>,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Recompute a snapshot's hash from its state and check it and the signature
+    Verify {
+        contract: String,
+        snapshot: String,
+        /// Fail unless this address signed the hash
+        #[arg(long)]
+        signer: Option<String>,
+    },
+}
+
+pub async fn run_archive(command: &ArchiveCommand) -> Result<()> {
+    match command {
+        ArchiveCommand::List { contract, all } => {
+            let contract = contract.as_deref().unwrap_or(CONTRACT_ADDRESS);
+            let mut snapshots = load_index(contract)?.snapshots;
+            snapshots.sort_by_key(|s| s.created_at);
+            println!("{:<22} {:<25} {:>10} {:>12}  status", "snapshot", "created", "holders", "block");
+            for snapshot in snapshots.iter().filter(|s| *all || s.is_active()) {
+                let status = match &snapshot.superseded {
+                    None if snapshot.incomplete => "active, incomplete".to_string(),
+                    None if snapshot.inconsistent => "active, inconsistent".to_string(),
+                    None => "active".to_string(),
+                    Some(Superseded { by: Some(by), .. }) => format!("superseded by {}", by),
+                    Some(Superseded { reason: Some(reason), .. }) => format!("removed: {}", reason),
+                    Some(_) => "removed".to_string(),
+                };
+                println!(
+                    "{:<22} {:<25} {:>10} {:>12}  {}",
+                    snapshot.id,
+                    snapshot.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                    snapshot.total_holders,
+                    snapshot.block.map(|b| b.to_string()).unwrap_or_default(),
+                    status
+                );
+            }
+        }
+        ArchiveCommand::Rm { contract, snapshots, reason, purge } => {
+            let mut index = load_index(contract)?;
+            check_ids(&index, snapshots)?;
+            let selected = |s: &SnapshotEntry| snapshots.is_empty() || snapshots.contains(&s.id);
+            if *purge {
+                let (purged, kept): (Vec<_>, Vec<_>) =
+                    index.snapshots.into_iter().partition(|s| selected(s) && !s.is_active());
+                for snapshot in &purged {
+                    if snapshot.path.exists() {
+                        std::fs::remove_dir_all(&snapshot.path)?;
+                    }
+                    info!(snapshot = %snapshot.id, "Purged superseded snapshot");
+                }
+                save_index(contract, &SnapshotIndex { snapshots: kept })?;
+                println!("{} superseded snapshot(s) purged", purged.len());
+                return Ok(());
+            }
+            let mut marked = 0;
+            for snapshot in index.snapshots.iter_mut().filter(|s| selected(s) && s.is_active()) {
+                snapshot.superseded = Some(Superseded { at: Utc::now(), by: None, reason: reason.clone() });
+                marked += 1;
+            }
+            save_index(contract, &index)?;
+            println!("{} snapshot(s) marked as superseded", marked);
+        }
+        ArchiveCommand::Rescan { contract, at_block, id_range, replaces, reason } => {
+            if !contract.eq_ignore_ascii_case(CONTRACT_ADDRESS) {
+                bail!("only {} can be re-scanned by this build", CONTRACT_ADDRESS);
+            }
+            let index = load_index(contract)?;
+            check_ids(&index, replaces)?;
+            let replaced: Vec<&SnapshotEntry> = index
+                .snapshots
+                .iter()
+                .filter(|s| s.is_active())
+                .filter(|s| match replaces.is_empty() {
+                    true => s.block == Some(*at_block),
+                    false => replaces.contains(&s.id),
+                })
+                .collect();
+            // First-seen times and metadata come from the snapshot being replaced
+            let previous = match replaced.first() {
+                Some(entry) => state::load_state_from(&entry.path.join("state.json"))?,
+                None => ScanState::default(),
+            };
+            let mut rescanned = enumerate::enumerate_state(Some(*at_block), *id_range, &previous).await?;
+            // Prices from another time would misvalue the holders
+            rescanned.valuation = None;
+            let created_at = rpc::block_time(&rpc::connect()?, *at_block).await?;
+            rescanned.last_save_time = created_at;
+            let entry = archive_state(contract, &rescanned, created_at, Some(*at_block))?;
+
+            let replaced: Vec<String> = replaced.into_iter().map(|s| s.id.clone()).collect();
+            let mut index = load_index(contract)?;
+            for snapshot in index.snapshots.iter_mut().filter(|s| replaced.contains(&s.id)) {
+                snapshot.superseded =
+                    Some(Superseded { at: Utc::now(), by: Some(entry.id.clone()), reason: reason.clone() });
+            }
+            save_index(contract, &index)?;
+            println!(
+                "Archived snapshot {} at block {} with {} holders; superseded {}",
+                entry.id,
+                at_block,
+                entry.total_holders,
+                if replaced.is_empty() { "nothing".to_string() } else { replaced.join(", ") }
+            );
+        }
+        ArchiveCommand::Verify { contract, snapshot, signer } => {
+            verify_snapshot(contract, snapshot, signer.as_deref())?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a snapshot's state still hashes to the recorded hash, and who
+/// signed it. Snapshots archived before hashing have nothing to check against.
+fn verify_snapshot(contract: &str, id: &str, expected_signer: Option<&str>) -> Result<()> {
+    let index = load_index(contract)?;
+    let Some(entry) = index.snapshots.iter().find(|s| s.id == id) else {
+        bail!("no snapshot {} in the archive", id);
+    };
+    let Some(recorded) = &entry.hash else {
+        bail!("snapshot {} was archived without a hash", id);
+    };
+    let state = state::load_state_from(&entry.path.join("state.json"))?;
+    let hash = digest::snapshot_hash(&digest::canonical_list(&state));
+    if &hash != recorded {
+        bail!("snapshot {} hashes to {}, but {} was recorded; its state has changed", id, hash, recorded);
+    }
+    println!("hash      {} (matches)", hash);
+
+    match (&entry.signature, expected_signer) {
+        (Some(signature), expected) => {
+            let recovered = digest::recover_signer(&hash, &signature.signature)?;
+            let recovered = address::checksummed(&format!("{:?}", recovered))?;
+            if !recovered.eq_ignore_ascii_case(&signature.signer) {
+                bail!("signature was made by {}, not the recorded signer {}", recovered, signature.signer);
+            }
+            if let Some(expected) = expected.filter(|expected| !expected.eq_ignore_ascii_case(&recovered)) {
+                bail!("snapshot {} was signed by {}, not {}", id, recovered, expected);
+            }
+            println!("signed by {} (valid)", recovered);
+        }
+        (None, Some(_)) => bail!("snapshot {} is not signed", id),
+        (None, None) => println!("not signed"),
+    }
+    Ok(())
+}
+
+fn check_ids(index: &SnapshotIndex, ids: &[String]) -> Result<()> {
+    for id in ids {
+        if !index.snapshots.iter().any(|s| &s.id == id) {
+            bail!("no snapshot {} in the archive", id);
+        }
+    }
+    Ok(())
+}
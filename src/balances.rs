@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use tracing::{info, warn};
+use web3::types::{Address, U256};
+
+use crate::multicall::Multicall;
+use crate::output;
+use crate::rpc;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, Provenance};
+
+#[derive(Debug, clap::Args)]
+pub struct BalancesOptions {
+    /// Block to read balances at (defaults to the latest block); older blocks need an archive node
+    #[arg(long)]
+    pub block: Option<u64>,
+    /// Holder list to check (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Comparison CSV
+    #[arg(long, default_value = "data/onchain_balances.csv")]
+    pub output: PathBuf,
+    /// Also write a state file holding the on-chain balances, without holders whose balance is zero
+    #[arg(long)]
+    pub save_state: Option<PathBuf>,
+}
+
+/// Reads `balanceOf` for every holder through Multicall3 and compares it with
+/// the scanned balance, optionally producing a snapshot pinned to the block.
+pub async fn run_balances(options: &BalancesOptions) -> Result<()> {
+    let mut state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    let web3 = rpc::connect()?;
+    let multicall = Multicall::from_env()?;
+    let contract: Address = CONTRACT_ADDRESS.parse().context("invalid contract address")?;
+
+    let mut holders: Vec<(String, Address)> =
+        state.holders.iter().filter_map(|h| Some((h.clone(), h.parse::<Address>().ok()?))).collect();
+    holders.sort();
+    let skipped = state.holders.len() - holders.len();
+    if skipped > 0 {
+        // Cluster ids and other non-address holder keys have no balanceOf
+        warn!(holders = skipped, "Skipping holders that aren't addresses");
+    }
+    let addresses: Vec<Address> = holders.iter().map(|(_, a)| *a).collect();
+    let balances = multicall.balances_of(&web3, contract, &addresses, options.block).await?;
+
+    let mut writer = output::csv_writer(&options.output)?;
+    writer.write_record(["address", "scanned_balance", "onchain_balance", "matches"])?;
+    let (mut mismatched, mut failed) = (0, 0);
+    for ((holder, _), onchain) in holders.iter().zip(&balances) {
+        let scanned = state.balances.get(holder).copied().unwrap_or(0);
+        let matches = onchain.is_some_and(|b| b == U256::from(scanned));
+        match onchain {
+            None => failed += 1,
+            Some(_) if !matches => mismatched += 1,
+            Some(_) => {}
+        }
+        writer.write_record([
+            holder.clone(),
+            scanned.to_string(),
+            onchain.map(|b| b.to_string()).unwrap_or_default(),
+            matches.to_string(),
+        ])?;
+    }
+    output::finish_csv(writer)?;
+    println!("Checked {} holders: {} differ, {} calls failed", holders.len(), mismatched, failed);
+
+    if let Some(path) = &options.save_state {
+        for ((holder, _), onchain) in holders.iter().zip(&balances) {
+            match onchain {
+                Some(balance) if !balance.is_zero() => {
+                    // The state keeps balances as u64
+                    let balance = if *balance > U256::from(u64::MAX) { u64::MAX } else { balance.as_u64() };
+                    state.balances.insert(holder.clone(), balance);
+                    let provenance = Provenance {
+                        source: "balance_of".to_string(),
+                        endpoint: None,
+                        run: None,
+                        page: None,
+                        block: options.block,
+                    };
+                    state.provenance.insert(holder.clone(), provenance);
+                }
+                Some(_) => {
+                    state.holders.remove(holder);
+                    state.balances.remove(holder);
+                    state.provenance.remove(holder);
+                }
+                None => {}
+            }
+        }
+        state.total_holders = state.holders.len() as u64;
+        state::save_state_to(&state, path)?;
+        info!(path = %path.display(), block = ?options.block, "State with on-chain balances written");
+    }
+    info!(output = %options.output.display(), "On-chain balance check written");
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::env;
+use std::fs::File;
+use std::path::PathBuf;
+use tracing::{debug, info};
+use web3::signing::keccak256;
+use web3::types::{Address, BlockNumber, FilterBuilder, Log, H256, U256};
+
+use crate::http;
+use crate::output;
+use crate::state::ScanState;
+
+// OP-stack predeploys, identical on every OP-stack chain
+const L2_STANDARD_BRIDGE: &str = "0x4200000000000000000000000000000000000010";
+const L2_ERC721_BRIDGE: &str = "0x4200000000000000000000000000000000000014";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TokenKind {
+    Erc20,
+    Erc721,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct BridgeOptions {
+    /// L2 address of the scanned token
+    #[arg(long)]
+    pub token: String,
+    #[arg(long, value_enum, default_value = "erc721")]
+    pub kind: TokenKind,
+    #[arg(long, default_value_t = 0)]
+    pub from_block: u64,
+    /// Last block to scan (defaults to the latest block)
+    #[arg(long)]
+    pub to_block: Option<u64>,
+    /// Blocks per eth_getLogs request
+    #[arg(long, default_value_t = 10_000)]
+    pub chunk_size: u64,
+    #[arg(long, default_value = "data/bridge_flows.csv")]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Deposit,
+    Withdrawal,
+}
+
+#[derive(Debug)]
+pub struct BridgeFlow {
+    pub direction: Direction,
+    pub block: u64,
+    pub tx_hash: H256,
+    pub from: Address,
+    pub to: Address,
+    /// Token amount for ERC-20 transfers, token id for ERC-721
+    pub value: U256,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BridgeReport {
+    pub token: String,
+    pub from_block: u64,
+    pub to_block: u64,
+    pub deposits: u64,
+    pub withdrawals: u64,
+    pub deposited: String,
+    pub withdrawn: String,
+    /// Deposited minus withdrawn, in tokens (ERC-20 base units or ERC-721 count)
+    pub net_bridged: String,
+    /// Token supply recorded by the last holder scan
+    pub scanned_supply: u128,
+    /// Share of the scanned supply that arrived over the bridge and is still on L2
+    pub bridged_share_of_scanned: Option<f64>,
+}
+
+fn event_topic(signature: &str) -> H256 {
+    H256::from(keccak256(signature.as_bytes()))
+}
+
+fn word(data: &[u8], index: usize) -> Option<&[u8]> {
+    data.get(index * 32..(index + 1) * 32)
+}
+
+// Both bridges emit (address indexed, address indexed, address indexed from,
+// address to, uint256 amountOrTokenId, bytes extraData)
+fn decode_flow(log: &Log, direction: Direction) -> Option<BridgeFlow> {
+    let data = &log.data.0;
+    Some(BridgeFlow {
+        direction,
+        block: log.block_number?.as_u64(),
+        tx_hash: log.transaction_hash?,
+        from: Address::from_slice(&log.topics.get(3)?.as_bytes()[12..]),
+        to: Address::from_slice(&word(data, 0)?[12..]),
+        value: U256::from_big_endian(word(data, 1)?),
+    })
+}
+
+pub async fn track_bridge_flows(options: &BridgeOptions, state: &ScanState) -> Result<BridgeReport> {
+    let rpc_url = env::var("OPTIMISM_RPC_URL").context("OPTIMISM_RPC_URL must be set to an OP-stack L2 RPC endpoint")?;
+    let web3 = web3::Web3::new(http::transport(&rpc_url)?);
+    let token: Address = options.token.parse().context("invalid token address")?;
+    let token_topic = H256::from(token);
+
+    let (bridge, deposit_topic, withdrawal_topic) = match options.kind {
+        TokenKind::Erc20 => (
+            L2_STANDARD_BRIDGE,
+            event_topic("DepositFinalized(address,address,address,address,uint256,bytes)"),
+            event_topic("WithdrawalInitiated(address,address,address,address,uint256,bytes)"),
+        ),
+        TokenKind::Erc721 => (
+            L2_ERC721_BRIDGE,
+            event_topic("ERC721BridgeFinalized(address,address,address,address,uint256,bytes)"),
+            event_topic("ERC721BridgeInitiated(address,address,address,address,uint256,bytes)"),
+        ),
+    };
+    let bridge: Address = bridge.parse()?;
+
+    let to_block = match options.to_block {
+        Some(block) => block,
+        None => web3.eth().block_number().await?.as_u64(),
+    };
+
+    let mut flows = Vec::new();
+    let mut start = options.from_block;
+    while start <= to_block {
+        let end = (start + options.chunk_size.max(1) - 1).min(to_block);
+        // The L2 token is the second indexed topic on the standard bridge (l1Token, l2Token)
+        // and the first on the ERC-721 bridge (localToken, remoteToken)
+        let (topic1, topic2) = match options.kind {
+            TokenKind::Erc20 => (None, Some(vec![token_topic])),
+            TokenKind::Erc721 => (Some(vec![token_topic]), None),
+        };
+        let filter = FilterBuilder::default()
+            .address(vec![bridge])
+            .topics(Some(vec![deposit_topic, withdrawal_topic]), topic1, topic2, None)
+            .from_block(BlockNumber::Number(start.into()))
+            .to_block(BlockNumber::Number(end.into()))
+            .build();
+        let logs = web3.eth().logs(filter).await?;
+        debug!(start, end, logs = logs.len(), "Fetched bridge logs");
+        for log in &logs {
+            let direction = if log.topics.first() == Some(&deposit_topic) {
+                Direction::Deposit
+            } else {
+                Direction::Withdrawal
+            };
+            flows.extend(decode_flow(log, direction));
+        }
+        start = end + 1;
+    }
+
+    save_flows(&flows, options)?;
+    let report = summarize(options, to_block, &flows, state);
+    std::fs::create_dir_all("data")?;
+    serde_json::to_writer_pretty(File::create("data/bridge_report.json")?, &report)?;
+    info!(
+        deposits = report.deposits,
+        withdrawals = report.withdrawals,
+        net_bridged = %report.net_bridged,
+        "Bridge flows reconciled"
+    );
+    Ok(report)
+}
+
+fn summarize(options: &BridgeOptions, to_block: u64, flows: &[BridgeFlow], state: &ScanState) -> BridgeReport {
+    // ERC-721 flows carry token ids, so each one counts as a single token
+    let amount = |flow: &BridgeFlow| match options.kind {
+        TokenKind::Erc20 => flow.value,
+        TokenKind::Erc721 => U256::one(),
+    };
+    let total = |direction: Direction| {
+        flows
+            .iter()
+            .filter(|f| f.direction == direction)
+            .fold(U256::zero(), |sum, f| sum + amount(f))
+    };
+    let deposited = total(Direction::Deposit);
+    let withdrawn = total(Direction::Withdrawal);
+    let net = deposited.saturating_sub(withdrawn);
+    let scanned_supply: u128 = state.balances.values().map(|balance| u128::from(*balance)).sum();
+
+    BridgeReport {
+        token: options.token.clone(),
+        from_block: options.from_block,
+        to_block,
+        deposits: flows.iter().filter(|f| f.direction == Direction::Deposit).count() as u64,
+        withdrawals: flows.iter().filter(|f| f.direction == Direction::Withdrawal).count() as u64,
+        deposited: deposited.to_string(),
+        withdrawn: withdrawn.to_string(),
+        net_bridged: net.to_string(),
+        scanned_supply,
+        bridged_share_of_scanned: (scanned_supply > 0 && net <= U256::from(u128::MAX))
+            .then(|| net.as_u128() as f64 / scanned_supply as f64),
+    }
+}
+
+fn save_flows(flows: &[BridgeFlow], options: &BridgeOptions) -> Result<()> {
+    let mut writer = output::csv_writer(&options.output)?;
+    writer.write_record(["direction", "block", "tx_hash", "from", "to", "value"])?;
+    for flow in flows {
+        writer.write_record([
+            match flow.direction {
+                Direction::Deposit => "deposit".to_string(),
+                Direction::Withdrawal => "withdrawal".to_string(),
+            },
+            flow.block.to_string(),
+            format!("{:?}", flow.tx_hash),
+            format!("{:?}", flow.from),
+            format!("{:?}", flow.to),
+            flow.value.to_string(),
+        ])?;
+    }
+    output::finish_csv(writer)
+}
@@ -0,0 +1,165 @@
+use anyhow::Result;
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+use crate::journal;
+
+pub const FAILED_RESPONSES_DIR: &str = "data/failed_responses";
+
+// Environment variables that make up the scanner's configuration
+const CONFIG_PREFIXES: [&str; 30] = [
+    "ALCHEMY_", "API_KEY_", "CHAIN_", "COINGECKO_RPS", "DATABASE_URL", "ENDPOINT_", "ETHEREUM_", "EXPORT_",
+    "HISTOGRAM_", "HOLDER_", "IDENTITY_", "MAX_RETRIES", "MULTICALL_", "OPTIMISM_", "PAGE_KEY_", "RATE_LIMIT_",
+    "RUST_LOG", "SCANNER_", "DIAGNOSTIC_", "GOOGLE_", "OUTPUT_URL", "OUTPUT_CHECKPOINT_SECS",
+    "EVENT_SINK_", "KAFKA_", "NATS_", "SUBGRAPH_", "ETHERSCAN_", "SNAPSHOT_", "LOG_CONCURRENCY",
+    "DELEGATE_REGISTRY_ADDRESS",
+];
+
+// Most recent failed provider responses included in a bundle
+const MAX_FAILED_RESPONSES: usize = 20;
+
+/// Keeps a provider response that could not be parsed so it can go into a
+/// diagnostic bundle.
+pub fn save_failed_response(run: &str, page: u64, body: &str) -> Result<PathBuf> {
+    fs::create_dir_all(FAILED_RESPONSES_DIR)?;
+    let path = Path::new(FAILED_RESPONSES_DIR).join(format!("{}-page{}.json", run, page));
+    fs::write(&path, body)?;
+    Ok(path)
+}
+
+/// After a fatal error, writes a diagnostic bundle when `DIAGNOSTIC_BUNDLE` is
+/// `always`, or asks first when running in a terminal (the default, `ask`).
+/// `never` disables it.
+pub fn offer_bundle(failure: &anyhow::Error) {
+    let mode = env::var("DIAGNOSTIC_BUNDLE").unwrap_or_else(|_| "ask".to_string());
+    let create = match mode.as_str() {
+        "always" => true,
+        "never" => false,
+        _ if io::stdin().is_terminal() && io::stderr().is_terminal() => {
+            eprint!("The scan failed. Write a redacted diagnostic bundle for a bug report? [y/N] ");
+            let mut answer = String::new();
+            io::stdin().lock().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
+        }
+        _ => false,
+    };
+    if create {
+        match create_bundle(Some(failure)) {
+            Ok(path) => info!("Diagnostic bundle written to {}", path.display()),
+            Err(e) => error!(error = %format!("{:#}", e), "Could not write diagnostic bundle"),
+        }
+    }
+}
+
+/// Writes `data/diagnostics-<timestamp>.tar.gz` with the error, the redacted
+/// configuration, the tail of the latest run journal and recent provider
+/// responses that failed to parse.
+pub fn create_bundle(failure: Option<&anyhow::Error>) -> Result<PathBuf> {
+    let path = PathBuf::from(format!("data/diagnostics-{}.tar.gz", Utc::now().format("%Y%m%dT%H%M%SZ")));
+    fs::create_dir_all("data")?;
+    let mut archive = tar::Builder::new(GzEncoder::new(File::create(&path)?, flate2::Compression::default()));
+
+    let mut summary = format!(
+        "scanner {}\nos: {} {}\ncreated: {}\nargs: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS,
+        env::consts::ARCH,
+        Utc::now().to_rfc3339(),
+        env::args().map(|arg| redact_value(&arg)).collect::<Vec<_>>().join(" "),
+    );
+    if let Some(failure) = failure {
+        summary.push_str(&format!("\nerror:\n{:?}\n", failure));
+    }
+    append(&mut archive, "error.txt", summary.as_bytes())?;
+    append(&mut archive, "config.env", redacted_config().as_bytes())?;
+
+    let journal_entries = env::var("DIAGNOSTIC_JOURNAL_ENTRIES").ok().and_then(|v| v.parse().ok()).unwrap_or(200);
+    if let Some(run) = journal::run_ids()?.pop() {
+        let entries = journal::load_run(&run)?;
+        let mut tail = String::new();
+        for entry in &entries[entries.len().saturating_sub(journal_entries)..] {
+            tail.push_str(&redact_value(&serde_json::to_string(entry)?));
+            tail.push('\n');
+        }
+        append(&mut archive, &format!("journal/{}.jsonl", run), tail.as_bytes())?;
+    }
+
+    let failed_dir = Path::new(FAILED_RESPONSES_DIR);
+    if failed_dir.exists() {
+        let mut responses: Vec<(std::time::SystemTime, PathBuf)> = fs::read_dir(failed_dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect();
+        responses.sort();
+        for (_, response) in responses.iter().rev().take(MAX_FAILED_RESPONSES) {
+            if let Some(name) = response.file_name().and_then(|n| n.to_str()) {
+                let body = fs::read_to_string(response)?;
+                append(&mut archive, &format!("failed_responses/{}", name), redact_value(&body).as_bytes())?;
+            }
+        }
+    }
+
+    archive.into_inner()?.finish()?.flush()?;
+    Ok(path)
+}
+
+fn append(archive: &mut tar::Builder<GzEncoder<File>>, name: &str, data: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+fn redacted_config() -> String {
+    let mut vars: Vec<(String, String)> = env::vars()
+        .filter(|(name, _)| CONFIG_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+        .collect();
+    vars.sort();
+    vars.iter()
+        .map(|(name, value)| format!("{}={}\n", name, redact_var(name, value)))
+        .collect()
+}
+
+fn redact_var(name: &str, value: &str) -> String {
+    let name = name.to_uppercase();
+    if ["KEY", "SECRET", "TOKEN", "PASSWORD"].iter().any(|word| name.contains(word)) {
+        "<redacted>".to_string()
+    } else {
+        redact_value(value)
+    }
+}
+
+/// Strips credentials from anything URL-like: `user:password@` userinfo and
+/// path segments that look like API keys (long runs of key characters).
+fn redact_value(value: &str) -> String {
+    value
+        .split_inclusive(|c: char| c == '/' || c == '?' || c == '&' || c == '=' || c.is_whitespace() || c == '"')
+        .map(|part| {
+            let (token, separator) = match part.char_indices().last() {
+                Some((i, c)) if !c.is_ascii_alphanumeric() && c != '-' && c != '_' && c != '@' && c != ':' => {
+                    part.split_at(i)
+                }
+                _ => (part, ""),
+            };
+            let token = match token.rfind('@') {
+                Some(at) if token[..at].contains(':') => format!("<redacted>{}", &token[at..]),
+                _ => token.to_string(),
+            };
+            let looks_like_key = token.len() >= 24
+                && !token.starts_with("0x")
+                && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if looks_like_key {
+                format!("<redacted>{}", separator)
+            } else {
+                format!("{}{}", token, separator)
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,210 @@
+use anyhow::Result;
+use axum::body::{Body, Bytes};
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{header, HeaderValue, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::warn;
+
+use crate::config::CacheConfig;
+use crate::live::SnapshotStore;
+
+// Responses larger than this are passed through uncached
+const MAX_CACHED_BODY: usize = 16 * 1024 * 1024;
+
+// In-memory entries kept at most; new responses aren't cached while it's full
+const MAX_MEMORY_ENTRIES: usize = 10_000;
+
+#[derive(Debug, Clone)]
+struct CachedPageResponse {
+    content_type: Option<String>,
+    body: Bytes,
+}
+
+impl CachedPageResponse {
+    // Stored in Redis as the content type, a newline, then the body
+    #[cfg(feature = "redis")]
+    fn encode(&self) -> Vec<u8> {
+        let mut value = self.content_type.clone().unwrap_or_default().into_bytes();
+        value.push(b'\n');
+        value.extend_from_slice(&self.body);
+        value
+    }
+
+    #[cfg(feature = "redis")]
+    fn decode(value: &[u8]) -> Option<Self> {
+        let split = value.iter().position(|b| *b == b'\n')?;
+        let content_type = std::str::from_utf8(&value[..split]).ok()?;
+        Some(CachedPageResponse {
+            content_type: (!content_type.is_empty()).then(|| content_type.to_string()),
+            body: Bytes::copy_from_slice(&value[split + 1..]),
+        })
+    }
+}
+
+enum Backend {
+    Memory(Mutex<MemoryStore>),
+    #[cfg(feature = "redis")]
+    Redis(redis::aio::ConnectionManager),
+}
+
+#[derive(Default)]
+struct MemoryStore {
+    generation: u64,
+    entries: HashMap<String, (Instant, CachedPageResponse)>,
+}
+
+/// GET response cache for `serve`. Entries live for their route's TTL and are
+/// dropped as soon as a new snapshot is swapped in or the files they were
+/// computed from change, so a new scan or archived snapshot is visible on
+/// the next request.
+pub struct PageResponseCache {
+    config: CacheConfig,
+    /// Files whose modification times make up the cache generation, with the store version
+    sources: Vec<PathBuf>,
+    store: Arc<SnapshotStore>,
+    backend: Backend,
+}
+
+impl PageResponseCache {
+    pub async fn new(config: CacheConfig, sources: Vec<PathBuf>, store: Arc<SnapshotStore>) -> Result<Arc<Self>> {
+        let backend = match &config.redis_url {
+            #[cfg(feature = "redis")]
+            Some(url) => Backend::Redis(redis::aio::ConnectionManager::new(redis::Client::open(url.as_str())?).await?),
+            #[cfg(not(feature = "redis"))]
+            Some(_) => anyhow::bail!("cache.redis_url is set but the scanner was built without the `redis` feature"),
+            None => Backend::Memory(Mutex::new(MemoryStore::default())),
+        };
+        Ok(Arc::new(PageResponseCache { config, sources, store, backend }))
+    }
+
+    fn ttl_for(&self, route: &str) -> Option<Duration> {
+        let secs = self.config.endpoints.get(route).copied().unwrap_or(self.config.ttl_secs);
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+
+    fn generation(&self) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.store.version().hash(&mut hasher);
+        for source in &self.sources {
+            let modified = std::fs::metadata(source).and_then(|m| m.modified()).ok();
+            modified.unwrap_or(SystemTime::UNIX_EPOCH).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    async fn get(&self, key: &str, generation: u64) -> Option<CachedPageResponse> {
+        match &self.backend {
+            Backend::Memory(store) => {
+                let mut store = store.lock().unwrap_or_else(|e| e.into_inner());
+                if store.generation != generation {
+                    store.generation = generation;
+                    store.entries.clear();
+                    return None;
+                }
+                match store.entries.get(key) {
+                    Some((expires, response)) if *expires > Instant::now() => Some(response.clone()),
+                    Some(_) => {
+                        store.entries.remove(key);
+                        None
+                    }
+                    None => None,
+                }
+            }
+            #[cfg(feature = "redis")]
+            Backend::Redis(connection) => {
+                use redis::AsyncCommands;
+                let mut connection = connection.clone();
+                match connection.get::<_, Option<Vec<u8>>>(redis_key(key, generation)).await {
+                    Ok(value) => value.as_deref().and_then(CachedPageResponse::decode),
+                    Err(e) => {
+                        warn!(error = %e, "Redis cache read failed");
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    async fn put(&self, key: &str, generation: u64, response: CachedPageResponse, ttl: Duration) {
+        match &self.backend {
+            Backend::Memory(store) => {
+                let mut store = store.lock().unwrap_or_else(|e| e.into_inner());
+                if store.generation != generation {
+                    return;
+                }
+                if store.entries.len() >= MAX_MEMORY_ENTRIES {
+                    let now = Instant::now();
+                    store.entries.retain(|_, (expires, _)| *expires > now);
+                }
+                if store.entries.len() < MAX_MEMORY_ENTRIES {
+                    store.entries.insert(key.to_string(), (Instant::now() + ttl, response));
+                }
+            }
+            #[cfg(feature = "redis")]
+            Backend::Redis(connection) => {
+                use redis::AsyncCommands;
+                let mut connection = connection.clone();
+                let stored: redis::RedisResult<()> =
+                    connection.set_ex(redis_key(key, generation), response.encode(), ttl.as_secs()).await;
+                if let Err(e) = stored {
+                    warn!(error = %e, "Redis cache write failed");
+                }
+            }
+        }
+    }
+}
+
+// The generation is part of the key, so entries from before a new snapshot
+// are never read again and simply expire
+#[cfg(feature = "redis")]
+fn redis_key(key: &str, generation: u64) -> String {
+    format!("scanner:cache:{:016x}:{}", generation, key)
+}
+
+/// Route middleware serving successful GET responses from the cache.
+pub async fn cache_responses(State(cache): State<Arc<PageResponseCache>>, request: Request, next: Next) -> Response {
+    let route = request.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string());
+    let ttl = route.as_deref().and_then(|route| cache.ttl_for(route));
+    let (Some(ttl), true) = (ttl, request.method() == Method::GET) else {
+        return next.run(request).await;
+    };
+
+    let key = request.uri().to_string();
+    let generation = cache.generation();
+    if let Some(cached) = cache.get(&key, generation).await {
+        return respond(cached, "hit");
+    }
+
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_CACHED_BODY).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!(error = %e, "Could not buffer response for the cache");
+            return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let content_type = parts.headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+    cache.put(&key, generation, CachedPageResponse { content_type, body: body.clone() }, ttl).await;
+    let mut response = Response::from_parts(parts, Body::from(body));
+    response.headers_mut().insert("x-cache", HeaderValue::from_static("miss"));
+    response
+}
+
+fn respond(cached: CachedPageResponse, status: &'static str) -> Response {
+    let mut response = Response::new(Body::from(cached.body));
+    if let Some(content_type) = cached.content_type.and_then(|c| HeaderValue::from_str(&c).ok()) {
+        response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    response.headers_mut().insert("x-cache", HeaderValue::from_static(status));
+    response
+}
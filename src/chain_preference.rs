@@ -0,0 +1,102 @@
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::address::AddressFormat;
+use crate::output;
+use crate::state::{self, ScanState};
+
+#[derive(Debug, clap::Args)]
+pub struct ChainPreferenceOptions {
+    /// Per-chain scan state as CHAIN=PATH, repeated for each chain the token is deployed on
+    #[arg(long = "state", value_name = "CHAIN=PATH", required = true, value_parser = parse_chain_state)]
+    pub states: Vec<(String, PathBuf)>,
+    #[arg(long, default_value = "data/chain_preference.csv")]
+    pub output: PathBuf,
+}
+
+fn parse_chain_state(value: &str) -> Result<(String, PathBuf), String> {
+    let (chain, path) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected CHAIN=PATH, got '{}'", value))?;
+    Ok((chain.to_lowercase(), PathBuf::from(path)))
+}
+
+#[derive(Debug)]
+pub struct HolderPreference {
+    pub address: String,
+    pub balances: Vec<u64>,
+    pub preferred_chain: String,
+    pub preferred_share: f64,
+}
+
+/// For every holder across the given chains, picks the chain holding the
+/// largest part of their balance. Ties go to the chain listed first.
+pub fn chain_preferences(chains: &[(String, ScanState)]) -> Vec<HolderPreference> {
+    // Addresses are compared in their chain's canonical form, e.g. EVM hex in lowercase
+    let mut balances: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for (index, (chain, state)) in chains.iter().enumerate() {
+        let format = AddressFormat::for_chain(chain);
+        for holder in &state.holders {
+            let per_chain = balances.entry(format.key(holder)).or_insert_with(|| vec![0; chains.len()]);
+            per_chain[index] += state.balances.get(holder).copied().unwrap_or(0);
+        }
+    }
+
+    balances
+        .into_iter()
+        .map(|(address, per_chain)| {
+            let total: u128 = per_chain.iter().map(|balance| u128::from(*balance)).sum();
+            let (best, best_balance) = per_chain
+                .iter()
+                .enumerate()
+                .fold((0, 0), |best, (i, &balance)| if balance > best.1 { (i, balance) } else { best });
+            HolderPreference {
+                address,
+                preferred_share: if total > 0 { best_balance as f64 / total as f64 } else { 0.0 },
+                preferred_chain: chains[best].0.clone(),
+                balances: per_chain,
+            }
+        })
+        .collect()
+}
+
+pub fn run_chain_preference(options: &ChainPreferenceOptions) -> Result<()> {
+    let chains = options
+        .states
+        .iter()
+        .map(|(chain, path)| {
+            let state = state::load_state_from(path).map_err(|e| anyhow!("loading {}: {}", path.display(), e))?;
+            Ok((chain.clone(), state))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let preferences = chain_preferences(&chains);
+
+    let mut writer = output::csv_writer(&options.output)?;
+    let mut header = vec!["address".to_string(), "preferred_chain".to_string(), "preferred_share".to_string()];
+    header.extend(chains.iter().map(|(chain, _)| format!("{}_balance", chain)));
+    header.push("total_balance".to_string());
+    writer.write_record(&header)?;
+    for preference in &preferences {
+        let mut record = vec![
+            preference.address.clone(),
+            preference.preferred_chain.clone(),
+            format!("{:.4}", preference.preferred_share),
+        ];
+        record.extend(preference.balances.iter().map(|b| b.to_string()));
+        record.push(preference.balances.iter().map(|balance| u128::from(*balance)).sum::<u128>().to_string());
+        writer.write_record(&record)?;
+    }
+    output::finish_csv(writer)?;
+
+    let mut per_chain: HashMap<&str, usize> = HashMap::new();
+    for preference in &preferences {
+        *per_chain.entry(preference.preferred_chain.as_str()).or_default() += 1;
+    }
+    for (chain, _) in &chains {
+        println!("{}: preferred by {} holders", chain, per_chain.get(chain.as_str()).copied().unwrap_or(0));
+    }
+    info!(holders = preferences.len(), output = %options.output.display(), "Chain preference report written");
+    Ok(())
+}
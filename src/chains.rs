@@ -0,0 +1,46 @@
+use anyhow::{anyhow, Result};
+use std::env;
+use std::time::Duration;
+
+/// Expected block times of the chains we scan, in milliseconds. Override or
+/// extend with `CHAIN_BLOCK_TIMES`, e.g. `optimism=2,ethereum=12,mychain=0.5`
+/// (seconds).
+const BLOCK_TIMES_MS: [(&str, u64); 6] = [
+    ("ethereum", 12_000),
+    ("optimism", 2_000),
+    ("base", 2_000),
+    ("arbitrum", 250),
+    ("polygon", 2_000),
+    ("bsc", 3_000),
+];
+
+#[derive(Debug, Clone)]
+pub struct ChainConfig {
+    pub name: String,
+    pub block_time: Duration,
+}
+
+impl ChainConfig {
+    pub fn blocks(&self, count: u64) -> Duration {
+        self.block_time * count as u32
+    }
+}
+
+pub fn chain_config(name: &str) -> Result<ChainConfig> {
+    let name = name.to_lowercase();
+    let overrides = env::var("CHAIN_BLOCK_TIMES").unwrap_or_default();
+    let configured = overrides.split(',').find_map(|entry| {
+        let (chain, seconds) = entry.split_once('=')?;
+        let seconds: f64 = seconds.trim().parse().ok()?;
+        (chain.trim().eq_ignore_ascii_case(&name) && seconds > 0.0).then(|| Duration::from_secs_f64(seconds))
+    });
+    let block_time = configured
+        .or_else(|| {
+            BLOCK_TIMES_MS
+                .iter()
+                .find(|(chain, _)| *chain == name)
+                .map(|(_, ms)| Duration::from_millis(*ms))
+        })
+        .ok_or_else(|| anyhow!("no block time known for chain '{}'; set it in CHAIN_BLOCK_TIMES", name))?;
+    Ok(ChainConfig { name, block_time })
+}
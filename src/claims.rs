@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::address;
+use crate::merkle::{self, MerkleTree};
+use crate::state::ScanState;
+
+const CHECKER_HTML: &str = include_str!("templates/claim_checker.html");
+
+#[derive(Debug, clap::Args)]
+pub struct ClaimOptions {
+    /// Directory to write the per-holder claim files and index into
+    #[arg(long, default_value = "data/claims")]
+    pub output_dir: PathBuf,
+    /// Claimable amount credited per token held
+    #[arg(long, default_value_t = 1)]
+    pub amount_per_token: u128,
+    /// Minimum number of tokens held to be eligible
+    #[arg(long, default_value_t = 1)]
+    pub min_balance: u64,
+    /// Also write a static index.html that looks addresses up in the claim files
+    #[arg(long)]
+    pub with_checker: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolderClaim {
+    pub address: String,
+    pub eligible: bool,
+    pub amount: String,
+    pub leaf: String,
+    pub proof: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClaimIndex {
+    pub merkle_root: String,
+    pub recipients: usize,
+    pub total_amount: String,
+    pub generated_at: chrono::DateTime<Utc>,
+    /// Per-holder files live at `<prefix>/<address>.json`, where prefix is the
+    /// first two hex characters of the lowercase address
+    pub layout: &'static str,
+}
+
+pub struct Recipient {
+    pub address: String,
+    pub bytes: [u8; 20],
+    pub amount: u128,
+}
+
+/// Holders with at least `min_balance`, in address order. Fails when a
+/// holder's amount doesn't fit in a `u128`.
+pub fn eligible_recipients(state: &ScanState, options: &ClaimOptions) -> Result<Vec<Recipient>> {
+    let mut recipients = Vec::new();
+    for holder in &state.holders {
+        let balance = state.balances.get(holder).copied().unwrap_or(0);
+        if balance < options.min_balance {
+            continue;
+        }
+        let amount = (balance as u128).checked_mul(options.amount_per_token).with_context(|| {
+            format!("{}: {} tokens at {} per token overflows u128", holder, balance, options.amount_per_token)
+        })?;
+        let parsed = address::normalize(holder).and_then(|address| Ok((merkle::parse_address(&address)?, address)));
+        match parsed {
+            Ok((bytes, address)) => recipients.push(Recipient { address, bytes, amount }),
+            Err(e) => warn!(holder = %holder, error = %e, "Skipping holder with malformed address"),
+        }
+    }
+    recipients.sort_by(|a, b| a.address.cmp(&b.address));
+    Ok(recipients)
+}
+
+pub fn holder_path(output_dir: &Path, address: &str) -> PathBuf {
+    let hex = address.trim_start_matches("0x");
+    output_dir.join(&hex[..2.min(hex.len())]).join(format!("{}.json", address))
+}
+
+pub fn export_claims(state: &ScanState, options: &ClaimOptions) -> Result<ClaimIndex> {
+    let recipients = eligible_recipients(state, options)?;
+    let total_amount = recipients
+        .iter()
+        .try_fold(0u128, |total, r| total.checked_add(r.amount))
+        .context("the total claim amount overflows u128")?;
+    let leaves: Vec<_> = recipients.iter().map(|r| merkle::leaf_hash(&r.bytes, r.amount)).collect();
+    let tree = MerkleTree::new(leaves.clone());
+
+    for (recipient, leaf) in recipients.iter().zip(&leaves) {
+        let index = tree.leaf_index(leaf).expect("leaf was inserted into the tree");
+        let claim = HolderClaim {
+            address: recipient.address.clone(),
+            eligible: true,
+            amount: recipient.amount.to_string(),
+            leaf: merkle::to_hex(leaf),
+            proof: tree.proof(index).iter().map(merkle::to_hex).collect(),
+        };
+        let path = holder_path(&options.output_dir, &recipient.address);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        serde_json::to_writer(File::create(path)?, &claim)?;
+    }
+
+    let index = ClaimIndex {
+        merkle_root: merkle::to_hex(&tree.root()),
+        recipients: recipients.len(),
+        total_amount: total_amount.to_string(),
+        generated_at: Utc::now(),
+        layout: "<first two hex chars of address>/<lowercase address>.json",
+    };
+    std::fs::create_dir_all(&options.output_dir)?;
+    serde_json::to_writer_pretty(File::create(options.output_dir.join("index.json"))?, &index)?;
+    if options.with_checker {
+        std::fs::write(options.output_dir.join("index.html"), CHECKER_HTML)?;
+    }
+    info!(
+        root = %index.merkle_root,
+        recipients = index.recipients,
+        dir = %options.output_dir.display(),
+        "Claim files written"
+    );
+    Ok(index)
+}
@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::address;
+use crate::config;
+use crate::funding::{Funding, FundingLookup};
+use crate::output;
+use crate::rpc;
+use crate::state;
+
+#[derive(Debug, clap::Args)]
+pub struct ClusterOptions {
+    /// Snapshot to cluster (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Funding hops to follow upwards: 1 groups holders with the same funder,
+    /// 2 also groups holders whose funders share a funder, and so on
+    #[arg(long, default_value_t = 1)]
+    pub depth: usize,
+    /// Exchange hot wallets, on top of `[funding] exchanges` (repeatable)
+    #[arg(long = "exchange", value_name = "ADDRESS")]
+    pub exchanges: Vec<String>,
+    /// Don't group holders funded straight from an exchange; each stays its own cluster
+    #[arg(long)]
+    pub split_exchanges: bool,
+    /// `address,cluster` CSV, readable through IDENTITY_CLUSTERS
+    #[arg(long, default_value = "data/clusters.csv")]
+    pub output: PathBuf,
+}
+
+/// Union-find over addresses
+#[derive(Default)]
+struct Groups {
+    parent: HashMap<String, String>,
+}
+
+impl Groups {
+    fn find(&mut self, address: &str) -> String {
+        let mut root = address.to_string();
+        while let Some(parent) = self.parent.get(&root).filter(|p| **p != root) {
+            root = parent.clone();
+        }
+        // Point the whole path at the root
+        let mut node = address.to_string();
+        while node != root {
+            let next = self.parent.insert(node, root.clone()).unwrap_or_else(|| root.clone());
+            node = next;
+        }
+        root
+    }
+
+    fn join(&mut self, a: &str, b: &str) {
+        let (a, b) = (self.find(a), self.find(b));
+        // The smaller address becomes the root, so cluster ids don't depend on join order
+        match a.cmp(&b) {
+            std::cmp::Ordering::Less => self.parent.insert(b, a),
+            std::cmp::Ordering::Greater => self.parent.insert(a, b),
+            std::cmp::Ordering::Equal => None,
+        };
+    }
+}
+
+/// Groups holders by where their first funding came from and writes a
+/// cluster id per holder. A cluster is named after the lowest address in it,
+/// which is often the common funder.
+pub async fn run_cluster(options: &ClusterOptions) -> Result<()> {
+    let state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    let exchanges = config::load_config()?.funding.exchanges_with(&options.exchanges)?;
+    let mut lookup = FundingLookup::open(rpc::connect()?);
+
+    let mut holders: Vec<String> = state
+        .holders
+        .iter()
+        .map(|h| address::normalize(h).context("clustering needs EVM holder addresses"))
+        .collect::<Result<_>>()?;
+    holders.sort();
+    holders.dedup();
+
+    // Walk up the funding chain one hop at a time, stopping at exchanges
+    let mut fundings: HashMap<String, Option<Funding>> = HashMap::new();
+    let mut level = holders.clone();
+    for hop in 1..=options.depth.max(1) {
+        let found = lookup.first_fundings(&level).await?;
+        level = found
+            .values()
+            .flatten()
+            .map(|f| f.funder.clone())
+            .filter(|f| !exchanges.contains(f) && !fundings.contains_key(f) && !found.contains_key(f))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        fundings.extend(found);
+        info!(hop, next = level.len(), "Followed first fundings");
+        if level.is_empty() {
+            break;
+        }
+    }
+
+    let mut groups = Groups::default();
+    for (address, funding) in &fundings {
+        let Some(funding) = funding else {
+            continue;
+        };
+        if exchanges.contains(&funding.funder) && options.split_exchanges {
+            continue;
+        }
+        groups.join(address, &funding.funder);
+    }
+
+    let clusters: Vec<String> = holders.iter().map(|h| groups.find(h)).collect();
+    let mut sizes: HashMap<&str, usize> = HashMap::new();
+    for cluster in &clusters {
+        *sizes.entry(cluster.as_str()).or_default() += 1;
+    }
+
+    let mut writer = output::csv_writer(&options.output)?;
+    writer.write_record(["address", "cluster", "cluster_size", "funder", "funder_kind", "first_funded"])?;
+    for (holder, cluster) in holders.iter().zip(&clusters) {
+        let funding = fundings.get(holder).cloned().flatten();
+        let kind = match &funding {
+            Some(f) if exchanges.contains(&f.funder) => "exchange",
+            Some(_) => "wallet",
+            None => "",
+        };
+        writer.write_record([
+            holder.clone(),
+            cluster.clone(),
+            sizes[cluster.as_str()].to_string(),
+            funding.as_ref().map(|f| f.funder.clone()).unwrap_or_default(),
+            kind.to_string(),
+            funding.and_then(|f| f.time).map(|t| t.to_rfc3339()).unwrap_or_default(),
+        ])?;
+    }
+    output::finish_csv(writer)?;
+
+    let mut largest: Vec<(&str, usize)> = sizes.into_iter().filter(|(_, size)| *size > 1).collect();
+    largest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let count = clusters.iter().collect::<HashSet<_>>().len();
+    println!("{} holders in {} clusters; {} clusters hold more than one", holders.len(), count, largest.len());
+    for (cluster, size) in largest.iter().take(10) {
+        println!("  {:<44} {:>6} holders", cluster, size);
+    }
+    info!(output = %options.output.display(), "Clusters written");
+    Ok(())
+}
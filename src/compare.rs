@@ -0,0 +1,265 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::address;
+use crate::journal::{self, JournalEntry, JournalEvent};
+use crate::metrics;
+use crate::output;
+use crate::state::{self, ScanState};
+
+#[derive(Debug, clap::Args)]
+pub struct CompareRunsOptions {
+    /// Run id as shown by `runs list`, usually the current provider
+    pub run_a: String,
+    /// Run id of the candidate provider or strategy
+    pub run_b: String,
+    /// State to compare for run A, when it wasn't scanned with --archive
+    #[arg(long)]
+    pub state_a: Option<PathBuf>,
+    /// State to compare for run B, when it wasn't scanned with --archive
+    #[arg(long)]
+    pub state_b: Option<PathBuf>,
+    /// Price of run A's provider in USD per 1,000 requests, for a cost estimate
+    #[arg(long, value_name = "USD")]
+    pub cost_a: Option<f64>,
+    /// Price of run B's provider in USD per 1,000 requests
+    #[arg(long, value_name = "USD")]
+    pub cost_b: Option<f64>,
+    /// CSV of the holders the two runs disagree on
+    #[arg(long, default_value = "data/compare_runs.csv")]
+    pub output: PathBuf,
+}
+
+/// What a run's journal says about it.
+#[derive(Debug, Default)]
+struct RunSummary {
+    contract: Option<String>,
+    status: String,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+    pages: u64,
+    holders: u64,
+    requests: u64,
+    retries: u64,
+    bytes: Option<u64>,
+    error_rate: Option<f64>,
+    compute_units: Option<f64>,
+    /// From the run's `[cost]` prices
+    estimated_usd: Option<f64>,
+    errors: usize,
+    warnings: usize,
+    provider: Option<String>,
+    endpoints: BTreeSet<String>,
+    p50_page_ms: u64,
+    p95_page_ms: u64,
+    snapshot: Option<PathBuf>,
+}
+
+impl RunSummary {
+    fn from_journal(entries: &[JournalEntry]) -> RunSummary {
+        let mut summary = RunSummary { status: "incomplete".to_string(), ..RunSummary::default() };
+        let mut latencies = Vec::new();
+        // Request totals from the metrics win over counts rebuilt from pages
+        let mut performance = None;
+        for entry in entries {
+            match &entry.event {
+                JournalEvent::RunStarted { contract, .. } => {
+                    summary.contract = Some(contract.clone());
+                    summary.started_at = Some(entry.at);
+                }
+                JournalEvent::Page { endpoint, latency_ms, attempts, holders, .. } => {
+                    summary.pages += 1;
+                    summary.holders = *holders;
+                    summary.requests += *attempts as u64;
+                    summary.retries += attempts.saturating_sub(1) as u64;
+                    summary.endpoints.insert(endpoint.clone());
+                    latencies.push(*latency_ms);
+                }
+                JournalEvent::Warning { .. } => summary.warnings += 1,
+                JournalEvent::Error { .. } => summary.errors += 1,
+                JournalEvent::Performance {
+                    provider,
+                    requests,
+                    retries,
+                    total_bytes,
+                    error_rate,
+                    compute_units,
+                    estimated_usd,
+                } => {
+                    performance = Some((provider.clone(), *requests, *retries, *total_bytes, *error_rate));
+                    summary.compute_units = Some(*compute_units).filter(|units| *units > 0.0);
+                    summary.estimated_usd = *estimated_usd;
+                }
+                JournalEvent::Snapshot { path, .. } => summary.snapshot = Some(path.join("state.json")),
+                JournalEvent::RunFinished { status, pages, holders } => {
+                    summary.status = status.clone();
+                    summary.pages = *pages;
+                    summary.holders = *holders;
+                    summary.finished_at = Some(entry.at);
+                }
+                JournalEvent::Checkpoint { .. } => {}
+            }
+        }
+        if let Some((provider, requests, retries, bytes, error_rate)) = performance {
+            summary.provider = Some(provider);
+            summary.requests = requests;
+            summary.retries = retries;
+            summary.bytes = Some(bytes);
+            summary.error_rate = Some(error_rate);
+        }
+        latencies.sort_unstable();
+        summary.p50_page_ms = metrics::percentile(&latencies, 50.0);
+        summary.p95_page_ms = metrics::percentile(&latencies, 95.0);
+        summary
+    }
+
+    fn duration_secs(&self) -> Option<i64> {
+        Some((self.finished_at? - self.started_at?).num_seconds())
+    }
+}
+
+fn row(label: &str, a: String, b: String) {
+    println!("{:<24} {:>26} {:>26}", label, a, b);
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+/// Side-by-side comparison of two journaled runs, meant for checking a new
+/// provider or strategy against the current one before switching.
+pub fn run_compare_runs(options: &CompareRunsOptions) -> Result<()> {
+    let a = RunSummary::from_journal(&journal::load_run(&options.run_a)?);
+    let b = RunSummary::from_journal(&journal::load_run(&options.run_b)?);
+    let lowercase = |contract: &Option<String>| contract.as_deref().map(str::to_lowercase);
+    if a.contract.is_some() && b.contract.is_some() && lowercase(&a.contract) != lowercase(&b.contract) {
+        bail!("runs scanned different contracts ({} and {})", optional(a.contract), optional(b.contract));
+    }
+
+    row("", options.run_a.clone(), options.run_b.clone());
+    row("status", a.status.clone(), b.status.clone());
+    row("provider", optional(a.provider.clone()), optional(b.provider.clone()));
+    row("endpoints", a.endpoints.len().to_string(), b.endpoints.len().to_string());
+    println!();
+    row("pages", a.pages.to_string(), b.pages.to_string());
+    row("holders", a.holders.to_string(), b.holders.to_string());
+    println!();
+    row("duration (s)", optional(a.duration_secs()), optional(b.duration_secs()));
+    row("p50 page latency (ms)", a.p50_page_ms.to_string(), b.p50_page_ms.to_string());
+    row("p95 page latency (ms)", a.p95_page_ms.to_string(), b.p95_page_ms.to_string());
+    println!();
+    row("requests", a.requests.to_string(), b.requests.to_string());
+    row("retries", a.retries.to_string(), b.retries.to_string());
+    row("bytes", optional(a.bytes), optional(b.bytes));
+    let error_rate = |rate: Option<f64>| optional(rate.map(|r| format!("{:.2}%", r * 100.0)));
+    row("error rate", error_rate(a.error_rate), error_rate(b.error_rate));
+    row("errors / warnings", format!("{} / {}", a.errors, a.warnings), format!("{} / {}", b.errors, b.warnings));
+    if a.compute_units.is_some() || b.compute_units.is_some() {
+        let units = |run: &RunSummary| optional(run.compute_units.map(|units| format!("{:.0}", units)));
+        row("compute units", units(&a), units(&b));
+    }
+    if options.cost_a.is_some() || options.cost_b.is_some() || a.estimated_usd.is_some() || b.estimated_usd.is_some() {
+        // A price on the command line wins over the estimate the run journaled
+        let cost = |run: &RunSummary, price: Option<f64>| {
+            let usd = price.map(|p| run.requests as f64 / 1000.0 * p).or(run.estimated_usd);
+            optional(usd.map(|usd| format!("${:.4}", usd)))
+        };
+        row("estimated cost", cost(&a, options.cost_a), cost(&b, options.cost_b));
+    }
+
+    let state_a = options.state_a.clone().or(a.snapshot.clone());
+    let state_b = options.state_b.clone().or(b.snapshot.clone());
+    let (Some(state_a), Some(state_b)) = (state_a, state_b) else {
+        println!();
+        println!("No holder data to compare: scan with --archive, or pass --state-a and --state-b");
+        return Ok(());
+    };
+    let state_a = state::load_state_from(&state_a)?;
+    let state_b = state::load_state_from(&state_b)?;
+    if state_a.last_processed_block != state_b.last_processed_block {
+        warn!(
+            block_a = state_a.last_processed_block,
+            block_b = state_b.last_processed_block,
+            "Runs read different blocks; some differences may be real transfers"
+        );
+    }
+    let found = write_discrepancies(&state_a, &state_b, &options.output)?;
+    println!();
+    row("block", state_a.last_processed_block.to_string(), state_b.last_processed_block.to_string());
+    row("holders in snapshot", found.holders_a.to_string(), found.holders_b.to_string());
+    row("found only by this run", found.only_a.to_string(), found.only_b.to_string());
+    row("share also in the other", found.coverage(found.only_a), found.coverage(found.only_b));
+    println!("{:<24} {:>53}", "balance mismatches", found.balance_mismatches);
+    info!(output = %options.output.display(), "Differences written");
+    Ok(())
+}
+
+/// Holder counts from comparing two holder sets.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct Discrepancies {
+    pub holders_a: usize,
+    pub holders_b: usize,
+    pub both: usize,
+    pub only_a: usize,
+    pub only_b: usize,
+    pub balance_mismatches: usize,
+}
+
+impl Discrepancies {
+    pub fn is_empty(&self) -> bool {
+        self.only_a == 0 && self.only_b == 0 && self.balance_mismatches == 0
+    }
+
+    /// Share of one side's holders the other side also found.
+    pub fn coverage(&self, only: usize) -> String {
+        let total = self.both + only;
+        if total > 0 { format!("{:.2}%", self.both as f64 / total as f64 * 100.0) } else { "-".to_string() }
+    }
+}
+
+/// Writes the holders found in only one state or with different balances to
+/// `output` and counts them.
+pub(crate) fn write_discrepancies(a: &ScanState, b: &ScanState, output: &Path) -> Result<Discrepancies> {
+    // Providers differ in address casing, so both sides are compared normalized;
+    // anything that isn't an address is compared as given
+    let key = |holder: &str| address::normalize(holder).unwrap_or_else(|_| holder.trim().to_string());
+    let addresses = |state: &ScanState| -> BTreeSet<String> { state.holders.iter().map(|h| key(h)).collect() };
+    let balances =
+        |state: &ScanState| -> HashMap<String, u64> { state.balances.iter().map(|(h, b)| (key(h), *b)).collect() };
+    let (holders_a, holders_b) = (addresses(a), addresses(b));
+    let (balances_a, balances_b) = (balances(a), balances(b));
+
+    let mut writer = output::csv_writer(output)?;
+    writer.write_record(["address", "in_a", "in_b", "balance_a", "balance_b"])?;
+    let (mut only_a, mut only_b, mut balance_mismatches) = (0, 0, 0);
+    for address in holders_a.union(&holders_b) {
+        let (in_a, in_b) = (holders_a.contains(address), holders_b.contains(address));
+        let (balance_a, balance_b) = (balances_a.get(address).copied(), balances_b.get(address).copied());
+        match (in_a, in_b) {
+            (true, false) => only_a += 1,
+            (false, true) => only_b += 1,
+            _ if balance_a != balance_b => balance_mismatches += 1,
+            _ => continue,
+        }
+        writer.write_record([
+            address.clone(),
+            in_a.to_string(),
+            in_b.to_string(),
+            optional(balance_a),
+            optional(balance_b),
+        ])?;
+    }
+    output::finish_csv(writer)?;
+    Ok(Discrepancies {
+        holders_a: holders_a.len(),
+        holders_b: holders_b.len(),
+        both: holders_a.intersection(&holders_b).count(),
+        only_a,
+        only_b,
+        balance_mismatches,
+    })
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use web3::types::{Address, U256};
+
+use crate::rpc;
+use crate::state::ScanState;
+
+pub const DEFAULT_TOLERANCE_PCT: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// Within tolerance of every total that could be read
+    Consistent,
+    /// Fewer tokens or holders than reported: holders are missing
+    Incomplete,
+    /// More than reported, such as holders counted twice or kept after they exited
+    Inconsistent,
+    /// Neither `totalSupply()` nor a provider total could be read
+    Unchecked,
+}
+
+impl Verdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verdict::Consistent => "consistent",
+            Verdict::Incomplete => "incomplete",
+            Verdict::Inconsistent => "inconsistent",
+            Verdict::Unchecked => "unchecked",
+        }
+    }
+}
+
+/// A finished scan's totals against what the contract and provider report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Completeness {
+    pub verdict: Verdict,
+    pub checked_at: DateTime<Utc>,
+    /// Sum of recorded balances; `None` for a streaming scan, whose balances aren't in the state
+    pub recorded_supply: Option<u128>,
+    /// `totalSupply()` of the contract, at `block` when the source reports one
+    pub total_supply: Option<u128>,
+    pub block: Option<u64>,
+    pub recorded_holders: u64,
+    /// Holder count in the provider's responses (Alchemy's `totalCount`)
+    pub reported_holders: Option<u64>,
+    pub tolerance_pct: f64,
+    /// Each total that diverged beyond the tolerance
+    pub findings: Vec<String>,
+}
+
+/// What to compare a finished scan with.
+#[derive(Debug, Clone, Copy)]
+pub struct Expected<'a> {
+    pub contract: &'a str,
+    /// Block the holders were read at, when the source knows it
+    pub block: Option<u64>,
+    pub reported_holders: Option<u64>,
+    /// Whether to call `totalSupply()`; a replay or offline scan doesn't
+    pub read_supply: bool,
+    pub tolerance_pct: f64,
+}
+
+/// Compares the holdings in `state` with `totalSupply()` over
+/// `OPTIMISM_RPC_URL` and with the provider's holder count. A total that
+/// can't be read is left out with a warning.
+pub async fn check(state: &ScanState, streaming: bool, expected: Expected<'_>) -> Completeness {
+    let recorded_supply = (!streaming).then(|| state.balances.values().map(|balance| u128::from(*balance)).sum::<u128>());
+    let total_supply = match (expected.read_supply, recorded_supply) {
+        (true, Some(_)) => total_supply(expected.contract, expected.block).await,
+        _ => None,
+    };
+
+    let mut findings = Vec::new();
+    let mut verdicts = Vec::new();
+    let mut compare = |what: &str, recorded: u128, reported: u128| {
+        let divergence = divergence_pct(recorded, reported);
+        let verdict = match divergence {
+            d if d.abs() <= expected.tolerance_pct => Verdict::Consistent,
+            d if d < 0.0 => Verdict::Incomplete,
+            _ => Verdict::Inconsistent,
+        };
+        if verdict != Verdict::Consistent {
+            findings.push(format!("{} {} against {} reported ({:+.2}%)", recorded, what, reported, divergence));
+        }
+        verdicts.push(verdict);
+    };
+    if let (Some(recorded), Some(supply)) = (recorded_supply, total_supply) {
+        compare("tokens held", recorded, supply);
+    }
+    if let Some(reported) = expected.reported_holders {
+        compare("holders", state.total_holders.into(), reported.into());
+    }
+    // Missing holders outweigh extra ones
+    let verdict = [Verdict::Incomplete, Verdict::Inconsistent, Verdict::Consistent]
+        .into_iter()
+        .find(|verdict| verdicts.contains(verdict))
+        .unwrap_or(Verdict::Unchecked);
+
+    Completeness {
+        verdict,
+        checked_at: Utc::now(),
+        recorded_supply,
+        total_supply,
+        block: expected.block,
+        recorded_holders: state.total_holders,
+        reported_holders: expected.reported_holders,
+        tolerance_pct: expected.tolerance_pct,
+        findings,
+    }
+}
+
+/// How far `recorded` is above (positive) or below `reported`, in percent.
+fn divergence_pct(recorded: u128, reported: u128) -> f64 {
+    match reported {
+        0 if recorded == 0 => 0.0,
+        0 => 100.0,
+        _ => (recorded as f64 - reported as f64) / reported as f64 * 100.0,
+    }
+}
+
+async fn total_supply(contract: &str, block: Option<u64>) -> Option<u128> {
+    if std::env::var("OPTIMISM_RPC_URL").map_or(true, |url| url.is_empty()) {
+        info!("OPTIMISM_RPC_URL is not set, skipping the totalSupply() check");
+        return None;
+    }
+    let read = async {
+        let address: Address = contract.parse()?;
+        let supply = rpc::total_supply_at(&rpc::connect()?, address, block).await?;
+        // Beyond u128 no recorded sum of u64 balances could come close anyway
+        anyhow::Ok(if supply > U256::from(u128::MAX) { u128::MAX } else { supply.low_u128() })
+    };
+    match read.await {
+        Ok(supply) => Some(supply),
+        Err(e) => {
+            warn!(error = %format!("{:#}", e), ?block, "Could not read totalSupply(), skipping the supply check");
+            None
+        }
+    }
+}
+
+/// How a scan that returned `state` ends in its journal: `finished`, or
+/// `incomplete` or `inconsistent` when holders are missing or over-counted.
+pub fn run_status(state: &ScanState) -> &'static str {
+    match state.completeness.as_ref().map(|completeness| completeness.verdict) {
+        _ if !state.complete() => "incomplete",
+        Some(Verdict::Incomplete) => "incomplete",
+        Some(Verdict::Inconsistent) => "inconsistent",
+        _ => "finished",
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+use crate::accounting::AccountingConfig;
+use crate::cost::CostConfig;
+use crate::eligibility::EligibilityConfig;
+use crate::etherscan::EtherscanConfig;
+use crate::funding::FundingConfig;
+use crate::http::HttpConfig;
+use crate::labels::LabelsConfig;
+use crate::preset::Preset;
+use crate::pricing::PricingConfig;
+use crate::schedule::ScheduleTarget;
+use crate::sheets::SheetsConfig;
+use crate::standards::EventsConfig;
+use crate::subgraph::SubgraphConfig;
+use crate::wrappers::WrapperConfig;
+
+/// Optional settings file, `scanner.toml` in the working directory or the
+/// path in `SCANNER_CONFIG`. A missing file means every section is unset.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub eligibility: EligibilityConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    pub pricing: Option<PricingConfig>,
+    #[serde(default)]
+    pub events: EventsConfig,
+    #[serde(default)]
+    pub funding: FundingConfig,
+    #[serde(default)]
+    pub accounting: AccountingConfig,
+    pub sheets: Option<SheetsConfig>,
+    #[serde(default)]
+    pub presets: HashMap<String, Preset>,
+    pub subgraph: Option<SubgraphConfig>,
+    pub etherscan: Option<EtherscanConfig>,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub labels: LabelsConfig,
+    /// `[[wrapper]]` entries for `look-through`
+    #[serde(default, rename = "wrapper")]
+    pub wrappers: Vec<WrapperConfig>,
+    #[serde(default)]
+    pub cost: CostConfig,
+    /// `[[schedule]]` targets for `schedule`
+    #[serde(default, rename = "schedule")]
+    pub schedules: Vec<ScheduleTarget>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationsConfig {
+    pub discord: Option<DiscordConfig>,
+    pub telegram: Option<TelegramConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiscordConfig {
+    pub webhook_url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+/// `[cache]` in `scanner.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    /// TTL for every GET endpoint without its own entry; 0 disables caching
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Per-route TTLs keyed by the route pattern, e.g. `"/contracts/:address/holders" = 300`
+    #[serde(default)]
+    pub endpoints: HashMap<String, u64>,
+    /// Share the cache between `serve` instances (needs the `redis` feature)
+    pub redis_url: Option<String>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig { ttl_secs: default_ttl_secs(), endpoints: HashMap::new(), redis_url: None }
+    }
+}
+
+fn default_ttl_secs() -> u64 {
+    60
+}
+
+pub fn config_path() -> PathBuf {
+    PathBuf::from(env::var("SCANNER_CONFIG").unwrap_or_else(|_| "scanner.toml".to_string()))
+}
+
+pub fn load_config() -> Result<Config> {
+    let path = config_path();
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let text = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::ratelimit;
+
+/// `[cost]` in `scanner.toml`: prices for estimating what a run cost, and an
+/// optional budget per run.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CostConfig {
+    /// Alchemy compute units per method, over the built-in table
+    #[serde(default)]
+    pub compute_units: BTreeMap<String, f64>,
+    /// Your Alchemy plan's price in USD per million compute units
+    pub usd_per_million_cu: Option<f64>,
+    /// Price in USD per 1,000 requests, for providers billed per request
+    pub usd_per_1000_requests: Option<f64>,
+    /// Cost of a request pinned to a past block relative to a plain one
+    #[serde(default = "default_archive_multiplier")]
+    pub archive_multiplier: f64,
+    pub budget: Option<CostBudget>,
+}
+
+impl Default for CostConfig {
+    fn default() -> Self {
+        CostConfig {
+            compute_units: BTreeMap::new(),
+            usd_per_million_cu: None,
+            usd_per_1000_requests: None,
+            archive_multiplier: default_archive_multiplier(),
+            budget: None,
+        }
+    }
+}
+
+fn default_archive_multiplier() -> f64 {
+    1.0
+}
+
+/// `[cost.budget]`: limits on one run. Whichever is reached first applies.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CostBudget {
+    pub max_compute_units: Option<f64>,
+    pub max_requests: Option<u64>,
+    /// Needs the matching price above
+    pub max_usd: Option<f64>,
+    #[serde(default)]
+    pub on_exceeded: OnExceeded,
+    /// How long `pause` waits before allowing another budget's worth
+    #[serde(default = "default_pause_secs")]
+    pub pause_secs: u64,
+}
+
+fn default_pause_secs() -> u64 {
+    3600
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnExceeded {
+    /// Stop the scan with an error; the checkpoint is saved, so rerunning resumes it
+    #[default]
+    Abort,
+    /// Wait `pause_secs`, then carry on with a fresh budget
+    Pause,
+}
+
+/// What a run's requests are estimated to have cost, kept up to date as
+/// requests are made.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CostReport {
+    pub requests: u64,
+    /// Requests pinned to a past block, which archive pricing may charge more for
+    pub archive_requests: u64,
+    /// Alchemy compute units; zero for other providers
+    pub compute_units: f64,
+    /// `None` without a price for the provider in `[cost]`
+    pub estimated_usd: Option<f64>,
+    /// Endpoint → method → requests
+    pub requests_by_endpoint: BTreeMap<String, BTreeMap<String, u64>>,
+    // Compute units or requests after the archive multiplier, in the unit the price is quoted in
+    #[serde(skip)]
+    billed_units: f64,
+}
+
+impl CostConfig {
+    /// Compute units of one call to an Alchemy `method`.
+    pub fn compute_units(&self, method: &str) -> f64 {
+        self.compute_units.get(method).copied().unwrap_or_else(|| ratelimit::compute_units(method))
+    }
+
+    /// Adds one request to `report`. Only the Alchemy provider is charged in compute units.
+    pub fn record(&self, report: &mut CostReport, provider: &str, endpoint: &str, method: &str, archive: bool) {
+        report.requests += 1;
+        let methods = report.requests_by_endpoint.entry(endpoint.to_string()).or_default();
+        *methods.entry(method.to_string()).or_default() += 1;
+        let units = if provider == "alchemy" {
+            let compute_units = self.compute_units(method);
+            report.compute_units += compute_units;
+            compute_units
+        } else {
+            1.0
+        };
+        report.archive_requests += u64::from(archive);
+        report.billed_units += if archive { units * self.archive_multiplier } else { units };
+        report.estimated_usd = match provider {
+            "alchemy" => self.usd_per_million_cu.map(|price| report.billed_units / 1_000_000.0 * price),
+            _ => self.usd_per_1000_requests.map(|price| report.billed_units / 1000.0 * price),
+        };
+    }
+}
+
+/// Tracks a run's spending against `[cost.budget]`. After a pause, usage is
+/// counted from where the pause began.
+#[derive(Debug)]
+pub struct BudgetTracker {
+    budget: CostBudget,
+    /// Usage when the current allowance started
+    base: (f64, u64, f64),
+}
+
+impl BudgetTracker {
+    pub fn new(budget: CostBudget) -> Self {
+        BudgetTracker { budget, base: (0.0, 0, 0.0) }
+    }
+
+    pub fn on_exceeded(&self) -> OnExceeded {
+        self.budget.on_exceeded
+    }
+
+    pub fn pause(&self) -> Duration {
+        Duration::from_secs(self.budget.pause_secs)
+    }
+
+    /// Which limit `report` has gone over, if any.
+    pub fn exceeded(&self, report: &CostReport) -> Option<String> {
+        let (compute_units, requests, usd) = self.base;
+        let used_usd = report.estimated_usd.unwrap_or(0.0) - usd;
+        if let Some(max) = self.budget.max_compute_units.filter(|max| report.compute_units - compute_units > *max) {
+            return Some(format!("{:.0} compute units used, budget {:.0}", report.compute_units - compute_units, max));
+        }
+        if let Some(max) = self.budget.max_requests.filter(|max| report.requests - requests > *max) {
+            return Some(format!("{} requests made, budget {}", report.requests - requests, max));
+        }
+        if let Some(max) = self.budget.max_usd.filter(|max| used_usd > *max) {
+            return Some(format!("${:.4} spent, budget ${:.4}", used_usd, max));
+        }
+        None
+    }
+
+    /// Starts a fresh allowance from the usage in `report`.
+    pub fn renew(&mut self, report: &CostReport) {
+        self.base = (report.compute_units, report.requests, report.estimated_usd.unwrap_or(0.0));
+    }
+}
@@ -0,0 +1,188 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use tokio_postgres::{Client, NoTls};
+use tracing::{debug, error, info};
+
+use crate::address;
+use crate::digest;
+use crate::state::ScanState;
+
+// Rows per INSERT ... UNNEST statement
+const BATCH_SIZE: usize = 10_000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS contracts (
+    id SERIAL PRIMARY KEY,
+    address TEXT NOT NULL,
+    chain TEXT NOT NULL,
+    UNIQUE (address, chain)
+);
+CREATE TABLE IF NOT EXISTS scan_runs (
+    id BIGSERIAL PRIMARY KEY,
+    contract_id INTEGER NOT NULL REFERENCES contracts (id),
+    instance TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL,
+    total_holders BIGINT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS holders (
+    contract_id INTEGER NOT NULL REFERENCES contracts (id),
+    address TEXT NOT NULL,
+    first_seen TIMESTAMPTZ NOT NULL,
+    last_seen_run BIGINT NOT NULL REFERENCES scan_runs (id),
+    PRIMARY KEY (contract_id, address)
+);
+CREATE TABLE IF NOT EXISTS balances (
+    run_id BIGINT NOT NULL REFERENCES scan_runs (id),
+    address TEXT NOT NULL,
+    balance BIGINT NOT NULL,
+    PRIMARY KEY (run_id, address)
+);
+CREATE INDEX IF NOT EXISTS balances_address_idx ON balances (address);
+CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER NOT NULL
+);
+";
+
+/// `MIGRATIONS[n]` upgrades the schema from version `n + 1` to `n + 2`;
+/// `SCHEMA` above is version 1 and stays as it is.
+const MIGRATIONS: &[&str] = &[
+    // 2: link runs to their journal and record how they were produced
+    "ALTER TABLE scan_runs ADD COLUMN journal_run TEXT, ADD COLUMN provider TEXT, ADD COLUMN holders_hash TEXT;",
+];
+
+/// Schema version this build creates and expects.
+pub const SCHEMA_VERSION: i32 = 1 + MIGRATIONS.len() as i32;
+
+/// Connects to `url`, creates the tables if they don't exist yet and applies
+/// pending schema migrations. A database migrated by a newer scanner is
+/// refused rather than written to.
+pub async fn connect(url: &str) -> Result<Client> {
+    let (mut client, connection) = tokio_postgres::connect(url, NoTls).await.context("connecting to Postgres")?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            error!(error = %e, "Postgres connection closed");
+        }
+    });
+    client.batch_execute(SCHEMA).await.context("creating Postgres schema")?;
+    migrate(&mut client).await?;
+    Ok(client)
+}
+
+async fn migrate(client: &mut Client) -> Result<()> {
+    let transaction = client.transaction().await?;
+    // Serializes instances connecting at the same time
+    transaction.batch_execute("LOCK TABLE schema_version IN EXCLUSIVE MODE").await?;
+    let row = transaction.query_opt("SELECT version FROM schema_version", &[]).await?;
+    let current: i32 = match row {
+        Some(row) => row.get(0),
+        None => {
+            // A new database, or one from before the schema was versioned, which is version 1
+            transaction.execute("INSERT INTO schema_version (version) VALUES (1)", &[]).await?;
+            1
+        }
+    };
+    if current > SCHEMA_VERSION {
+        bail!(
+            "the Postgres schema is version {} but this build supports up to {}; upgrade the scanner",
+            current,
+            SCHEMA_VERSION
+        );
+    }
+    for (version, migration) in (current..).zip(&MIGRATIONS[(current - 1) as usize..]) {
+        transaction.batch_execute(migration).await.with_context(|| format!("migrating schema to {}", version + 1))?;
+        info!(version = version + 1, "Migrated Postgres schema");
+    }
+    transaction.execute("UPDATE schema_version SET version = $1", &[&SCHEMA_VERSION]).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Records a completed scan as a new run: its balances, and the holders table
+/// updated with first-seen times and the latest run each holder appeared in.
+/// Everything is written in one transaction so concurrent instances never see
+/// a partial run. `journal_run` is the run's id in `data/runs`. Returns the
+/// run id.
+pub async fn record_scan(
+    client: &mut Client,
+    contract: &str,
+    chain: &str,
+    started_at: DateTime<Utc>,
+    state: &ScanState,
+    journal_run: &str,
+    provider: &str,
+) -> Result<i64> {
+    let instance = std::env::var("SCANNER_INSTANCE").unwrap_or_else(|_| hostname());
+    // Streaming scans don't hold the holder set, so there's nothing to hash
+    let holders_hash = (state.holders.len() as u64 == state.total_holders)
+        .then(|| digest::snapshot_hash(&digest::canonical_list(state)));
+    let tx = client.transaction().await?;
+
+    let contract_id: i32 = tx
+        .query_one(
+            "INSERT INTO contracts (address, chain) VALUES ($1, $2)
+             ON CONFLICT (address, chain) DO UPDATE SET address = EXCLUDED.address
+             RETURNING id",
+            &[&contract.to_lowercase(), &chain],
+        )
+        .await?
+        .get(0);
+
+    let run_id: i64 = tx
+        .query_one(
+            "INSERT INTO scan_runs
+                 (contract_id, instance, started_at, finished_at, total_holders, journal_run, provider, holders_hash)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+            &[
+                &contract_id,
+                &instance,
+                &started_at,
+                &Utc::now(),
+                &(state.total_holders as i64),
+                &journal_run,
+                &provider,
+                &holders_hash,
+            ],
+        )
+        .await?
+        .get(0);
+
+    let mut holders: Vec<&String> = state.holders.iter().collect();
+    holders.sort();
+    for batch in holders.chunks(BATCH_SIZE) {
+        let addresses: Vec<String> =
+            batch.iter().map(|h| address::normalize(h).unwrap_or_else(|_| h.trim().to_string())).collect();
+        let balances: Vec<i64> = batch
+            .iter()
+            .map(|h| state.balances.get(*h).copied().unwrap_or(0) as i64)
+            .collect();
+        let first_seen: Vec<DateTime<Utc>> = batch
+            .iter()
+            .map(|h| state.first_seen.get(*h).copied().unwrap_or(started_at))
+            .collect();
+
+        tx.execute(
+            "INSERT INTO balances (run_id, address, balance)
+             SELECT $1, address, balance FROM UNNEST($2::text[], $3::bigint[]) AS t (address, balance)",
+            &[&run_id, &addresses, &balances],
+        )
+        .await?;
+        tx.execute(
+            "INSERT INTO holders (contract_id, address, first_seen, last_seen_run)
+             SELECT $1, address, first_seen, $2 FROM UNNEST($3::text[], $4::timestamptz[]) AS t (address, first_seen)
+             ON CONFLICT (contract_id, address) DO UPDATE
+             SET last_seen_run = EXCLUDED.last_seen_run,
+                 first_seen = LEAST(holders.first_seen, EXCLUDED.first_seen)",
+            &[&contract_id, &run_id, &addresses, &first_seen],
+        )
+        .await?;
+        debug!(run_id, rows = batch.len(), "Wrote holder batch to Postgres");
+    }
+
+    tx.commit().await?;
+    Ok(run_id)
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME").unwrap_or_else(|_| "scanner".to_string())
+}
@@ -0,0 +1,174 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::path::PathBuf;
+use tracing::{info, warn};
+use web3::ethabi::{self, ParamType, Token};
+use web3::signing::keccak256;
+use web3::types::{Address, U256};
+
+use crate::holder_updates::parse_token_id;
+use crate::multicall::Multicall;
+use crate::rpc;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, Delegations};
+
+// delegate.xyz DelegateRegistry v2, deployed at the same address on Optimism, mainnet and most EVM chains
+const DELEGATE_REGISTRY_ADDRESS: &str = "0x00000000000000447e69651d841bD8D104Bed493";
+
+// `DelegationType` in the registry
+const DELEGATE_ALL: u64 = 1;
+const DELEGATE_CONTRACT: u64 = 2;
+const DELEGATE_ERC721: u64 = 3;
+const DELEGATE_ERC20: u64 = 4;
+const DELEGATE_ERC1155: u64 = 5;
+
+#[derive(Debug, clap::Args)]
+pub struct DelegationOptions {
+    /// Snapshot to annotate (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Block to read the registry at, usually the block the snapshot was taken at
+    #[arg(long)]
+    pub block: Option<u64>,
+}
+
+/// One entry of `getOutgoingDelegations`.
+#[derive(Debug)]
+struct Delegation {
+    kind: u64,
+    to: Address,
+    rights: [u8; 32],
+    contract: Address,
+    token_id: U256,
+}
+
+impl Delegation {
+    fn decode(token: Token) -> Option<Delegation> {
+        let Token::Tuple(fields) = token else {
+            return None;
+        };
+        let [
+            Token::Uint(kind),
+            Token::Address(to),
+            _from,
+            Token::FixedBytes(rights),
+            Token::Address(contract),
+            Token::Uint(token_id),
+            _amount,
+        ] = fields.as_slice()
+        else {
+            return None;
+        };
+        Some(Delegation {
+            kind: kind.low_u64(),
+            to: *to,
+            rights: rights.as_slice().try_into().ok()?,
+            contract: *contract,
+            token_id: *token_id,
+        })
+    }
+
+    /// Whether this delegation hands the delegate the vault's position in
+    /// `contract`: a whole-wallet or whole-contract delegation, or one for a
+    /// token id the vault holds. Delegations limited to specific rights don't.
+    fn covers(&self, contract: Address, held: &HashSet<U256>) -> bool {
+        if self.rights != [0; 32] || self.to.is_zero() {
+            return false;
+        }
+        match self.kind {
+            DELEGATE_ALL => true,
+            DELEGATE_CONTRACT | DELEGATE_ERC20 => self.contract == contract,
+            DELEGATE_ERC721 | DELEGATE_ERC1155 => self.contract == contract && held.contains(&self.token_id),
+            _ => false,
+        }
+    }
+}
+
+/// Looks up every holder's outgoing delegations in the delegate.xyz registry
+/// and records which hot wallets each vault has delegated its position to, so
+/// `[eligibility] delegation` can credit them. `DELEGATE_REGISTRY_ADDRESS`
+/// overrides the registry.
+pub async fn run_delegations(options: &DelegationOptions) -> Result<()> {
+    let mut state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    let address = env::var("DELEGATE_REGISTRY_ADDRESS").unwrap_or_else(|_| DELEGATE_REGISTRY_ADDRESS.to_string());
+    let registry: Address =
+        address.parse().with_context(|| format!("invalid DELEGATE_REGISTRY_ADDRESS {}", address))?;
+    let contract: Address = CONTRACT_ADDRESS.parse().context("invalid contract address")?;
+    let web3 = rpc::connect()?;
+    let multicall = Multicall::from_env()?;
+
+    let mut holders: Vec<(String, Address)> = state
+        .holders
+        .iter()
+        .filter_map(|h| Some((h.clone(), h.parse::<Address>().ok()?)))
+        .collect();
+    holders.sort();
+    let selector = &keccak256(b"getOutgoingDelegations(address)")[..4];
+    let calls: Vec<(Address, Vec<u8>)> = holders
+        .iter()
+        .map(|(_, holder)| {
+            let mut data = selector.to_vec();
+            data.extend(ethabi::encode(&[Token::Address(*holder)]));
+            (registry, data)
+        })
+        .collect();
+    info!(holders = calls.len(), registry = ?registry, "Reading outgoing delegations");
+    let results = multicall.aggregate(&web3, &calls, options.block).await?;
+
+    let returns = ParamType::Array(Box::new(ParamType::Tuple(vec![
+        ParamType::Uint(8),
+        ParamType::Address,
+        ParamType::Address,
+        ParamType::FixedBytes(32),
+        ParamType::Address,
+        ParamType::Uint(256),
+        ParamType::Uint(256),
+    ])));
+    let mut delegates = BTreeMap::new();
+    let mut failed = 0;
+    for ((holder, _), result) in holders.iter().zip(results) {
+        let decoded = result.and_then(|data| ethabi::decode(std::slice::from_ref(&returns), &data).ok());
+        let Some(Token::Array(entries)) = decoded.and_then(|tokens| tokens.into_iter().next()) else {
+            failed += 1;
+            continue;
+        };
+        let held: HashSet<U256> =
+            state.tokens.get(holder).into_iter().flatten().filter_map(|id| parse_token_id(id)).collect();
+        let mut to: Vec<String> = entries
+            .into_iter()
+            .filter_map(Delegation::decode)
+            .filter(|delegation| delegation.covers(contract, &held))
+            .map(|delegation| format!("{:?}", delegation.to))
+            .filter(|delegate| delegate != holder)
+            .collect();
+        to.sort();
+        to.dedup();
+        if !to.is_empty() {
+            delegates.insert(holder.clone(), to);
+        }
+    }
+    if failed == holders.len() && !holders.is_empty() {
+        bail!("getOutgoingDelegations failed for every holder; is the registry deployed at {:?}?", registry);
+    }
+    if failed > 0 {
+        warn!(holders = failed, "getOutgoingDelegations failed for some holders; they are recorded without delegates");
+    }
+    info!(vaults = delegates.len(), "Delegations resolved");
+
+    state.delegations = Some(Delegations {
+        registry: format!("{:?}", registry),
+        block: options.block,
+        resolved_at: Utc::now(),
+        delegates,
+    });
+    match &options.state {
+        Some(path) => state::save_state_to(&state, path)?,
+        None => state::save_state(&state)?,
+    }
+    Ok(())
+}
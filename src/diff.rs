@@ -0,0 +1,106 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::output;
+use crate::state::ScanState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    BalanceChanged,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::BalanceChanged => "balance_changed",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HolderChange {
+    pub address: String,
+    pub kind: ChangeKind,
+    pub old_balance: u64,
+    pub new_balance: u64,
+}
+
+impl HolderChange {
+    pub fn delta(&self) -> i128 {
+        self.new_balance as i128 - self.old_balance as i128
+    }
+}
+
+/// One holder change as pushed to event subscribers (WebSocket, Kafka, NATS).
+#[derive(Debug, Clone, Serialize)]
+pub struct HolderEvent {
+    pub cycle: u64,
+    pub at: DateTime<Utc>,
+    /// `added`, `removed` or `balance_changed`
+    pub kind: &'static str,
+    pub address: String,
+    pub old_balance: u64,
+    pub new_balance: u64,
+}
+
+impl HolderEvent {
+    pub fn new(cycle: u64, at: DateTime<Utc>, change: &HolderChange) -> HolderEvent {
+        HolderEvent {
+            cycle,
+            at,
+            kind: change.kind.as_str(),
+            address: change.address.clone(),
+            old_balance: change.old_balance,
+            new_balance: change.new_balance,
+        }
+    }
+}
+
+pub fn diff_states(old: &ScanState, new: &ScanState) -> Vec<HolderChange> {
+    let addresses: BTreeSet<&String> = old.holders.iter().chain(new.holders.iter()).collect();
+    let balance = |state: &ScanState, address: &str| state.balances.get(address).copied().unwrap_or(0);
+
+    addresses
+        .into_iter()
+        .filter_map(|address| {
+            let old_balance = balance(old, address.as_str());
+            let new_balance = balance(new, address.as_str());
+            let kind = match (old.holders.contains(address), new.holders.contains(address)) {
+                (false, true) => ChangeKind::Added,
+                (true, false) => ChangeKind::Removed,
+                _ if old_balance != new_balance => ChangeKind::BalanceChanged,
+                _ => return None,
+            };
+            Some(HolderChange { address: address.clone(), kind, old_balance, new_balance })
+        })
+        .collect()
+}
+
+pub fn save_diff(changes: &[HolderChange], path: &Path) -> Result<()> {
+    let mut writer = output::csv_writer(path)?;
+    writer.write_record(["address", "change", "old_balance", "new_balance", "delta"])?;
+    for change in changes {
+        writer.write_record([
+            change.address.clone(),
+            change.kind.as_str().to_string(),
+            change.old_balance.to_string(),
+            change.new_balance.to_string(),
+            change.delta().to_string(),
+        ])?;
+    }
+    output::finish_csv(writer)
+}
+
+pub fn print_summary(changes: &[HolderChange]) {
+    let count = |kind: ChangeKind| changes.iter().filter(|c| c.kind == kind).count();
+    println!("New holders: {}", count(ChangeKind::Added));
+    println!("Exited holders: {}", count(ChangeKind::Removed));
+    println!("Balance changes: {}", count(ChangeKind::BalanceChanged));
+}
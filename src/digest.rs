@@ -0,0 +1,89 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use web3::signing::{self, keccak256, Key, SecretKeyRef};
+use web3::types::Address;
+
+use crate::address;
+use crate::state::ScanState;
+
+/// File in each snapshot directory holding the list the hash is taken over.
+pub const CANONICAL_LIST: &str = "holders.canonical.csv";
+
+/// EIP-191 signature of a snapshot hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotSignature {
+    /// Checksummed address of the signing key
+    pub signer: String,
+    /// 65 bytes `r || s || v` as 0x-hex, with `v` 27 or 28
+    pub signature: String,
+}
+
+/// The holder list in canonical form: one `address,amount` line per holder
+/// with an EIP-55 checksummed address, sorted by lowercase address, each line
+/// ending in `\n`. Addresses that aren't EVM addresses are kept as stored,
+/// trimmed.
+pub fn canonical_list(state: &ScanState) -> String {
+    let mut holders: Vec<(String, String, u64)> = state
+        .holders
+        .iter()
+        .map(|holder| {
+            let published = address::checksummed(holder).unwrap_or_else(|_| holder.trim().to_string());
+            let key = address::normalize(holder).unwrap_or_else(|_| holder.trim().to_lowercase());
+            (key, published, state.balances.get(holder).copied().unwrap_or(0))
+        })
+        .collect();
+    holders.sort();
+    holders.iter().map(|(_, published, amount)| format!("{},{}\n", published, amount)).collect()
+}
+
+/// `keccak256` of the canonical list's bytes, as 0x-hex.
+pub fn snapshot_hash(canonical: &str) -> String {
+    format!("0x{}", hex::encode(keccak256(canonical.as_bytes())))
+}
+
+/// Signs `hash` with `SNAPSHOT_SIGNING_KEY`, a hex secp256k1 private key, as
+/// `personal_sign` would sign the 32 hash bytes. `None` when no key is set.
+pub fn sign_from_env(hash: &str) -> Result<Option<SnapshotSignature>> {
+    let Ok(raw) = env::var("SNAPSHOT_SIGNING_KEY") else {
+        return Ok(None);
+    };
+    let bytes = hex::decode(raw.trim().trim_start_matches("0x")).context("SNAPSHOT_SIGNING_KEY is not hex")?;
+    let key =
+        signing::SecretKey::from_slice(&bytes).context("SNAPSHOT_SIGNING_KEY is not a secp256k1 private key")?;
+    let key = SecretKeyRef::new(&key);
+    let message = signing::hash_message(hash_bytes(hash)?);
+    let signature = key.sign_message(message.as_bytes()).map_err(|e| anyhow!("signing failed: {:?}", e))?;
+    let mut encoded = Vec::with_capacity(65);
+    encoded.extend_from_slice(signature.r.as_bytes());
+    encoded.extend_from_slice(signature.s.as_bytes());
+    encoded.push(signature.v as u8 + 27);
+    Ok(Some(SnapshotSignature {
+        signer: address::checksummed(&format!("{:?}", key.address()))?,
+        signature: format!("0x{}", hex::encode(encoded)),
+    }))
+}
+
+/// The address that signed `hash`.
+pub fn recover_signer(hash: &str, signature: &str) -> Result<Address> {
+    let bytes = hex::decode(signature.trim_start_matches("0x")).context("signature is not hex")?;
+    if bytes.len() != 65 {
+        bail!("signature is {} bytes, expected 65", bytes.len());
+    }
+    let recovery_id = match bytes[64] {
+        v @ (27 | 28) => v as i32 - 27,
+        v @ (0 | 1) => v as i32,
+        v => bail!("signature has an invalid recovery byte {}", v),
+    };
+    let message = signing::hash_message(hash_bytes(hash)?);
+    signing::recover(message.as_bytes(), &bytes[..64], recovery_id)
+        .map_err(|e| anyhow!("could not recover the signer: {:?}", e))
+}
+
+fn hash_bytes(hash: &str) -> Result<Vec<u8>> {
+    let bytes = hex::decode(hash.trim_start_matches("0x")).context("hash is not hex")?;
+    if bytes.len() != 32 {
+        bail!("hash is {} bytes, expected 32", bytes.len());
+    }
+    Ok(bytes)
+}
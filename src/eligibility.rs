@@ -0,0 +1,442 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+use crate::address;
+use crate::address_set;
+use crate::archive::{self, SnapshotEntry};
+use crate::config;
+use crate::output;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, ScanState};
+use crate::traits::TraitFilter;
+
+/// `[eligibility]` in `scanner.toml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EligibilityConfig {
+    /// CSV of `address,label`; an address may have several rows
+    pub labels: Option<PathBuf>,
+    /// `[[eligibility.rule]]` entries; a holder is eligible when it passes all of them
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<RuleConfig>,
+    /// Who a vault's row in `data/eligibility.csv` goes to when it has
+    /// delegated to a hot wallet; see the `delegations` command
+    #[serde(default)]
+    pub delegation: DelegationPolicy,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DelegationPolicy {
+    /// The vault, as if it hadn't delegated
+    #[default]
+    Vault,
+    /// The delegate in place of the vault
+    Delegate,
+    /// Both the vault and its delegates
+    Both,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleConfig {
+    /// Shown in exports and lookups; defaults to a description of the rule
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub rule: Rule,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rule {
+    /// Balance of at least `min`, in the live state or in an archived snapshot
+    /// picked by id, as the latest one read at or before `block`, or as the
+    /// latest one taken at or before `at`
+    MinBalance {
+        min: u64,
+        snapshot: Option<String>,
+        block: Option<u64>,
+        at: Option<DateTime<Utc>>,
+    },
+    /// Holding since at least `days` days before the evaluation time. Uses the
+    /// replayed acquisition time when the `acquisition` command has run, the
+    /// first-seen time otherwise
+    HeldFor { days: i64 },
+    /// None of the given labels in the labels file
+    NotLabeled { labels: Vec<String> },
+    /// In the address set imported under `set` (see `import`)
+    InSet { set: String },
+    /// Not in the address set imported under `set`
+    NotInSet { set: String },
+    /// At least `min_tokens` held tokens whose attributes match `filter`.
+    /// Needs the attributes recorded by `metadata --token-attributes`
+    Trait {
+        filter: TraitFilter,
+        #[serde(default = "one")]
+        min_tokens: usize,
+    },
+}
+
+fn one() -> usize {
+    1
+}
+
+impl RuleConfig {
+    pub fn name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        match &self.rule {
+            Rule::MinBalance { min, snapshot: Some(id), .. } => format!("balance >= {} in snapshot {}", min, id),
+            Rule::MinBalance { min, block: Some(block), .. } => format!("balance >= {} at block {}", min, block),
+            Rule::MinBalance { min, at: Some(at), .. } => format!("balance >= {} at {}", min, at.to_rfc3339()),
+            Rule::MinBalance { min, .. } => format!("balance >= {}", min),
+            Rule::HeldFor { days } => format!("held for {} days", days),
+            Rule::NotLabeled { labels } => format!("not labeled {}", labels.join("/")),
+            Rule::InSet { set } => format!("in set {}", set),
+            Rule::NotInSet { set } => format!("not in set {}", set),
+            Rule::Trait { filter, min_tokens: 1 } => format!("holds a token with {}", filter),
+            Rule::Trait { filter, min_tokens } => format!("holds {} tokens with {}", min_tokens, filter),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleOutcome {
+    pub rule: String,
+    pub passed: bool,
+    /// What was observed, e.g. "held 3, needs 5"
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Verdict {
+    pub address: String,
+    pub balance: u64,
+    pub eligible: bool,
+    pub outcomes: Vec<RuleOutcome>,
+}
+
+impl Verdict {
+    pub fn failed(&self) -> impl Iterator<Item = &RuleOutcome> {
+        self.outcomes.iter().filter(|o| !o.passed)
+    }
+}
+
+/// Evaluates the configured rules against one scan state. Addresses are
+/// compared in lowercase.
+#[derive(Debug)]
+pub struct Engine {
+    rules: Vec<RuleConfig>,
+    as_of: DateTime<Utc>,
+    current: HashMap<String, (u64, Option<DateTime<Utc>>)>,
+    labels: HashMap<String, Vec<String>>,
+    /// Addresses of each imported set an `in_set` or `not_in_set` rule refers to
+    sets: HashMap<String, BTreeSet<String>>,
+    /// Attributes of each token each current holder holds, when a `trait` rule needs them
+    token_attributes: HashMap<String, Vec<BTreeMap<String, String>>>,
+    /// Balances of the archived snapshot each `min_balance` rule refers to, by rule index
+    snapshots: HashMap<usize, (SnapshotEntry, HashMap<String, u64>)>,
+    delegation: DelegationPolicy,
+    /// Delegates of each vault, when the policy uses them
+    delegates: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LabelRow {
+    address: String,
+    label: String,
+}
+
+impl Engine {
+    /// Loads the labels file and every address set and archived snapshot the
+    /// rules refer to.
+    pub fn new(config: &EligibilityConfig, state: &ScanState, as_of: DateTime<Utc>) -> Result<Engine> {
+        if config.rules.is_empty() {
+            bail!("no [[eligibility.rule]] entries in {}", config::config_path().display());
+        }
+        let current = state
+            .holders
+            .iter()
+            .map(|h| {
+                let balance = state.balances.get(h).copied().unwrap_or(0);
+                let since = state.acquisitions.get(h).map(|a| a.held_since).or_else(|| state.first_seen.get(h).copied());
+                Ok((address::normalize(h)?, (balance, since)))
+            })
+            .collect::<Result<_>>()?;
+
+        // Labels the `labels` command recorded from the `[labels]` datasets count too
+        let mut labels: HashMap<String, Vec<String>> = state
+            .labels
+            .iter()
+            .map(|(h, l)| Ok((address::normalize(h)?, l.iter().map(|l| l.to_lowercase()).collect())))
+            .collect::<Result<_>>()?;
+        if let Some(path) = &config.labels {
+            let mut reader = csv::Reader::from_path(path).with_context(|| format!("reading {}", path.display()))?;
+            for row in reader.deserialize() {
+                let row: LabelRow = row?;
+                match address::normalize(&row.address) {
+                    Ok(holder) => labels.entry(holder).or_default().push(row.label.to_lowercase()),
+                    Err(e) => warn!(labels = %path.display(), error = %e, "Skipping a label row"),
+                }
+            }
+        } else if state.labels.is_empty() && config.rules.iter().any(|r| matches!(r.rule, Rule::NotLabeled { .. })) {
+            bail!("not_labeled rules need `labels` in [eligibility] or holder labels from the `labels` command");
+        }
+
+        let mut sets = HashMap::new();
+        for rule in &config.rules {
+            if let Rule::InSet { set } | Rule::NotInSet { set } = &rule.rule {
+                if !sets.contains_key(set) {
+                    let addresses = address_set::load_set(set).with_context(|| format!("rule '{}'", rule.name()))?;
+                    sets.insert(set.clone(), addresses);
+                }
+            }
+        }
+
+        let mut token_attributes = HashMap::new();
+        if config.rules.iter().any(|r| matches!(r.rule, Rule::Trait { .. })) {
+            let recorded = state.metadata.as_ref().map(|m| &m.token_attributes).filter(|a| !a.is_empty());
+            let recorded = recorded.context("trait rules need token attributes; run `metadata --token-attributes`")?;
+            for holder in &state.holders {
+                let tokens = state.tokens.get(holder).map(Vec::as_slice).unwrap_or_default();
+                // A token whose document couldn't be fetched has no attributes and matches nothing
+                let attributes = tokens.iter().map(|id| recorded.get(id).cloned().unwrap_or_default()).collect();
+                token_attributes.insert(address::normalize(holder)?, attributes);
+            }
+        }
+
+        let delegates = match (config.delegation, &state.delegations) {
+            (DelegationPolicy::Vault, _) => BTreeMap::new(),
+            (_, Some(delegations)) => delegations.delegates.clone(),
+            (_, None) => bail!("[eligibility] delegation needs the delegations of this state; run `delegations` first"),
+        };
+
+        let mut snapshots = HashMap::new();
+        for (index, rule) in config.rules.iter().enumerate() {
+            let Rule::MinBalance { snapshot, block, at, .. } = &rule.rule else {
+                continue;
+            };
+            match [snapshot.is_some(), block.is_some(), at.is_some()].into_iter().filter(|set| *set).count() {
+                0 => continue,
+                1 => {}
+                _ => bail!("rule '{}': give only one of snapshot, block and at", rule.name()),
+            }
+            let mut archived = archive::load_index(CONTRACT_ADDRESS)?.snapshots;
+            archived.sort_by_key(|s| s.created_at);
+            let entry = match (snapshot, block, at) {
+                (Some(id), _, _) => archived.into_iter().find(|s| &s.id == id),
+                // Only snapshots that recorded their block (`archive rescan`) can be placed by block
+                (None, Some(block), _) => archived
+                    .into_iter()
+                    .filter(|s| s.is_active() && s.block.is_some_and(|b| b <= *block))
+                    .max_by_key(|s| s.block),
+                (None, None, Some(at)) => archived.into_iter().rev().find(|s| s.is_active() && s.created_at <= *at),
+                (None, None, None) => None,
+            }
+            .with_context(|| format!("rule '{}': no matching archived snapshot", rule.name()))?;
+            let archived_state = state::load_state_from(&entry.path.join("state.json"))?;
+            let balances = archived_state
+                .holders
+                .iter()
+                .map(|h| Ok((address::normalize(h)?, archived_state.balances.get(h).copied().unwrap_or(0))))
+                .collect::<Result<_>>()
+                .with_context(|| format!("snapshot {}", entry.id))?;
+            snapshots.insert(index, (entry, balances));
+        }
+
+        Ok(Engine {
+            rules: config.rules.clone(),
+            as_of,
+            current,
+            labels,
+            sets,
+            token_attributes,
+            snapshots,
+            delegation: config.delegation,
+            delegates,
+        })
+    }
+
+    /// Current holders plus holders of every snapshot a rule refers to, so
+    /// addresses that held at the snapshot but exited since are explained too.
+    pub fn candidates(&self) -> BTreeSet<String> {
+        let mut candidates: BTreeSet<String> = self.current.keys().cloned().collect();
+        for (_, balances) in self.snapshots.values() {
+            candidates.extend(balances.keys().cloned());
+        }
+        candidates
+    }
+
+    /// Addresses `verdict` is credited to under the delegation policy, each
+    /// with the vault it is credited through. A vault with several delegates
+    /// stays credited itself under `delegate`, as there is no telling which
+    /// wallet should get it.
+    pub fn credited<'a>(&'a self, verdict: &'a Verdict) -> Vec<(&'a str, Option<&'a str>)> {
+        let vault = verdict.address.as_str();
+        let delegates = self.delegates.get(vault).map(Vec::as_slice).unwrap_or_default();
+        let via_vault = delegates.iter().map(|delegate| (delegate.as_str(), Some(vault)));
+        match (self.delegation, delegates) {
+            (DelegationPolicy::Delegate, [_]) => via_vault.collect(),
+            (DelegationPolicy::Both, _) => std::iter::once((vault, None)).chain(via_vault).collect(),
+            _ => vec![(vault, None)],
+        }
+    }
+
+    /// Fails when `address` isn't a valid address.
+    pub fn evaluate(&self, address: &str) -> Result<Verdict> {
+        let address = address::normalize(address)?;
+        let (balance, held_since) = self.current.get(&address).copied().unwrap_or((0, None));
+        let outcomes: Vec<RuleOutcome> = self
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                let (passed, detail) = match &rule.rule {
+                    Rule::MinBalance { min, .. } => match self.snapshots.get(&index) {
+                        Some((snapshot, balances)) => {
+                            let held = balances.get(&address).copied().unwrap_or(0);
+                            (held >= *min, format!("held {} in snapshot {}, needs {}", held, snapshot.id, min))
+                        }
+                        None => (balance >= *min, format!("holds {}, needs {}", balance, min)),
+                    },
+                    Rule::HeldFor { days } => match held_since {
+                        Some(since) => {
+                            let held = (self.as_of - since).num_days();
+                            let until = Duration::try_days(*days)
+                                .and_then(|days| since.checked_add_signed(days))
+                                .with_context(|| format!("rule '{}': {} days is out of range", rule.name(), days))?;
+                            let passed = until <= self.as_of;
+                            let since = since.format("%Y-%m-%d");
+                            (passed, format!("holding since {} ({} days), needs {}", since, held, days))
+                        }
+                        None => (false, "not a current holder".to_string()),
+                    },
+                    Rule::NotLabeled { labels } => {
+                        let own = self.labels.get(&address).map(Vec::as_slice).unwrap_or_default();
+                        let matched: Vec<&str> = own
+                            .iter()
+                            .filter(|l| labels.iter().any(|x| x.eq_ignore_ascii_case(l)))
+                            .map(String::as_str)
+                            .collect();
+                        if matched.is_empty() {
+                            (true, "no excluded label".to_string())
+                        } else {
+                            (false, format!("labeled {}", matched.join(", ")))
+                        }
+                    }
+                    Rule::InSet { set } | Rule::NotInSet { set } => {
+                        let member = self.sets.get(set).is_some_and(|addresses| addresses.contains(&address));
+                        let detail = if member { format!("in set {}", set) } else { format!("not in set {}", set) };
+                        (member == matches!(rule.rule, Rule::InSet { .. }), detail)
+                    }
+                    Rule::Trait { filter, min_tokens } => {
+                        let tokens = self.token_attributes.get(&address).map(Vec::as_slice).unwrap_or_default();
+                        let matching = tokens.iter().filter(|attributes| filter.matches(attributes)).count();
+                        (matching >= *min_tokens, format!("holds {} matching tokens, needs {}", matching, min_tokens))
+                    }
+                };
+                Ok(RuleOutcome { rule: rule.name(), passed, detail })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Verdict { eligible: outcomes.iter().all(|o| o.passed), address, balance, outcomes })
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct EligibilityOptions {
+    /// Snapshot to evaluate (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Time holding durations are measured to (RFC 3339, defaults to now)
+    #[arg(long)]
+    pub as_of: Option<DateTime<Utc>>,
+    #[arg(long, default_value = "data/eligibility.csv")]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CheckOptions {
+    /// Address to look up
+    pub address: String,
+    /// Snapshot to evaluate (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Time holding durations are measured to (RFC 3339, defaults to now)
+    #[arg(long)]
+    pub as_of: Option<DateTime<Utc>>,
+    /// Print the verdict as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Loads the configured rules and the state they are evaluated against.
+pub fn load_engine(state_path: Option<&PathBuf>, as_of: Option<DateTime<Utc>>) -> Result<Engine> {
+    let config = config::load_config()?;
+    let state = match state_path {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    Engine::new(&config.eligibility, &state, as_of.unwrap_or_else(Utc::now))
+}
+
+pub fn run_eligibility(options: &EligibilityOptions) -> Result<()> {
+    let engine = load_engine(options.state.as_ref(), options.as_of)?;
+    let verdicts: Vec<Verdict> = engine.candidates().iter().map(|a| engine.evaluate(a)).collect::<Result<_>>()?;
+
+    let mut writer = output::csv_writer(&options.output)?;
+    writer.write_record(["address", "eligible", "balance", "failed_rules", "reasons", "vault"])?;
+    let mut ambiguous = 0;
+    for verdict in &verdicts {
+        let delegates = engine.delegates.get(&verdict.address).map_or(0, Vec::len);
+        if engine.delegation == DelegationPolicy::Delegate && delegates > 1 {
+            ambiguous += 1;
+        }
+        for (address, vault) in engine.credited(verdict) {
+            writer.write_record([
+                address.to_string(),
+                verdict.eligible.to_string(),
+                verdict.balance.to_string(),
+                verdict.failed().map(|o| o.rule.as_str()).collect::<Vec<_>>().join("; "),
+                verdict.failed().map(|o| o.detail.as_str()).collect::<Vec<_>>().join("; "),
+                vault.unwrap_or_default().to_string(),
+            ])?;
+        }
+    }
+    output::finish_csv(writer)?;
+    if ambiguous > 0 {
+        warn!(vaults = ambiguous, "Some vaults delegated to several wallets; their rows stay with the vault");
+    }
+
+    let eligible = verdicts.iter().filter(|v| v.eligible).count();
+    let mut failures: BTreeMap<&str, usize> = BTreeMap::new();
+    for outcome in verdicts.iter().flat_map(|v| v.failed()) {
+        *failures.entry(outcome.rule.as_str()).or_default() += 1;
+    }
+    println!("Eligible: {} of {}", eligible, verdicts.len());
+    for (rule, count) in failures {
+        println!("  failed '{}': {}", rule, count);
+    }
+    info!(output = %options.output.display(), "Eligibility report written");
+    Ok(())
+}
+
+/// Looks up one address, for answering "am I eligible?" without handing out the full list.
+pub fn run_check(options: &CheckOptions) -> Result<()> {
+    let address = address::normalize(&options.address)?;
+    let engine = load_engine(options.state.as_ref(), options.as_of)?;
+    let verdict = engine.evaluate(&address)?;
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&verdict)?);
+        return Ok(());
+    }
+    println!("{}: {}", verdict.address, if verdict.eligible { "eligible" } else { "NOT eligible" });
+    println!("balance: {}", verdict.balance);
+    for outcome in &verdict.outcomes {
+        println!("  {} {}: {}", if outcome.passed { "PASS" } else { "FAIL" }, outcome.rule, outcome.detail);
+    }
+    Ok(())
+}
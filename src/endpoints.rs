@@ -0,0 +1,140 @@
+use std::env;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+pub const DEFAULT_ALCHEMY_ENDPOINT: &str = "https://opt-mainnet.g.alchemy.com/nft/v3";
+
+// Weight given to the newest latency sample in the moving average
+const EWMA_ALPHA: f64 = 0.3;
+const FAILURES_BEFORE_BENCH: u32 = 3;
+
+#[derive(Debug)]
+pub struct Endpoint {
+    pub base_url: String,
+    pub ewma_latency_ms: Option<f64>,
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+impl Endpoint {
+    fn new(base_url: &str) -> Self {
+        Endpoint {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            ewma_latency_ms: None,
+            consecutive_failures: 0,
+            unhealthy_until: None,
+        }
+    }
+
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until.is_none_or(|until| now >= until)
+    }
+}
+
+/// Routes requests to the lowest-latency healthy endpoint, probing every endpoint
+/// again each `reevaluate_every` so a recovered or faster region gets picked up.
+#[derive(Debug)]
+pub struct EndpointPool {
+    endpoints: Vec<Endpoint>,
+    reevaluate_every: Duration,
+    cooldown: Duration,
+    next_evaluation: Instant,
+    probe_queue: Vec<usize>,
+}
+
+impl EndpointPool {
+    pub fn new(base_urls: &[String], reevaluate_every: Duration, cooldown: Duration) -> Self {
+        EndpointPool {
+            endpoints: base_urls.iter().map(|url| Endpoint::new(url)).collect(),
+            reevaluate_every,
+            cooldown,
+            next_evaluation: Instant::now(),
+            probe_queue: Vec::new(),
+        }
+    }
+
+    /// Reads the pool from `ALCHEMY_ENDPOINTS` (comma-separated base URLs). When that
+    /// is unset, the single endpoint in `ALCHEMY_API_URL` or the Optimism mainnet
+    /// endpoint is used on its own.
+    pub fn from_env() -> Self {
+        let mut urls: Vec<String> = env::var("ALCHEMY_ENDPOINTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+        if urls.is_empty() {
+            let single = env::var("ALCHEMY_API_URL").map(|url| url.trim().to_string()).unwrap_or_default();
+            urls.push(if single.is_empty() { DEFAULT_ALCHEMY_ENDPOINT.to_string() } else { single });
+        }
+        EndpointPool::with_urls(&urls)
+    }
+
+    /// A pool over `urls` with reevaluation and cooldown times from
+    /// `ENDPOINT_REEVALUATE_SECS` and `ENDPOINT_COOLDOWN_SECS`.
+    pub fn with_urls(urls: &[String]) -> Self {
+        let seconds = |name: &str, default: u64| {
+            Duration::from_secs(env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default))
+        };
+        EndpointPool::new(
+            urls,
+            seconds("ENDPOINT_REEVALUATE_SECS", 300),
+            seconds("ENDPOINT_COOLDOWN_SECS", 60),
+        )
+    }
+
+    pub fn get(&self, index: usize) -> &Endpoint {
+        &self.endpoints[index]
+    }
+
+    pub fn select(&mut self) -> usize {
+        let now = Instant::now();
+        if self.endpoints.len() == 1 {
+            return 0;
+        }
+        if now >= self.next_evaluation {
+            self.next_evaluation = now + self.reevaluate_every;
+            self.probe_queue = (0..self.endpoints.len())
+                .filter(|&i| self.endpoints[i].is_healthy(now))
+                .rev()
+                .collect();
+        }
+        if let Some(index) = self.probe_queue.pop() {
+            return index;
+        }
+
+        let healthy = (0..self.endpoints.len()).filter(|&i| self.endpoints[i].is_healthy(now));
+        // Endpoints without a sample yet sort first so they get measured
+        let fastest = healthy.min_by(|&a, &b| {
+            let latency = |i: usize| self.endpoints[i].ewma_latency_ms.unwrap_or(0.0);
+            latency(a).total_cmp(&latency(b))
+        });
+        // When everything is benched, use whichever endpoint comes back soonest
+        fastest.unwrap_or_else(|| {
+            (0..self.endpoints.len())
+                .min_by_key(|&i| self.endpoints[i].unhealthy_until)
+                .unwrap_or(0)
+        })
+    }
+
+    pub fn record_success(&mut self, index: usize, latency: Duration) {
+        let endpoint = &mut self.endpoints[index];
+        let sample = latency.as_secs_f64() * 1000.0;
+        endpoint.ewma_latency_ms = Some(match endpoint.ewma_latency_ms {
+            Some(previous) => EWMA_ALPHA * sample + (1.0 - EWMA_ALPHA) * previous,
+            None => sample,
+        });
+        endpoint.consecutive_failures = 0;
+        endpoint.unhealthy_until = None;
+    }
+
+    pub fn record_failure(&mut self, index: usize) {
+        let endpoint = &mut self.endpoints[index];
+        endpoint.consecutive_failures += 1;
+        if endpoint.consecutive_failures >= FAILURES_BEFORE_BENCH {
+            warn!(endpoint = %endpoint.base_url, cooldown = ?self.cooldown, "Benching endpoint");
+            endpoint.unhealthy_until = Some(Instant::now() + self.cooldown);
+            endpoint.consecutive_failures = 0;
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use web3::ethabi::{self, ParamType, Token};
+use web3::signing::keccak256;
+use web3::types::Address;
+
+use crate::rpc;
+
+// ENS ReverseRecords helper on mainnet. `getNames` only returns a name when it
+// also resolves forward to the same address, so spoofed reverse records are dropped
+const REVERSE_RECORDS: &str = "3671aE578E63FdF66ad4F3E12CC0c0d71Ac7510C";
+
+// Addresses per `getNames` call
+const BATCH_SIZE: usize = 200;
+
+/// Primary ENS names of the given addresses, keyed by the address as passed in.
+/// Addresses without a verified primary name, or that aren't addresses at all
+/// (cluster ids), are left out.
+pub async fn reverse_names(addresses: &[&str]) -> Result<HashMap<String, String>> {
+    let web3 = rpc::connect_ethereum()?;
+    let contract: Address = REVERSE_RECORDS.parse()?;
+    let selector = &keccak256(b"getNames(address[])")[..4];
+
+    let parsed: Vec<(&str, Address)> = addresses
+        .iter()
+        .filter_map(|a| Some((*a, a.strip_prefix("0x")?.parse::<Address>().ok()?)))
+        .collect();
+    let mut names = HashMap::new();
+    for batch in parsed.chunks(BATCH_SIZE) {
+        let mut data = selector.to_vec();
+        data.extend(ethabi::encode(&[Token::Array(batch.iter().map(|(_, a)| Token::Address(*a)).collect())]));
+        let output = rpc::call(&web3, contract, data).await.context("ENS getNames call failed")?;
+        let decoded = ethabi::decode(&[ParamType::Array(Box::new(ParamType::String))], &output)?;
+        let Some(Token::Array(tokens)) = decoded.into_iter().next() else {
+            anyhow::bail!("unexpected getNames response");
+        };
+        for ((address, _), token) in batch.iter().zip(tokens) {
+            if let Token::String(name) = token {
+                if !name.is_empty() {
+                    names.insert(address.to_string(), name);
+                }
+            }
+        }
+    }
+    Ok(names)
+}
@@ -0,0 +1,146 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+use web3::types::{Address, U256};
+
+use crate::multicall::Multicall;
+use crate::rpc;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, Provenance, ScanState};
+
+// Token ids resolved per round of Multicall3 batches, between progress logs
+const WINDOW: u64 = 10_000;
+
+#[derive(Debug, clap::Args)]
+pub struct EnumerateOptions {
+    /// Block to read owners at (defaults to the latest block); older blocks need an archive node
+    #[arg(long)]
+    pub block: Option<u64>,
+    /// Token ids to query as START:END (inclusive), instead of listing them with
+    /// tokenByIndex; for contracts whose ids are dense or that aren't Enumerable
+    #[arg(long, value_name = "START:END", value_parser = parse_id_range)]
+    pub id_range: Option<(u64, u64)>,
+    /// Where to write the resulting state
+    #[arg(long, default_value = state::STATE_PATH)]
+    pub output: PathBuf,
+}
+
+pub(crate) fn parse_id_range(value: &str) -> Result<(u64, u64), String> {
+    let (start, end) = value.split_once(':').ok_or_else(|| format!("expected START:END, got '{}'", value))?;
+    let start: u64 = start.trim().parse().map_err(|_| format!("invalid start id '{}'", start))?;
+    let end: u64 = end.trim().parse().map_err(|_| format!("invalid end id '{}'", end))?;
+    if end < start {
+        return Err(format!("end id {} is before start id {}", end, start));
+    }
+    // The id count has to fit a u64
+    (end - start).checked_add(1).ok_or_else(|| format!("id range {} is too large", value))?;
+    Ok((start, end))
+}
+
+/// Rebuilds the holder set from the contract itself: lists the token ids
+/// with `tokenByIndex(0..totalSupply)` (or takes `--id-range`) and resolves
+/// each id with `ownerOf`, batched through Multicall3. Slower than the
+/// provider's owners API but needs nothing beyond an RPC endpoint, and the
+/// result can be checked by anyone against the same block.
+pub async fn run_enumerate(options: &EnumerateOptions) -> Result<()> {
+    let previous = state::load_state_or_default()?;
+    let state = enumerate_state(options.block, options.id_range, &previous).await?;
+    if options.output == Path::new(state::STATE_PATH) {
+        state::save_state(&state)?;
+        state::save_holders_to_file(&state)?;
+    } else {
+        state::save_state_to(&state, &options.output)?;
+    }
+    info!(
+        holders = state.total_holders,
+        block = state.last_processed_block,
+        output = %options.output.display(),
+        "Holder set rebuilt from ownerOf"
+    );
+    Ok(())
+}
+
+/// The holder set at `block` (default latest) read through `ownerOf`. First-seen
+/// times, acquisitions, valuation and metadata carry over from `previous`.
+pub async fn enumerate_state(
+    block: Option<u64>,
+    id_range: Option<(u64, u64)>,
+    previous: &ScanState,
+) -> Result<ScanState> {
+    let web3 = rpc::connect()?;
+    let multicall = Multicall::from_env()?;
+    let contract: Address = CONTRACT_ADDRESS.parse().context("invalid contract address")?;
+    let block = match block {
+        Some(block) => block,
+        // Pin every batch to one block so the holder set is consistent
+        None => web3.eth().block_number().await?.as_u64(),
+    };
+
+    let (first, count) = match id_range {
+        Some((start, end)) => {
+            (start, end.checked_sub(start).and_then(|n| n.checked_add(1)).context("id range too large")?)
+        }
+        None => (0, rpc::total_supply_at(&web3, contract, Some(block)).await?.low_u64()),
+    };
+    info!(block, count, by_index = id_range.is_none(), "Enumerating token owners");
+
+    let seen_at = Utc::now();
+    let mut state = ScanState {
+        acquisitions: previous.acquisitions.clone(),
+        valuation: previous.valuation.clone(),
+        metadata: previous.metadata.clone(),
+        delegations: previous.delegations.clone(),
+        labels: previous.labels.clone(),
+        ..ScanState::default()
+    };
+    let (mut missing, mut done) = (0u64, 0u64);
+    while done < count {
+        let size = WINDOW.min(count - done);
+        // Inclusive, so a range ending at u64::MAX doesn't overflow
+        let positions: Vec<U256> = (first + done..=first + done + (size - 1)).map(U256::from).collect();
+        let ids = match id_range {
+            Some(_) => positions,
+            None => {
+                let ids = multicall.tokens_by_index(&web3, contract, &positions, Some(block)).await?;
+                if ids.iter().any(Option::is_none) {
+                    bail!("tokenByIndex failed; the contract may not be ERC-721 Enumerable, use --id-range");
+                }
+                ids.into_iter().flatten().collect()
+            }
+        };
+        let owners = multicall.owners_of(&web3, contract, &ids, Some(block)).await?;
+        for (id, owner) in ids.iter().zip(owners) {
+            // ownerOf reverts for burned and never-minted ids
+            let Some(owner) = owner.filter(|o| !o.is_zero()) else {
+                missing += 1;
+                continue;
+            };
+            let owner = format!("{:?}", owner);
+            *state.balances.entry(owner.clone()).or_insert(0) += 1;
+            state.tokens.entry(owner.clone()).or_default().push(id.to_string());
+            let first_seen = previous.first_seen.get(&owner).copied().unwrap_or(seen_at);
+            state.first_seen.insert(owner.clone(), first_seen);
+            let provenance = Provenance {
+                source: "enumerate".to_string(),
+                endpoint: None,
+                run: None,
+                page: None,
+                block: Some(block),
+            };
+            state.provenance.insert(owner.clone(), provenance);
+            state.holders.insert(owner);
+        }
+        done += size;
+        info!(done, count, holders = state.holders.len(), "Token ids resolved");
+    }
+    if missing > 0 {
+        warn!(ids = missing, "Token ids without an owner (burned or never minted)");
+    }
+
+    state.acquisitions.retain(|holder, _| state.holders.contains(holder));
+    state.total_holders = state.holders.len() as u64;
+    state.last_processed_block = block;
+    state.last_save_time = Utc::now();
+    Ok(state)
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+use thiserror::Error;
+
+/// Errors surfaced by provider interactions. Everything else in the scanner
+/// goes through `anyhow`, which can carry these for callers that downcast.
+#[derive(Debug, Error)]
+pub enum ScannerError {
+    #[error("rate limited by provider (retry after {retry_after:?})")]
+    RateLimited { retry_after: Option<Duration> },
+
+    #[error("provider rejected the API key ({status}): {message}")]
+    Auth { status: u16, message: String },
+
+    #[error("malformed provider response: {reason}")]
+    MalformedResponse { reason: String, body: String },
+
+    #[error("provider error ({status}): {message}")]
+    Provider { status: u16, message: String },
+
+    /// The request URL is stripped: it carries the API key in its path or query.
+    #[error("request failed: {0}")]
+    Http(reqwest::Error),
+
+    #[error("no cached response to {request}, and --offline never calls the provider")]
+    NotCached { request: String },
+}
+
+impl From<reqwest::Error> for ScannerError {
+    fn from(error: reqwest::Error) -> Self {
+        ScannerError::Http(error.without_url())
+    }
+}
+
+impl ScannerError {
+    /// Maps an error status and its (possibly enveloped) body to an error.
+    pub fn from_status(status: reqwest::StatusCode, retry_after: Option<Duration>, body: &str) -> Self {
+        let message = crate::alchemy::error_message(body).unwrap_or_else(|| status.to_string());
+        match status.as_u16() {
+            429 => ScannerError::RateLimited { retry_after },
+            401 | 403 => ScannerError::Auth { status: status.as_u16(), message },
+            code => ScannerError::Provider { status: code, message },
+        }
+    }
+}
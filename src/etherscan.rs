@@ -0,0 +1,338 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::time::Duration;
+use web3::types::U256;
+
+use crate::address;
+use crate::alchemy::{Owner, OwnersResponse, TokenBalance};
+use crate::config::{self, Config};
+use crate::endpoints::EndpointPool;
+use crate::error::ScannerError;
+use crate::holder_updates::checked_amount;
+use crate::keys::KeyPool;
+
+const DEFAULT_URL: &str = "https://api.etherscan.io/v2/api";
+
+// Chain ids for the `chainid` parameter of the multichain API
+const CHAIN_IDS: [(&str, u64); 6] =
+    [("ethereum", 1), ("optimism", 10), ("base", 8453), ("arbitrum", 42161), ("polygon", 137), ("bsc", 56)];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EtherscanMode {
+    /// Replay `tokennfttx`/`token1155tx` transfers; works with a free key
+    #[default]
+    Transfers,
+    /// Page through `tokenholderlist`, which needs a paid plan on most explorers
+    HolderList,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NftStandard {
+    #[default]
+    Erc721,
+    Erc1155,
+}
+
+/// `[etherscan]` in `scanner.toml`, read by `scan --provider etherscan`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EtherscanConfig {
+    /// Explorer API; defaults to the Etherscan multichain API. Per-chain
+    /// explorers such as Basescan take their own `.../api` URL
+    #[serde(default = "default_url")]
+    pub url: String,
+    /// Sent as `chainid`; one of ethereum, optimism, base, arbitrum, polygon, bsc
+    #[serde(default = "default_chain")]
+    pub chain: String,
+    #[serde(default)]
+    pub mode: EtherscanMode,
+    #[serde(default)]
+    pub standard: NftStandard,
+    /// Deployment block of the contract, where transfer replay starts
+    #[serde(default)]
+    pub start_block: u64,
+    /// Records per request; the explorers return at most 10,000
+    #[serde(default = "default_page_size")]
+    pub page_size: usize,
+}
+
+fn default_url() -> String {
+    DEFAULT_URL.to_string()
+}
+
+fn default_chain() -> String {
+    "optimism".to_string()
+}
+
+fn default_page_size() -> usize {
+    10_000
+}
+
+/// `{"status": "1", "message": "OK", "result": [...]}`. Errors come back with
+/// HTTP 200, status `0` and the reason in `result`.
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    status: String,
+    message: String,
+    result: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Transfer {
+    #[serde(deserialize_with = "number")]
+    block_number: u64,
+    from: String,
+    to: String,
+    #[serde(rename = "tokenID")]
+    token_id: String,
+    /// Only in ERC-1155 transfers
+    token_value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Holder {
+    token_holder_address: String,
+    token_holder_quantity: String,
+}
+
+fn number<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+}
+
+/// Reads holders from an Etherscan-family explorer API.
+///
+/// In `transfers` mode every transfer since `start_block` is replayed. Pages
+/// are block windows: a full page is applied up to, but not including, its
+/// last block, and the next page starts at that block, so no transfer is
+/// applied twice or skipped. Each page reports the owners it touched with
+/// their balances so far; owners left with nothing have an empty token list.
+pub struct EtherscanSource {
+    config: EtherscanConfig,
+    contract: String,
+    chain_id: u64,
+    /// owner -> token id -> amount, for the current pass
+    owned: HashMap<String, BTreeMap<String, u64>>,
+    /// Highest block applied in this pass
+    pub block: Option<u64>,
+    cursor: Option<u64>,
+}
+
+impl EtherscanSource {
+    pub fn new(config: EtherscanConfig, contract: &str) -> Result<EtherscanSource> {
+        let chain = config.chain.to_lowercase();
+        let Some((_, chain_id)) = CHAIN_IDS.iter().find(|(name, _)| *name == chain) else {
+            bail!("no chain id known for '{}'; use one of ethereum, optimism, base, arbitrum, polygon, bsc", chain);
+        };
+        Ok(EtherscanSource {
+            chain_id: *chain_id,
+            config,
+            contract: contract.to_string(),
+            owned: HashMap::new(),
+            block: None,
+            cursor: None,
+        })
+    }
+
+    pub fn endpoints(&self) -> EndpointPool {
+        EndpointPool::with_urls(std::slice::from_ref(&self.config.url))
+    }
+
+    /// Keys from `ETHERSCAN_API_KEYS` or `ETHERSCAN_API_KEY`.
+    pub fn keys() -> Result<KeyPool> {
+        let raw = env::var("ETHERSCAN_API_KEYS")
+            .or_else(|_| env::var("ETHERSCAN_API_KEY"))
+            .context("ETHERSCAN_API_KEY or ETHERSCAN_API_KEYS must be set for --provider etherscan")?;
+        let keys: Vec<String> =
+            raw.split(',').map(|key| key.trim().to_string()).filter(|key| !key.is_empty()).collect();
+        let cooldown = env::var("API_KEY_COOLDOWN_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(120);
+        KeyPool::new(keys, Duration::from_secs(cooldown))
+    }
+
+    /// Query string for the page at `cursor` (a start block, or a page number
+    /// in `holder_list` mode); `None` starts a new pass.
+    pub fn query(&mut self, cursor: Option<&str>) -> Result<String, ScannerError> {
+        let cursor = match cursor {
+            Some(cursor) => Some(cursor.parse().map_err(|_| ScannerError::Provider {
+                status: 400,
+                message: format!("invalid Etherscan page key {}", cursor),
+            })?),
+            None => {
+                self.owned.clear();
+                self.block = None;
+                None
+            }
+        };
+        self.cursor = cursor;
+        let common =
+            format!("chainid={}&contractaddress={}&offset={}", self.chain_id, self.contract, self.config.page_size);
+        Ok(match self.config.mode {
+            EtherscanMode::Transfers => {
+                let action = match self.config.standard {
+                    NftStandard::Erc721 => "tokennfttx",
+                    NftStandard::Erc1155 => "token1155tx",
+                };
+                let start = cursor.unwrap_or(self.config.start_block);
+                format!("module=account&action={}&{}&startblock={}&page=1&sort=asc", action, common, start)
+            }
+            EtherscanMode::HolderList => {
+                format!("module=token&action=tokenholderlist&{}&page={}", common, cursor.unwrap_or(1))
+            }
+        })
+    }
+
+    /// The page key after `cursor` in `holder_list` mode, whose pages are
+    /// numbered; transfer windows start where the previous response ended.
+    pub fn page_after(&self, cursor: Option<&str>) -> Option<String> {
+        match self.config.mode {
+            EtherscanMode::HolderList => {
+                let page: u64 = cursor.map_or(Some(1), |cursor| cursor.parse().ok())?;
+                Some((page + 1).to_string())
+            }
+            EtherscanMode::Transfers => None,
+        }
+    }
+
+    pub fn parse_page(&mut self, status: reqwest::StatusCode, body: &str) -> Result<OwnersResponse, ScannerError> {
+        if !status.is_success() {
+            return Err(ScannerError::from_status(status, None, body));
+        }
+        let malformed = |reason: String| ScannerError::MalformedResponse { reason, body: body.to_string() };
+        let response: ApiResponse = serde_json::from_str(body).map_err(|e| malformed(e.to_string()))?;
+        let rows = match response.result {
+            Value::Array(rows) => rows,
+            Value::String(reason) if response.status == "0" => return Err(api_error(&response.message, &reason)),
+            _ => return Err(malformed("result is not a list".to_string())),
+        };
+        let full = rows.len() >= self.config.page_size;
+        match self.config.mode {
+            EtherscanMode::Transfers => self.apply_transfers(rows, full, &malformed),
+            EtherscanMode::HolderList => {
+                let mut owners = Vec::with_capacity(rows.len());
+                for row in rows {
+                    let holder: Holder = serde_json::from_value(row).map_err(|e| malformed(e.to_string()))?;
+                    let quantity = U256::from_dec_str(&holder.token_holder_quantity).map_err(|_| {
+                        malformed(format!("quantity {} is not a whole number", holder.token_holder_quantity))
+                    })?;
+                    let balance = checked_amount(quantity)
+                        .ok_or_else(|| malformed(format!("quantity {} exceeds u64", quantity)))?;
+                    let owner_address =
+                        address::normalize(&holder.token_holder_address).map_err(|e| malformed(format!("{:#}", e)))?;
+                    owners.push(Owner {
+                        owner_address,
+                        // The holder list has totals only, no token ids
+                        token_balances: vec![TokenBalance { token_id: String::new(), balance }],
+                    });
+                }
+                let next = full.then(|| (self.cursor.unwrap_or(1) + 1).to_string());
+                Ok(OwnersResponse { owners, page_key: next, total_count: None })
+            }
+        }
+    }
+
+    fn apply_transfers(
+        &mut self,
+        rows: Vec<Value>,
+        full: bool,
+        malformed: &dyn Fn(String) -> ScannerError,
+    ) -> Result<OwnersResponse, ScannerError> {
+        let transfers = rows
+            .into_iter()
+            .map(serde_json::from_value::<Transfer>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| malformed(e.to_string()))?;
+        let (first, last) = match (transfers.first(), transfers.last()) {
+            (Some(first), Some(last)) => (first.block_number, last.block_number),
+            _ => return Ok(OwnersResponse { owners: Vec::new(), page_key: None, total_count: None }),
+        };
+        // A full page may end partway through its last block, which the next page starts over
+        let until = match full {
+            true if first == last => {
+                return Err(malformed(format!("more than {} transfers in block {}", self.config.page_size, last)));
+            }
+            true => last,
+            false => u64::MAX,
+        };
+
+        // Addresses and amounts are checked before any transfer is applied, so a bad page can be retried
+        let parsed = transfers
+            .iter()
+            .map(|transfer| {
+                let amount = match &transfer.token_value {
+                    Some(value) => {
+                        let value = U256::from_dec_str(value)
+                            .map_err(|_| malformed(format!("token value {} is not a number", value)))?;
+                        checked_amount(value).ok_or_else(|| malformed(format!("token value {} exceeds u64", value)))?
+                    }
+                    None => 1,
+                };
+                let from = address::normalize(&transfer.from).map_err(|e| malformed(format!("{:#}", e)))?;
+                let to = address::normalize(&transfer.to).map_err(|e| malformed(format!("{:#}", e)))?;
+                Ok((from, to, amount))
+            })
+            .collect::<Result<Vec<(String, String, u64)>, ScannerError>>()?;
+        let mut touched = BTreeSet::new();
+        for (transfer, (from, to, amount)) in transfers.iter().zip(parsed).filter(|(t, _)| t.block_number < until) {
+            if !is_zero_address(&from) {
+                let tokens = self.owned.entry(from.clone()).or_default();
+                if let Some(held) = tokens.get_mut(&transfer.token_id) {
+                    *held = held.saturating_sub(amount);
+                    if *held == 0 {
+                        tokens.remove(&transfer.token_id);
+                    }
+                }
+                touched.insert(from);
+            }
+            if !is_zero_address(&to) {
+                let held = self.owned.entry(to.clone()).or_default().entry(transfer.token_id.clone()).or_default();
+                *held = held.saturating_add(amount);
+                touched.insert(to);
+            }
+            self.block = Some(transfer.block_number);
+        }
+
+        let owners = touched
+            .into_iter()
+            .map(|owner| {
+                let token_balances = self.owned[&owner]
+                    .iter()
+                    .map(|(token_id, balance)| TokenBalance { token_id: token_id.clone(), balance: *balance })
+                    .collect();
+                Owner { owner_address: owner, token_balances }
+            })
+            .collect();
+        Ok(OwnersResponse { owners, page_key: full.then(|| last.to_string()), total_count: None })
+    }
+}
+
+fn is_zero_address(address: &str) -> bool {
+    address.trim_start_matches("0x").chars().all(|c| c == '0')
+}
+
+/// Explorer errors arrive as HTTP 200; map the ones the fetcher would have
+/// recognised by status.
+fn api_error(message: &str, reason: &str) -> ScannerError {
+    let lower = reason.to_lowercase();
+    if lower.contains("rate limit") {
+        ScannerError::RateLimited { retry_after: None }
+    } else if lower.contains("api key") || lower.contains("apikey") {
+        ScannerError::Auth { status: 401, message: reason.to_string() }
+    } else {
+        ScannerError::Provider { status: 400, message: format!("{}: {}", message, reason) }
+    }
+}
+
+/// The `[etherscan]` section, required by `--provider etherscan`.
+pub fn from_config(config: &Config, contract: &str) -> Result<EtherscanSource> {
+    let etherscan = config
+        .etherscan
+        .clone()
+        .with_context(|| format!("--provider etherscan needs [etherscan] in {}", config::config_path().display()))?;
+    EtherscanSource::new(etherscan, contract)
+}
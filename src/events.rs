@@ -0,0 +1,66 @@
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use chrono::Utc;
+use std::net::SocketAddr;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info};
+
+use crate::diff::{HolderChange, HolderEvent};
+
+// Events buffered per subscriber before a slow client starts missing them
+const CHANNEL_CAPACITY: usize = 10_000;
+
+pub type EventSender = broadcast::Sender<HolderEvent>;
+
+/// Starts a WebSocket endpoint at `ws://<listen>/events` in the background and
+/// returns the sender that watch cycles publish holder changes to.
+pub async fn start(listen: SocketAddr) -> Result<EventSender> {
+    let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+    let app = Router::new().route("/events", get(subscribe)).with_state(sender.clone());
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    info!(%listen, "Pushing holder changes at ws://{}/events", listen);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            error!(error = %e, "Event server stopped");
+        }
+    });
+    Ok(sender)
+}
+
+/// Sends one event per holder change. Nothing is buffered when no client is
+/// connected.
+pub fn publish(sender: &EventSender, cycle: u64, changes: &[HolderChange]) {
+    if sender.receiver_count() == 0 {
+        return;
+    }
+    let at = Utc::now();
+    for change in changes {
+        // Only fails when every subscriber disconnected in the meantime
+        let _ = sender.send(HolderEvent::new(cycle, at, change));
+    }
+    debug!(cycle, events = changes.len(), subscribers = sender.receiver_count(), "Published holder changes");
+}
+
+async fn subscribe(ws: WebSocketUpgrade, State(sender): State<EventSender>) -> Response {
+    ws.on_upgrade(move |socket| push_events(socket, sender.subscribe()))
+}
+
+async fn push_events(mut socket: WebSocket, mut events: broadcast::Receiver<HolderEvent>) {
+    loop {
+        let text = match events.recv().await {
+            Ok(event) => serde_json::to_string(&event),
+            // Tell the client it missed events so it can resync from /stats or a diff
+            Err(RecvError::Lagged(skipped)) => serde_json::to_string(&serde_json::json!({ "kind": "lagged", "skipped": skipped })),
+            Err(RecvError::Closed) => break,
+        };
+        let Ok(text) = text else { continue };
+        if socket.send(Message::Text(text)).await.is_err() {
+            // Client went away
+            break;
+        }
+    }
+}
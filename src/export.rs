@@ -0,0 +1,400 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use tracing::info;
+
+use crate::ens;
+use crate::config;
+use crate::identity::HolderIdentity;
+use crate::labels::{self, EXCLUDED_HOLDERS_PATH};
+use crate::leaderboard::{self, SortBy};
+use crate::output::{self, ExportWriter};
+use crate::pricing;
+use crate::state::{self, Provenance, ScanState, HOLDERS_PATH};
+use crate::token_owners::{self, TOKEN_OWNERS_CSV, TOKEN_OWNERS_JSON};
+use crate::xlsx;
+
+#[derive(Debug, clap::Args)]
+pub struct ExportOptions {
+    /// Snapshot to export (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Write a leaderboard of the N largest holders instead of the full holder list
+    #[arg(long, value_name = "N")]
+    pub top: Option<usize>,
+    /// Leaderboard order
+    #[arg(long, value_enum, default_value = "balance", requires = "top")]
+    pub sort_by: SortBy,
+    /// Look up primary ENS names for the leaderboard (needs ETHEREUM_RPC_URL)
+    #[arg(long, requires = "top")]
+    pub ens: bool,
+    /// Leave out holders whose balance is worth less than this many USD (needs `price`)
+    #[arg(long)]
+    pub min_usd: Option<f64>,
+    /// Leave out holders whose labels match `[labels] exclude` (needs `labels`); they are listed with the
+    /// matching label in data/excluded_holders.csv
+    #[arg(long)]
+    pub exclude_labeled: bool,
+    /// Leaderboard CSV
+    #[arg(long, default_value = "data/leaderboard.csv", requires = "top")]
+    pub output: PathBuf,
+    /// Write an Excel workbook with holders, top holders, statistics and run sheets
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        default_missing_value = "data/uniswap_v3_holders.xlsx",
+        conflicts_with = "top"
+    )]
+    pub xlsx: Option<PathBuf>,
+    /// Rows in the workbook's top-holders sheet
+    #[arg(long, value_name = "N", default_value_t = 100, requires = "xlsx")]
+    pub xlsx_top: usize,
+    /// Write the token id → current owner map of an ERC-721 snapshot to data/token_owners.csv and .json
+    #[arg(long, conflicts_with_all = ["top", "xlsx"])]
+    pub token_owners: bool,
+    /// Add the block each token moved to its owner in, replayed from Transfer logs (needs OPTIMISM_RPC_URL)
+    #[arg(long, requires = "token_owners")]
+    pub acquisition_blocks: bool,
+    /// First block to replay for --acquisition-blocks; use the contract's deployment block
+    #[arg(long, default_value_t = 0, requires = "acquisition_blocks")]
+    pub from_block: u64,
+    /// Blocks per eth_getLogs request for --acquisition-blocks
+    #[arg(long, default_value_t = 10_000, requires = "acquisition_blocks")]
+    pub chunk_size: u64,
+}
+
+/// One holder as handed to an export format.
+#[derive(Debug, Clone, Copy)]
+pub struct HolderRow<'a> {
+    pub address: &'a str,
+    pub balance: u64,
+    pub first_seen: Option<DateTime<Utc>>,
+    /// Replayed from Transfer logs by the `acquisition` command
+    pub first_acquired: Option<DateTime<Utc>>,
+    /// Start of the current unbroken holding
+    pub held_since: Option<DateTime<Utc>>,
+    /// Balance valued at the price recorded by the `price` command
+    pub usd_value: Option<f64>,
+    /// Provider or strategy that reported the holder
+    pub provenance: Option<&'a Provenance>,
+    /// From the `[labels]` datasets, recorded by the `labels` command
+    pub labels: &'a [String],
+}
+
+impl HolderRow<'_> {
+    pub fn holding_days(&self) -> Option<i64> {
+        self.held_since.map(|since| (Utc::now() - since).num_days())
+    }
+
+    /// The record written by the `json` and `jsonl` formats.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "address": self.address,
+            "balance": self.balance,
+            "first_seen": self.first_seen,
+            "first_acquired": self.first_acquired,
+            "held_since": self.held_since,
+            "holding_days": self.holding_days(),
+            "usd_value": self.usd_value,
+            "provenance": self.provenance,
+            "labels": self.labels,
+        })
+    }
+}
+
+/// A holder-list file format. Formats are looked up by name in the registry,
+/// so a crate built on this library can add its own with [`register_format`]
+/// without touching the export code.
+pub trait ExportFormat: Send + Sync {
+    /// Name used in `EXPORT_FORMATS`
+    fn name(&self) -> &str;
+    /// File extension without the dot; the holder list is written to
+    /// `data/uniswap_v3_holders.<extension>`
+    fn extension(&self) -> &str;
+    /// Writes every row, in address order, to `out`.
+    fn write(&self, rows: &mut dyn Iterator<Item = HolderRow<'_>>, out: &mut dyn Write) -> Result<()>;
+}
+
+struct TextFormat;
+
+impl ExportFormat for TextFormat {
+    fn name(&self) -> &str {
+        "txt"
+    }
+
+    fn extension(&self) -> &str {
+        "txt"
+    }
+
+    fn write(&self, rows: &mut dyn Iterator<Item = HolderRow<'_>>, out: &mut dyn Write) -> Result<()> {
+        for row in rows {
+            writeln!(out, "{}", row.address)?;
+        }
+        Ok(())
+    }
+}
+
+struct CsvFormat;
+
+impl ExportFormat for CsvFormat {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    fn extension(&self) -> &str {
+        "csv"
+    }
+
+    fn write(&self, rows: &mut dyn Iterator<Item = HolderRow<'_>>, out: &mut dyn Write) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(out);
+        writer.write_record([
+            "address",
+            "balance",
+            "first_seen",
+            "first_acquired",
+            "held_since",
+            "holding_days",
+            "usd_value",
+            "source",
+            "source_run",
+            "source_page",
+            "source_block",
+            "labels",
+        ])?;
+        for row in rows {
+            let provenance = row.provenance;
+            let time = |t: Option<DateTime<Utc>>| t.map(|t| t.to_rfc3339()).unwrap_or_default();
+            writer.write_record([
+                row.address.to_string(),
+                row.balance.to_string(),
+                time(row.first_seen),
+                time(row.first_acquired),
+                time(row.held_since),
+                row.holding_days().map(|d| d.to_string()).unwrap_or_default(),
+                row.usd_value.map(|v| format!("{:.2}", v)).unwrap_or_default(),
+                provenance.map(|p| p.source.clone()).unwrap_or_default(),
+                provenance.and_then(|p| p.run.clone()).unwrap_or_default(),
+                provenance.and_then(|p| p.page).map(|p| p.to_string()).unwrap_or_default(),
+                provenance.and_then(|p| p.block).map(|b| b.to_string()).unwrap_or_default(),
+                row.labels.join("; "),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+struct JsonFormat;
+
+impl ExportFormat for JsonFormat {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn extension(&self) -> &str {
+        "json"
+    }
+
+    fn write(&self, rows: &mut dyn Iterator<Item = HolderRow<'_>>, out: &mut dyn Write) -> Result<()> {
+        // Streamed element by element so large holder sets are never held as one JSON value
+        write!(out, "[")?;
+        for (i, row) in rows.enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            write!(out, "\n  {}", row.to_json())?;
+        }
+        writeln!(out, "\n]")?;
+        Ok(())
+    }
+}
+
+/// One holder per line, for jq, BigQuery loads and stream processors.
+struct JsonlFormat;
+
+impl ExportFormat for JsonlFormat {
+    fn name(&self) -> &str {
+        "jsonl"
+    }
+
+    fn extension(&self) -> &str {
+        "jsonl"
+    }
+
+    fn write(&self, rows: &mut dyn Iterator<Item = HolderRow<'_>>, out: &mut dyn Write) -> Result<()> {
+        for row in rows {
+            writeln!(out, "{}", row.to_json())?;
+        }
+        Ok(())
+    }
+}
+
+fn registry() -> &'static RwLock<Vec<Arc<dyn ExportFormat>>> {
+    static REGISTRY: OnceLock<RwLock<Vec<Arc<dyn ExportFormat>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        RwLock::new(vec![Arc::new(TextFormat), Arc::new(CsvFormat), Arc::new(JsonFormat), Arc::new(JsonlFormat)])
+    })
+}
+
+/// Adds a format to the registry, replacing any existing format of the same name.
+pub fn register_format(format: impl ExportFormat + 'static) {
+    let mut formats = registry().write().unwrap_or_else(|e| e.into_inner());
+    formats.retain(|existing| existing.name() != format.name());
+    formats.push(Arc::new(format));
+}
+
+pub fn format(name: &str) -> Option<Arc<dyn ExportFormat>> {
+    let formats = registry().read().unwrap_or_else(|e| e.into_inner());
+    formats.iter().find(|f| f.name().eq_ignore_ascii_case(name)).cloned()
+}
+
+pub fn format_names() -> Vec<String> {
+    let formats = registry().read().unwrap_or_else(|e| e.into_inner());
+    formats.iter().map(|f| f.name().to_string()).collect()
+}
+
+/// Formats named in `EXPORT_FORMATS` (comma-separated, default `txt`).
+pub fn configured_formats() -> Result<Vec<Arc<dyn ExportFormat>>> {
+    let names = env::var("EXPORT_FORMATS").unwrap_or_else(|_| "txt".to_string());
+    let mut formats = Vec::new();
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        match format(name) {
+            Some(format) => formats.push(format),
+            None => bail!("unknown export format '{}' (available: {})", name, format_names().join(", ")),
+        }
+    }
+    Ok(formats)
+}
+
+/// Holder records appended as each page of a scan arrives, one JSON object
+/// per line, so consumers can start before the scan finishes. A holder can
+/// appear twice when a scan resumes or restarts its pagination; the `jsonl`
+/// export written at the end is the deduplicated list.
+pub struct JsonlStream {
+    out: Box<dyn Write + Send>,
+}
+
+impl JsonlStream {
+    /// Opens `path`, or stdout for `-`. A resumed scan appends to the file.
+    pub fn open(path: &Path, append: bool) -> Result<JsonlStream> {
+        if path == Path::new("-") {
+            return Ok(JsonlStream { out: Box::new(std::io::stdout()) });
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::OpenOptions::new().create(true).write(true).append(append).truncate(!append).open(path)?;
+        Ok(JsonlStream { out: Box::new(std::io::BufWriter::new(file)) })
+    }
+
+    /// Writes one page of holders and flushes, so readers see whole pages.
+    pub fn write_page<'a>(&mut self, rows: impl IntoIterator<Item = HolderRow<'a>>) -> Result<()> {
+        for row in rows {
+            writeln!(self.out, "{}", row.to_json())?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Path the holder list is written to in `format`, with the configured compression suffix.
+pub fn holders_path(format: &dyn ExportFormat) -> Result<String> {
+    let path = Path::new(HOLDERS_PATH).with_extension(format.extension());
+    output::export_path(&path.to_string_lossy())
+}
+
+/// Contract metadata written next to the holder list, so the exports say
+/// which token they list.
+pub fn metadata_path() -> String {
+    let path = Path::new(HOLDERS_PATH).with_extension("meta.json");
+    path.to_string_lossy().into_owned()
+}
+
+/// Writes the holder list in every configured format.
+pub fn export_holders(state: &ScanState) -> Result<()> {
+    let mut holders: Vec<&String> = state.holders.iter().collect();
+    holders.sort(); // Sort addresses for consistent output
+    for format in configured_formats()? {
+        let mut file = ExportWriter::create(holders_path(format.as_ref())?)?;
+        let mut rows = holders.iter().map(|address| HolderRow {
+            address: address.as_str(),
+            balance: state.balances.get(*address).copied().unwrap_or(0),
+            first_seen: state.first_seen.get(*address).copied(),
+            first_acquired: state.acquisitions.get(*address).map(|a| a.first_acquired),
+            held_since: state.acquisitions.get(*address).map(|a| a.held_since),
+            usd_value: state.usd_value(address),
+            provenance: state.provenance.get(*address),
+            labels: state.labels.get(*address).map(Vec::as_slice).unwrap_or_default(),
+        });
+        format.write(&mut rows, &mut file)?;
+        file.finish()?;
+    }
+    if let Some(metadata) = &state.metadata {
+        std::fs::write(metadata_path(), serde_json::to_string_pretty(metadata)?)?;
+    }
+    Ok(())
+}
+
+/// Rewrites the holder list of a saved snapshot in the configured formats, or
+/// with `--top` writes a ranked leaderboard.
+pub async fn run_export(options: &ExportOptions) -> Result<()> {
+    let mut state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    if let Some(min_usd) = options.min_usd {
+        pricing::require_valuation(&state)?;
+        let below: Vec<String> =
+            state.holders.iter().filter(|h| state.usd_value(h).unwrap_or(0.0) < min_usd).cloned().collect();
+        for holder in below {
+            state.holders.remove(&holder);
+            state.balances.remove(&holder);
+        }
+        state.total_holders = state.holders.len() as u64;
+    }
+    if options.exclude_labeled {
+        let excluded = labels::exclude_labeled(&mut state, &config::load_config()?, Path::new(EXCLUDED_HOLDERS_PATH))?;
+        info!(excluded, report = EXCLUDED_HOLDERS_PATH, "Left out labeled exchange wallets");
+    }
+    if options.token_owners {
+        let owners = token_owners::token_owners(&state);
+        if owners.is_empty() {
+            bail!("the snapshot records no token ids; token owners need an ERC-721 scan with token balances");
+        }
+        let blocks = if options.acquisition_blocks {
+            Some(token_owners::acquisition_blocks(&owners, options.from_block, options.chunk_size).await?)
+        } else {
+            None
+        };
+        let (csv, json) = (Path::new(TOKEN_OWNERS_CSV), Path::new(TOKEN_OWNERS_JSON));
+        token_owners::save_token_owners(&owners, blocks.as_ref(), csv, json)?;
+        info!(tokens = owners.len(), csv = TOKEN_OWNERS_CSV, json = TOKEN_OWNERS_JSON, "Token owners written");
+        return Ok(());
+    }
+    if let Some(path) = &options.xlsx {
+        xlsx::write_workbook(&state, options.xlsx_top, path)?;
+        info!(holders = state.holders.len(), output = %path.display(), "Workbook written");
+        return Ok(());
+    }
+    let Some(top) = options.top else {
+        export_holders(&state)?;
+        info!(holders = state.holders.len(), "Holder list exported");
+        return Ok(());
+    };
+
+    let identity = HolderIdentity::from_env()?;
+    let mut rows = leaderboard::leaderboard(&identity.regroup(&state), top, options.sort_by);
+    if options.ens {
+        let addresses: Vec<&str> = rows.iter().map(|r| r.address.as_str()).collect();
+        let names = ens::reverse_names(&addresses).await?;
+        leaderboard::attach_ens_names(&mut rows, &names);
+    }
+    leaderboard::save_leaderboard(&rows, &options.output)?;
+    leaderboard::print_leaderboard(&rows);
+    info!(rows = rows.len(), output = %options.output.display(), "Leaderboard written");
+    Ok(())
+}
@@ -0,0 +1,221 @@
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::endpoints::EndpointPool;
+use crate::error::ScannerError;
+use crate::http_cache::HttpCache;
+use crate::keys::KeyPool;
+use crate::metrics::{ErrorKind, ProviderMetrics, RequestSample};
+use crate::ratelimit::{self, RateLimiter};
+
+#[derive(Debug)]
+pub struct FetchedPage {
+    pub status: reqwest::StatusCode,
+    pub body: String,
+    /// Attempts it took, including the successful one
+    pub attempts: u32,
+    pub latency_ms: u64,
+    pub endpoint: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Request<'a> {
+    /// Path after the endpoint base URL and API key
+    Get(&'a str),
+    /// JSON body posted to the endpoint URL, with the API key as a bearer token
+    Post(&'a serde_json::Value),
+    /// Query string for the endpoint URL, with the API key as `apikey`
+    Query(&'a str),
+}
+
+impl Request<'_> {
+    /// What the request asks for, without endpoint or API key
+    fn describe(&self) -> String {
+        match self {
+            Request::Get(path) => format!("GET {}", path),
+            Request::Post(body) => format!("POST {}", body),
+            Request::Query(query) => format!("QUERY {}", query),
+        }
+    }
+}
+
+/// Issues provider requests with rate limiting, retries, key rotation,
+/// endpoint routing and metrics collection.
+pub struct Fetcher {
+    client: reqwest::Client,
+    pub endpoints: EndpointPool,
+    keys: KeyPool,
+    pub metrics: ProviderMetrics,
+    limiter: RateLimiter,
+    max_retries: u32,
+    /// Block requests are pinned to, for cost accounting and the cache
+    pub block: Option<u64>,
+    pub cache: Option<HttpCache>,
+    /// Skip cached answers, such as a bad page being retried, but still cache what comes back
+    pub refresh: bool,
+}
+
+impl Fetcher {
+    pub fn new(
+        client: reqwest::Client,
+        endpoints: EndpointPool,
+        keys: KeyPool,
+        metrics: ProviderMetrics,
+        limiter: RateLimiter,
+        max_retries: u32,
+    ) -> Self {
+        Fetcher { client, endpoints, keys, metrics, limiter, max_retries, block: None, cache: None, refresh: false }
+    }
+
+    /// Fetches `path` (everything after the endpoint base URL and API key, e.g.
+    /// `/getOwnersForContract?...`). Non-retryable error statuses are returned
+    /// as a page so the caller can turn the body into a `ScannerError`.
+    #[tracing::instrument(skip(self, path))]
+    pub async fn fetch(&mut self, path: &str, page: u64) -> Result<FetchedPage, ScannerError> {
+        self.send(Request::Get(path), page).await
+    }
+
+    /// Posts a GraphQL query to the endpoint URL itself. Keys, when the pool
+    /// has non-empty ones, go in an `Authorization: Bearer` header.
+    #[tracing::instrument(skip(self, body))]
+    pub async fn post(&mut self, body: &serde_json::Value, page: u64) -> Result<FetchedPage, ScannerError> {
+        self.send(Request::Post(body), page).await
+    }
+
+    /// Fetches `<endpoint>?<query>&apikey=<key>`, the explorer APIs' style.
+    #[tracing::instrument(skip(self, query))]
+    pub async fn query(&mut self, query: &str, page: u64) -> Result<FetchedPage, ScannerError> {
+        self.send(Request::Query(query), page).await
+    }
+
+    async fn send(&mut self, request: Request<'_>, page: u64) -> Result<FetchedPage, ScannerError> {
+        let described = self.cache.as_ref().map(|_| request.describe());
+        if let (Some(cache), Some(described)) = (self.cache.as_mut(), &described) {
+            // Offline there is nothing but the cache to answer from
+            let cached = match self.refresh && !cache.offline {
+                true => None,
+                false => cache.get(described, self.block),
+            };
+            if let Some(cached) = cached {
+                debug!(page, "Answered from the HTTP cache");
+                return Ok(cached);
+            }
+            if cache.offline {
+                return Err(ScannerError::NotCached { request: described.clone() });
+            }
+        }
+        let fetched = self.send_uncached(request, page).await?;
+        if let (Some(cache), Some(described)) = (&self.cache, &described) {
+            cache.put(described, self.block, &fetched);
+        }
+        Ok(fetched)
+    }
+
+    async fn send_uncached(&mut self, request: Request<'_>, page: u64) -> Result<FetchedPage, ScannerError> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let method = match request {
+                Request::Get(path) => ratelimit::method_from_path(path),
+                Request::Post(_) => "graphql",
+                Request::Query(_) => "query",
+            };
+            self.limiter.acquire(method).await;
+            let key = self.keys.next_key().await;
+            let endpoint = self.endpoints.select();
+            let base_url = self.endpoints.get(endpoint).base_url.clone();
+            let builder = match request {
+                Request::Get(path) => self.client.get(format!("{}/{}{}", base_url, self.keys.key(key), path)),
+                Request::Post(body) => match self.keys.key(key) {
+                    "" => self.client.post(&base_url).json(body),
+                    api_key => self.client.post(&base_url).bearer_auth(api_key).json(body),
+                },
+                Request::Query(query) => {
+                    self.client.get(format!("{}?{}&apikey={}", base_url, query, self.keys.key(key)))
+                }
+            };
+            let started = Instant::now();
+
+            let mut sample = RequestSample {
+                page,
+                attempt,
+                endpoint: base_url.clone(),
+                key,
+                latency_ms: 0,
+                bytes: 0,
+                status: None,
+                error: None,
+                method: method.to_string(),
+                archive: self.block.is_some(),
+            };
+
+            let result = builder.header("accept", "application/json").send().await;
+            let error = match result {
+                Ok(response) => {
+                    let status = response.status();
+                    debug!(%status, "Response received");
+                    sample.status = Some(status.as_u16());
+                    self.limiter.observe(method, status, response.headers());
+                    let retry_after = response
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse().ok())
+                        .map(Duration::from_secs);
+                    // A throttled or rejected key is worth retrying when another key can take over
+                    let rotated = self.keys.record_status(key, status) && self.keys.count() > 1;
+                    match response.text().await {
+                        Ok(body) => {
+                            sample.latency_ms = started.elapsed().as_millis() as u64;
+                            sample.bytes = body.len() as u64;
+                            let kind = ErrorKind::from_status(status);
+                            sample.error = kind;
+                            self.metrics.record(sample);
+                            match kind {
+                                Some(kind) if kind.is_retryable() || rotated => {
+                                    self.endpoints.record_failure(endpoint);
+                                    ScannerError::from_status(status, retry_after, &body)
+                                }
+                                _ => {
+                                    self.endpoints.record_success(endpoint, started.elapsed());
+                                    return Ok(FetchedPage {
+                                        status,
+                                        body,
+                                        attempts: attempt,
+                                        latency_ms: started.elapsed().as_millis() as u64,
+                                        endpoint: base_url,
+                                    });
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            sample.latency_ms = started.elapsed().as_millis() as u64;
+                            sample.error = Some(ErrorKind::from_reqwest(&e));
+                            self.metrics.record(sample);
+                            self.endpoints.record_failure(endpoint);
+                            e.into()
+                        }
+                    }
+                }
+                Err(e) => {
+                    sample.latency_ms = started.elapsed().as_millis() as u64;
+                    sample.error = Some(ErrorKind::from_reqwest(&e));
+                    self.metrics.record(sample);
+                    self.endpoints.record_failure(endpoint);
+                    e.into()
+                }
+            };
+
+            if attempt > self.max_retries {
+                return Err(error);
+            }
+            // Honour the provider's Retry-After when it asks for longer than our backoff
+            let mut backoff = Duration::from_secs(1 << attempt.min(5));
+            if let ScannerError::RateLimited { retry_after: Some(retry_after) } = &error {
+                backoff = backoff.max(*retry_after);
+            }
+            warn!(attempt, error = %error, ?backoff, "Request failed, retrying");
+            tokio::time::sleep(backoff).await;
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use tracing::info;
+use web3::transports::Http;
+use web3::{Transport, Web3};
+
+use crate::address;
+
+pub const FUNDING_CACHE_PATH: &str = "data/funding_cache.json";
+
+/// `[funding]` in `scanner.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FundingConfig {
+    /// Exchange hot wallets. They fund many unrelated users, so sharing one
+    /// says nothing about who controls an address.
+    #[serde(default)]
+    pub exchanges: Vec<String>,
+}
+
+impl FundingConfig {
+    /// The configured exchanges plus `extra`, normalized. Fails on an entry
+    /// that isn't a valid address.
+    pub fn exchanges_with(&self, extra: &[String]) -> Result<HashSet<String>> {
+        self.exchanges.iter().chain(extra).map(|a| address::normalize(a).context("exchange address")).collect()
+    }
+}
+
+/// The first native-token transfer an address received: usually the wallet
+/// or exchange that created it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Funding {
+    /// Lowercase address of the sender
+    pub funder: String,
+    pub block: u64,
+    pub time: Option<DateTime<Utc>>,
+    pub tx_hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransfersResult {
+    transfers: Vec<Transfer>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Transfer {
+    from: String,
+    block_num: String,
+    hash: String,
+    #[serde(default)]
+    metadata: Option<TransferMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransferMetadata {
+    block_timestamp: Option<DateTime<Utc>>,
+}
+
+/// Looks up first fundings through `alchemy_getAssetTransfers`, which
+/// `OPTIMISM_RPC_URL` must support (an Alchemy node URL does). Answers,
+/// including "never funded", are cached in `data/funding_cache.json`, since a
+/// first funding never changes.
+pub struct FundingLookup {
+    web3: Web3<Http>,
+    cache: HashMap<String, Option<Funding>>,
+}
+
+impl FundingLookup {
+    pub fn open(web3: Web3<Http>) -> FundingLookup {
+        let cache = match File::open(FUNDING_CACHE_PATH) {
+            Ok(file) => serde_json::from_reader(file).unwrap_or_default(),
+            Err(_) => HashMap::new(),
+        };
+        FundingLookup { web3, cache }
+    }
+
+    /// First funding of each address, `None` for addresses that never
+    /// received a plain transfer (contracts, or wallets funded through one).
+    pub async fn first_fundings(&mut self, addresses: &[String]) -> Result<HashMap<String, Option<Funding>>> {
+        let mut looked_up = 0;
+        for address in addresses {
+            if self.cache.contains_key(address) {
+                continue;
+            }
+            let funding = self.first_funding(address).await?;
+            self.cache.insert(address.clone(), funding);
+            looked_up += 1;
+            // Save now and then so an interrupted run keeps what it fetched
+            if looked_up % 500 == 0 {
+                self.save()?;
+                info!(looked_up, "Looked up first fundings");
+            }
+        }
+        self.save()?;
+        Ok(addresses.iter().map(|a| (a.clone(), self.cache.get(a).cloned().flatten())).collect())
+    }
+
+    async fn first_funding(&self, address: &str) -> Result<Option<Funding>> {
+        let params = serde_json::json!({
+            "fromBlock": "0x0",
+            "toBlock": "latest",
+            "toAddress": address,
+            "category": ["external"],
+            "order": "asc",
+            "maxCount": "0x1",
+            "excludeZeroValue": true,
+            "withMetadata": true,
+        });
+        let value = self
+            .web3
+            .transport()
+            .execute("alchemy_getAssetTransfers", vec![params])
+            .await
+            .context("alchemy_getAssetTransfers failed; OPTIMISM_RPC_URL must be an Alchemy endpoint")?;
+        let result: TransfersResult = serde_json::from_value(value)?;
+        let Some(transfer) = result.transfers.into_iter().next() else {
+            return Ok(None);
+        };
+        let Some(block) = transfer.block_num.strip_prefix("0x").and_then(|b| u64::from_str_radix(b, 16).ok()) else {
+            bail!("unexpected block number {} for a transfer to {}", transfer.block_num, address);
+        };
+        Ok(Some(Funding {
+            funder: address::normalize(&transfer.from)
+                .with_context(|| format!("funder of {} from alchemy_getAssetTransfers", address))?,
+            block,
+            time: transfer.metadata.and_then(|m| m.block_timestamp),
+            tx_hash: transfer.hash,
+        }))
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::create_dir_all("data")?;
+        serde_json::to_writer(File::create(FUNDING_CACHE_PATH)?, &self.cache)?;
+        Ok(())
+    }
+}
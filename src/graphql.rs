@@ -0,0 +1,215 @@
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Enum, Object, Result, Schema, SimpleObject};
+use axum::response::{Html, IntoResponse};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+use crate::address;
+use crate::archive;
+use crate::diff::{self, ChangeKind};
+use crate::identity::HolderIdentity;
+use crate::live::SnapshotStore;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::server::{read_state, snapshot_state_path};
+use crate::state::ScanState;
+
+pub type HolderSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+// Largest page any list field returns
+const MAX_LIMIT: usize = 10_000;
+
+pub fn schema(store: Arc<SnapshotStore>) -> HolderSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription).data(store).finish()
+}
+
+pub async fn graphiql() -> impl IntoResponse {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+/// Loads an archived snapshot by id, or returns the active snapshot when `snapshot` is `None`.
+async fn load(ctx: &Context<'_>, snapshot: Option<&str>) -> Result<Arc<ScanState>> {
+    let Some(id) = snapshot else {
+        return Ok(ctx.data::<Arc<SnapshotStore>>()?.current().state.clone());
+    };
+    let path = snapshot_state_path(id)?.ok_or_else(|| format!("unknown snapshot id {}", id))?;
+    Ok(Arc::new(read_state(path).await?))
+}
+
+#[derive(SimpleObject)]
+pub struct Holder {
+    address: String,
+    balance: u64,
+    first_seen: Option<DateTime<Utc>>,
+    token_ids: Vec<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct HolderPage {
+    /// Holders matching the filter, before pagination
+    total: usize,
+    items: Vec<Holder>,
+}
+
+#[derive(SimpleObject)]
+pub struct Snapshot {
+    id: String,
+    created_at: DateTime<Utc>,
+    total_holders: u64,
+}
+
+#[derive(SimpleObject)]
+pub struct BalancePoint {
+    snapshot_id: String,
+    created_at: DateTime<Utc>,
+    balance: u64,
+}
+
+#[derive(Enum, Copy, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    BalanceChanged,
+}
+
+impl From<ChangeKind> for Change {
+    fn from(kind: ChangeKind) -> Self {
+        match kind {
+            ChangeKind::Added => Change::Added,
+            ChangeKind::Removed => Change::Removed,
+            ChangeKind::BalanceChanged => Change::BalanceChanged,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct HolderChange {
+    address: String,
+    change: Change,
+    old_balance: u64,
+    new_balance: u64,
+    delta: i64,
+}
+
+#[derive(SimpleObject)]
+pub struct DiffPage {
+    added: usize,
+    removed: usize,
+    balance_changed: usize,
+    /// Changes matching the filter, before pagination
+    total: usize,
+    items: Vec<HolderChange>,
+}
+
+fn holder(state: &ScanState, address: &str) -> Holder {
+    Holder {
+        address: address.to_string(),
+        balance: state.balances.get(address).copied().unwrap_or(0),
+        first_seen: state.first_seen.get(address).copied(),
+        token_ids: state.tokens.get(address).cloned().unwrap_or_default(),
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Holders of the active snapshot or an archived one, sorted by address.
+    #[allow(clippy::too_many_arguments)]
+    async fn holders(
+        &self,
+        ctx: &Context<'_>,
+        snapshot: Option<String>,
+        min_balance: Option<u64>,
+        max_balance: Option<u64>,
+        address_prefix: Option<String>,
+        #[graphql(default = 0)] offset: usize,
+        #[graphql(default = 100)] limit: usize,
+    ) -> Result<HolderPage> {
+        let state = load(ctx, snapshot.as_deref()).await?;
+        let prefix = address_prefix.map(|p| p.to_lowercase());
+        let mut matching: Vec<&String> = state
+            .holders
+            .iter()
+            .filter(|h| {
+                let balance = state.balances.get(*h).copied().unwrap_or(0);
+                min_balance.map_or(true, |min| balance >= min)
+                    && max_balance.map_or(true, |max| balance <= max)
+                    && prefix.as_ref().map_or(true, |p| h.to_lowercase().starts_with(p))
+            })
+            .collect();
+        matching.sort();
+        Ok(HolderPage {
+            total: matching.len(),
+            items: matching.iter().skip(offset).take(limit.min(MAX_LIMIT)).map(|h| holder(&state, h)).collect(),
+        })
+    }
+
+    /// One holder by address (case-insensitive), or null if it holds nothing.
+    async fn holder(&self, ctx: &Context<'_>, address: String, snapshot: Option<String>) -> Result<Option<Holder>> {
+        let address = address::normalize(&address)?;
+        let state = load(ctx, snapshot.as_deref()).await?;
+        Ok(state.holders.get(&address).map(|h| holder(&state, h)))
+    }
+
+    /// An address's balance in every archived snapshot, oldest first.
+    async fn balance_history(&self, address: String) -> Result<Vec<BalancePoint>> {
+        let address = address::normalize(&address)?;
+        let snapshots = archive::active_snapshots(CONTRACT_ADDRESS)?;
+        let mut points = Vec::with_capacity(snapshots.len());
+        for snapshot in snapshots {
+            let state = read_state(snapshot.path.join("state.json")).await?;
+            let balance = state.balances.get(&address).copied().unwrap_or(0);
+            points.push(BalancePoint { snapshot_id: snapshot.id, created_at: snapshot.created_at, balance });
+        }
+        Ok(points)
+    }
+
+    /// Archived snapshots, newest first.
+    async fn snapshots(&self) -> Result<Vec<Snapshot>> {
+        let snapshots = archive::active_snapshots(CONTRACT_ADDRESS)?;
+        Ok(snapshots
+            .into_iter()
+            .rev()
+            .map(|s| Snapshot { id: s.id, created_at: s.created_at, total_holders: s.total_holders })
+            .collect())
+    }
+
+    /// Changes between two snapshots; `to` defaults to the active snapshot.
+    async fn diff(
+        &self,
+        ctx: &Context<'_>,
+        from: String,
+        to: Option<String>,
+        change: Option<Change>,
+        #[graphql(default = 0)] offset: usize,
+        #[graphql(default = 100)] limit: usize,
+    ) -> Result<DiffPage> {
+        let old = load(ctx, Some(&from)).await?;
+        let new = load(ctx, to.as_deref()).await?;
+        let identity = HolderIdentity::from_env()?;
+        let changes = diff::diff_states(&identity.regroup(&old), &identity.regroup(&new));
+        let count = |kind: ChangeKind| changes.iter().filter(|c| c.kind == kind).count();
+        let matching: Vec<&diff::HolderChange> = changes
+            .iter()
+            .filter(|c| change.map_or(true, |wanted| Change::from(c.kind) == wanted))
+            .collect();
+        Ok(DiffPage {
+            added: count(ChangeKind::Added),
+            removed: count(ChangeKind::Removed),
+            balance_changed: count(ChangeKind::BalanceChanged),
+            total: matching.len(),
+            items: matching
+                .iter()
+                .skip(offset)
+                .take(limit.min(MAX_LIMIT))
+                .map(|c| HolderChange {
+                    address: c.address.clone(),
+                    change: c.kind.into(),
+                    old_balance: c.old_balance,
+                    new_balance: c.new_balance,
+                    delta: c.delta() as i64,
+                })
+                .collect(),
+        })
+    }
+}
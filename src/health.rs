@@ -0,0 +1,259 @@
+use anyhow::Result;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+use crate::heartbeat::HEARTBEAT_PATH;
+use crate::live::SnapshotStore;
+use crate::rpc;
+
+// Provider checks are reused for this long, so tight probe intervals don't spend RPC calls
+const PROVIDER_CHECK_TTL: Duration = Duration::from_secs(15);
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub type HealthState = Arc<Health>;
+
+/// What `/healthz` and `/readyz` report on: the watch loop's cycles, or the
+/// snapshot `serve` answers from.
+pub struct Health {
+    mode: &'static str,
+    started_at: DateTime<Utc>,
+    /// Watch mode: no successful cycle for this long makes the scanner not ready
+    stale_after: Option<Duration>,
+    /// Watch mode: a running scan with no page for this long makes the scanner not live
+    stall_after: Option<Duration>,
+    cycles: Mutex<Cycles>,
+    store: Option<Arc<SnapshotStore>>,
+    provider: tokio::sync::Mutex<Option<(Instant, ProviderCheck)>>,
+}
+
+#[derive(Debug, Default)]
+struct Cycles {
+    running_since: Option<DateTime<Utc>>,
+    last_success: Option<DateTime<Utc>>,
+    last_failure: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ProviderCheck {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    checked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+struct Liveness {
+    status: &'static str,
+    mode: &'static str,
+    started_at: DateTime<Utc>,
+    uptime_secs: i64,
+    cycle_running_since: Option<DateTime<Utc>>,
+    last_progress_at: Option<DateTime<Utc>>,
+    last_success: Option<DateTime<Utc>>,
+    last_failure: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Readiness {
+    status: &'static str,
+    mode: &'static str,
+    last_success: Option<DateTime<Utc>>,
+    provider: ProviderCheck,
+    /// Why the scanner isn't ready; empty when it is
+    reasons: Vec<String>,
+}
+
+// The part of data/heartbeat.json liveness needs
+#[derive(Debug, Deserialize)]
+struct HeartbeatFile {
+    status: String,
+    last_progress_at: DateTime<Utc>,
+}
+
+impl Health {
+    /// Health of a `watch` loop. It is ready once a cycle has succeeded
+    /// within `stale_after` and the provider answers, and stops being live
+    /// when a scan makes no progress for `stall_after`.
+    pub fn watch(stale_after: Duration, stall_after: Duration) -> HealthState {
+        Arc::new(Health {
+            mode: "watch",
+            started_at: Utc::now(),
+            stale_after: Some(stale_after),
+            stall_after: Some(stall_after),
+            cycles: Mutex::new(Cycles::default()),
+            store: None,
+            provider: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    /// Health of `serve`. It is live while it answers and ready once it has a
+    /// finished snapshot to serve; the provider is reported but not required.
+    pub fn serve(store: Arc<SnapshotStore>) -> HealthState {
+        Arc::new(Health {
+            mode: "serve",
+            started_at: Utc::now(),
+            stale_after: None,
+            stall_after: None,
+            cycles: Mutex::new(Cycles::default()),
+            store: Some(store),
+            provider: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    pub fn cycle_started(&self) {
+        self.cycles.lock().unwrap_or_else(|e| e.into_inner()).running_since = Some(Utc::now());
+    }
+
+    pub fn cycle_succeeded(&self) {
+        let mut cycles = self.cycles.lock().unwrap_or_else(|e| e.into_inner());
+        cycles.running_since = None;
+        cycles.last_success = Some(Utc::now());
+    }
+
+    pub fn cycle_failed(&self, error: &anyhow::Error) {
+        let mut cycles = self.cycles.lock().unwrap_or_else(|e| e.into_inner());
+        cycles.running_since = None;
+        cycles.last_failure = Some(Utc::now());
+        cycles.last_error = Some(format!("{:#}", error));
+    }
+
+    /// When the holder data was last complete: the last successful cycle, or
+    /// the save time of the served snapshot if its pass has finished.
+    fn last_success(&self) -> Option<DateTime<Utc>> {
+        match &self.store {
+            Some(store) => {
+                let state = store.current().state.clone();
+                (state.complete() && !state.holders.is_empty()).then_some(state.last_save_time)
+            }
+            None => self.cycles.lock().unwrap_or_else(|e| e.into_inner()).last_success,
+        }
+    }
+
+    /// `eth_blockNumber` against `OPTIMISM_RPC_URL`, reusing a recent answer.
+    async fn provider(&self) -> ProviderCheck {
+        let mut cached = self.provider.lock().await;
+        if let Some((at, check)) = cached.as_ref() {
+            if at.elapsed() < PROVIDER_CHECK_TTL {
+                return check.clone();
+            }
+        }
+        let started = Instant::now();
+        let result = |status: &'static str, block: Option<u64>, error: Option<String>| ProviderCheck {
+            status,
+            block,
+            latency_ms: block.map(|_| started.elapsed().as_millis() as u64),
+            error,
+            checked_at: Utc::now(),
+        };
+        let check = if std::env::var("OPTIMISM_RPC_URL").map_or(true, |url| url.is_empty()) {
+            result("not_configured", None, None)
+        } else {
+            let probe = async { anyhow::Ok(rpc::connect()?.eth().block_number().await?.as_u64()) };
+            match tokio::time::timeout(PROVIDER_TIMEOUT, probe).await {
+                Ok(Ok(block)) => result("ok", Some(block), None),
+                Ok(Err(e)) => result("error", None, Some(format!("{:#}", e))),
+                Err(_) => result("error", None, Some(format!("no answer within {:?}", PROVIDER_TIMEOUT))),
+            }
+        };
+        *cached = Some((Instant::now(), check.clone()));
+        check
+    }
+}
+
+/// `/healthz` and `/readyz` routes, to merge into a server's router.
+pub fn router(health: HealthState) -> Router {
+    Router::new().route("/healthz", get(healthz)).route("/readyz", get(readyz)).with_state(health)
+}
+
+/// Serves `/healthz` and `/readyz` at `listen` in the background.
+pub async fn start(listen: SocketAddr, health: HealthState) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    info!(%listen, "Serving health checks at http://{}/healthz and /readyz", listen);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router(health)).await {
+            error!(error = %e, "Health check server stopped");
+        }
+    });
+    Ok(())
+}
+
+/// Liveness: 503 when a running scan has completed no page within the stall
+/// window, so the orchestrator restarts a wedged scanner.
+async fn healthz(State(health): State<HealthState>) -> Response {
+    let now = Utc::now();
+    let (running_since, last_failure, last_error) = {
+        let cycles = health.cycles.lock().unwrap_or_else(|e| e.into_inner());
+        (cycles.running_since, cycles.last_failure, cycles.last_error.clone())
+    };
+    let last_success = health.last_success();
+    // The heartbeat file is only current while this process has a scan running
+    let heartbeat = match running_since {
+        Some(since) => std::fs::read(HEARTBEAT_PATH)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<HeartbeatFile>(&bytes).ok())
+            .filter(|heartbeat| heartbeat.status == "running" && heartbeat.last_progress_at >= since),
+        None => None,
+    };
+    let last_progress_at = heartbeat.map(|heartbeat| heartbeat.last_progress_at).or(running_since);
+    let stalled = match (health.stall_after, last_progress_at) {
+        (Some(stall_after), Some(progress)) => (now - progress).to_std().unwrap_or_default() > stall_after,
+        _ => false,
+    };
+    let body = Liveness {
+        status: if stalled { "stalled" } else { "ok" },
+        mode: health.mode,
+        started_at: health.started_at,
+        uptime_secs: (now - health.started_at).num_seconds(),
+        cycle_running_since: running_since,
+        last_progress_at: running_since.and(last_progress_at),
+        last_success,
+        last_failure,
+        last_error,
+    };
+    let status = if stalled { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+    (status, Json(body)).into_response()
+}
+
+/// Readiness: 503 until there is complete holder data that isn't stale and,
+/// in watch mode, the provider answers.
+async fn readyz(State(health): State<HealthState>) -> Response {
+    let last_success = health.last_success();
+    let provider = health.provider().await;
+    let mut reasons = Vec::new();
+    match (last_success, health.stale_after) {
+        (None, _) if health.store.is_some() => reasons.push("no finished snapshot to serve".to_string()),
+        (None, _) => reasons.push("no scan has succeeded yet".to_string()),
+        (Some(at), Some(stale_after)) if (Utc::now() - at).to_std().unwrap_or_default() > stale_after => {
+            reasons.push(format!("no scan has succeeded within {:?}", stale_after));
+        }
+        _ => {}
+    }
+    if health.store.is_none() && provider.status == "error" {
+        reasons.push(format!("provider unreachable: {}", provider.error.as_deref().unwrap_or_default()));
+    }
+    let ready = reasons.is_empty();
+    let body = Readiness {
+        status: if ready { "ready" } else { "not_ready" },
+        mode: health.mode,
+        last_success,
+        provider,
+        reasons,
+    };
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(body)).into_response()
+}
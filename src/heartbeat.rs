@@ -0,0 +1,82 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs::File;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+pub const HEARTBEAT_PATH: &str = "data/heartbeat.json";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Heartbeat {
+    pub pid: u32,
+    pub status: &'static str,
+    pub updated_at: DateTime<Utc>,
+    pub last_progress_at: DateTime<Utc>,
+    pub page: u64,
+    pub holders: u64,
+}
+
+fn write(heartbeat: &Heartbeat) -> Result<()> {
+    std::fs::create_dir_all("data")?;
+    // Write then rename so readers never see a half-written file
+    let tmp = format!("{}.tmp", HEARTBEAT_PATH);
+    serde_json::to_writer_pretty(File::create(&tmp)?, heartbeat)?;
+    std::fs::rename(&tmp, HEARTBEAT_PATH)?;
+    Ok(())
+}
+
+/// Rewrites the heartbeat file every `interval` from a background task, so
+/// external monitors can tell a slow scan from a dead one.
+pub struct HeartbeatWriter {
+    shared: Arc<Mutex<Heartbeat>>,
+    task: JoinHandle<()>,
+}
+
+impl HeartbeatWriter {
+    pub fn start(interval: Duration, holders: u64) -> Self {
+        let now = Utc::now();
+        let shared = Arc::new(Mutex::new(Heartbeat {
+            pid: std::process::id(),
+            status: "running",
+            updated_at: now,
+            last_progress_at: now,
+            page: 0,
+            holders,
+        }));
+        let task_shared = Arc::clone(&shared);
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let snapshot = {
+                    let mut heartbeat = task_shared.lock().unwrap();
+                    heartbeat.updated_at = Utc::now();
+                    heartbeat.clone()
+                };
+                if let Err(e) = write(&snapshot) {
+                    warn!(error = %e, "Failed to write heartbeat");
+                }
+            }
+        });
+        HeartbeatWriter { shared, task }
+    }
+
+    pub fn progress(&self, page: u64, holders: u64) {
+        let mut heartbeat = self.shared.lock().unwrap();
+        heartbeat.page = page;
+        heartbeat.holders = holders;
+        heartbeat.last_progress_at = Utc::now();
+    }
+
+    /// Stops the background writer and records the final status.
+    pub fn stop(self, status: &'static str) -> Result<()> {
+        self.task.abort();
+        let mut heartbeat = self.shared.lock().unwrap().clone();
+        heartbeat.status = status;
+        heartbeat.updated_at = Utc::now();
+        write(&heartbeat)
+    }
+}
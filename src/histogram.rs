@@ -0,0 +1,163 @@
+use anyhow::Result;
+#[cfg(feature = "charts")]
+use plotters::coord::Shift;
+#[cfg(feature = "charts")]
+use plotters::prelude::*;
+use std::env;
+#[cfg(feature = "charts")]
+use std::path::Path;
+
+use crate::output;
+use crate::state::ScanState;
+
+pub const HISTOGRAM_TABLE_PATH: &str = "data/balance_histogram.csv";
+
+#[derive(Debug, serde::Serialize)]
+pub struct HistogramBucket {
+    pub lower: u64,
+    pub upper: u64,
+    pub holders: u64,
+    pub supply: u64,
+}
+
+impl HistogramBucket {
+    pub fn label(&self) -> String {
+        if self.upper - self.lower <= 1 {
+            self.lower.to_string()
+        } else {
+            format!("{}-{}", self.lower, self.upper - 1)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HistogramConfig {
+    pub buckets_per_decade: u32,
+    pub sig_figs: u32,
+    pub chart_path: String,
+}
+
+impl HistogramConfig {
+    pub fn from_env() -> Self {
+        HistogramConfig {
+            buckets_per_decade: env_or("HISTOGRAM_BUCKETS_PER_DECADE", 3).max(1),
+            sig_figs: env_or("HISTOGRAM_SIG_FIGS", 1).max(1),
+            chart_path: env::var("HISTOGRAM_CHART_PATH")
+                .unwrap_or_else(|_| "data/balance_histogram.svg".to_string()),
+        }
+    }
+}
+
+fn env_or(name: &str, default: u32) -> u32 {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+fn round_sig_figs(value: f64, sig_figs: u32) -> u64 {
+    if value <= 0.0 {
+        return 0;
+    }
+    let magnitude = value.log10().floor() as i32;
+    let scale = 10f64.powi(magnitude - sig_figs as i32 + 1);
+    ((value / scale).round() * scale) as u64
+}
+
+// Bucket edges on a log scale, rounded to the configured number of significant
+// figures (3 per decade at 1 sig fig gives 1, 2, 5, 10, 20, 50, ...). Edges
+// saturate at u64::MAX, which ends the list
+fn log_boundaries(max: u64, config: &HistogramConfig) -> Vec<u64> {
+    let mut bounds = vec![0, 1];
+    let mut step = 1;
+    while *bounds.last().unwrap() <= max && *bounds.last().unwrap() < u64::MAX {
+        let exponent = step as f64 / config.buckets_per_decade as f64;
+        let next = round_sig_figs(10f64.powf(exponent), config.sig_figs);
+        step += 1;
+        // Rounding can collapse neighbouring edges onto the same value
+        if next > *bounds.last().unwrap() {
+            bounds.push(next);
+        }
+    }
+    bounds
+}
+
+pub fn balance_histogram(state: &ScanState, config: &HistogramConfig) -> Vec<HistogramBucket> {
+    let balances: Vec<u64> = state
+        .holders
+        .iter()
+        .map(|h| state.balances.get(h).copied().unwrap_or(0))
+        .collect();
+    let max = balances.iter().copied().max().unwrap_or(0);
+
+    let mut buckets: Vec<HistogramBucket> = log_boundaries(max, config)
+        .windows(2)
+        .map(|w| HistogramBucket { lower: w[0], upper: w[1], holders: 0, supply: 0 })
+        .collect();
+
+    for balance in balances {
+        // A balance of u64::MAX has no edge above it and goes in the last bucket
+        let index = buckets.partition_point(|b| b.upper <= balance).min(buckets.len() - 1);
+        let bucket = &mut buckets[index];
+        bucket.holders += 1;
+        bucket.supply = bucket.supply.saturating_add(balance);
+    }
+    buckets
+}
+
+pub fn save_histogram_table(buckets: &[HistogramBucket]) -> Result<()> {
+    let mut writer = output::csv_writer(output::export_path(HISTOGRAM_TABLE_PATH)?)?;
+    writer.write_record(["bucket", "lower", "upper_exclusive", "holders", "supply"])?;
+    for bucket in buckets {
+        writer.write_record([
+            bucket.label(),
+            bucket.lower.to_string(),
+            bucket.upper.to_string(),
+            bucket.holders.to_string(),
+            bucket.supply.to_string(),
+        ])?;
+    }
+    output::finish_csv(writer)
+}
+
+#[cfg(feature = "charts")]
+pub fn save_histogram_chart(buckets: &[HistogramBucket], path: &str) -> Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let is_png = path.to_lowercase().ends_with(".png");
+    if is_png {
+        draw_histogram(BitMapBackend::new(path, (1024, 600)).into_drawing_area(), buckets)
+    } else {
+        draw_histogram(SVGBackend::new(path, (1024, 600)).into_drawing_area(), buckets)
+    }
+}
+
+#[cfg(feature = "charts")]
+fn draw_histogram<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, buckets: &[HistogramBucket]) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let max_holders = buckets.iter().map(|b| b.holders).max().unwrap_or(0).max(1);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Holder balance distribution", ("sans-serif", 24))
+        .margin(16)
+        .x_label_area_size(48)
+        .y_label_area_size(64)
+        .build_cartesian_2d(0..buckets.len(), 0..max_holders + max_holders / 10)?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(buckets.len())
+        .x_label_formatter(&|i| buckets.get(*i).map(|b| b.label()).unwrap_or_default())
+        .x_desc("Tokens held")
+        .y_desc("Holders")
+        .draw()?;
+
+    chart.draw_series(buckets.iter().enumerate().map(|(i, bucket)| {
+        Rectangle::new([(i, 0), (i + 1, bucket.holders)], BLUE.mix(0.6).filled())
+    }))?;
+
+    root.present()?;
+    Ok(())
+}
@@ -0,0 +1,396 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::output::ExportWriter;
+
+pub const HOLDER_RUNS_DIR: &str = "data/holder_runs";
+#[cfg(feature = "sled")]
+pub const HOLDER_KV_DIR: &str = "data/holder_kv";
+
+const PENDING_LOG: &str = "pending.log";
+
+#[derive(Debug, Clone, Copy)]
+pub struct HolderRecord {
+    pub balance: u64,
+    pub first_seen: i64,
+}
+
+impl HolderRecord {
+    /// Later observations win the balance; the earliest first-seen time is kept.
+    pub(crate) fn merge(self, newer: HolderRecord) -> HolderRecord {
+        HolderRecord { balance: newer.balance, first_seen: self.first_seen.min(newer.first_seen) }
+    }
+}
+
+/// Disk-backed holder set for scans too large to keep in memory, in sorted
+/// runs (the default) or, with the `sled` feature and `HOLDER_STORE=sled`, in
+/// an embedded key-value store.
+#[derive(Debug)]
+pub enum HolderStore {
+    Runs(RunStore),
+    #[cfg(feature = "sled")]
+    Kv(crate::kv_store::KvStore),
+}
+
+impl HolderStore {
+    /// Opens the store `HOLDER_STORE` selects: `runs` in `data/holder_runs/`
+    /// or `sled` in `data/holder_kv/`.
+    pub fn from_env() -> Result<Self> {
+        match env::var("HOLDER_STORE").unwrap_or_default().trim() {
+            "" | "runs" => Ok(HolderStore::Runs(RunStore::open(Path::new(HOLDER_RUNS_DIR))?)),
+            #[cfg(feature = "sled")]
+            "sled" => Ok(HolderStore::Kv(crate::kv_store::KvStore::open(Path::new(HOLDER_KV_DIR))?)),
+            other => bail!("HOLDER_STORE={} isn't supported by this build (runs, or sled with feature `sled`)", other),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        match self {
+            HolderStore::Runs(store) => &store.dir,
+            #[cfg(feature = "sled")]
+            HolderStore::Kv(store) => store.dir(),
+        }
+    }
+
+    /// Opens an empty store next to this one, for a pass that must not touch it.
+    pub fn shadow(&self) -> Result<HolderStore> {
+        Ok(match self {
+            HolderStore::Runs(store) => HolderStore::Runs(store.shadow()?),
+            #[cfg(feature = "sled")]
+            HolderStore::Kv(store) => HolderStore::Kv(store.shadow()?),
+        })
+    }
+
+    /// Replaces `target`'s contents with this store's and returns the store at its location.
+    pub fn replace(self, target: HolderStore) -> Result<HolderStore> {
+        let target_dir = target.dir().to_path_buf();
+        target.discard()?;
+        Ok(match self {
+            HolderStore::Runs(store) => HolderStore::Runs(store.move_to(&target_dir)?),
+            #[cfg(feature = "sled")]
+            HolderStore::Kv(store) => HolderStore::Kv(store.move_to(&target_dir)?),
+        })
+    }
+
+    /// Deletes everything in the store.
+    pub fn discard(self) -> Result<()> {
+        let dir = self.dir().to_path_buf();
+        drop(self);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        match self {
+            HolderStore::Runs(store) => store.clear(),
+            #[cfg(feature = "sled")]
+            HolderStore::Kv(store) => store.clear(),
+        }
+    }
+
+    pub fn insert(&mut self, address: &str, balance: u64, seen_at: DateTime<Utc>) -> Result<()> {
+        match self {
+            HolderStore::Runs(store) => store.insert(address, balance, seen_at),
+            #[cfg(feature = "sled")]
+            HolderStore::Kv(store) => store.insert(address, balance, seen_at),
+        }
+    }
+
+    /// Makes every inserted holder durable. Call before checkpointing the page
+    /// key so a resumed scan never skips holders from an unsaved page.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        match self {
+            HolderStore::Runs(store) => store.checkpoint(),
+            #[cfg(feature = "sled")]
+            HolderStore::Kv(store) => store.checkpoint(),
+        }
+    }
+
+    pub fn holder_count(&self) -> u64 {
+        match self {
+            HolderStore::Runs(store) => store.holder_count(),
+            #[cfg(feature = "sled")]
+            HolderStore::Kv(store) => store.holder_count(),
+        }
+    }
+
+    /// Writes the sorted address list to `path`. Returns the number of unique holders.
+    pub fn export(&mut self, path: &str) -> Result<u64> {
+        match self {
+            HolderStore::Runs(store) => store.export(path),
+            #[cfg(feature = "sled")]
+            HolderStore::Kv(store) => store.export(path),
+        }
+    }
+}
+
+/// Holders in sorted run files.
+///
+/// Each page is appended to `pending.log` and buffered in memory; once the
+/// buffer reaches `run_size` it is written out as a sorted, deduplicated run
+/// file and the log is truncated. When more than `max_runs` runs pile up they
+/// are merged into one, so memory stays bounded by the run size and the holder
+/// list is only rewritten during compaction.
+#[derive(Debug)]
+pub struct RunStore {
+    dir: PathBuf,
+    pending: BTreeMap<String, HolderRecord>,
+    log: BufWriter<File>,
+    runs: Vec<PathBuf>,
+    next_run: u32,
+    run_size: usize,
+    max_runs: usize,
+    compacted_holders: u64,
+    spilled_holders: u64,
+}
+
+impl RunStore {
+    /// Opens the store at `dir`, replaying any pending log left by an
+    /// interrupted run. Sizes come from `HOLDER_RUN_SIZE` and `HOLDER_MAX_RUNS`.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let run_size = env::var("HOLDER_RUN_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(100_000);
+        let max_runs = env::var("HOLDER_MAX_RUNS").ok().and_then(|v| v.parse().ok()).unwrap_or(8);
+        fs::create_dir_all(dir)?;
+
+        let mut runs: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("tsv"))
+            .collect();
+        runs.sort();
+        let next_run = runs
+            .last()
+            .and_then(|path| path.file_stem()?.to_str()?.strip_prefix("run-")?.parse::<u32>().ok())
+            .map_or(0, |n| n + 1);
+
+        let log_path = dir.join(PENDING_LOG);
+        let mut pending = BTreeMap::new();
+        if log_path.exists() {
+            for line in BufReader::new(File::open(&log_path)?).lines() {
+                if let Some((address, record)) = parse_line(&line?) {
+                    insert_record(&mut pending, address, record);
+                }
+            }
+        }
+        let log = BufWriter::new(OpenOptions::new().create(true).append(true).open(&log_path)?);
+
+        let mut store = RunStore {
+            dir: dir.to_path_buf(),
+            pending,
+            log,
+            runs,
+            next_run,
+            run_size: run_size.max(1),
+            max_runs: max_runs.max(1),
+            compacted_holders: 0,
+            spilled_holders: 0,
+        };
+        // Merging up front gives an exact holder count for a resumed scan
+        store.compact()?;
+        if store.compacted_holders > 0 || !store.pending.is_empty() {
+            info!(holders = store.holder_count(), dir = %dir.display(), "Resuming on-disk holder store");
+        }
+        Ok(store)
+    }
+
+    fn shadow(&self) -> Result<RunStore> {
+        let dir = self.dir.with_extension("shadow");
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        RunStore::open(&dir)
+    }
+
+    /// Moves the store's files to `dir`, which must not exist.
+    fn move_to(mut self, dir: &Path) -> Result<RunStore> {
+        self.log.flush()?;
+        let from = self.dir.clone();
+        drop(self);
+        fs::rename(&from, dir)?;
+        RunStore::open(dir)
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        for run in self.runs.drain(..) {
+            fs::remove_file(run)?;
+        }
+        self.pending.clear();
+        self.compacted_holders = 0;
+        self.spilled_holders = 0;
+        self.reset_log()
+    }
+
+    pub fn insert(&mut self, address: &str, balance: u64, seen_at: DateTime<Utc>) -> Result<()> {
+        let record = HolderRecord { balance, first_seen: seen_at.timestamp() };
+        writeln!(self.log, "{}", format_line(address, record))?;
+        insert_record(&mut self.pending, address.to_string(), record);
+        Ok(())
+    }
+
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.log.flush()?;
+        self.log.get_ref().sync_data()?;
+        if self.pending.len() >= self.run_size {
+            self.spill()?;
+            if self.runs.len() > self.max_runs {
+                self.compact()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Unique holders in compacted runs plus the buffered page holders. Holders
+    /// in uncompacted runs may be counted more than once until the next compaction.
+    pub fn holder_count(&self) -> u64 {
+        self.compacted_holders + self.spilled_holders + self.pending.len() as u64
+    }
+
+    /// Merges everything into a single run and writes the sorted address list
+    /// to `path`. Returns the number of unique holders.
+    pub fn export(&mut self, path: &str) -> Result<u64> {
+        self.spill()?;
+        self.compact()?;
+        let mut export = ExportWriter::create(path)?;
+        let count = merge_runs(&self.runs, |address, _| {
+            writeln!(export, "{}", address)?;
+            Ok(())
+        })?;
+        export.finish()?;
+        Ok(count)
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let path = self.dir.join(format!("run-{:06}.tsv", self.next_run));
+        self.next_run += 1;
+        let mut run = BufWriter::new(File::create(&path)?);
+        for (address, record) in &self.pending {
+            writeln!(run, "{}", format_line(address, *record))?;
+        }
+        run.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        debug!(run = %path.display(), holders = self.pending.len(), "Spilled holder run");
+        self.runs.push(path);
+        self.spilled_holders += self.pending.len() as u64;
+        self.pending.clear();
+        self.reset_log()
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        if self.runs.len() <= 1 {
+            self.compacted_holders += self.spilled_holders;
+            self.spilled_holders = 0;
+            if self.compacted_holders == 0 {
+                self.compacted_holders = self.runs.first().map_or(0, |run| run_len(run));
+            }
+            return Ok(());
+        }
+        let path = self.dir.join(format!("run-{:06}.tsv", self.next_run));
+        self.next_run += 1;
+        let mut merged = BufWriter::new(File::create(&path)?);
+        let count = merge_runs(&self.runs, |address, record| {
+            writeln!(merged, "{}", format_line(address, record))?;
+            Ok(())
+        })?;
+        merged.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        for run in self.runs.drain(..) {
+            fs::remove_file(run)?;
+        }
+        debug!(holders = count, "Compacted holder runs");
+        self.runs.push(path);
+        self.compacted_holders = count;
+        self.spilled_holders = 0;
+        Ok(())
+    }
+
+    fn reset_log(&mut self) -> Result<()> {
+        self.log = BufWriter::new(File::create(self.dir.join(PENDING_LOG))?);
+        Ok(())
+    }
+}
+
+fn insert_record(pending: &mut BTreeMap<String, HolderRecord>, address: String, record: HolderRecord) {
+    pending
+        .entry(address)
+        .and_modify(|existing| *existing = existing.merge(record))
+        .or_insert(record);
+}
+
+fn format_line(address: &str, record: HolderRecord) -> String {
+    format!("{}\t{}\t{}", address, record.balance, record.first_seen)
+}
+
+fn parse_line(line: &str) -> Option<(String, HolderRecord)> {
+    let mut fields = line.split('\t');
+    let address = fields.next()?.to_string();
+    let balance = fields.next()?.parse().ok()?;
+    let first_seen = fields.next()?.parse().ok()?;
+    Some((address, HolderRecord { balance, first_seen }))
+}
+
+fn run_len(path: &Path) -> u64 {
+    File::open(path).map_or(0, |file| BufReader::new(file).lines().count() as u64)
+}
+
+/// K-way merge of sorted runs, calling `emit` once per address in order.
+/// Runs are listed oldest first, so later runs win when records are merged.
+fn merge_runs(runs: &[PathBuf], mut emit: impl FnMut(&str, HolderRecord) -> Result<()>) -> Result<u64> {
+    let mut readers = Vec::with_capacity(runs.len());
+    for run in runs {
+        let file = File::open(run).with_context(|| format!("opening holder run {}", run.display()))?;
+        readers.push(BufReader::new(file).lines());
+    }
+
+    let mut heap = BinaryHeap::new();
+    let mut heads: Vec<Option<HolderRecord>> = vec![None; readers.len()];
+    for (index, reader) in readers.iter_mut().enumerate() {
+        if let Some((address, record)) = next_record(reader)? {
+            heads[index] = Some(record);
+            heap.push(Reverse((address, index)));
+        }
+    }
+
+    let mut count = 0;
+    while let Some(Reverse((address, index))) = heap.pop() {
+        let mut sources = vec![index];
+        while let Some(Reverse((next_address, _))) = heap.peek() {
+            if *next_address != address {
+                break;
+            }
+            let Reverse((_, other)) = heap.pop().expect("peeked entry");
+            sources.push(other);
+        }
+        sources.sort_unstable();
+        let record = sources
+            .iter()
+            .map(|&source| heads[source].take().expect("heap entry without a record"))
+            .reduce(HolderRecord::merge)
+            .expect("at least one source");
+        emit(&address, record)?;
+        count += 1;
+
+        for source in sources {
+            if let Some((next_address, next)) = next_record(&mut readers[source])? {
+                heads[source] = Some(next);
+                heap.push(Reverse((next_address, source)));
+            }
+        }
+    }
+    Ok(count)
+}
+
+fn next_record(lines: &mut std::io::Lines<BufReader<File>>) -> Result<Option<(String, HolderRecord)>> {
+    for line in lines.by_ref() {
+        if let Some(parsed) = parse_line(&line?) {
+            return Ok(Some(parsed));
+        }
+    }
+    Ok(None)
+}
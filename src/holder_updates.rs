@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::collections::BTreeMap;
+use tracing::{debug, warn};
+use web3::types::U256;
+
+use crate::address;
+use crate::diff::{ChangeKind, HolderChange};
+use crate::state::{Provenance, ScanState};
+
+const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+/// Applies single transfers to a holder set between full scans, for `ingest`
+/// and `subscribe`, and reports the holder changes they add up to.
+pub struct HolderUpdates<'a> {
+    state: &'a mut ScanState,
+    /// Provenance source of the holders credited
+    source: &'static str,
+    /// Balance of each touched address before the first transfer, `None` if it wasn't a holder
+    before: BTreeMap<String, Option<u64>>,
+}
+
+impl<'a> HolderUpdates<'a> {
+    pub fn new(state: &'a mut ScanState, source: &'static str) -> HolderUpdates<'a> {
+        HolderUpdates { state, source, before: BTreeMap::new() }
+    }
+
+    /// Moves `amount` from `from` to `to`, with `token` the id moved as the
+    /// source reports it, hex or decimal, or `None` for fungible tokens. The
+    /// zero address mints and burns. A transfer naming something that isn't
+    /// an address is skipped with a warning.
+    pub fn transfer(&mut self, from: &str, to: &str, token: Option<&str>, amount: u64, block: Option<u64>) {
+        let (from, to) = match (address::normalize(from), address::normalize(to)) {
+            (Ok(from), Ok(to)) => (from, to),
+            (Err(e), _) | (_, Err(e)) => {
+                warn!(error = %e, ?block, "Skipping a transfer with an invalid address");
+                return;
+            }
+        };
+        for address in [&from, &to] {
+            if address != ZERO_ADDRESS && !self.before.contains_key(address) {
+                let balance = self.balance(address);
+                self.before.insert(address.clone(), balance);
+            }
+        }
+        if from != ZERO_ADDRESS {
+            self.debit(&from, token, amount);
+        }
+        if to != ZERO_ADDRESS {
+            self.credit(&to, token, amount, block);
+        }
+        if let Some(block) = block {
+            self.state.last_processed_block = self.state.last_processed_block.max(block);
+        }
+        self.state.total_holders = self.state.holders.len() as u64;
+    }
+
+    /// The changes since `new`, one per address whose holding changed.
+    pub fn finish(self) -> Vec<HolderChange> {
+        let mut changes = Vec::new();
+        for (address, old) in &self.before {
+            let new = self.balance(address);
+            let kind = match (old, new) {
+                (None, Some(_)) => ChangeKind::Added,
+                (Some(_), None) => ChangeKind::Removed,
+                (Some(old), Some(new)) if *old != new => ChangeKind::BalanceChanged,
+                _ => continue,
+            };
+            let (old_balance, new_balance) = (old.unwrap_or(0), new.unwrap_or(0));
+            changes.push(HolderChange { address: address.clone(), kind, old_balance, new_balance });
+        }
+        if !changes.is_empty() {
+            self.state.last_save_time = Utc::now();
+        }
+        changes
+    }
+
+    fn balance(&self, address: &str) -> Option<u64> {
+        let holding = self.state.holders.contains(address);
+        holding.then(|| self.state.balances.get(address).copied().unwrap_or(0))
+    }
+
+    fn debit(&mut self, address: &str, token: Option<&str>, amount: u64) {
+        let state = &mut *self.state;
+        if !state.holders.contains(address) {
+            // The holder set predates a transfer in; the next full scan corrects it
+            debug!(address, "Transfer out of an address that isn't a holder");
+            return;
+        }
+        let balance = state.balances.entry(address.to_string()).or_insert(0);
+        *balance = balance.saturating_sub(amount);
+        let emptied = *balance == 0;
+        if let (Some(token), Some(tokens)) = (token.and_then(parse_token_id), state.tokens.get_mut(address)) {
+            if let Some(position) = tokens.iter().position(|t| parse_token_id(t) == Some(token)) {
+                tokens.remove(position);
+            }
+        }
+        if emptied {
+            // Like a watch cycle, a holder who leaves and comes back counts as a new holding
+            state.holders.remove(address);
+            state.balances.remove(address);
+            state.tokens.remove(address);
+            state.first_seen.remove(address);
+            state.acquisitions.remove(address);
+            state.provenance.remove(address);
+        }
+    }
+
+    fn credit(&mut self, address: &str, token: Option<&str>, amount: u64, block: Option<u64>) {
+        let state = &mut *self.state;
+        if amount == 0 {
+            return;
+        }
+        if state.holders.insert(address.to_string()) {
+            state.first_seen.insert(address.to_string(), Utc::now());
+        }
+        let balance = state.balances.entry(address.to_string()).or_insert(0);
+        *balance = balance.saturating_add(amount);
+        if let Some(token) = token {
+            state.tokens.entry(address.to_string()).or_default().push(token.to_string());
+        }
+        let provenance = Provenance { source: self.source.to_string(), endpoint: None, run: None, page: None, block };
+        state.provenance.insert(address.to_string(), provenance);
+    }
+}
+
+/// A token id as the owners API (hex) or `enumerate` (decimal) stores it.
+pub fn parse_token_id(id: &str) -> Option<U256> {
+    match id.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(id).ok(),
+    }
+}
+
+/// A hex or decimal amount as a balance.
+pub fn parse_amount(value: &str) -> Result<u64> {
+    let amount = parse_token_id(value).with_context(|| format!("amount {} is not a hex or decimal number", value))?;
+    checked_amount(amount).with_context(|| format!("amount {} exceeds u64", value))
+}
+
+/// `amount` as a balance, or `None` when it doesn't fit in `u64`.
+pub fn checked_amount(amount: U256) -> Option<u64> {
+    (amount <= U256::from(u64::MAX)).then(|| amount.low_u64())
+}
@@ -0,0 +1,97 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+use web3::transports::Http;
+
+use crate::config;
+
+/// `[http]` in `scanner.toml`: settings for every HTTP client the scanner
+/// builds, for providers, RPC nodes, notifications and exports alike.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    /// `http://`, `https://`, `socks5://` or `socks5h://` proxy for all requests,
+    /// with credentials in the URL if needed. Without it, `HTTPS_PROXY`,
+    /// `HTTP_PROXY` and `ALL_PROXY` apply as usual
+    pub proxy: Option<String>,
+    /// Comma-separated hosts and domains that bypass `proxy`, like `NO_PROXY`
+    pub no_proxy: Option<String>,
+    /// Sent with every request, e.g. an egress gateway's token
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub connect_timeout_secs: Option<u64>,
+    /// Whole-request timeout; replaces each client's own default
+    pub timeout_secs: Option<u64>,
+    /// Idle connections kept open per host
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout_secs: Option<u64>,
+    /// PEM file with extra root certificates, e.g. a TLS-inspecting proxy's CA
+    pub ca_certificate: Option<PathBuf>,
+    /// Skip certificate verification. Only for debugging a proxy setup
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+}
+
+static SETTINGS: OnceLock<HttpConfig> = OnceLock::new();
+
+fn settings() -> Result<&'static HttpConfig> {
+    if let Some(settings) = SETTINGS.get() {
+        return Ok(settings);
+    }
+    let http = config::load_config()?.http;
+    Ok(SETTINGS.get_or_init(|| http))
+}
+
+/// A client with the `[http]` settings. `timeout` applies unless `[http]`
+/// sets `timeout_secs`.
+pub fn client(timeout: Duration) -> Result<reqwest::Client> {
+    let settings = settings()?;
+    let mut builder = reqwest::Client::builder()
+        .timeout(settings.timeout_secs.map(Duration::from_secs).unwrap_or(timeout))
+        .danger_accept_invalid_certs(settings.accept_invalid_certs);
+    if let Some(proxy) = &settings.proxy {
+        let proxy = reqwest::Proxy::all(proxy).context("invalid [http] proxy")?;
+        let no_proxy = settings.no_proxy.as_deref().and_then(reqwest::NoProxy::from_string);
+        builder = builder.proxy(proxy.no_proxy(no_proxy));
+    }
+    if !settings.headers.is_empty() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in &settings.headers {
+            let name: reqwest::header::HeaderName =
+                name.parse().with_context(|| format!("invalid header name {} in [http] headers", name))?;
+            let mut value: reqwest::header::HeaderValue =
+                value.parse().with_context(|| format!("invalid value for header {} in [http] headers", name))?;
+            value.set_sensitive(true);
+            headers.insert(name, value);
+        }
+        builder = builder.default_headers(headers);
+    }
+    if let Some(secs) = settings.connect_timeout_secs {
+        builder = builder.connect_timeout(Duration::from_secs(secs));
+    }
+    if let Some(idle) = settings.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(idle);
+    }
+    if let Some(secs) = settings.pool_idle_timeout_secs {
+        builder = builder.pool_idle_timeout(Duration::from_secs(secs));
+    }
+    if let Some(path) = &settings.ca_certificate {
+        let pem = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        const END: &str = "-----END CERTIFICATE-----";
+        for block in pem.split_inclusive(END).filter(|block| block.contains(END)) {
+            let certificate = reqwest::Certificate::from_pem(block.as_bytes())
+                .with_context(|| format!("invalid certificate in {}", path.display()))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// JSON-RPC transport to `url` over a client with the `[http]` settings.
+pub fn transport(url: &str) -> Result<Http> {
+    let url = url.parse().with_context(|| format!("invalid RPC URL {}", url))?;
+    Ok(Http::with_client(client(Duration::from_secs(60))?, url))
+}
@@ -0,0 +1,137 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn};
+use web3::signing::keccak256;
+
+use crate::fetcher::FetchedPage;
+use crate::watch::parse_interval;
+
+pub const DEFAULT_CACHE_DIR: &str = "data/http_cache";
+
+/// A cached provider response, stored under the hash of what was asked.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    provider: String,
+    /// The request without endpoint or API key: method and path, body or query
+    request: String,
+    /// Block the request was pinned to; such responses never go stale
+    block: Option<u64>,
+    fetched_at: DateTime<Utc>,
+    endpoint: String,
+    status: u16,
+    body: String,
+}
+
+/// Successful provider responses on disk, content-addressed by provider,
+/// request and block, so repeated runs skip pages they already fetched.
+/// Endpoints and API keys aren't part of the address, so any of them can
+/// answer from it.
+#[derive(Debug)]
+pub struct HttpCache {
+    dir: PathBuf,
+    provider: &'static str,
+    /// Age after which unpinned responses are fetched again; `None` accepts any age
+    ttl: Option<Duration>,
+    /// Never call the provider; a miss is an error
+    pub offline: bool,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl HttpCache {
+    /// The cache for a scan: `--cache-ttl`, else `HTTP_CACHE_TTL`, turns it
+    /// on, `--offline` reads it at any age, and `--no-cache` turns it off.
+    /// Lives in `HTTP_CACHE_DIR` (default `data/http_cache`).
+    pub fn for_scan(
+        provider: &'static str,
+        ttl: Option<Duration>,
+        offline: bool,
+        no_cache: bool,
+    ) -> Result<Option<HttpCache>> {
+        if no_cache {
+            return Ok(None);
+        }
+        let ttl = match (ttl, std::env::var("HTTP_CACHE_TTL").ok().filter(|v| !v.trim().is_empty())) {
+            (Some(ttl), _) => Some(ttl),
+            (None, Some(value)) => {
+                Some(parse_interval(&value).map_err(anyhow::Error::msg).context("invalid HTTP_CACHE_TTL")?)
+            }
+            (None, None) => None,
+        };
+        if ttl.is_none() && !offline {
+            return Ok(None);
+        }
+        let dir = std::env::var("HTTP_CACHE_DIR").unwrap_or_else(|_| DEFAULT_CACHE_DIR.to_string());
+        let dir = PathBuf::from(dir).join(provider);
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        info!(dir = %dir.display(), ?ttl, offline, "Caching provider responses");
+        Ok(Some(HttpCache { dir, provider, ttl: if offline { None } else { ttl }, offline, hits: 0, misses: 0 }))
+    }
+
+    fn path(&self, request: &str, block: Option<u64>) -> PathBuf {
+        let address = format!("{}\n{}\n{}", self.provider, request, block.map(|b| b.to_string()).unwrap_or_default());
+        let hash = hex::encode(keccak256(address.as_bytes()));
+        self.dir.join(&hash[..2]).join(format!("{}.json", hash))
+    }
+
+    /// The cached response to `request`, if there is one young enough. A
+    /// cache that can't be read counts as a miss.
+    pub fn get(&mut self, request: &str, block: Option<u64>) -> Option<FetchedPage> {
+        let path = self.path(request, block);
+        let entry = match File::open(&path) {
+            Ok(file) => serde_json::from_reader::<_, CacheEntry>(file)
+                .map_err(|e| warn!(path = %path.display(), error = %e, "Ignoring unreadable cache entry"))
+                .ok(),
+            Err(_) => None,
+        };
+        // A hash collision would show as a different request
+        let fresh = entry.filter(|entry| entry.request == request).filter(|entry| {
+            let age = (Utc::now() - entry.fetched_at).to_std().unwrap_or_default();
+            entry.block.is_some() || self.ttl.is_none_or(|ttl| age <= ttl)
+        });
+        let Some(entry) = fresh else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        Some(FetchedPage {
+            status: reqwest::StatusCode::from_u16(entry.status).ok()?,
+            body: entry.body,
+            attempts: 0,
+            latency_ms: 0,
+            endpoint: entry.endpoint,
+        })
+    }
+
+    /// Keeps a successful response. Failing to write only logs a warning.
+    pub fn put(&self, request: &str, block: Option<u64>, page: &FetchedPage) {
+        if !page.status.is_success() {
+            return;
+        }
+        let entry = CacheEntry {
+            provider: self.provider.to_string(),
+            request: request.to_string(),
+            block,
+            fetched_at: Utc::now(),
+            endpoint: page.endpoint.clone(),
+            status: page.status.as_u16(),
+            body: page.body.clone(),
+        };
+        let path = self.path(request, block);
+        let written = (|| -> Result<()> {
+            fs::create_dir_all(path.parent().expect("cache paths have a parent"))?;
+            // Write then rename so a concurrent run never reads half an entry
+            let tmp = path.with_extension("json.tmp");
+            serde_json::to_writer(File::create(&tmp)?, &entry)?;
+            fs::rename(&tmp, &path)?;
+            Ok(())
+        })();
+        if let Err(e) = written {
+            warn!(path = %path.display(), error = %e, "Could not write cache entry");
+        }
+    }
+}
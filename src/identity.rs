@@ -0,0 +1,128 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+
+use crate::address::AddressFormat;
+use crate::state::ScanState;
+
+/// What counts as one holder when deduplicating and aggregating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HolderKey {
+    /// The raw address as returned by the provider
+    Address,
+    /// `chain:address` with the address in the chain's canonical form, so the
+    /// same address on two chains counts twice
+    ChainAddress,
+    /// A cluster id from the identity file; unlisted addresses are their own cluster
+    Cluster,
+}
+
+impl HolderKey {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "" | "address" => Ok(HolderKey::Address),
+            "chain-address" | "chain_address" => Ok(HolderKey::ChainAddress),
+            "cluster" => Ok(HolderKey::Cluster),
+            other => bail!("unknown holder key '{}' (use address, chain-address or cluster)", other),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ClusterRow {
+    address: String,
+    cluster: String,
+}
+
+#[derive(Debug)]
+pub struct HolderIdentity {
+    pub key: HolderKey,
+    chain: String,
+    format: AddressFormat,
+    clusters: HashMap<String, String>,
+}
+
+impl HolderIdentity {
+    /// Reads `HOLDER_KEY` (default `address`), `HOLDER_CHAIN` for the
+    /// chain-address key (default `optimism`), and for the cluster key the
+    /// `address,cluster` CSV named by `IDENTITY_CLUSTERS`. Addresses are
+    /// canonicalized in `HOLDER_CHAIN`'s address format.
+    pub fn from_env() -> Result<Self> {
+        let key = HolderKey::parse(&env::var("HOLDER_KEY").unwrap_or_default())?;
+        let chain = env::var("HOLDER_CHAIN").unwrap_or_else(|_| "optimism".to_string());
+        let format = AddressFormat::for_chain(&chain);
+        let mut clusters = HashMap::new();
+        if key == HolderKey::Cluster {
+            let path = env::var("IDENTITY_CLUSTERS").context("HOLDER_KEY=cluster requires IDENTITY_CLUSTERS")?;
+            let mut reader = csv::Reader::from_path(&path).with_context(|| format!("reading {}", path))?;
+            for row in reader.deserialize() {
+                let row: ClusterRow = row?;
+                clusters.insert(format.key(&row.address), row.cluster);
+            }
+        }
+        Ok(HolderIdentity { key, chain, format, clusters })
+    }
+
+    pub fn key_for(&self, address: &str) -> String {
+        match self.key {
+            HolderKey::Address => address.to_string(),
+            HolderKey::ChainAddress => format!("{}:{}", self.chain, self.format.key(address)),
+            HolderKey::Cluster => {
+                let address = self.format.key(address);
+                self.clusters.get(&address).cloned().unwrap_or(address)
+            }
+        }
+    }
+
+    /// Re-keys a state by holder identity: balances of addresses sharing a key
+    /// are summed and the earliest first-seen and acquisition times are kept. The address key
+    /// returns the state unchanged.
+    pub fn regroup<'a>(&self, state: &'a ScanState) -> Cow<'a, ScanState> {
+        if self.key == HolderKey::Address {
+            return Cow::Borrowed(state);
+        }
+        let mut grouped = ScanState {
+            last_processed_block: state.last_processed_block,
+            last_save_time: state.last_save_time,
+            last_page_key: state.last_page_key.clone(),
+            last_page_key_at: state.last_page_key_at,
+            quarantine: state.quarantine.clone(),
+            completeness: state.completeness.clone(),
+            valuation: state.valuation.clone(),
+            metadata: state.metadata.clone(),
+            ..ScanState::default()
+        };
+        for address in &state.holders {
+            let key = self.key_for(address);
+            *grouped.balances.entry(key.clone()).or_insert(0) += state.balances.get(address).copied().unwrap_or(0);
+            if let Some(seen) = state.first_seen.get(address) {
+                grouped
+                    .first_seen
+                    .entry(key.clone())
+                    .and_modify(|existing| *existing = (*existing).min(*seen))
+                    .or_insert(*seen);
+            }
+            if let Some(acquisition) = state.acquisitions.get(address) {
+                grouped
+                    .acquisitions
+                    .entry(key.clone())
+                    .and_modify(|existing| {
+                        if acquisition.first_block < existing.first_block {
+                            existing.first_block = acquisition.first_block;
+                            existing.first_acquired = acquisition.first_acquired;
+                        }
+                        if acquisition.held_since_block < existing.held_since_block {
+                            existing.held_since_block = acquisition.held_since_block;
+                            existing.held_since = acquisition.held_since;
+                        }
+                    })
+                    .or_insert(*acquisition);
+            }
+            grouped.holders.insert(key);
+        }
+        grouped.total_holders = grouped.holders.len() as u64;
+        Cow::Owned(grouped)
+    }
+}
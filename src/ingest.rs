@@ -0,0 +1,297 @@
+use anyhow::{bail, Context, Result};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::Router;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::{HashSet, VecDeque};
+use std::env;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+use crate::diff::HolderChange;
+use crate::events::{self, EventSender};
+use crate::holder_updates::{parse_amount, HolderUpdates};
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, ScanState};
+
+// Delivery ids remembered to drop Alchemy's retries of a delivery already applied
+const RECENT_DELIVERIES: usize = 10_000;
+
+#[derive(Debug, clap::Args)]
+pub struct IngestOptions {
+    /// Address to listen on; Alchemy posts to http://<ADDR>/webhooks/alchemy
+    #[arg(long, default_value = "127.0.0.1:8090")]
+    pub listen: SocketAddr,
+    /// Seconds between writes of the updated holder set to data/state.json
+    #[arg(long, default_value_t = 5)]
+    pub save_secs: u64,
+    /// Push holder added/removed/balance-changed events to WebSocket clients at
+    /// ws://<ADDR>/events as deliveries are applied
+    #[arg(long, value_name = "ADDR")]
+    pub events_listen: Option<SocketAddr>,
+}
+
+/// An Alchemy Notify delivery. Address Activity and NFT Activity webhooks
+/// share this shape; other webhook types are acknowledged and ignored.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Notification {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    event: NotificationEvent,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationEvent {
+    #[serde(default)]
+    activity: Vec<Activity>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Activity {
+    from_address: String,
+    to_address: String,
+    /// Set by NFT Activity webhooks
+    contract_address: Option<String>,
+    /// Set by Address Activity webhooks
+    raw_contract: Option<RawContract>,
+    block_num: Option<String>,
+    #[serde(default)]
+    category: String,
+    erc721_token_id: Option<String>,
+    #[serde(default)]
+    erc1155_metadata: Vec<Erc1155Transfer>,
+    log: Option<ActivityLog>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawContract {
+    address: Option<String>,
+    raw_value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Erc1155Transfer {
+    token_id: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ActivityLog {
+    /// Set when a reorg dropped the log that was delivered earlier
+    #[serde(default)]
+    removed: bool,
+}
+
+impl Activity {
+    fn contract(&self) -> Option<&str> {
+        self.contract_address.as_deref().or(self.raw_contract.as_ref()?.address.as_deref())
+    }
+
+    fn block(&self) -> Option<u64> {
+        u64::from_str_radix(self.block_num.as_deref()?.trim_start_matches("0x"), 16).ok()
+    }
+
+    /// The token ids and amounts moved, `None` as the id for fungible tokens.
+    /// An amount that isn't a number or doesn't fit a balance is an error.
+    fn amounts(&self) -> Result<Vec<(Option<String>, u64)>> {
+        let amounts = match self.category.as_str() {
+            "erc721" => vec![(self.erc721_token_id.clone(), 1)],
+            "erc1155" => self
+                .erc1155_metadata
+                .iter()
+                .map(|t| Ok((Some(t.token_id.clone()), parse_amount(&t.value)?)))
+                .collect::<Result<_>>()?,
+            "erc20" | "token" => {
+                let raw = self.raw_contract.as_ref().and_then(|c| c.raw_value.as_deref()).unwrap_or("0x0");
+                vec![(None, parse_amount(raw)?)]
+            }
+            // Native transfers have no contract
+            _ => Vec::new(),
+        };
+        Ok(amounts)
+    }
+}
+
+struct Ingest {
+    /// Signing keys of the webhooks allowed to post, from `ALCHEMY_WEBHOOK_SIGNING_KEYS`
+    signing_keys: Vec<String>,
+    events: Option<EventSender>,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    state: ScanState,
+    /// Changed since it was last saved
+    dirty: bool,
+    deliveries: u64,
+    recent: VecDeque<String>,
+    recent_ids: HashSet<String>,
+}
+
+pub async fn run_ingest(options: &IngestOptions) -> Result<()> {
+    let signing_keys: Vec<String> = env::var("ALCHEMY_WEBHOOK_SIGNING_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+        .collect();
+    if signing_keys.is_empty() {
+        bail!("set ALCHEMY_WEBHOOK_SIGNING_KEYS to the signing key of each webhook that posts here");
+    }
+    let state = state::load_state_or_default()?;
+    if state.last_page_key.is_some() {
+        // Deliveries applied to a partial holder set would be lost or wrong once the pass continues
+        bail!("data/state.json holds an unfinished scan pass; finish the scan before ingesting webhooks");
+    }
+    info!(holders = state.holders.len(), "Loaded holder set");
+    let events = match options.events_listen {
+        Some(listen) => Some(events::start(listen).await?),
+        None => None,
+    };
+    let ingest = Arc::new(Ingest {
+        signing_keys,
+        events,
+        inner: Mutex::new(Inner {
+            state,
+            dirty: false,
+            deliveries: 0,
+            recent: VecDeque::new(),
+            recent_ids: HashSet::new(),
+        }),
+    });
+
+    let saver = ingest.clone();
+    let save_every = Duration::from_secs(options.save_secs.max(1));
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(save_every);
+        loop {
+            ticks.tick().await;
+            if let Err(e) = save(&saver) {
+                error!(error = %format!("{:#}", e), "Could not save the holder set");
+            }
+        }
+    });
+
+    let app = Router::new().route("/webhooks/alchemy", post(receive)).with_state(ingest.clone());
+    let listener = tokio::net::TcpListener::bind(options.listen).await?;
+    info!(listen = %options.listen, "Accepting Alchemy webhooks at http://{}/webhooks/alchemy", options.listen);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    save(&ingest)?;
+    info!("Holder set saved, stopping");
+    Ok(())
+}
+
+fn save(ingest: &Ingest) -> Result<()> {
+    let state = {
+        let mut inner = ingest.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if !inner.dirty {
+            return Ok(());
+        }
+        inner.dirty = false;
+        inner.state.clone()
+    };
+    state::save_state(&state)
+}
+
+async fn receive(State(ingest): State<Arc<Ingest>>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let signature = headers.get("x-alchemy-signature").and_then(|value| value.to_str().ok());
+    if !signature.is_some_and(|signature| verify_signature(&ingest.signing_keys, &body, signature)) {
+        warn!("Rejected a webhook delivery with a missing or wrong signature");
+        return StatusCode::UNAUTHORIZED;
+    }
+    let notification: Notification = match serde_json::from_slice(&body) {
+        Ok(notification) => notification,
+        Err(e) => {
+            warn!(error = %e, "Could not parse a webhook delivery");
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    if notification.kind != "ADDRESS_ACTIVITY" && notification.kind != "NFT_ACTIVITY" {
+        debug!(kind = %notification.kind, "Ignoring webhook type");
+        return StatusCode::OK;
+    }
+
+    let (changes, sequence) = {
+        let mut inner = ingest.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if inner.recent_ids.contains(&notification.id) {
+            debug!(id = %notification.id, "Ignoring a repeated delivery");
+            return StatusCode::OK;
+        }
+        let changes = match apply(&mut inner.state, &notification.event.activity) {
+            Ok(changes) => changes,
+            Err(e) => {
+                warn!(id = %notification.id, error = %format!("{:#}", e), "Rejected a webhook delivery");
+                return StatusCode::BAD_REQUEST;
+            }
+        };
+        inner.recent_ids.insert(notification.id.clone());
+        inner.recent.push_back(notification.id.clone());
+        if inner.recent.len() > RECENT_DELIVERIES {
+            if let Some(oldest) = inner.recent.pop_front() {
+                inner.recent_ids.remove(&oldest);
+            }
+        }
+        inner.deliveries += 1;
+        inner.dirty |= !changes.is_empty();
+        (changes, inner.deliveries)
+    };
+    info!(id = %notification.id, changes = changes.len(), "Applied webhook delivery");
+    if let Some(events) = &ingest.events {
+        events::publish(events, sequence, &changes);
+    }
+    StatusCode::OK
+}
+
+/// `X-Alchemy-Signature` is the hex HMAC-SHA256 of the raw body under the
+/// webhook's signing key.
+fn verify_signature(keys: &[String], body: &[u8], signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature.trim()) else {
+        return false;
+    };
+    keys.iter().any(|key| {
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(key.as_bytes()) else {
+            return false;
+        };
+        mac.update(body);
+        mac.verify_slice(&signature).is_ok()
+    })
+}
+
+/// Applies the tracked contract's transfers in `activity` to `state` and
+/// returns the resulting holder changes. Every amount is read before any
+/// transfer is applied, so a delivery with a bad amount changes nothing.
+fn apply(state: &mut ScanState, activity: &[Activity]) -> Result<Vec<HolderChange>> {
+    let mut transfers = Vec::new();
+    for transfer in activity {
+        if !transfer.contract().is_some_and(|c| c.eq_ignore_ascii_case(CONTRACT_ADDRESS)) {
+            continue;
+        }
+        let (mut from, mut to) = (transfer.from_address.as_str(), transfer.to_address.as_str());
+        if transfer.log.as_ref().is_some_and(|log| log.removed) {
+            // Undo a transfer a reorg dropped
+            std::mem::swap(&mut from, &mut to);
+        }
+        let amounts = transfer.amounts().with_context(|| format!("transfer from {} to {}", from, to))?;
+        transfers.extend(amounts.into_iter().map(|(token, amount)| (from, to, token, amount, transfer.block())));
+    }
+    let mut updates = HolderUpdates::new(state, "webhook");
+    for (from, to, token, amount, block) in transfers {
+        updates.transfer(from, to, token.as_deref(), amount, block);
+    }
+    Ok(updates.finish())
+}
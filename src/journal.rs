@@ -0,0 +1,319 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+pub const RUNS_DIR: &str = "data/runs";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEvent {
+    RunStarted {
+        contract: String,
+        holders: u64,
+        resume_page_key: Option<String>,
+        /// Missing in journals written before runs recorded how they were started
+        #[serde(default)]
+        provider: Option<String>,
+        /// Command line the scanner was started with
+        #[serde(default)]
+        arguments: Vec<String>,
+    },
+    Page {
+        page: u64,
+        endpoint: String,
+        latency_ms: u64,
+        attempts: u32,
+        owners: usize,
+        holders: u64,
+    },
+    Checkpoint {
+        page: u64,
+        holders: u64,
+        page_key: Option<String>,
+    },
+    Warning {
+        page: Option<u64>,
+        message: String,
+    },
+    Error {
+        page: Option<u64>,
+        message: String,
+    },
+    /// Request totals from the provider metrics, once the pages are done
+    Performance {
+        provider: String,
+        requests: u64,
+        retries: u64,
+        total_bytes: u64,
+        error_rate: f64,
+        /// Missing in journals written before runs estimated their cost
+        #[serde(default)]
+        compute_units: f64,
+        #[serde(default)]
+        estimated_usd: Option<f64>,
+    },
+    /// The run's state was archived as this snapshot
+    Snapshot {
+        id: String,
+        path: PathBuf,
+        /// The snapshot's hash; see `digest::snapshot_hash`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hash: Option<String>,
+    },
+    RunFinished {
+        status: String,
+        pages: u64,
+        holders: u64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+/// Append-only JSONL record of one scan run under `data/runs/<id>.jsonl`.
+/// Every entry is flushed as it is written so the journal survives a crash.
+#[derive(Debug)]
+pub struct Journal {
+    pub id: String,
+    file: File,
+    pages: u64,
+}
+
+impl Journal {
+    pub fn start() -> Result<Journal> {
+        fs::create_dir_all(RUNS_DIR)?;
+        let mut id = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        // Two runs started within the same second get a numeric suffix
+        let mut suffix = 1;
+        while run_path(&id).exists() {
+            suffix += 1;
+            id = format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%SZ"), suffix);
+        }
+        let file = OpenOptions::new().create(true).append(true).open(run_path(&id))?;
+        Ok(Journal { id, file, pages: 0 })
+    }
+
+    /// Appends an event. A journal that can't be written only logs a warning;
+    /// it never fails the scan it describes.
+    pub fn record(&mut self, event: JournalEvent) {
+        if matches!(event, JournalEvent::Page { .. }) {
+            self.pages += 1;
+        }
+        let entry = JournalEntry { at: Utc::now(), event };
+        let written = serde_json::to_string(&entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(self.file, "{}", line)?));
+        if let Err(e) = written {
+            warn!(run = %self.id, error = %e, "Could not write run journal");
+        }
+    }
+
+    pub fn pages(&self) -> u64 {
+        self.pages
+    }
+
+    pub fn path(&self) -> PathBuf {
+        run_path(&self.id)
+    }
+}
+
+fn run_path(id: &str) -> PathBuf {
+    Path::new(RUNS_DIR).join(format!("{}.jsonl", id))
+}
+
+/// Holder count of the most recent run that finished successfully.
+pub fn last_finished_holders() -> Result<Option<u64>> {
+    for id in run_ids()?.iter().rev() {
+        let finished = load_run(id)?.into_iter().rev().find_map(|entry| match entry.event {
+            JournalEvent::RunFinished { status, holders, .. } if status == "finished" => Some(holders),
+            _ => None,
+        });
+        if finished.is_some() {
+            return Ok(finished);
+        }
+    }
+    Ok(None)
+}
+
+pub fn load_run(id: &str) -> Result<Vec<JournalEntry>> {
+    let path = run_path(id);
+    if !path.exists() {
+        bail!("no journal for run {} in {}", id, RUNS_DIR);
+    }
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(File::open(&path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(
+            serde_json::from_str(&line).with_context(|| format!("{} line {}", path.display(), number + 1))?,
+        );
+    }
+    Ok(entries)
+}
+
+/// Run ids, oldest first.
+pub fn run_ids() -> Result<Vec<String>> {
+    let dir = Path::new(RUNS_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut ids: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".jsonl").map(str::to_string))
+        .collect();
+    ids.sort();
+    Ok(ids)
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum RunsCommand {
+    /// List journaled runs with their outcome, provider and snapshot hash
+    List,
+    /// Print how a run was started, then every page, checkpoint and error recorded for it
+    Show {
+        /// Run id as shown by `runs list`
+        id: String,
+        /// Print the raw JSONL entries instead of a readable timeline
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+pub fn run_runs(command: &RunsCommand) -> Result<()> {
+    match command {
+        RunsCommand::List => {
+            println!(
+                "{:<22} {:<10} {:<10} {:>9} {:>8} {:>10} {:>7}  snapshot hash",
+                "run", "status", "provider", "duration", "pages", "holders", "errors"
+            );
+            for id in run_ids()? {
+                let entries = load_run(&id)?;
+                let provider = entries.iter().find_map(|e| match &e.event {
+                    JournalEvent::RunStarted { provider, .. } => provider.clone(),
+                    JournalEvent::Performance { provider, .. } => Some(provider.clone()),
+                    _ => None,
+                });
+                let hash = entries.iter().find_map(|e| match &e.event {
+                    JournalEvent::Snapshot { hash, .. } => hash.clone(),
+                    _ => None,
+                });
+                let duration = match (entries.first(), entries.last()) {
+                    (Some(first), Some(last)) => format!("{}s", (last.at - first.at).num_seconds()),
+                    _ => "-".to_string(),
+                };
+                let errors = entries.iter().filter(|e| matches!(e.event, JournalEvent::Error { .. })).count();
+                let (status, pages, holders) = entries
+                    .iter()
+                    .rev()
+                    .find_map(|e| match &e.event {
+                        JournalEvent::RunFinished { status, pages, holders } => Some((status.clone(), *pages, *holders)),
+                        _ => None,
+                    })
+                    // No finish entry: still running, or the process was killed
+                    .unwrap_or_else(|| {
+                        let pages = entries.iter().filter(|e| matches!(e.event, JournalEvent::Page { .. })).count();
+                        ("incomplete".to_string(), pages as u64, last_holders(&entries))
+                    });
+                println!(
+                    "{:<22} {:<10} {:<10} {:>9} {:>8} {:>10} {:>7}  {}",
+                    id,
+                    status,
+                    provider.as_deref().unwrap_or("-"),
+                    duration,
+                    pages,
+                    holders,
+                    errors,
+                    hash.as_deref().unwrap_or("-")
+                );
+            }
+        }
+        RunsCommand::Show { id, json } => {
+            for entry in load_run(id)? {
+                if *json {
+                    println!("{}", serde_json::to_string(&entry)?);
+                } else {
+                    println!("{} {}", entry.at.format("%Y-%m-%d %H:%M:%S%.3f"), describe(&entry.event));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn last_holders(entries: &[JournalEntry]) -> u64 {
+    entries
+        .iter()
+        .rev()
+        .find_map(|e| match &e.event {
+            JournalEvent::Page { holders, .. } | JournalEvent::Checkpoint { holders, .. } => Some(*holders),
+            JournalEvent::RunStarted { holders, .. } => Some(*holders),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+fn describe(event: &JournalEvent) -> String {
+    let at_page = |page: &Option<u64>| page.map(|p| format!(" (page {})", p)).unwrap_or_default();
+    match event {
+        JournalEvent::RunStarted { contract, holders, resume_page_key, provider, arguments } => format!(
+            "started scan of {}{} with {} holders{}{}",
+            contract,
+            provider.as_ref().map(|p| format!(" via {}", p)).unwrap_or_default(),
+            holders,
+            if resume_page_key.is_some() { ", resuming from checkpoint" } else { "" },
+            if arguments.is_empty() { String::new() } else { format!(" (args: {})", arguments.join(" ")) }
+        ),
+        JournalEvent::Page { page, endpoint, latency_ms, attempts, owners, holders } => format!(
+            "page {}: {} owners, {} holders total, {} ms, {} attempt(s) via {}",
+            page, owners, holders, latency_ms, attempts, endpoint
+        ),
+        JournalEvent::Checkpoint { page, holders, page_key } => format!(
+            "checkpoint after page {}: {} holders, next page key {}",
+            page,
+            holders,
+            page_key.as_deref().unwrap_or("none")
+        ),
+        JournalEvent::Warning { page, message } => format!("warning{}: {}", at_page(page), message),
+        JournalEvent::Error { page, message } => format!("ERROR{}: {}", at_page(page), message),
+        JournalEvent::Performance {
+            provider,
+            requests,
+            retries,
+            total_bytes,
+            error_rate,
+            compute_units,
+            estimated_usd,
+        } => {
+            let compute_units = Some(compute_units).filter(|units| **units > 0.0);
+            format!(
+                "{} requests to {} ({} retries, {} bytes, {:.1}% errors){}{}",
+                requests,
+                provider,
+                retries,
+                total_bytes,
+                error_rate * 100.0,
+                compute_units.map(|units| format!(", {:.0} compute units", units)).unwrap_or_default(),
+                estimated_usd.map(|usd| format!(", estimated ${:.4}", usd)).unwrap_or_default()
+            )
+        }
+        JournalEvent::Snapshot { id, path, hash } => format!(
+            "archived snapshot {} to {}{}",
+            id,
+            path.display(),
+            hash.as_ref().map(|h| format!(", hash {}", h)).unwrap_or_default()
+        ),
+        JournalEvent::RunFinished { status, pages, holders } => {
+            format!("run {} after {} pages with {} holders", status, pages, holders)
+        }
+    }
+}
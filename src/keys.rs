@@ -0,0 +1,91 @@
+use anyhow::{bail, Result};
+use reqwest::StatusCode;
+use std::env;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug)]
+struct ApiKey {
+    key: String,
+    benched_until: Option<Instant>,
+}
+
+/// Round-robin pool of API keys. A key that gets a 429 or 403 is benched for
+/// `cooldown` so the remaining keys carry the scan.
+#[derive(Debug)]
+pub struct KeyPool {
+    keys: Vec<ApiKey>,
+    next: usize,
+    cooldown: Duration,
+}
+
+impl KeyPool {
+    pub fn new(keys: Vec<String>, cooldown: Duration) -> Result<Self> {
+        if keys.is_empty() {
+            bail!("at least one API key is required");
+        }
+        Ok(KeyPool {
+            keys: keys.into_iter().map(|key| ApiKey { key, benched_until: None }).collect(),
+            next: 0,
+            cooldown,
+        })
+    }
+
+    /// Reads comma-separated keys from `ALCHEMY_API_KEYS`, falling back to
+    /// `ALCHEMY_API_KEY`, with the bench time from `API_KEY_COOLDOWN_SECS`.
+    pub fn from_env() -> Result<Self> {
+        let raw = env::var("ALCHEMY_API_KEYS")
+            .or_else(|_| env::var("ALCHEMY_API_KEY"))
+            .map_err(|_| anyhow::anyhow!("ALCHEMY_API_KEY or ALCHEMY_API_KEYS must be set"))?;
+        let keys: Vec<String> = raw
+            .split(',')
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .collect();
+        let cooldown = env::var("API_KEY_COOLDOWN_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(120);
+        if keys.len() > 1 {
+            info!(keys = keys.len(), "Rotating between API keys");
+        }
+        KeyPool::new(keys, Duration::from_secs(cooldown))
+    }
+
+    pub fn count(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn key(&self, index: usize) -> &str {
+        &self.keys[index].key
+    }
+
+    /// Picks the next available key in round-robin order, waiting for the
+    /// first one to come off the bench when every key is benched.
+    pub async fn next_key(&mut self) -> usize {
+        loop {
+            let now = Instant::now();
+            for offset in 0..self.keys.len() {
+                let index = (self.next + offset) % self.keys.len();
+                if self.keys[index].benched_until.is_none_or(|until| now >= until) {
+                    self.keys[index].benched_until = None;
+                    self.next = (index + 1) % self.keys.len();
+                    return index;
+                }
+            }
+            let soonest = self.keys.iter().filter_map(|k| k.benched_until).min().unwrap_or(now);
+            let wait = soonest.saturating_duration_since(now);
+            warn!(?wait, "All API keys are benched, waiting");
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Benches the key if the status indicates it is throttled or rejected.
+    /// Returns true when the key was benched.
+    pub fn record_status(&mut self, index: usize, status: StatusCode) -> bool {
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::FORBIDDEN {
+            warn!(key = index, %status, cooldown = ?self.cooldown, "Benching API key");
+            self.keys[index].benched_until = Some(Instant::now() + self.cooldown);
+            true
+        } else {
+            false
+        }
+    }
+}
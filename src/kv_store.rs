@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::holder_store::HolderRecord;
+use crate::output::ExportWriter;
+
+/// Holders in a sled database keyed by address, with the balance and
+/// first-seen time as 16 big-endian bytes. Updating a holder touches only its
+/// own key, and the export walks the keys in order, so memory use doesn't
+/// grow with the holder set.
+#[derive(Debug)]
+pub struct KvStore {
+    dir: PathBuf,
+    db: sled::Db,
+    holders: u64,
+}
+
+impl KvStore {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let db = sled::open(dir).with_context(|| format!("opening holder store {}", dir.display()))?;
+        // sled counts by walking the keys, so this is done once and kept up to date
+        let holders = db.len() as u64;
+        if holders > 0 {
+            info!(holders, dir = %dir.display(), "Resuming on-disk holder store");
+        }
+        Ok(KvStore { dir: dir.to_path_buf(), db, holders })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn shadow(&self) -> Result<KvStore> {
+        let dir = self.dir.with_extension("shadow");
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        KvStore::open(&dir)
+    }
+
+    /// Moves the database to `dir`, which must not exist.
+    pub fn move_to(self, dir: &Path) -> Result<KvStore> {
+        self.db.flush()?;
+        let from = self.dir.clone();
+        // The database holds a lock on its files until dropped
+        drop(self);
+        fs::rename(&from, dir)?;
+        KvStore::open(dir)
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.db.clear()?;
+        self.holders = 0;
+        Ok(())
+    }
+
+    pub fn insert(&mut self, address: &str, balance: u64, seen_at: DateTime<Utc>) -> Result<()> {
+        let newer = HolderRecord { balance, first_seen: seen_at.timestamp() };
+        let previous = self.db.fetch_and_update(address.as_bytes(), |existing| {
+            let record = match existing.and_then(decode) {
+                Some(existing) => existing.merge(newer),
+                None => newer,
+            };
+            Some(encode(record).to_vec())
+        })?;
+        if previous.is_none() {
+            self.holders += 1;
+        }
+        Ok(())
+    }
+
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub fn holder_count(&self) -> u64 {
+        self.holders
+    }
+
+    pub fn export(&mut self, path: &str) -> Result<u64> {
+        let mut export = ExportWriter::create(path)?;
+        let mut count = 0;
+        for entry in self.db.iter() {
+            let (address, _) = entry?;
+            export.write_all(&address)?;
+            export.write_all(b"\n")?;
+            count += 1;
+        }
+        export.finish()?;
+        Ok(count)
+    }
+}
+
+fn encode(record: HolderRecord) -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&record.balance.to_be_bytes());
+    bytes[8..].copy_from_slice(&record.first_seen.to_be_bytes());
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> Option<HolderRecord> {
+    let balance = u64::from_be_bytes(bytes.get(..8)?.try_into().ok()?);
+    let first_seen = i64::from_be_bytes(bytes.get(8..16)?.try_into().ok()?);
+    Some(HolderRecord { balance, first_seen })
+}
@@ -0,0 +1,204 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::address;
+use crate::config::{self, Config};
+use crate::output;
+use crate::state::{self, ScanState};
+
+pub const HOLDER_LABELS_PATH: &str = "data/holder_labels.csv";
+pub const EXCLUDED_HOLDERS_PATH: &str = "data/excluded_holders.csv";
+
+/// `[labels]` in `scanner.toml`: address-label datasets to tag holders with.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabelsConfig {
+    #[serde(default)]
+    pub datasets: Vec<LabelDataset>,
+    /// Labels that mark a holder as an exchange deposit or hot wallet rather
+    /// than a user, matched case-insensitively anywhere in the label
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// A CSV with one address per row, such as an exported Etherscan label list
+/// or a hand-kept one.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabelDataset {
+    pub path: PathBuf,
+    #[serde(default = "default_address_column")]
+    pub address_column: String,
+    /// Column of labels; several in one cell are separated by `,` or `;`
+    #[serde(default = "default_label_column")]
+    pub label_column: String,
+    /// Column with the address's name tag, e.g. an exchange wallet's name,
+    /// recorded as one more label
+    pub name_column: Option<String>,
+}
+
+fn default_address_column() -> String {
+    "address".to_string()
+}
+
+fn default_label_column() -> String {
+    "label".to_string()
+}
+
+impl LabelsConfig {
+    /// The first of `labels` that matches an `exclude` pattern, if any.
+    pub fn excluded_by<'a>(&self, labels: &'a [String]) -> Option<&'a str> {
+        labels
+            .iter()
+            .find(|label| {
+                let label = label.to_lowercase();
+                self.exclude.iter().any(|pattern| label.contains(&pattern.trim().to_lowercase()))
+            })
+            .map(String::as_str)
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct LabelOptions {
+    /// Snapshot to tag (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Labeled holders report
+    #[arg(long, default_value = HOLDER_LABELS_PATH)]
+    pub output: PathBuf,
+}
+
+/// Labels of every address in the `[labels]` datasets, lowercase address to
+/// labels. `[funding] exchanges` are labeled `exchange`.
+pub fn load_datasets(config: &Config) -> Result<HashMap<String, BTreeSet<String>>> {
+    let mut labels: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for dataset in &config.labels.datasets {
+        let mut reader =
+            csv::Reader::from_path(&dataset.path).with_context(|| format!("reading {}", dataset.path.display()))?;
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(name))
+                .with_context(|| format!("{} has no '{}' column", dataset.path.display(), name))
+        };
+        let address_column = column(&dataset.address_column)?;
+        let label_column = column(&dataset.label_column)?;
+        let name_column = dataset.name_column.as_deref().map(column).transpose()?;
+        let (mut rows, mut invalid) = (0, 0);
+        for record in reader.records() {
+            let record = record?;
+            let Some(raw) = record.get(address_column).filter(|a| !a.trim().is_empty()) else {
+                continue;
+            };
+            let Ok(address) = address::normalize(raw) else {
+                invalid += 1;
+                continue;
+            };
+            let entry = labels.entry(address).or_default();
+            let cell = record.get(label_column).unwrap_or_default();
+            entry.extend(cell.split([',', ';']).map(str::trim).filter(|l| !l.is_empty()).map(str::to_string));
+            if let Some(name) = name_column.and_then(|c| record.get(c)).map(str::trim).filter(|n| !n.is_empty()) {
+                entry.insert(name.to_string());
+            }
+            rows += 1;
+        }
+        if invalid > 0 {
+            warn!(dataset = %dataset.path.display(), rows = invalid, "Skipped rows whose address isn't valid");
+        }
+        info!(dataset = %dataset.path.display(), rows, "Loaded address labels");
+    }
+    for exchange in &config.funding.exchanges {
+        let exchange = address::normalize(exchange).context("[funding] exchanges")?;
+        labels.entry(exchange).or_default().insert("exchange".to_string());
+    }
+    Ok(labels)
+}
+
+/// Tags every holder found in the `[labels]` datasets with its labels in the
+/// snapshot, so exports carry them and `export --exclude-labeled` and
+/// `not_labeled` eligibility rules can act on them.
+pub fn run_labels(options: &LabelOptions) -> Result<()> {
+    let config = config::load_config()?;
+    if config.labels.datasets.is_empty() && config.funding.exchanges.is_empty() {
+        bail!("no [labels] datasets or [funding] exchanges in {}", config::config_path().display());
+    }
+    let mut state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    let datasets = load_datasets(&config)?;
+    state.labels = state
+        .holders
+        .iter()
+        .filter_map(|holder| {
+            let labels = datasets.get(&address::normalize(holder).ok()?).filter(|l| !l.is_empty())?;
+            Some((holder.clone(), labels.iter().cloned().collect()))
+        })
+        .collect();
+
+    let mut holders: Vec<(&String, &Vec<String>)> = state.labels.iter().collect();
+    holders.sort();
+    let mut writer = output::csv_writer(&options.output)?;
+    writer.write_record(["address", "balance", "labels", "excluded_by"])?;
+    let mut excluded = 0;
+    for (holder, labels) in &holders {
+        let excluded_by = config.labels.excluded_by(labels);
+        excluded += usize::from(excluded_by.is_some());
+        writer.write_record([
+            holder.as_str(),
+            &state.balances.get(*holder).copied().unwrap_or(0).to_string(),
+            &labels.join("; "),
+            excluded_by.unwrap_or_default(),
+        ])?;
+    }
+    output::finish_csv(writer)?;
+    if config.labels.exclude.is_empty() {
+        warn!("[labels] exclude is empty, so no holder counts as an exchange wallet");
+    }
+    println!("Labeled holders: {} of {}, {} matching [labels] exclude", holders.len(), state.holders.len(), excluded);
+
+    match &options.state {
+        Some(path) => state::save_state_to(&state, path)?,
+        None => state::save_state(&state)?,
+    }
+    info!(output = %options.output.display(), "Holder labels written");
+    Ok(())
+}
+
+/// Removes holders whose labels match `[labels] exclude` from `state` and
+/// writes them, with the label that matched, to `path`. Returns how many
+/// were removed.
+pub fn exclude_labeled(state: &mut ScanState, config: &Config, path: &Path) -> Result<usize> {
+    if state.labels.is_empty() {
+        bail!("the snapshot has no holder labels; run `labels` first");
+    }
+    if config.labels.exclude.is_empty() {
+        bail!("--exclude-labeled needs `exclude` patterns in [labels]");
+    }
+    let mut excluded: Vec<(String, String)> = state
+        .labels
+        .iter()
+        .filter(|(holder, _)| state.holders.contains(*holder))
+        .filter_map(|(holder, labels)| Some((holder.clone(), config.labels.excluded_by(labels)?.to_string())))
+        .collect();
+    excluded.sort();
+    let mut writer = output::csv_writer(path)?;
+    writer.write_record(["address", "balance", "excluded_by", "labels"])?;
+    for (holder, excluded_by) in &excluded {
+        writer.write_record([
+            holder.as_str(),
+            &state.balances.get(holder).copied().unwrap_or(0).to_string(),
+            excluded_by,
+            &state.labels[holder].join("; "),
+        ])?;
+        state.holders.remove(holder);
+        state.balances.remove(holder);
+    }
+    output::finish_csv(writer)?;
+    state.total_holders = state.holders.len() as u64;
+    Ok(excluded.len())
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::output;
+use crate::state::ScanState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortBy {
+    /// Largest balance first
+    Balance,
+    /// Longest-standing holder first
+    FirstSeen,
+}
+
+#[derive(Debug)]
+pub struct LeaderboardRow {
+    /// Rows with equal sort keys share a rank (1, 2, 2, 4, ...)
+    pub rank: usize,
+    pub address: String,
+    pub balance: u64,
+    /// Fraction of the snapshot's total balance
+    pub share: f64,
+    pub first_seen: Option<DateTime<Utc>>,
+    /// Start of the current unbroken holding, when acquisitions have been replayed
+    pub held_since: Option<DateTime<Utc>>,
+    pub usd_value: Option<f64>,
+    /// Provider or strategy that reported the holder, see `Provenance::label`
+    pub source: Option<String>,
+    pub ens_name: Option<String>,
+}
+
+/// The `top` holders ordered by `sort_by`, ties broken by address.
+pub fn leaderboard(state: &ScanState, top: usize, sort_by: SortBy) -> Vec<LeaderboardRow> {
+    let total: u128 = state.balances.values().map(|b| *b as u128).sum();
+    let mut holders: Vec<(&String, u64, Option<DateTime<Utc>>)> = state
+        .holders
+        .iter()
+        .map(|h| (h, state.balances.get(h).copied().unwrap_or(0), state.first_seen.get(h).copied()))
+        .collect();
+    match sort_by {
+        SortBy::Balance => holders.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0))),
+        // Holders without a first-seen time sort last
+        SortBy::FirstSeen => holders.sort_by(|a, b| {
+            a.2.is_none().cmp(&b.2.is_none()).then_with(|| a.2.cmp(&b.2)).then_with(|| a.0.cmp(b.0))
+        }),
+    }
+
+    let mut rows: Vec<LeaderboardRow> = Vec::with_capacity(top.min(holders.len()));
+    for (i, (address, balance, first_seen)) in holders.into_iter().take(top).enumerate() {
+        let tied = rows.last().is_some_and(|prev| match sort_by {
+            SortBy::Balance => prev.balance == balance,
+            SortBy::FirstSeen => prev.first_seen == first_seen,
+        });
+        rows.push(LeaderboardRow {
+            rank: if tied { rows[i - 1].rank } else { i + 1 },
+            address: address.clone(),
+            balance,
+            share: if total > 0 { balance as f64 / total as f64 } else { 0.0 },
+            first_seen,
+            held_since: state.acquisitions.get(address).map(|a| a.held_since),
+            usd_value: state.usd_value(address),
+            source: state.provenance.get(address).map(|p| p.label()),
+            ens_name: None,
+        });
+    }
+    rows
+}
+
+pub fn attach_ens_names(rows: &mut [LeaderboardRow], names: &HashMap<String, String>) {
+    for row in rows {
+        row.ens_name = names.get(&row.address).cloned();
+    }
+}
+
+pub fn save_leaderboard(rows: &[LeaderboardRow], path: &Path) -> Result<()> {
+    let mut writer = output::csv_writer(path)?;
+    writer.write_record([
+        "rank",
+        "address",
+        "ens_name",
+        "balance",
+        "share_pct",
+        "first_seen",
+        "holding_days",
+        "usd_value",
+        "source",
+    ])?;
+    for row in rows {
+        writer.write_record([
+            row.rank.to_string(),
+            row.address.clone(),
+            row.ens_name.clone().unwrap_or_default(),
+            row.balance.to_string(),
+            format!("{:.4}", row.share * 100.0),
+            row.first_seen.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            row.held_since.map(|t| (Utc::now() - t).num_days().to_string()).unwrap_or_default(),
+            row.usd_value.map(|v| format!("{:.2}", v)).unwrap_or_default(),
+            row.source.clone().unwrap_or_default(),
+        ])?;
+    }
+    output::finish_csv(writer)
+}
+
+pub fn print_leaderboard(rows: &[LeaderboardRow]) {
+    println!("{:>5}  {:<44} {:>12} {:>9}", "rank", "holder", "balance", "share");
+    for row in rows {
+        let holder = match &row.ens_name {
+            Some(name) => format!("{} ({})", name, &row.address[..row.address.len().min(10)]),
+            None => row.address.clone(),
+        };
+        println!("{:>5}  {:<44} {:>12} {:>8.2}%", row.rank, holder, row.balance, row.share * 100.0);
+    }
+}
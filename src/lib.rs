@@ -0,0 +1,196 @@
+//! Holder snapshots for the Uniswap V3 positions NFT.
+//!
+//! Downstream services should use the stable types re-exported at the crate
+//! root: [`Snapshot`], [`HolderRecord`], [`Diff`] and [`Allocation`]. They are
+//! covered by semver; the modules below them back the `scanner` binary and may
+//! change in any release.
+
+mod snapshot;
+
+pub use snapshot::{
+    Allocation, AllocationBuilder, AllocationEntry, AllocationStrategy, ChangeKind, Diff, HolderChange, HolderRecord,
+    HolderRecordBuilder, Snapshot, SnapshotBuilder, SnapshotError,
+};
+
+pub use export::{register_format, ExportFormat, HolderRow};
+
+#[doc(hidden)]
+pub mod accounting;
+#[doc(hidden)]
+pub mod acquisition;
+#[doc(hidden)]
+pub mod address;
+#[doc(hidden)]
+pub mod address_set;
+#[doc(hidden)]
+pub mod alchemy;
+#[doc(hidden)]
+pub mod allocate;
+#[doc(hidden)]
+pub mod archive;
+#[doc(hidden)]
+pub mod balances;
+#[doc(hidden)]
+pub mod bridge;
+#[doc(hidden)]
+pub mod bundle;
+#[cfg(feature = "server")]
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod chain_preference;
+#[doc(hidden)]
+pub mod chains;
+#[doc(hidden)]
+pub mod claims;
+#[doc(hidden)]
+pub mod cluster;
+#[doc(hidden)]
+pub mod compare;
+#[doc(hidden)]
+pub mod completeness;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod cost;
+#[cfg(feature = "postgres")]
+#[doc(hidden)]
+pub mod db;
+#[doc(hidden)]
+pub mod delegation;
+#[doc(hidden)]
+pub mod diff;
+#[doc(hidden)]
+pub mod digest;
+#[doc(hidden)]
+pub mod eligibility;
+#[doc(hidden)]
+pub mod endpoints;
+#[doc(hidden)]
+pub mod ens;
+#[doc(hidden)]
+pub mod enumerate;
+#[doc(hidden)]
+pub mod error;
+#[doc(hidden)]
+pub mod etherscan;
+#[cfg(feature = "server")]
+#[doc(hidden)]
+pub mod events;
+#[doc(hidden)]
+pub mod export;
+#[doc(hidden)]
+pub mod fetcher;
+#[doc(hidden)]
+pub mod funding;
+#[cfg(feature = "server")]
+#[doc(hidden)]
+pub mod graphql;
+#[cfg(feature = "server")]
+#[doc(hidden)]
+pub mod health;
+#[doc(hidden)]
+pub mod heartbeat;
+#[doc(hidden)]
+pub mod histogram;
+#[doc(hidden)]
+pub mod holder_store;
+#[doc(hidden)]
+pub mod holder_updates;
+#[doc(hidden)]
+pub mod http;
+#[doc(hidden)]
+pub mod http_cache;
+#[doc(hidden)]
+pub mod identity;
+#[cfg(feature = "server")]
+#[doc(hidden)]
+pub mod ingest;
+#[doc(hidden)]
+pub mod journal;
+#[doc(hidden)]
+pub mod keys;
+#[cfg(feature = "sled")]
+#[doc(hidden)]
+pub mod kv_store;
+#[doc(hidden)]
+pub mod labels;
+#[doc(hidden)]
+pub mod leaderboard;
+#[cfg(feature = "server")]
+#[doc(hidden)]
+pub mod live;
+#[doc(hidden)]
+pub mod logging;
+#[doc(hidden)]
+pub mod merkle;
+#[doc(hidden)]
+pub mod metadata;
+#[doc(hidden)]
+pub mod metrics;
+#[doc(hidden)]
+pub mod multicall;
+#[doc(hidden)]
+pub mod notify;
+#[doc(hidden)]
+pub mod output;
+#[doc(hidden)]
+pub mod overlap;
+#[doc(hidden)]
+pub mod plan;
+#[doc(hidden)]
+pub mod preset;
+#[doc(hidden)]
+pub mod pricing;
+#[doc(hidden)]
+pub mod progress;
+#[cfg(feature = "duckdb")]
+#[doc(hidden)]
+pub mod query;
+#[doc(hidden)]
+pub mod ratelimit;
+#[doc(hidden)]
+pub mod reconcile;
+#[doc(hidden)]
+pub mod replay;
+#[doc(hidden)]
+pub mod report;
+#[doc(hidden)]
+pub mod rpc;
+#[doc(hidden)]
+pub mod scan;
+#[doc(hidden)]
+pub mod schedule;
+#[cfg(feature = "server")]
+#[doc(hidden)]
+pub mod server;
+#[doc(hidden)]
+pub mod sheets;
+#[cfg(any(feature = "kafka", feature = "nats"))]
+#[doc(hidden)]
+pub mod sink;
+#[doc(hidden)]
+pub mod standards;
+#[doc(hidden)]
+pub mod state;
+#[doc(hidden)]
+pub mod stats;
+#[cfg(feature = "cloud")]
+#[doc(hidden)]
+pub mod storage;
+#[doc(hidden)]
+pub mod subgraph;
+#[doc(hidden)]
+pub mod subscribe;
+#[doc(hidden)]
+pub mod sybil;
+#[doc(hidden)]
+pub mod token_owners;
+#[doc(hidden)]
+pub mod traits;
+#[doc(hidden)]
+pub mod watch;
+#[doc(hidden)]
+pub mod wrappers;
+#[doc(hidden)]
+pub mod xlsx;
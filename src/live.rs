@@ -0,0 +1,186 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+
+use crate::server::{read_state, snapshot_state_path};
+use crate::state::ScanState;
+
+/// Where the active snapshot was loaded from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Source {
+    /// `data/state.json`, replaced whenever the scanner writes a new one
+    Live,
+    /// An archived snapshot, by id
+    Archived { id: String },
+}
+
+/// One loaded snapshot. Requests hold an `Arc` to it, so a swap never changes
+/// the data under a request that is already running.
+#[derive(Debug)]
+pub struct ActiveSnapshot {
+    pub source: Source,
+    /// Increases with every swap; part of the response and eligibility cache keys
+    pub version: u64,
+    pub loaded_at: DateTime<Utc>,
+    /// Modification time of the file it was loaded from
+    modified: Option<SystemTime>,
+    pub state: Arc<ScanState>,
+}
+
+/// The snapshot `serve` answers from. A background task loads a new state
+/// file next to the active one and swaps it in once it has parsed, so
+/// refreshes need no restart and a broken file never replaces good data.
+/// Pinning holds the active snapshot until it is unpinned.
+#[derive(Debug)]
+pub struct SnapshotStore {
+    state_path: PathBuf,
+    active: RwLock<Arc<ActiveSnapshot>>,
+    /// The snapshot active before the last swap, for `rollback`
+    previous: Mutex<Option<Arc<ActiveSnapshot>>>,
+    pinned: Mutex<bool>,
+    versions: AtomicU64,
+    /// Modification time of the last live file passed over as incomplete
+    incomplete: Mutex<Option<SystemTime>>,
+}
+
+impl SnapshotStore {
+    pub async fn open(state_path: PathBuf) -> Result<Arc<SnapshotStore>> {
+        let modified = modified(&state_path);
+        let state = read_state(state_path.clone()).await?;
+        let active = ActiveSnapshot {
+            source: Source::Live,
+            version: 1,
+            loaded_at: Utc::now(),
+            modified,
+            state: Arc::new(state),
+        };
+        Ok(Arc::new(SnapshotStore {
+            state_path,
+            active: RwLock::new(Arc::new(active)),
+            previous: Mutex::new(None),
+            pinned: Mutex::new(false),
+            versions: AtomicU64::new(1),
+            incomplete: Mutex::new(None),
+        }))
+    }
+
+    pub fn current(&self) -> Arc<ActiveSnapshot> {
+        self.active.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.current().version
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        *self.pinned.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn previous(&self) -> Option<Arc<ActiveSnapshot>> {
+        self.previous.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn swap(&self, source: Source, modified: Option<SystemTime>, state: Arc<ScanState>) -> Arc<ActiveSnapshot> {
+        let next = Arc::new(ActiveSnapshot {
+            source,
+            version: self.versions.fetch_add(1, Ordering::SeqCst) + 1,
+            loaded_at: Utc::now(),
+            modified,
+            state,
+        });
+        let old = std::mem::replace(&mut *self.active.write().unwrap_or_else(|e| e.into_inner()), next.clone());
+        *self.previous.lock().unwrap_or_else(|e| e.into_inner()) = Some(old);
+        next
+    }
+
+    /// Loads the live state file if it changed since it was last loaded.
+    /// Returns whether a new snapshot was swapped in; does nothing while pinned.
+    /// Checkpoints of a pass still running, and passes that ended with pages
+    /// in quarantine, are passed over so the active snapshot stays whole.
+    pub async fn refresh(&self) -> Result<bool> {
+        let current = self.current();
+        let modified = modified(&self.state_path);
+        if self.is_pinned() || (current.source == Source::Live && current.modified == modified) {
+            return Ok(false);
+        }
+        if modified.is_some() && *self.incomplete.lock().unwrap_or_else(|e| e.into_inner()) == modified {
+            return Ok(false);
+        }
+        let state = read_state(self.state_path.clone()).await?;
+        // An admin may have pinned a snapshot while the file was loading
+        if self.is_pinned() {
+            return Ok(false);
+        }
+        if !state.complete() {
+            debug!(
+                pending_page = state.last_page_key.is_some(),
+                quarantined = state.quarantine.len(),
+                "Keeping the active snapshot; the live state file is not a completed pass"
+            );
+            *self.incomplete.lock().unwrap_or_else(|e| e.into_inner()) = modified;
+            return Ok(false);
+        }
+        let active = self.swap(Source::Live, modified, Arc::new(state));
+        info!(version = active.version, holders = active.state.holders.len(), "Swapped in the live snapshot");
+        Ok(true)
+    }
+
+    /// Serves an archived snapshot until `unpin`.
+    pub async fn pin(&self, id: &str) -> Result<Arc<ActiveSnapshot>> {
+        let path = snapshot_state_path(id)?.with_context(|| format!("unknown snapshot id {}", id))?;
+        let modified = modified(&path);
+        let state = read_state(path).await?;
+        *self.pinned.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        let active = self.swap(Source::Archived { id: id.to_string() }, modified, Arc::new(state));
+        info!(id, version = active.version, "Pinned archived snapshot");
+        Ok(active)
+    }
+
+    /// Swaps the previous snapshot back in and pins it, so the next refresh
+    /// doesn't replace it again.
+    pub fn rollback(&self) -> Result<Arc<ActiveSnapshot>> {
+        let Some(previous) = self.previous() else {
+            bail!("no previous snapshot to roll back to");
+        };
+        *self.pinned.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        let active = self.swap(previous.source.clone(), previous.modified, previous.state.clone());
+        info!(version = active.version, source = ?active.source, "Rolled back to the previous snapshot");
+        Ok(active)
+    }
+
+    /// Follows the live state file again, loading it right away.
+    pub async fn unpin(&self) -> Result<Arc<ActiveSnapshot>> {
+        *self.pinned.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        let modified = modified(&self.state_path);
+        let state = read_state(self.state_path.clone()).await?;
+        let active = self.swap(Source::Live, modified, Arc::new(state));
+        info!(version = active.version, "Unpinned; following the live snapshot");
+        Ok(active)
+    }
+
+    /// Checks the live state file every `interval` and swaps in new versions.
+    pub fn spawn_refresh(self: &Arc<Self>, interval: Duration) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = store.refresh().await {
+                    // Keep serving the active snapshot; the next tick retries
+                    warn!(error = %e, "Could not load the new snapshot");
+                }
+            }
+        });
+    }
+}
+
+fn modified(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
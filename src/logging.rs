@@ -0,0 +1,83 @@
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// Installs the global subscriber. `RUST_LOG` takes precedence over the
+/// verbosity flags when set. With the `otel` feature and
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` set, spans are also exported over OTLP.
+pub fn init(verbose: u8, quiet: bool, format: LogFormat) {
+    let level = if quiet {
+        "warn"
+    } else {
+        match verbose {
+            0 => "info",
+            1 => "debug",
+            _ => "trace",
+        }
+    };
+    // Dependencies (hyper, reqwest) stay at warn so -vv doesn't drown in connection noise
+    // The binary logs as `scanner`, the modules it calls into as the library crate
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,scanner={level},uniswap_nft_holders={level}")));
+
+    let output = match format {
+        LogFormat::Text => fmt::layer().with_writer(std::io::stderr).boxed(),
+        LogFormat::Json => fmt::layer().json().with_writer(std::io::stderr).boxed(),
+    };
+    let registry = tracing_subscriber::registry().with(output.with_filter(filter));
+    #[cfg(feature = "otel")]
+    {
+        let (otel, error) = match otel_layer() {
+            Ok(layer) => (layer, None),
+            Err(e) => (None, Some(e)),
+        };
+        registry.with(otel).init();
+        if let Some(e) = error {
+            tracing::warn!(error = %e, "Could not start OTLP trace export");
+        }
+    }
+    #[cfg(not(feature = "otel"))]
+    registry.init();
+}
+
+/// The OTLP export layer, or `None` when `OTEL_EXPORTER_OTLP_ENDPOINT` isn't
+/// set. Spans go over gRPC in batches; `OTEL_SERVICE_NAME` names the service
+/// (default `scanner`) and the standard `OTEL_TRACES_SAMPLER` variables pick
+/// the sampler.
+#[cfg(feature = "otel")]
+fn otel_layer<S>() -> Result<Option<impl Layer<S>>, opentelemetry::trace::TraceError>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::KeyValue;
+    use opentelemetry_sdk::Resource;
+    use tracing_subscriber::filter::Targets;
+
+    let Some(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok().filter(|e| !e.is_empty()) else {
+        return Ok(None);
+    };
+    let service = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "scanner".to_string());
+    let resource = Resource::default().merge(&Resource::new([KeyValue::new("service.name", service)]));
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .with_trace_config(opentelemetry_sdk::trace::config().with_resource(resource))
+        .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+    // Only the scanner's own spans; the verbosity flags don't thin out traces
+    let targets = Targets::new()
+        .with_target("uniswap_nft_holders", tracing::Level::INFO)
+        .with_target("scanner", tracing::Level::INFO);
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer).with_filter(targets)))
+}
+
+/// Flushes spans still waiting to be exported. Call before exiting.
+pub async fn shutdown() {
+    #[cfg(feature = "otel")]
+    let _ = tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider).await;
+}
@@ -1,218 +1,217 @@
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::Write;
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
-use std::env;
-use std::path::Path;
-use chrono::Utc;
-use serde_json::Value;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct TokenBalance {
-    #[serde(rename = "tokenId")]
-    token_id: String,
-    balance: u64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct OwnerWithBalance {
-    #[serde(rename = "ownerAddress")]
-    owner_address: String,
-    #[serde(rename = "tokenBalances")]
-    token_balances: Vec<TokenBalance>,
+use std::path::PathBuf;
+
+use uniswap_nft_holders::{
+    acquisition, address_set, allocate, archive, balances, bridge, bundle, chain_preference, claims, cluster, compare,
+    delegation, diff, eligibility, enumerate, export, identity, journal, labels, logging, metadata, overlap, preset,
+    pricing, reconcile, report, scan, schedule, sheets, state, stats, subscribe, sybil, watch, wrappers,
+};
+#[cfg(feature = "server")]
+use uniswap_nft_holders::{ingest, server};
+#[cfg(feature = "cloud")]
+use uniswap_nft_holders::storage;
+#[cfg(feature = "duckdb")]
+use uniswap_nft_holders::query;
+
+#[derive(Debug, Parser)]
+#[command(name = "scanner", about = "Scan and analyze NFT holder data", args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Options for the default `scan` command when no subcommand is given
+    #[command(flatten)]
+    scan: scan::ScanOptions,
+    /// Increase log detail (-v for debug, -vv for trace)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Only log warnings and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Log output format
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    log_format: logging::LogFormat,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct AlchemyResponse {
-    #[serde(rename = "pageKey")]
-    page_key: Option<String>,
-    owners: Option<Vec<OwnerWithBalance>>,
-    result: Option<Vec<String>>,  // Some responses might return just a list of addresses
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct ScanState {
-    last_processed_block: u64,
-    last_save_time: chrono::DateTime<Utc>,
-    total_holders: u64,
-    holders: HashSet<String>,
-    last_page_key: Option<String>,
-}
-
-impl Default for ScanState {
-    fn default() -> Self {
-        ScanState {
-            last_processed_block: 0,
-            last_save_time: Utc::now(),
-            total_holders: 0,
-            holders: HashSet::new(),
-            last_page_key: None,
-        }
-    }
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Fetch holders from the provider, resuming from the saved state (default)
+    Scan(scan::ScanOptions),
+    /// Keep running and rescan on a fixed interval, logging deltas between cycles
+    Watch(watch::WatchOptions),
+    /// Keep running and start the `[[schedule]]` targets in scanner.toml at their cron times
+    Schedule(schedule::ScheduleOptions),
+    /// Follow the contract's Transfer logs over a WebSocket RPC subscription and update holders live
+    Subscribe(subscribe::SubscribeOptions),
+    /// Write one merkle claim file per eligible holder for static hosting
+    Claims(claims::ClaimOptions),
+    /// Replay Transfer logs to record when each holder first acquired the token and how long they have held it
+    Acquisition(acquisition::AcquisitionOptions),
+    /// Rebuild the holder set from ownerOf over every token id, without the provider's owners API
+    Enumerate(enumerate::EnumerateOptions),
+    /// Read every holder's on-chain balance through Multicall3, optionally at a past block
+    Balances(balances::BalancesOptions),
+    /// Replace staking and vault contracts in `[[wrapper]]` with the depositors they hold positions for
+    LookThrough(wrappers::LookThroughOptions),
+    /// Track OP-stack bridge deposits/withdrawals of the token and reconcile against the scan
+    Bridge(bridge::BridgeOptions),
+    /// Report which chain holds the majority of each holder's balance for multichain tokens
+    ChainPreference(chain_preference::ChainPreferenceOptions),
+    /// Compare the holder sets of several contracts: intersection, union, exclusive holders and Jaccard similarity
+    Overlap(overlap::OverlapOptions),
+    /// Merge externally supplied address lists (CSV or text) into a named set
+    Import(address_set::ImportOptions),
+    /// List imported address sets, or combine sets and scan results with union, intersect and subtract
+    Sets {
+        #[command(subcommand)]
+        command: address_set::SetsCommand,
+    },
+    /// Report holder concentration: top shares, Gini coefficient, percentiles and histogram
+    Stats(stats::StatsOptions),
+    /// Rewrite the holder list in the configured formats, or write a top-N leaderboard with --top
+    Export(export::ExportOptions),
+    /// Evaluate the eligibility rules in scanner.toml and export per-address pass/fail with the failed rules
+    Eligibility(eligibility::EligibilityOptions),
+    /// Show whether one address is eligible and which rules it fails
+    Check(eligibility::CheckOptions),
+    /// Split a reward budget across holders, or compare policy variants with --what-if
+    Allocate(allocate::AllocateOptions),
+    /// Fetch the token's USD price from CoinGecko or a Chainlink feed and add USD values to the exports
+    Price(pricing::PriceOptions),
+    /// Record the token's name, symbol, decimals and supply, and optionally each token's URI, in the snapshot
+    Metadata(metadata::MetadataOptions),
+    /// Tag holders found in the `[labels]` address-label datasets, such as exchange deposit and hot wallets
+    Labels(labels::LabelOptions),
+    /// Record which hot wallets holders have delegated to in the delegate.xyz registry
+    Delegations(delegation::DelegationOptions),
+    /// Compare the snapshot with an externally produced holder list before signing off a distribution
+    Reconcile(reconcile::ReconcileOptions),
+    /// Flag holders that look like one operator's wallets, with a sybil_score for review before an airdrop
+    Sybil(sybil::SybilOptions),
+    /// Regenerate the snapshot's report tables, or with --format html a standalone report page
+    Report(report::ReportOptions),
+    /// Group holders by the wallet that first funded them and write a cluster id per address
+    Cluster(cluster::ClusterOptions),
+    /// Push the snapshot's summary and top holders to the Google Sheet in `[sheets]`
+    Sheets(sheets::SheetsOptions),
+    /// Serve holders, stats and snapshot diffs over HTTP
+    #[cfg(feature = "server")]
+    Serve(server::ServeOptions),
+    /// Apply Alchemy Notify webhook deliveries to the holder set as they arrive, between full scans
+    #[cfg(feature = "server")]
+    Ingest(ingest::IngestOptions),
+    /// Run SQL over the holders, archived snapshots and their diffs in embedded DuckDB
+    #[cfg(feature = "duckdb")]
+    Query(query::QueryOptions),
+    /// Compare two journaled runs side by side: coverage, timing, requests and holder differences
+    CompareRuns(compare::CompareRunsOptions),
+    /// Inspect the per-run journals of past scans
+    Runs {
+        #[command(subcommand)]
+        command: journal::RunsCommand,
+    },
+    /// Manage archived snapshots: list, soft-delete, and re-scan at a block
+    Archive {
+        #[command(subcommand)]
+        command: archive::ArchiveCommand,
+    },
+    /// Copy data/ to or from the bucket in OUTPUT_URL
+    #[cfg(feature = "cloud")]
+    Sync {
+        #[command(subcommand)]
+        command: storage::SyncCommand,
+    },
+    /// Write a redacted diagnostic bundle to attach to a bug report
+    Bundle,
+    /// Compare two state snapshots and report new, exited, and changed holders
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        /// CSV file to write the per-address changes to
+        #[arg(long, default_value = "data/diff.csv")]
+        output: PathBuf,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
-    
-    // Get Alchemy API key from environment variable
-    let api_key = env::var("ALCHEMY_API_KEY").expect("ALCHEMY_API_KEY must be set");
-    
-    // Load existing state or create new one
-    let mut state = load_state().unwrap_or_default();
-    
-    // Initialize HTTP client with longer timeout
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
-    
-    let mut page_key = state.last_page_key.clone();
-    let contract_address = "0xC36442b4a4522E871399CD717aBDD847Ab11FE88";
-
-    println!("Starting with {} existing holders", state.holders.len());
-    println!("Last page key: {:?}", page_key);
-    
-    let mut page_count = 0;
-    
-    // Fetch all pages of owners
-    loop {
-        page_count += 1;
-        println!("\nFetching page {}", page_count);
-        
-        let url = if let Some(key) = &page_key {
-            format!(
-                "https://opt-mainnet.g.alchemy.com/nft/v3/{}/getOwnersForContract?contractAddress={}&withTokenBalances=true&pageKey={}",
-                api_key, contract_address, key
-            )
-        } else {
-            format!(
-                "https://opt-mainnet.g.alchemy.com/nft/v3/{}/getOwnersForContract?contractAddress={}&withTokenBalances=true",
-                api_key, contract_address
-            )
-        };
-
-        println!("Requesting URL: {}", url);
-
-        let response = client
-            .get(&url)
-            .header("accept", "application/json")
-            .send()
-            .await?;
-            
-        println!("Response status: {}", response.status());
-        
-        let response_text = response.text().await?;
-        
-        // Try to parse as raw JSON first
-        let raw_json: Value = match serde_json::from_str(&response_text) {
-            Ok(json) => {
-                println!("Raw JSON response structure:");
-                println!("{}", serde_json::to_string_pretty(&json)?);
-                json
-            },
-            Err(e) => {
-                println!("Failed to parse response as JSON: {}", e);
-                println!("Raw response: {}", response_text);
-                break;
-            }
-        };
-
-        // Try to get owners from different possible response formats
-        let mut new_owners = Vec::new();
 
-        if let Some(owners) = raw_json.get("owners").and_then(|o| o.as_array()) {
-            println!("Found {} owners in response", owners.len());
-            for owner in owners {
-                if let Some(addr) = owner.get("ownerAddress").and_then(|a| a.as_str()) {
-                    new_owners.push(addr.to_string());
-                }
-            }
-        } else if let Some(result) = raw_json.get("result").and_then(|r| r.as_array()) {
-            println!("Found {} addresses in result", result.len());
-            for addr in result {
-                if let Some(addr_str) = addr.as_str() {
-                    new_owners.push(addr_str.to_string());
-                }
-            }
+    let cli = Cli::parse();
+    logging::init(cli.verbose, cli.quiet, cli.log_format);
+
+    let result = match cli.command.unwrap_or(Command::Scan(cli.scan)) {
+        Command::Scan(options) => match &options.preset {
+            Some(name) => preset::run_preset(name).await,
+            None => scan::run_scan(options).await.map(|_| ()),
+        },
+        Command::Watch(options) => watch::run_watch(options).await,
+        Command::Schedule(options) => schedule::run_schedule(&options).await,
+        Command::Subscribe(options) => subscribe::run_subscribe(&options).await,
+        Command::Claims(options) => {
+            let state = state::load_state()?;
+            claims::export_claims(&state, &options)?;
+            Ok(())
         }
-
-        println!("Parsed {} new owners", new_owners.len());
-
-        if new_owners.is_empty() {
-            println!("No owners found in response");
-            break;
+        Command::Acquisition(options) => acquisition::run_acquisition(&options).await,
+        Command::Bridge(options) => {
+            let state = state::load_state()?;
+            bridge::track_bridge_flows(&options, &state).await?;
+            Ok(())
         }
-
-        // Add the new owners to our state
-        let initial_count = state.holders.len();
-        for owner in &new_owners {
-            state.holders.insert(owner.clone());
+        Command::Enumerate(options) => enumerate::run_enumerate(&options).await,
+        Command::Balances(options) => balances::run_balances(&options).await,
+        Command::LookThrough(options) => wrappers::run_look_through(&options).await,
+        Command::ChainPreference(options) => chain_preference::run_chain_preference(&options),
+        Command::Overlap(options) => overlap::run_overlap(&options),
+        Command::Import(options) => address_set::run_import(&options),
+        Command::Sets { command } => address_set::run_sets(&command),
+        Command::Stats(options) => stats::run_stats(&options),
+        Command::Export(options) => export::run_export(&options).await,
+        Command::Eligibility(options) => eligibility::run_eligibility(&options),
+        Command::Check(options) => eligibility::run_check(&options),
+        Command::Allocate(options) => allocate::run_allocate(&options),
+        Command::Price(options) => pricing::run_price(&options).await,
+        Command::Metadata(options) => metadata::run_metadata(&options).await,
+        Command::Labels(options) => labels::run_labels(&options),
+        Command::Delegations(options) => delegation::run_delegations(&options).await,
+        Command::Reconcile(options) => reconcile::run_reconcile(&options),
+        Command::Sybil(options) => sybil::run_sybil(&options).await,
+        Command::Report(options) => report::run_report(&options),
+        Command::Cluster(options) => cluster::run_cluster(&options).await,
+        Command::Sheets(options) => sheets::run_sheets(&options).await,
+        #[cfg(feature = "server")]
+        Command::Serve(options) => server::run_serve(&options).await,
+        #[cfg(feature = "server")]
+        Command::Ingest(options) => ingest::run_ingest(&options).await,
+        #[cfg(feature = "duckdb")]
+        Command::Query(options) => query::run_query(&options),
+        Command::Runs { command } => journal::run_runs(&command),
+        Command::CompareRuns(options) => compare::run_compare_runs(&options),
+        Command::Archive { command } => archive::run_archive(&command).await,
+        #[cfg(feature = "cloud")]
+        Command::Sync { command } => storage::run_sync(&command).await,
+        Command::Bundle => {
+            let path = bundle::create_bundle(None)?;
+            tracing::info!("Diagnostic bundle written to {}", path.display());
+            Ok(())
         }
-        let new_count = state.holders.len();
-        println!("Added {} new unique owners", new_count - initial_count);
-        
-        // Update state
-        state.total_holders = state.holders.len() as u64;
-        state.last_save_time = Utc::now();
-        
-        println!("Current unique owners count: {}", state.holders.len());
-        
-        // Try to get the next page key
-        page_key = raw_json.get("pageKey")
-            .and_then(|k| k.as_str())
-            .map(String::from);
-            
-        // Save the page key in state
-        state.last_page_key = page_key.clone();
-        
-        // Save progress after each page
-        save_state(&state)?;
-        save_holders_to_file(&state.holders)?;
-        
-        if page_key.is_none() {
-            println!("No more pages to fetch");
-            break;
+        Command::Diff { old, new, output } => {
+            let identity = identity::HolderIdentity::from_env()?;
+            let old_state = state::load_state_from(&old)?;
+            let new_state = state::load_state_from(&new)?;
+            let changes = diff::diff_states(&identity.regroup(&old_state), &identity.regroup(&new_state));
+            diff::save_diff(&changes, &output)?;
+            diff::print_summary(&changes);
+            tracing::info!("Diff saved to {}", output.display());
+            Ok(())
         }
-        
-        // Add a delay between requests to avoid rate limiting
-        println!("Waiting before next request...");
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    }
-
-    println!("\nScan complete!");
-    println!("Results saved to data/state.json and data/uniswap_v3_holders.txt");
-    println!("Total unique holders: {}", state.total_holders);
-    println!("Total pages processed: {}", page_count);
-
-    Ok(())
-}
-
-fn save_state(state: &ScanState) -> Result<()> {
-    std::fs::create_dir_all("data")?;
-    let mut state_file = File::create("data/state.json")?;
-    serde_json::to_writer_pretty(&mut state_file, &state)?;
-    Ok(())
-}
-
-fn save_holders_to_file(holders: &HashSet<String>) -> Result<()> {
-    std::fs::create_dir_all("data")?;
-    let mut holders_file = File::create("data/uniswap_v3_holders.txt")?;
-    let mut holders_vec: Vec<_> = holders.iter().collect();
-    holders_vec.sort(); // Sort addresses for consistent output
-    for holder in holders_vec {
-        writeln!(holders_file, "{}", holder)?;
-    }
-    Ok(())
-}
+    };
 
-fn load_state() -> Result<ScanState> {
-    let state_path = Path::new("data/state.json");
-    if state_path.exists() {
-        let file = File::open(state_path)?;
-        Ok(serde_json::from_reader(file)?)
-    } else {
-        Ok(ScanState::default())
+    if let Err(e) = &result {
+        bundle::offer_bundle(e);
     }
+    logging::shutdown().await;
+    result
 }
@@ -0,0 +1,86 @@
+use anyhow::{bail, Result};
+use web3::signing::keccak256;
+
+pub type Hash = [u8; 32];
+
+/// Parses a 0x-prefixed 20-byte hex address.
+pub fn parse_address(address: &str) -> Result<[u8; 20]> {
+    let bytes = hex::decode(address.trim_start_matches("0x"))?;
+    if bytes.len() != 20 {
+        bail!("address {} is {} bytes, expected 20", address, bytes.len());
+    }
+    let mut out = [0u8; 20];
+    out.copy_from_slice(&bytes);
+    Ok(out)
+}
+
+/// Leaf encoding compatible with OpenZeppelin's `StandardMerkleTree` for
+/// `["address", "uint256"]`: `keccak256(keccak256(abi.encode(account, amount)))`.
+pub fn leaf_hash(address: &[u8; 20], amount: u128) -> Hash {
+    let mut encoded = [0u8; 64];
+    encoded[12..32].copy_from_slice(address);
+    encoded[48..64].copy_from_slice(&amount.to_be_bytes());
+    keccak256(&keccak256(&encoded))
+}
+
+fn hash_pair(a: &Hash, b: &Hash) -> Hash {
+    // Sorted pairs make the proof order-independent, as `MerkleProof.verify` expects
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+    let mut buf = [0u8; 64];
+    buf[..32].copy_from_slice(low);
+    buf[32..].copy_from_slice(high);
+    keccak256(&buf)
+}
+
+pub fn to_hex(hash: &Hash) -> String {
+    format!("0x{}", hex::encode(hash))
+}
+
+#[derive(Debug)]
+pub struct MerkleTree {
+    // layers[0] are the sorted leaves, the last layer holds the root
+    layers: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(mut leaves: Vec<Hash>) -> Self {
+        leaves.sort();
+        let mut layers = vec![leaves];
+        while layers.last().is_some_and(|layer| layer.len() > 1) {
+            let next = layers
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => hash_pair(a, b),
+                    // An odd node is promoted to the next layer unchanged
+                    [a] => *a,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+        MerkleTree { layers }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.layers.last().and_then(|layer| layer.first()).copied().unwrap_or([0u8; 32])
+    }
+
+    /// Position of a leaf in the sorted leaf layer.
+    pub fn leaf_index(&self, leaf: &Hash) -> Option<usize> {
+        self.layers[0].binary_search(leaf).ok()
+    }
+
+    pub fn proof(&self, mut index: usize) -> Vec<Hash> {
+        let mut proof = Vec::new();
+        for layer in &self.layers[..self.layers.len() - 1] {
+            let sibling = index ^ 1;
+            if let Some(hash) = layer.get(sibling) {
+                proof.push(*hash);
+            }
+            index /= 2;
+        }
+        proof
+    }
+}
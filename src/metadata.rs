@@ -0,0 +1,241 @@
+use anyhow::{bail, Context, Result};
+use base64::Engine as _;
+use chrono::Utc;
+use futures::StreamExt;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use web3::types::{Address, U256};
+
+use crate::http;
+use crate::multicall::{self, Multicall};
+use crate::rpc;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, TokenMetadata};
+
+pub const METADATA_CACHE_PATH: &str = "data/token_metadata.json";
+
+const DEFAULT_IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
+// Metadata documents fetched at once
+const ATTRIBUTE_FETCHES: usize = 8;
+
+#[derive(Debug, clap::Args)]
+pub struct MetadataOptions {
+    /// Also fetch tokenURI for every held token id; ids already cached are not fetched again
+    #[arg(long)]
+    pub token_uris: bool,
+    /// Also fetch the metadata document behind every token URI and record its
+    /// `attributes`, for `trait` eligibility rules. Implies --token-uris
+    #[arg(long)]
+    pub token_attributes: bool,
+    /// Ignore cached metadata and token URIs
+    #[arg(long)]
+    pub refresh: bool,
+    /// Snapshot to annotate (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Block to read at, usually the block the snapshot was taken at
+    #[arg(long)]
+    pub block: Option<u64>,
+}
+
+/// Reads `name()`, `symbol()`, `decimals()` and `totalSupply()` in one
+/// Multicall3 batch. Calls the contract doesn't implement come back empty.
+pub async fn contract_metadata(
+    web3: &web3::Web3<web3::transports::Http>,
+    multicall: &Multicall,
+    contract: Address,
+    block: Option<u64>,
+) -> Result<TokenMetadata> {
+    let selectors = [
+        [0x06, 0xfd, 0xde, 0x03], // name()
+        [0x95, 0xd8, 0x9b, 0x41], // symbol()
+        [0x31, 0x3c, 0xe5, 0x67], // decimals()
+        [0x18, 0x16, 0x0d, 0xdd], // totalSupply()
+    ];
+    let calls: Vec<(Address, Vec<u8>)> = selectors.iter().map(|s| (contract, s.to_vec())).collect();
+    let mut results = multicall.aggregate(web3, &calls, block).await?.into_iter();
+    let mut next = || results.next().flatten();
+    let name = next().and_then(|d| multicall::string(&d));
+    let symbol = next().and_then(|d| multicall::string(&d));
+    let decimals = next().and_then(|d| multicall::word(&d)).filter(|d| *d <= U256::from(u8::MAX));
+    let total_supply = next().and_then(|d| multicall::word(&d));
+    Ok(TokenMetadata {
+        contract: format!("{:?}", contract),
+        name,
+        symbol,
+        decimals: decimals.map(|d| d.low_u32() as u8),
+        total_supply: total_supply.map(|s| s.to_string()),
+        fetched_at: Utc::now(),
+        token_uris: HashMap::new(),
+        token_attributes: HashMap::new(),
+    })
+}
+
+/// Token ids as stored in the state: hex with `0x` (Alchemy) or decimal.
+fn parse_token_id(id: &str) -> Option<U256> {
+    match id.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(id).ok(),
+    }
+}
+
+/// Fetches the JSON document a token URI points at: `data:` URIs are decoded
+/// in place, `ipfs://` goes through `IPFS_GATEWAY` and `{id}` is replaced with
+/// the id as 64 hex digits, as ERC-1155 URIs expect.
+async fn fetch_document(client: &reqwest::Client, uri: &str, id: U256) -> Result<serde_json::Value> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (header, body) = data.split_once(',').context("malformed data: URI")?;
+        let body = if header.ends_with(";base64") {
+            base64::engine::general_purpose::STANDARD.decode(body.trim())?
+        } else {
+            body.as_bytes().to_vec()
+        };
+        return Ok(serde_json::from_slice(&body)?);
+    }
+    let uri = uri.replace("{id}", &format!("{:064x}", id));
+    let url = match uri.strip_prefix("ipfs://") {
+        Some(path) => {
+            let gateway = env::var("IPFS_GATEWAY").unwrap_or_else(|_| DEFAULT_IPFS_GATEWAY.to_string());
+            format!("{}/{}", gateway.trim_end_matches('/'), path.trim_start_matches("ipfs/"))
+        }
+        None if uri.starts_with("http://") || uri.starts_with("https://") => uri,
+        None => bail!("unsupported token URI scheme in {}", uri),
+    };
+    Ok(client.get(&url).send().await?.error_for_status()?.json().await?)
+}
+
+/// `attributes: [{"trait_type": .., "value": ..}]` of a metadata document,
+/// with numbers and booleans as their JSON text. Entries without a
+/// `trait_type` are skipped.
+fn attributes(document: &serde_json::Value) -> BTreeMap<String, String> {
+    let entries = document.get("attributes").and_then(|a| a.as_array()).map(Vec::as_slice).unwrap_or_default();
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let name = entry.get("trait_type")?.as_str()?;
+            let value = match entry.get("value")? {
+                serde_json::Value::String(value) => value.clone(),
+                serde_json::Value::Null => return None,
+                value => value.to_string(),
+            };
+            Some((name.to_string(), value))
+        })
+        .collect()
+}
+
+fn load_cache() -> HashMap<String, TokenMetadata> {
+    match File::open(METADATA_CACHE_PATH) {
+        Ok(file) => serde_json::from_reader(file).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+fn save_cache(cache: &HashMap<String, TokenMetadata>) -> Result<()> {
+    fs::create_dir_all("data")?;
+    serde_json::to_writer_pretty(File::create(METADATA_CACHE_PATH)?, cache)?;
+    Ok(())
+}
+
+/// Fetches the contract's metadata, with `--token-uris` the URI of every held
+/// token and with `--token-attributes` the attributes behind it, and stores
+/// it in the snapshot so exports and reports can name the token. Results are
+/// cached in `data/token_metadata.json`.
+pub async fn run_metadata(options: &MetadataOptions) -> Result<()> {
+    let mut state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    let contract: Address = CONTRACT_ADDRESS.parse().context("invalid contract address")?;
+    let key = format!("{:?}", contract);
+    let mut cache = load_cache();
+    let web3 = rpc::connect()?;
+    let multicall = Multicall::from_env()?;
+
+    let cached = cache.get(&key).filter(|_| !options.refresh).cloned();
+    let mut metadata = match cached {
+        Some(metadata) => metadata,
+        None => {
+            let fetched = contract_metadata(&web3, &multicall, contract, options.block).await?;
+            info!(name = ?fetched.name, symbol = ?fetched.symbol, "Fetched contract metadata");
+            fetched
+        }
+    };
+
+    if options.token_uris || options.token_attributes {
+        let ids: BTreeSet<&String> = state.tokens.values().flatten().collect();
+        let missing: Vec<(&String, U256)> = ids
+            .into_iter()
+            .filter(|id| !metadata.token_uris.contains_key(*id))
+            .filter_map(|id| Some((id, parse_token_id(id)?)))
+            .collect();
+        let token_ids: Vec<U256> = missing.iter().map(|(_, id)| *id).collect();
+        let uris = multicall.token_uris(&web3, contract, &token_ids, options.block).await?;
+        let mut failed = 0;
+        for ((id, _), uri) in missing.iter().zip(uris) {
+            match uri {
+                Some(uri) => {
+                    metadata.token_uris.insert((*id).clone(), uri);
+                }
+                None => failed += 1,
+            }
+        }
+        if failed > 0 {
+            warn!(tokens = failed, "tokenURI failed for some tokens");
+        }
+        info!(fetched = missing.len() - failed, cached = metadata.token_uris.len(), "Token URIs updated");
+    }
+
+    if options.token_attributes {
+        let client = http::client(Duration::from_secs(30))?;
+        let ids: BTreeSet<&String> = state.tokens.values().flatten().collect();
+        let missing: Vec<(String, U256, String)> = ids
+            .into_iter()
+            .filter(|id| !metadata.token_attributes.contains_key(*id))
+            .filter_map(|id| Some((id.clone(), parse_token_id(id)?, metadata.token_uris.get(id)?.clone())))
+            .collect();
+        let fetched: Vec<(String, Result<serde_json::Value>)> = futures::stream::iter(missing)
+            .map(|(id, token_id, uri)| {
+                let client = &client;
+                async move { (id, fetch_document(client, &uri, token_id).await) }
+            })
+            .buffer_unordered(ATTRIBUTE_FETCHES)
+            .collect()
+            .await;
+        let mut failed = 0;
+        for (id, document) in fetched {
+            match document {
+                Ok(document) => {
+                    metadata.token_attributes.insert(id, attributes(&document));
+                }
+                Err(e) => {
+                    debug!(id = %id, error = %format!("{:#}", e), "Could not fetch token metadata document");
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            warn!(tokens = failed, "Metadata documents failed for some tokens; run again to retry them");
+        }
+        info!(cached = metadata.token_attributes.len(), "Token attributes updated");
+    }
+
+    cache.insert(key, metadata.clone());
+    save_cache(&cache)?;
+    // The snapshot only keeps URIs of tokens it holds
+    let held: HashSet<&String> = state.tokens.values().flatten().collect();
+    metadata.token_uris.retain(|id, _| held.contains(id));
+    metadata.token_attributes.retain(|id, _| held.contains(id));
+    state.metadata = Some(metadata);
+    match &options.state {
+        Some(path) => state::save_state_to(&state, path)?,
+        None => {
+            state::save_state(&state)?;
+            state::save_holders_to_file(&state)?;
+        }
+    }
+    Ok(())
+}
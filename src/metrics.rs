@@ -0,0 +1,201 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+
+use crate::cost::{CostConfig, CostReport};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Timeout,
+    Connect,
+    RateLimited,
+    Auth,
+    ClientError,
+    ServerError,
+    Body,
+    Parse,
+}
+
+impl ErrorKind {
+    pub fn from_reqwest(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            ErrorKind::Timeout
+        } else if error.is_connect() {
+            ErrorKind::Connect
+        } else {
+            ErrorKind::Body
+        }
+    }
+
+    pub fn from_status(status: reqwest::StatusCode) -> Option<Self> {
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            Some(ErrorKind::RateLimited)
+        } else if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            Some(ErrorKind::Auth)
+        } else if status.is_server_error() {
+            Some(ErrorKind::ServerError)
+        } else if status.is_client_error() {
+            Some(ErrorKind::ClientError)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, ErrorKind::Auth | ErrorKind::ClientError | ErrorKind::Parse)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RequestSample {
+    pub page: u64,
+    pub attempt: u32,
+    pub endpoint: String,
+    /// Position of the API key in the configured key list (never the key itself)
+    pub key: usize,
+    pub latency_ms: u64,
+    pub bytes: u64,
+    pub status: Option<u16>,
+    pub error: Option<ErrorKind>,
+    /// API method, e.g. `getOwnersForContract`, `graphql` or `query`
+    pub method: String,
+    /// Pinned to a past block
+    pub archive: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PerformanceReport {
+    pub provider: String,
+    pub requests: u64,
+    pub pages: u64,
+    pub retries: u64,
+    pub total_bytes: u64,
+    pub avg_bytes: u64,
+    pub p50_latency_ms: u64,
+    pub p95_latency_ms: u64,
+    pub max_latency_ms: u64,
+    pub error_rate: f64,
+    pub errors_by_kind: BTreeMap<ErrorKind, u64>,
+    pub p50_latency_ms_by_endpoint: BTreeMap<String, u64>,
+    pub cost: CostReport,
+}
+
+#[derive(Debug)]
+pub struct ProviderMetrics {
+    provider: String,
+    samples: Vec<RequestSample>,
+    prices: CostConfig,
+    cost: CostReport,
+}
+
+impl ProviderMetrics {
+    pub fn new(provider: &str, prices: CostConfig) -> Self {
+        ProviderMetrics { provider: provider.to_string(), samples: Vec::new(), prices, cost: CostReport::default() }
+    }
+
+    pub fn record(&mut self, sample: RequestSample) {
+        self.prices.record(&mut self.cost, &self.provider, &sample.endpoint, &sample.method, sample.archive);
+        self.samples.push(sample);
+    }
+
+    /// Estimated cost of the requests so far.
+    pub fn cost(&self) -> &CostReport {
+        &self.cost
+    }
+
+    // A body that arrived fine but couldn't be parsed is charged to the request that fetched it
+    pub fn record_parse_error(&mut self) {
+        if let Some(sample) = self.samples.last_mut() {
+            sample.error.get_or_insert(ErrorKind::Parse);
+        }
+    }
+
+    pub fn report(&self) -> PerformanceReport {
+        let requests = self.samples.len() as u64;
+        let mut latencies: Vec<u64> = self.samples.iter().map(|s| s.latency_ms).collect();
+        latencies.sort_unstable();
+        let total_bytes: u64 = self.samples.iter().map(|s| s.bytes).sum();
+
+        let mut errors_by_kind = BTreeMap::new();
+        for error in self.samples.iter().filter_map(|s| s.error) {
+            *errors_by_kind.entry(error).or_insert(0) += 1;
+        }
+        let errors: u64 = errors_by_kind.values().sum();
+
+        let mut latencies_by_endpoint: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        for sample in &self.samples {
+            latencies_by_endpoint.entry(sample.endpoint.clone()).or_default().push(sample.latency_ms);
+        }
+        let p50_latency_ms_by_endpoint = latencies_by_endpoint
+            .into_iter()
+            .map(|(endpoint, mut latencies)| {
+                latencies.sort_unstable();
+                (endpoint, percentile(&latencies, 50.0))
+            })
+            .collect();
+
+        PerformanceReport {
+            provider: self.provider.clone(),
+            requests,
+            pages: self.samples.iter().filter(|s| s.attempt == 1).count() as u64,
+            retries: self.samples.iter().filter(|s| s.attempt > 1).count() as u64,
+            total_bytes,
+            avg_bytes: total_bytes.checked_div(requests).unwrap_or(0),
+            p50_latency_ms: percentile(&latencies, 50.0),
+            p95_latency_ms: percentile(&latencies, 95.0),
+            max_latency_ms: latencies.last().copied().unwrap_or(0),
+            error_rate: if requests > 0 { errors as f64 / requests as f64 } else { 0.0 },
+            errors_by_kind,
+            p50_latency_ms_by_endpoint,
+            cost: self.cost.clone(),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<PerformanceReport> {
+        std::fs::create_dir_all("data")?;
+        let report = self.report();
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, &serde_json::json!({
+            "summary": &report,
+            "requests": &self.samples,
+        }))?;
+        Ok(report)
+    }
+}
+
+// Nearest-rank percentile over an already sorted slice
+pub(crate) fn percentile(sorted: &[u64], pct: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+pub fn print_report(report: &PerformanceReport) {
+    println!("\nProvider performance ({})", report.provider);
+    println!("  Requests: {} ({} pages, {} retries)", report.requests, report.pages, report.retries);
+    println!("  Latency: p50 {} ms, p95 {} ms, max {} ms", report.p50_latency_ms, report.p95_latency_ms, report.max_latency_ms);
+    println!("  Payload: {} bytes total, {} bytes avg", report.total_bytes, report.avg_bytes);
+    println!("  Error rate: {:.2}%", report.error_rate * 100.0);
+    for (kind, count) in &report.errors_by_kind {
+        println!("    {:?}: {}", kind, count);
+    }
+    if report.p50_latency_ms_by_endpoint.len() > 1 {
+        for (endpoint, latency) in &report.p50_latency_ms_by_endpoint {
+            println!("  {}: p50 {} ms", endpoint, latency);
+        }
+    }
+    let cost = &report.cost;
+    if cost.compute_units > 0.0 {
+        println!("  Compute units: {:.0}", cost.compute_units);
+    }
+    if cost.archive_requests > 0 {
+        println!("  Archive requests: {}", cost.archive_requests);
+    }
+    if let Some(usd) = cost.estimated_usd {
+        println!("  Estimated cost: ${:.4}", usd);
+    }
+}
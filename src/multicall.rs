@@ -0,0 +1,184 @@
+use anyhow::{bail, Context, Result};
+use std::env;
+use tracing::debug;
+use web3::ethabi::{self, ParamType, Token};
+use web3::transports::Http;
+use web3::types::{Address, U256};
+use web3::Web3;
+
+use crate::rpc;
+
+// Multicall3 is deployed at the same address on Optimism, mainnet and most EVM chains
+const MULTICALL3_ADDRESS: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+
+const DEFAULT_CHUNK_SIZE: usize = 500;
+
+/// Batches `eth_call`s through Multicall3's `aggregate3`, so reading a value
+/// for every holder takes one RPC call per chunk instead of one per address.
+/// `MULTICALL_ADDRESS` overrides the contract and `MULTICALL_CHUNK_SIZE` the
+/// calls per batch (default 500); lower it if the node rejects large calls.
+#[derive(Debug, Clone)]
+pub struct Multicall {
+    address: Address,
+    chunk_size: usize,
+}
+
+impl Multicall {
+    pub fn from_env() -> Result<Multicall> {
+        let address = env::var("MULTICALL_ADDRESS").unwrap_or_else(|_| MULTICALL3_ADDRESS.to_string());
+        let chunk_size =
+            env::var("MULTICALL_CHUNK_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_CHUNK_SIZE);
+        Ok(Multicall {
+            address: address.parse().with_context(|| format!("invalid MULTICALL_ADDRESS {}", address))?,
+            chunk_size: chunk_size.max(1),
+        })
+    }
+
+    /// Runs the calls at `block` (latest when `None`) and returns each call's
+    /// return data in order, or `None` where that call reverted.
+    pub async fn aggregate(
+        &self,
+        web3: &Web3<Http>,
+        calls: &[(Address, Vec<u8>)],
+        block: Option<u64>,
+    ) -> Result<Vec<Option<Vec<u8>>>> {
+        // aggregate3((address target, bool allowFailure, bytes callData)[])
+        let selector = [0x82, 0xad, 0x56, 0xcb];
+        let mut results = Vec::with_capacity(calls.len());
+        for (index, chunk) in calls.chunks(self.chunk_size).enumerate() {
+            let tuples = chunk
+                .iter()
+                .map(|(target, data)| {
+                    Token::Tuple(vec![Token::Address(*target), Token::Bool(true), Token::Bytes(data.clone())])
+                })
+                .collect();
+            let mut data = selector.to_vec();
+            data.extend(ethabi::encode(&[Token::Array(tuples)]));
+            let output = rpc::call_at(web3, self.address, data, block)
+                .await
+                .with_context(|| format!("Multicall3 batch {} failed", index))?;
+            let decoded = ethabi::decode(
+                &[ParamType::Array(Box::new(ParamType::Tuple(vec![ParamType::Bool, ParamType::Bytes])))],
+                &output,
+            )?;
+            let Some(Token::Array(returned)) = decoded.into_iter().next() else {
+                bail!("unexpected aggregate3 response");
+            };
+            if returned.len() != chunk.len() {
+                bail!("aggregate3 returned {} results for {} calls", returned.len(), chunk.len());
+            }
+            for token in returned {
+                results.push(match token {
+                    Token::Tuple(fields) => match fields.as_slice() {
+                        [Token::Bool(true), Token::Bytes(data)] => Some(data.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                });
+            }
+            debug!(batch = index, calls = chunk.len(), "Multicall batch done");
+        }
+        Ok(results)
+    }
+
+    /// `balanceOf(owner)` of an ERC-20 or ERC-721 contract for each owner.
+    pub async fn balances_of(
+        &self,
+        web3: &Web3<Http>,
+        token: Address,
+        owners: &[Address],
+        block: Option<u64>,
+    ) -> Result<Vec<Option<U256>>> {
+        let calls: Vec<(Address, Vec<u8>)> = owners
+            .iter()
+            .map(|owner| {
+                let mut data = vec![0x70, 0xa0, 0x82, 0x31];
+                data.extend(ethabi::encode(&[Token::Address(*owner)]));
+                (token, data)
+            })
+            .collect();
+        Ok(self.aggregate(web3, &calls, block).await?.into_iter().map(|r| r.and_then(|d| word(&d))).collect())
+    }
+
+    /// `tokenByIndex(index)` of an ERC-721 Enumerable contract for each index.
+    pub async fn tokens_by_index(
+        &self,
+        web3: &Web3<Http>,
+        token: Address,
+        indexes: &[U256],
+        block: Option<u64>,
+    ) -> Result<Vec<Option<U256>>> {
+        let calls: Vec<(Address, Vec<u8>)> = indexes
+            .iter()
+            .map(|index| {
+                let mut data = vec![0x4f, 0x6c, 0xcc, 0xe7];
+                data.extend(ethabi::encode(&[Token::Uint(*index)]));
+                (token, data)
+            })
+            .collect();
+        Ok(self.aggregate(web3, &calls, block).await?.into_iter().map(|r| r.and_then(|d| word(&d))).collect())
+    }
+
+    /// `ownerOf(tokenId)` of an ERC-721 contract for each token id; `None` for
+    /// burned or never-minted ids.
+    pub async fn owners_of(
+        &self,
+        web3: &Web3<Http>,
+        token: Address,
+        token_ids: &[U256],
+        block: Option<u64>,
+    ) -> Result<Vec<Option<Address>>> {
+        let calls: Vec<(Address, Vec<u8>)> = token_ids
+            .iter()
+            .map(|id| {
+                let mut data = vec![0x63, 0x52, 0x21, 0x1e];
+                data.extend(ethabi::encode(&[Token::Uint(*id)]));
+                (token, data)
+            })
+            .collect();
+        Ok(self
+            .aggregate(web3, &calls, block)
+            .await?
+            .into_iter()
+            .map(|r| r.filter(|d| d.len() >= 32).map(|d| Address::from_slice(&d[12..32])))
+            .collect())
+    }
+
+    /// `tokenURI(tokenId)` of an ERC-721 contract for each token id.
+    pub async fn token_uris(
+        &self,
+        web3: &Web3<Http>,
+        token: Address,
+        token_ids: &[U256],
+        block: Option<u64>,
+    ) -> Result<Vec<Option<String>>> {
+        let calls: Vec<(Address, Vec<u8>)> = token_ids
+            .iter()
+            .map(|id| {
+                let mut data = vec![0xc8, 0x7b, 0x56, 0xdd];
+                data.extend(ethabi::encode(&[Token::Uint(*id)]));
+                (token, data)
+            })
+            .collect();
+        Ok(self.aggregate(web3, &calls, block).await?.into_iter().map(|r| r.and_then(|d| string(&d))).collect())
+    }
+}
+
+pub(crate) fn word(data: &[u8]) -> Option<U256> {
+    (data.len() >= 32).then(|| U256::from_big_endian(&data[..32]))
+}
+
+/// Decodes an ABI `string` return value, falling back to the `bytes32` some
+/// older tokens return from `name()` and `symbol()`.
+pub(crate) fn string(data: &[u8]) -> Option<String> {
+    if let Ok(tokens) = ethabi::decode(&[ParamType::String], data) {
+        if let Some(Token::String(value)) = tokens.into_iter().next() {
+            return Some(value);
+        }
+    }
+    if data.len() != 32 {
+        return None;
+    }
+    let end = data.iter().position(|b| *b == 0).unwrap_or(32);
+    String::from_utf8(data[..end].to_vec()).ok().filter(|s| !s.is_empty())
+}
@@ -0,0 +1,243 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::env;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::config::{Config, DiscordConfig, TelegramConfig};
+use crate::http;
+
+// New holders listed in chat summaries
+pub const TOP_NEW_HOLDERS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum NotificationEvent {
+    #[serde(rename = "scan.completed")]
+    ScanCompleted,
+    #[serde(rename = "scan.failed")]
+    ScanFailed,
+    /// The holder count moved by more than the configured threshold since the last completed scan
+    #[serde(rename = "holders.changed")]
+    HoldersChanged,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanSummary {
+    pub contract: String,
+    pub run_id: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub pages: u64,
+    pub holders: u64,
+    /// Holders at the end of the last completed scan, if there was one
+    pub previous_holders: Option<u64>,
+    pub holder_delta: Option<i64>,
+    pub total_balance: u128,
+    /// Largest holders first seen during this run, biggest first
+    pub top_new_holders: Vec<NewHolder>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NewHolder {
+    pub address: String,
+    pub balance: u64,
+}
+
+/// The `limit` largest holders whose first-seen time is at or after `since`.
+pub fn top_new_holders(state: &crate::state::ScanState, since: DateTime<Utc>, limit: usize) -> Vec<NewHolder> {
+    let mut new: Vec<NewHolder> = state
+        .holders
+        .iter()
+        .filter(|h| state.first_seen.get(*h).is_some_and(|seen| *seen >= since))
+        .map(|h| NewHolder { address: h.clone(), balance: state.balances.get(h).copied().unwrap_or(0) })
+        .collect();
+    new.sort_by(|a, b| b.balance.cmp(&a.balance).then_with(|| a.address.cmp(&b.address)));
+    new.truncate(limit);
+    new
+}
+
+impl ScanSummary {
+    /// Relative change in holders since the previous completed scan, in percent.
+    pub fn holder_change_pct(&self) -> Option<f64> {
+        let previous = self.previous_holders.filter(|p| *p > 0)?;
+        Some((self.holders as f64 - previous as f64) / previous as f64 * 100.0)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Payload<'a> {
+    event: NotificationEvent,
+    summary: &'a ScanSummary,
+}
+
+/// Every configured notification channel: generic webhooks from the
+/// environment and the Discord/Telegram sections of the config file.
+#[derive(Debug)]
+pub struct Notifiers {
+    client: reqwest::Client,
+    webhooks: Option<Webhooks>,
+    discord: Option<DiscordConfig>,
+    telegram: Option<TelegramConfig>,
+}
+
+impl Notifiers {
+    /// `None` when no channel is configured. Fails when the `[http]` settings
+    /// don't make a client.
+    pub fn new(config: &Config) -> Result<Option<Self>> {
+        let webhooks = Webhooks::from_env()?;
+        let discord = config.notifications.discord.clone();
+        let telegram = config.notifications.telegram.clone();
+        if webhooks.is_none() && discord.is_none() && telegram.is_none() {
+            return Ok(None);
+        }
+        let client = http::client(Duration::from_secs(10))?;
+        Ok(Some(Notifiers { client, webhooks, discord, telegram }))
+    }
+
+    pub async fn notify_scan(&self, summary: &ScanSummary) {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.notify_scan(summary).await;
+        }
+        if let Some(discord) = &self.discord {
+            let body = serde_json::json!({ "content": chat_summary(summary, Markup::Discord) });
+            self.post("discord", &discord.webhook_url, &body).await;
+        }
+        if let Some(telegram) = &self.telegram {
+            let url = format!("https://api.telegram.org/bot{}/sendMessage", telegram.bot_token);
+            let body = serde_json::json!({
+                "chat_id": telegram.chat_id,
+                "text": chat_summary(summary, Markup::TelegramHtml),
+                "parse_mode": "HTML",
+                "disable_web_page_preview": true,
+            });
+            self.post("telegram", &url, &body).await;
+        }
+    }
+
+    async fn post(&self, channel: &str, url: &str, body: &serde_json::Value) {
+        match self.client.post(url).json(body).send().await {
+            Ok(response) if response.status().is_success() => debug!(channel, "Notification sent"),
+            Ok(response) => warn!(channel, status = %response.status(), "Notification rejected"),
+            Err(e) => warn!(channel, error = %e.without_url(), "Could not send notification"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Markup {
+    Discord,
+    TelegramHtml,
+}
+
+fn chat_summary(summary: &ScanSummary, markup: Markup) -> String {
+    let bold = |text: &str| match markup {
+        Markup::Discord => format!("**{}**", text),
+        Markup::TelegramHtml => format!("<b>{}</b>", escape_html(text)),
+    };
+    let code = |text: &str| match markup {
+        Markup::Discord => format!("`{}`", text),
+        Markup::TelegramHtml => format!("<code>{}</code>", escape_html(text)),
+    };
+
+    let mut lines = Vec::new();
+    match &summary.error {
+        Some(error) => {
+            lines.push(bold(&format!("Holder scan failed for {}", summary.contract)));
+            lines.push(format!("Error: {}", code(error)));
+            lines.push(format!("Holders at last checkpoint: {}", summary.holders));
+        }
+        None => {
+            lines.push(bold(&format!("Holder scan completed for {}", summary.contract)));
+            let delta = match summary.holder_delta {
+                Some(delta) => format!(" ({:+} since last run)", delta),
+                None => String::new(),
+            };
+            lines.push(format!("Total holders: {}{}", summary.holders, delta));
+            if !summary.top_new_holders.is_empty() {
+                lines.push("Top new whales:".to_string());
+                for (rank, holder) in summary.top_new_holders.iter().enumerate() {
+                    lines.push(format!("{}. {}: {} tokens", rank + 1, code(&holder.address), holder.balance));
+                }
+            }
+        }
+    }
+    lines.push(format!("Run {} ({} pages)", code(&summary.run_id), summary.pages));
+    lines.join("\n")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Generic JSON webhooks from `WEBHOOK_URLS` (comma-separated). Each receives
+/// `{"event": ..., "summary": {...}}` for completed and failed scans, and an
+/// extra `holders.changed` event when the holder count moved by more than
+/// `WEBHOOK_HOLDER_CHANGE_PCT` percent.
+#[derive(Debug)]
+pub struct Webhooks {
+    client: reqwest::Client,
+    urls: Vec<String>,
+    change_threshold_pct: Option<f64>,
+}
+
+impl Webhooks {
+    pub fn from_env() -> Result<Option<Self>> {
+        let urls: Vec<String> = env::var("WEBHOOK_URLS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+        if urls.is_empty() {
+            return Ok(None);
+        }
+        let client = http::client(Duration::from_secs(10))?;
+        let change_threshold_pct = env::var("WEBHOOK_HOLDER_CHANGE_PCT").ok().and_then(|v| v.parse().ok());
+        Ok(Some(Webhooks { client, urls, change_threshold_pct }))
+    }
+
+    /// Sends the events that apply to `summary`. Delivery failures are logged;
+    /// a broken webhook never fails the scan.
+    pub async fn notify_scan(&self, summary: &ScanSummary) {
+        let event = match summary.error {
+            Some(_) => NotificationEvent::ScanFailed,
+            None => NotificationEvent::ScanCompleted,
+        };
+        self.send(event, summary).await;
+
+        if let (None, Some(threshold), Some(change)) = (&summary.error, self.change_threshold_pct, summary.holder_change_pct()) {
+            if change.abs() > threshold {
+                self.send(NotificationEvent::HoldersChanged, summary).await;
+            }
+        }
+    }
+
+    async fn send(&self, event: NotificationEvent, summary: &ScanSummary) {
+        let payload = Payload { event, summary };
+        for url in &self.urls {
+            let mut delivered = false;
+            for attempt in 1..=3 {
+                match self.client.post(url).json(&payload).send().await {
+                    Ok(response) if response.status().is_success() => {
+                        delivered = true;
+                        break;
+                    }
+                    Ok(response) => debug!(attempt, status = %response.status(), "Webhook rejected notification"),
+                    Err(e) => debug!(attempt, error = %e.without_url(), "Webhook request failed"),
+                }
+                tokio::time::sleep(Duration::from_secs(attempt)).await;
+            }
+            if !delivered {
+                warn!(?event, host = %host(url), "Could not deliver webhook notification");
+            }
+        }
+    }
+}
+
+// Webhook URLs often embed a token in the path, so only the host is logged
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?']).next().unwrap_or(rest)
+}
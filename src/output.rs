@@ -0,0 +1,112 @@
+use anyhow::{bail, Context, Result};
+use flate2::write::GzEncoder;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "" | "none" => Ok(Compression::None),
+            "gz" | "gzip" => Ok(Compression::Gzip),
+            "zst" | "zstd" => Ok(Compression::Zstd),
+            other => bail!("unknown compression '{}' (use none, gz or zst)", other),
+        }
+    }
+
+    /// Compression applied to exports, from `EXPORT_COMPRESSION`.
+    pub fn from_env() -> Result<Self> {
+        Compression::parse(&env::var("EXPORT_COMPRESSION").unwrap_or_default())
+    }
+
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+/// Appends the configured compression suffix to an export path
+/// (`data/age_distribution.csv` becomes `data/age_distribution.csv.zst`).
+/// Fails when `EXPORT_COMPRESSION` names no known compression.
+pub fn export_path(path: &str) -> Result<String> {
+    let compression = Compression::from_env().context("EXPORT_COMPRESSION")?;
+    Ok(format!("{}{}", path, compression.suffix()))
+}
+
+/// An export file that is compressed on the fly according to its extension.
+/// Call `finish` to flush the compressed trailer; dropping it may lose data.
+pub enum ExportWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl ExportWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match Compression::from_path(path) {
+            Compression::None => ExportWriter::Plain(file),
+            Compression::Gzip => ExportWriter::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Compression::Zstd => ExportWriter::Zstd(zstd::Encoder::new(file, 3)?),
+        })
+    }
+
+    pub fn finish(self) -> Result<()> {
+        let mut file = match self {
+            ExportWriter::Plain(file) => file,
+            ExportWriter::Gzip(encoder) => encoder.finish()?,
+            ExportWriter::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl Write for ExportWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ExportWriter::Plain(w) => w.write(buf),
+            ExportWriter::Gzip(w) => w.write(buf),
+            ExportWriter::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ExportWriter::Plain(w) => w.flush(),
+            ExportWriter::Gzip(w) => w.flush(),
+            ExportWriter::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+pub fn csv_writer(path: impl AsRef<Path>) -> Result<csv::Writer<ExportWriter>> {
+    Ok(csv::Writer::from_writer(ExportWriter::create(path)?))
+}
+
+pub fn finish_csv(writer: csv::Writer<ExportWriter>) -> Result<()> {
+    writer.into_inner().map_err(|e| e.into_error())?.finish()
+}
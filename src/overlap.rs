@@ -0,0 +1,136 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::address;
+use crate::state;
+
+#[derive(Debug, clap::Args)]
+pub struct OverlapOptions {
+    /// Holder set as NAME=PATH, repeated for each contract. PATH is a scan state
+    /// (`.json`) or a holder list with one address per line
+    #[arg(long = "holders", value_name = "NAME=PATH", required = true, num_args = 1, value_parser = parse_holder_set)]
+    pub sets: Vec<(String, PathBuf)>,
+    /// Directory the intersection, union and exclusive address lists are written to
+    #[arg(long, default_value = "data/overlap")]
+    pub output: PathBuf,
+}
+
+fn parse_holder_set(value: &str) -> Result<(String, PathBuf), String> {
+    let (name, path) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=PATH, got '{}'", value))?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("'{}' is not a valid set name (letters, digits, - and _)", name));
+    }
+    Ok((name.to_string(), PathBuf::from(path)))
+}
+
+/// Normalized holder addresses of a scan state or a plain address list.
+/// Lines of a list that aren't addresses are skipped with a warning.
+pub fn load_holder_set(path: &Path) -> Result<BTreeSet<String>> {
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let state = state::load_state_from(path)?;
+        return state.holders.iter().map(|h| address::normalize(h)).collect();
+    }
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut holders = BTreeSet::new();
+    let mut invalid = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let address = line.trim();
+        if address.is_empty() || address.starts_with('#') {
+            continue;
+        }
+        match address::normalize(address) {
+            Ok(address) => {
+                holders.insert(address);
+            }
+            Err(_) => invalid += 1,
+        }
+    }
+    if invalid > 0 {
+        warn!(list = %path.display(), lines = invalid, "Skipped lines that aren't valid addresses");
+    }
+    Ok(holders)
+}
+
+#[derive(Debug)]
+pub struct Overlap {
+    pub intersection: BTreeSet<String>,
+    pub union: BTreeSet<String>,
+    /// Per set, the addresses found in no other set
+    pub exclusive: Vec<BTreeSet<String>>,
+}
+
+pub fn overlap(sets: &[BTreeSet<String>]) -> Overlap {
+    let union: BTreeSet<String> = sets.iter().flatten().cloned().collect();
+    let intersection = union.iter().filter(|a| sets.iter().all(|s| s.contains(*a))).cloned().collect();
+    let exclusive = sets
+        .iter()
+        .enumerate()
+        .map(|(i, set)| {
+            set.iter()
+                .filter(|a| sets.iter().enumerate().all(|(j, other)| j == i || !other.contains(*a)))
+                .cloned()
+                .collect()
+        })
+        .collect();
+    Overlap { intersection, union, exclusive }
+}
+
+/// |A ∩ B| / |A ∪ B|, or 0 when both sets are empty.
+pub fn jaccard(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let shared = a.intersection(b).count();
+    let total = a.len() + b.len() - shared;
+    if total > 0 { shared as f64 / total as f64 } else { 0.0 }
+}
+
+fn write_addresses(path: &Path, addresses: &BTreeSet<String>) -> Result<()> {
+    let mut file = std::io::BufWriter::new(File::create(path)?);
+    for address in addresses {
+        writeln!(file, "{}", address)?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+pub fn run_overlap(options: &OverlapOptions) -> Result<()> {
+    if options.sets.len() < 2 {
+        bail!("overlap needs at least two --holders sets");
+    }
+    let names: Vec<&str> = options.sets.iter().map(|(name, _)| name.as_str()).collect();
+    let sets = options
+        .sets
+        .iter()
+        .map(|(_, path)| load_holder_set(path))
+        .collect::<Result<Vec<_>>>()?;
+    let result = overlap(&sets);
+
+    fs::create_dir_all(&options.output)?;
+    write_addresses(&options.output.join("intersection.txt"), &result.intersection)?;
+    write_addresses(&options.output.join("union.txt"), &result.union)?;
+    for (name, exclusive) in names.iter().zip(&result.exclusive) {
+        write_addresses(&options.output.join(format!("only_{}.txt", name)), exclusive)?;
+    }
+
+    println!("{:<20} {:>10} {:>10}", "set", "holders", "exclusive");
+    for ((name, set), exclusive) in names.iter().zip(&sets).zip(&result.exclusive) {
+        println!("{:<20} {:>10} {:>10}", name, set.len(), exclusive.len());
+    }
+    println!();
+    println!("In all sets: {}", result.intersection.len());
+    println!("In any set: {}", result.union.len());
+    println!();
+    println!("Jaccard similarity");
+    for (i, a) in sets.iter().enumerate() {
+        for (j, b) in sets.iter().enumerate().skip(i + 1) {
+            println!("  {} / {}: {:.4}", names[i], names[j], jaccard(a, b));
+        }
+    }
+    info!(output = %options.output.display(), "Overlap address lists written");
+    Ok(())
+}
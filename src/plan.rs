@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use std::time::Duration;
+use tracing::info;
+
+use crate::ratelimit::RateLimiter;
+use crate::rpc;
+use crate::state::ScanState;
+
+/// Owners returned per `getOwnersForContract` page; override with
+/// `--owners-per-page` if the provider pages differently.
+pub const DEFAULT_OWNERS_PER_PAGE: u64 = 50_000;
+
+#[derive(Debug)]
+pub struct ScanPlan {
+    pub estimated_holders: u64,
+    pub holders_source: &'static str,
+    pub pages: u64,
+    pub requests: u64,
+    pub budget_units: f64,
+    pub estimated_duration: Duration,
+}
+
+/// Estimates the work a scan will take without calling the provider's paged
+/// API. The holder estimate comes from the previous scan when there is one,
+/// else from `--total-supply`, else from one `totalSupply()` RPC call.
+pub async fn estimate(
+    contract: &str,
+    state: &ScanState,
+    total_supply: Option<u64>,
+    owners_per_page: u64,
+    limiter: &RateLimiter,
+) -> Result<ScanPlan> {
+    let (estimated_holders, holders_source) = if !state.holders.is_empty() {
+        (state.holders.len() as u64, "previous scan")
+    } else if let Some(supply) = total_supply {
+        (supply, "--total-supply (upper bound)")
+    } else {
+        let web3 = rpc::connect()?;
+        let supply = rpc::total_supply(&web3, contract.parse().context("invalid contract address")?).await?;
+        (supply.low_u64(), "totalSupply() (upper bound)")
+    };
+
+    let pages = estimated_holders.div_ceil(owners_per_page.max(1)).max(1);
+    let (budget_units, estimated_duration) = limiter.plan(pages, "getOwnersForContract");
+    Ok(ScanPlan {
+        estimated_holders,
+        holders_source,
+        pages,
+        requests: pages,
+        budget_units,
+        estimated_duration,
+    })
+}
+
+pub fn print_plan(plan: &ScanPlan, limiter: &RateLimiter) {
+    info!("Dry run: nothing will be fetched or written");
+    println!("Estimated holders:  {} (from {})", plan.estimated_holders, plan.holders_source);
+    println!("Pages / requests:   {} / {}", plan.pages, plan.requests);
+    println!("Budget:             {:.0} {}", plan.budget_units, limiter.unit_name());
+    println!("Minimum duration:   {:?} at the configured rate limit", plan.estimated_duration);
+}
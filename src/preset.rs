@@ -0,0 +1,250 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use tracing::{info, warn};
+use web3::types::{Address, U256};
+
+use crate::config;
+use crate::output;
+use crate::rpc;
+use crate::standards;
+
+pub const PRESETS_DIR: &str = "data/presets";
+
+// Blocks per eth_getLogs request when a source doesn't set its own
+const DEFAULT_CHUNK_SIZE: u64 = 10_000;
+
+// Presets shipped with the scanner, by name
+const BUILT_IN: [(&str, &str); 1] = [("btb", include_str!("presets/btb.toml"))];
+
+/// A named set of contracts scanned together. Built-in presets live in
+/// `src/presets/`; `[presets.<name>]` in `scanner.toml` defines new ones or
+/// fills in and overrides the sources of a built-in one by name.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub sources: Vec<PresetSource>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    #[default]
+    Token,
+    /// LP token of a pool; its holders are the liquidity providers
+    Lp,
+    /// Staking contract's receipt token, or its stake events as a custom standard
+    Staking,
+}
+
+impl SourceKind {
+    fn label(&self) -> &'static str {
+        match self {
+            SourceKind::Token => "token",
+            SourceKind::Lp => "lp",
+            SourceKind::Staking => "staking",
+        }
+    }
+}
+
+/// One contract of a preset. Fields left out take the built-in preset's value
+/// when overriding one, else the defaults noted below.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PresetSource {
+    pub name: String,
+    /// Default `token`
+    pub kind: Option<SourceKind>,
+    /// Read through `<CHAIN>_RPC_URL`; default `optimism`
+    pub chain: Option<String>,
+    pub address: Option<String>,
+    /// Deployment block, so no transfer is missed; default 0
+    pub from_block: Option<u64>,
+    /// Built-in or `[events.custom]` standard; default the contract's
+    /// `[events.contracts]` entry, else `transfer`
+    pub standard: Option<String>,
+    pub chunk_size: Option<u64>,
+}
+
+impl PresetSource {
+    fn merge(&mut self, other: &PresetSource) {
+        self.kind = other.kind.or(self.kind);
+        self.chain = other.chain.clone().or(self.chain.take());
+        self.address = other.address.clone().or(self.address.take());
+        self.from_block = other.from_block.or(self.from_block);
+        self.standard = other.standard.clone().or(self.standard.take());
+        self.chunk_size = other.chunk_size.or(self.chunk_size);
+    }
+
+    fn chain(&self) -> &str {
+        self.chain.as_deref().unwrap_or("optimism")
+    }
+}
+
+/// Per-source summary written to `manifest.json`.
+#[derive(Debug, Serialize)]
+struct SourceResult {
+    name: String,
+    kind: SourceKind,
+    chain: String,
+    address: String,
+    from_block: u64,
+    to_block: u64,
+    holders: usize,
+}
+
+pub fn preset_names(config: &HashMap<String, Preset>) -> Vec<String> {
+    let mut names: Vec<String> = BUILT_IN.iter().map(|(name, _)| name.to_string()).collect();
+    names.extend(config.keys().cloned());
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The preset called `name`: the built-in one with `[presets.<name>]` merged
+/// over it, or the configured one alone.
+pub fn load_preset(name: &str, config: &HashMap<String, Preset>) -> Result<Preset> {
+    let built_in = BUILT_IN.iter().find(|(built_in, _)| built_in.eq_ignore_ascii_case(name));
+    let configured = config.iter().find(|(configured, _)| configured.eq_ignore_ascii_case(name)).map(|(_, p)| p);
+    let mut preset: Preset = match (built_in, configured) {
+        (Some((_, text)), _) => toml::from_str(text).with_context(|| format!("built-in preset {}", name))?,
+        (None, Some(preset)) => return Ok(preset.clone()),
+        (None, None) => bail!("unknown preset '{}' (available: {})", name, preset_names(config).join(", ")),
+    };
+    if let Some(configured) = configured {
+        if !configured.description.is_empty() {
+            preset.description = configured.description.clone();
+        }
+        for source in &configured.sources {
+            match preset.sources.iter_mut().find(|s| s.name == source.name) {
+                Some(existing) => existing.merge(source),
+                None => preset.sources.push(source.clone()),
+            }
+        }
+    }
+    Ok(preset)
+}
+
+/// Replays every source of the preset up to each chain's latest block and
+/// writes `data/presets/<name>/`: one `<source>.csv` of balances per source,
+/// `holders.csv` with every address and its balance in each source, and
+/// `manifest.json` recording the blocks read.
+pub async fn run_preset(name: &str) -> Result<()> {
+    let config = config::load_config()?;
+    let preset = load_preset(name, &config.presets)?;
+    let (sources, missing): (Vec<&PresetSource>, Vec<&PresetSource>) =
+        preset.sources.iter().partition(|s| s.address.as_deref().is_some_and(|a| !a.trim().is_empty()));
+    for source in &missing {
+        warn!(source = %source.name, "No address configured for preset source, skipping");
+    }
+    if sources.is_empty() {
+        bail!("preset '{}' has no source with an address; set them under [presets.{}] in scanner.toml", name, name);
+    }
+    info!(preset = name, sources = sources.len(), "{}", preset.description);
+
+    let dir = Path::new(PRESETS_DIR).join(name.to_lowercase());
+    std::fs::create_dir_all(&dir)?;
+    let mut latest: HashMap<String, u64> = HashMap::new();
+    let mut results = Vec::new();
+    // address -> balance per source, in source order
+    let mut combined: BTreeMap<String, Vec<Option<U256>>> = BTreeMap::new();
+    for (i, source) in sources.iter().enumerate() {
+        let address = source.address.as_deref().unwrap_or_default().trim();
+        let contract: Address =
+            address.parse().with_context(|| format!("preset source {}: invalid address {}", source.name, address))?;
+        let web3 = rpc::connect_chain(source.chain())?;
+        // One block per chain, so sources on the same chain line up
+        let to_block = match latest.get(source.chain()) {
+            Some(block) => *block,
+            None => {
+                let block = web3.eth().block_number().await?.as_u64();
+                latest.insert(source.chain().to_string(), block);
+                block
+            }
+        };
+        let events = match &source.standard {
+            Some(standard) => config.events.events_named(standard, address)?,
+            None => config.events.events_for(address)?,
+        };
+        let from_block = source.from_block.unwrap_or(0);
+        let range = (from_block, to_block, source.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE));
+        info!(source = %source.name, chain = source.chain(), from_block, to_block, "Replaying preset source");
+
+        let mut balances: HashMap<Address, U256> = HashMap::new();
+        standards::replay_transfers(&web3, contract, &events, range, |_, from, to, amount| {
+            if !from.is_zero() {
+                let balance = balances.entry(from).or_default();
+                *balance = balance.saturating_sub(amount);
+            }
+            if !to.is_zero() {
+                *balances.entry(to).or_default() += amount;
+            }
+        })
+        .await?;
+
+        let mut holders: Vec<(String, U256)> = balances
+            .into_iter()
+            .filter(|(_, balance)| !balance.is_zero())
+            .map(|(address, balance)| (format!("{:?}", address), balance))
+            .collect();
+        holders.sort();
+        let mut writer = output::csv_writer(dir.join(format!("{}.csv", source.name)))?;
+        writer.write_record(["address", "balance"])?;
+        for (holder, balance) in &holders {
+            writer.write_record([holder.clone(), balance.to_string()])?;
+            combined.entry(holder.clone()).or_insert_with(|| vec![None; sources.len()])[i] = Some(*balance);
+        }
+        output::finish_csv(writer)?;
+        info!(source = %source.name, holders = holders.len(), "Preset source replayed");
+
+        results.push(SourceResult {
+            name: source.name.clone(),
+            kind: source.kind.unwrap_or_default(),
+            chain: source.chain().to_string(),
+            address: address.to_string(),
+            from_block,
+            to_block,
+            holders: holders.len(),
+        });
+    }
+
+    let mut writer = output::csv_writer(dir.join("holders.csv"))?;
+    let mut header = vec!["address".to_string(), "sources".to_string()];
+    header.extend(sources.iter().map(|s| s.name.clone()));
+    writer.write_record(&header)?;
+    for (address, balances) in &combined {
+        let mut record = vec![address.clone(), balances.iter().flatten().count().to_string()];
+        record.extend(balances.iter().map(|b| b.map(|b| b.to_string()).unwrap_or_default()));
+        writer.write_record(&record)?;
+    }
+    output::finish_csv(writer)?;
+
+    let manifest = serde_json::json!({
+        "preset": name,
+        "description": preset.description,
+        "created_at": Utc::now(),
+        "holders": combined.len(),
+        "sources": results,
+        "skipped": missing.iter().map(|s| &s.name).collect::<Vec<_>>(),
+    });
+    std::fs::write(dir.join("manifest.json"), serde_json::to_string_pretty(&manifest)?)?;
+
+    println!("Preset {}: {} addresses across {} sources", name, combined.len(), results.len());
+    for result in &results {
+        println!(
+            "  {:<28} {:<8} {:<10} {:>9} holders at block {}",
+            result.name,
+            result.kind.label(),
+            result.chain,
+            result.holders,
+            result.to_block
+        );
+    }
+    info!(output = %dir.display(), "Preset snapshot written");
+    Ok(())
+}
@@ -0,0 +1,279 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::Duration;
+use web3::types::{Address, U256};
+use tracing::{debug, info};
+
+use crate::config;
+use crate::http;
+use crate::ratelimit::{Budget, RateLimiter};
+use crate::rpc;
+use crate::state::{self, ScanState, Valuation};
+
+pub const PRICE_CACHE_PATH: &str = "data/price_cache.json";
+
+const COINGECKO_API_URL: &str = "https://api.coingecko.com/api/v3";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    #[default]
+    Coingecko,
+    /// A Chainlink aggregator on Optimism, read through `OPTIMISM_RPC_URL`
+    Chainlink,
+}
+
+impl PriceSource {
+    fn name(self) -> &'static str {
+        match self {
+            PriceSource::Coingecko => "coingecko",
+            PriceSource::Chainlink => "chainlink",
+        }
+    }
+}
+
+/// `[pricing]` in `scanner.toml`. For CoinGecko set exactly one of
+/// `coingecko_id` (a coin), `platform` + `contract` (a token by address) or
+/// `nft_id` (a collection, valued at its floor price). For Chainlink set
+/// `feed` to the token's USD aggregator.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PricingConfig {
+    #[serde(default)]
+    pub source: PriceSource,
+    /// Chainlink aggregator (proxy) address
+    pub feed: Option<String>,
+    pub coingecko_id: Option<String>,
+    /// CoinGecko asset platform, e.g. `optimistic-ethereum`
+    pub platform: Option<String>,
+    pub contract: Option<String>,
+    pub nft_id: Option<String>,
+    /// Balances are divided by 10^decimals before pricing; 0 for NFTs
+    #[serde(default)]
+    pub decimals: u32,
+    /// How long a fetched CoinGecko price is reused
+    #[serde(default = "default_cache_secs")]
+    pub cache_secs: u64,
+}
+
+fn default_cache_secs() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct CachedPrice {
+    price_usd: f64,
+    fetched_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+enum Query {
+    Coin(String),
+    Token { platform: String, contract: String },
+    Nft(String),
+}
+
+impl Query {
+    fn from_config(config: &PricingConfig) -> Result<Query> {
+        match (&config.coingecko_id, &config.platform, &config.contract, &config.nft_id) {
+            (Some(id), None, None, None) => Ok(Query::Coin(id.clone())),
+            (None, Some(platform), Some(contract), None) => {
+                Ok(Query::Token { platform: platform.clone(), contract: contract.to_lowercase() })
+            }
+            (None, None, None, Some(id)) => Ok(Query::Nft(id.clone())),
+            _ => bail!("[pricing] needs exactly one of coingecko_id, platform + contract, or nft_id"),
+        }
+    }
+
+    fn cache_key(&self) -> String {
+        match self {
+            Query::Coin(id) => format!("coin:{}", id),
+            Query::Token { platform, contract } => format!("token:{}:{}", platform, contract),
+            Query::Nft(id) => format!("nft:{}", id),
+        }
+    }
+
+    fn path(&self) -> String {
+        match self {
+            Query::Coin(id) => format!("/simple/price?ids={}&vs_currencies=usd", id),
+            Query::Token { platform, contract } => {
+                format!("/simple/token_price/{}?contract_addresses={}&vs_currencies=usd", platform, contract)
+            }
+            Query::Nft(id) => format!("/nfts/{}", id),
+        }
+    }
+
+    fn parse(&self, body: &serde_json::Value) -> Option<f64> {
+        match self {
+            Query::Coin(id) => body.get(id)?.get("usd")?.as_f64(),
+            Query::Token { contract, .. } => body.get(contract)?.get("usd")?.as_f64(),
+            Query::Nft(_) => body.get("floor_price")?.get("usd")?.as_f64(),
+        }
+    }
+}
+
+/// CoinGecko client with a file cache and a request rate limit.
+/// `COINGECKO_API_URL` overrides the endpoint (e.g. the pro API) and
+/// `COINGECKO_API_KEY` is sent with every request.
+pub struct PriceClient {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    limiter: RateLimiter,
+    cache: HashMap<String, CachedPrice>,
+}
+
+impl PriceClient {
+    pub fn from_env() -> Result<PriceClient> {
+        let rps = env::var("COINGECKO_RPS").ok().and_then(|v| v.parse::<f64>().ok()).filter(|v| *v > 0.0);
+        let cache = match File::open(PRICE_CACHE_PATH) {
+            Ok(file) => serde_json::from_reader(file).unwrap_or_default(),
+            Err(_) => HashMap::new(),
+        };
+        Ok(PriceClient {
+            client: http::client(Duration::from_secs(20))?,
+            base_url: env::var("COINGECKO_API_URL").unwrap_or_else(|_| COINGECKO_API_URL.to_string()),
+            api_key: env::var("COINGECKO_API_KEY").ok().filter(|k| !k.is_empty()),
+            // The public API allows a few requests per minute without a key
+            limiter: RateLimiter::new(Budget::Requests, rps.unwrap_or(0.2), 1.0),
+            cache,
+        })
+    }
+
+    /// USD price per whole token, from the cache when it is younger than `cache_secs`.
+    pub async fn price_usd(&mut self, config: &PricingConfig) -> Result<(f64, DateTime<Utc>)> {
+        let query = Query::from_config(config)?;
+        let key = query.cache_key();
+        if let Some(cached) = self.cache.get(&key) {
+            let age = Utc::now() - cached.fetched_at;
+            if age.num_seconds() >= 0 && (age.num_seconds() as u64) < config.cache_secs {
+                debug!(key, "Using cached price");
+                return Ok((cached.price_usd, cached.fetched_at));
+            }
+        }
+
+        self.limiter.acquire("coingecko").await;
+        let mut request = self.client.get(format!("{}{}", self.base_url, query.path()));
+        if let Some(key) = &self.api_key {
+            let header = if self.base_url.contains("pro-api") { "x-cg-pro-api-key" } else { "x-cg-demo-api-key" };
+            request = request.header(header, key);
+        }
+        let response = request.send().await?.error_for_status().context("CoinGecko request failed")?;
+        let body: serde_json::Value = response.json().await?;
+        let price_usd = query.parse(&body).with_context(|| format!("CoinGecko returned no USD price for {}", key))?;
+
+        let fetched_at = Utc::now();
+        self.cache.insert(key, CachedPrice { price_usd, fetched_at });
+        fs::create_dir_all("data")?;
+        serde_json::to_writer_pretty(File::create(PRICE_CACHE_PATH)?, &self.cache)?;
+        Ok((price_usd, fetched_at))
+    }
+}
+
+/// Reads `latestRoundData()` of a Chainlink aggregator, at `block` when given.
+/// Returns the USD price and the time the round was last updated.
+pub async fn chainlink_price(feed: &str, block: Option<u64>) -> Result<(f64, DateTime<Utc>)> {
+    let web3 = rpc::connect()?;
+    let feed: Address = feed.parse().with_context(|| format!("invalid Chainlink feed address {}", feed))?;
+
+    // decimals()
+    let output = rpc::call_at(&web3, feed, vec![0x31, 0x3c, 0xe5, 0x67], block).await?;
+    anyhow::ensure!(output.len() >= 32, "decimals() returned {} bytes", output.len());
+    let decimals = U256::from_big_endian(&output[..32]).low_u32();
+
+    // latestRoundData() -> (roundId, answer, startedAt, updatedAt, answeredInRound)
+    let output = rpc::call_at(&web3, feed, vec![0xfe, 0xaf, 0x96, 0x8c], block)
+        .await
+        .context("Chainlink latestRoundData call failed")?;
+    anyhow::ensure!(output.len() >= 160, "latestRoundData() returned {} bytes", output.len());
+    // The answer is an int256; a set top bit means a negative price
+    if output[32] & 0x80 != 0 {
+        bail!("Chainlink feed {:?} reported a negative price", feed);
+    }
+    let answer = U256::from_big_endian(&output[32..64]);
+    let updated_at = U256::from_big_endian(&output[96..128]).low_u64();
+    if answer.is_zero() || updated_at == 0 {
+        let at = block.map(|b| format!(" at block {}", b)).unwrap_or_default();
+        bail!("Chainlink feed {:?} has no completed round{}", feed, at);
+    }
+    let price_usd = answer.to_string().parse::<f64>()? / 10f64.powi(decimals as i32);
+    let updated_at = DateTime::from_timestamp(updated_at as i64, 0).context("invalid round timestamp")?;
+    Ok((price_usd, updated_at))
+}
+
+#[derive(Debug, clap::Args)]
+pub struct PriceOptions {
+    /// Ignore cached prices
+    #[arg(long)]
+    pub refresh: bool,
+    /// Snapshot to price (defaults to data/state.json), e.g. an archived snapshot's state.json
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Read the Chainlink feed at this block, usually the block the snapshot was taken at
+    #[arg(long)]
+    pub block: Option<u64>,
+}
+
+/// Prices the token and stores the valuation in the snapshot, so exports
+/// gain a USD column and `--min-usd` filters work.
+pub async fn run_price(options: &PriceOptions) -> Result<()> {
+    let mut pricing = config::load_config()?
+        .pricing
+        .with_context(|| format!("no [pricing] section in {}", config::config_path().display()))?;
+    if options.refresh {
+        pricing.cache_secs = 0;
+    }
+    let (price_usd, priced_at) = match pricing.source {
+        PriceSource::Coingecko => {
+            if options.block.is_some() {
+                bail!("--block needs `source = \"chainlink\"`; CoinGecko prices can't be pinned to a block");
+            }
+            PriceClient::from_env()?.price_usd(&pricing).await?
+        }
+        PriceSource::Chainlink => {
+            let feed = pricing.feed.as_deref().context("[pricing] with source = \"chainlink\" needs `feed`")?;
+            chainlink_price(feed, options.block).await?
+        }
+    };
+
+    let mut state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    state.valuation = Some(Valuation {
+        source: pricing.source.name().to_string(),
+        price_usd,
+        decimals: pricing.decimals,
+        priced_at,
+        block: options.block,
+    });
+    match &options.state {
+        Some(path) => state::save_state_to(&state, path)?,
+        None => {
+            state::save_state(&state)?;
+            state::save_holders_to_file(&state)?;
+        }
+    }
+    let total: f64 = state.holders.iter().map(|h| state.usd_value(h).unwrap_or(0.0)).sum();
+    info!(
+        source = pricing.source.name(),
+        price_usd,
+        block = ?options.block,
+        total_usd = format!("{:.2}", total),
+        "Holdings valued"
+    );
+    Ok(())
+}
+
+/// Fails unless the state has been priced, for commands taking `--min-usd`.
+pub fn require_valuation(state: &ScanState) -> Result<()> {
+    if state.valuation.is_none() {
+        bail!("the snapshot has no USD valuation; run `scanner price` first");
+    }
+    Ok(())
+}
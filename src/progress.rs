@@ -0,0 +1,55 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::time::{Duration, Instant};
+
+/// Terminal progress display for a scan. Draws nothing when stderr is not a
+/// terminal or when disabled, so piped and logged runs are unaffected.
+pub struct ScanProgress {
+    bar: ProgressBar,
+    started: Instant,
+    pages: u64,
+    has_total: bool,
+}
+
+impl ScanProgress {
+    pub fn new(enabled: bool, initial_holders: u64) -> Self {
+        let bar = if enabled {
+            ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr())
+        } else {
+            ProgressBar::hidden()
+        };
+        bar.set_style(
+            ProgressStyle::with_template("{spinner} [{elapsed_precise}] {pos} holders | {msg}")
+                .expect("valid progress template"),
+        );
+        bar.set_position(initial_holders);
+        bar.enable_steady_tick(Duration::from_millis(200));
+        ScanProgress { bar, started: Instant::now(), pages: 0, has_total: false }
+    }
+
+    /// Switches to a bounded bar with an ETA once the provider reports how many
+    /// holders the contract has.
+    pub fn set_total(&mut self, total: u64) {
+        if self.has_total {
+            return;
+        }
+        self.has_total = true;
+        self.bar.set_length(total);
+        self.bar.set_style(
+            ProgressStyle::with_template(
+                "{spinner} [{elapsed_precise}] {wide_bar} {pos}/{len} holders | {msg} | ETA {eta}",
+            )
+            .expect("valid progress template"),
+        );
+    }
+
+    pub fn page_done(&mut self, holders: u64) {
+        self.pages += 1;
+        let elapsed = self.started.elapsed().as_secs_f64().max(f64::EPSILON);
+        self.bar.set_position(holders);
+        self.bar.set_message(format!("page {} | {:.2} req/s", self.pages, self.pages as f64 / elapsed));
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
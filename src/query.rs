@@ -0,0 +1,304 @@
+use anyhow::{bail, Context, Result};
+use duckdb::{params, Connection};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+use crate::archive;
+use crate::identity::HolderIdentity;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, ScanState, STATE_PATH};
+
+const SCHEMA: &str = "
+CREATE OR REPLACE TABLE holders (
+    address VARCHAR NOT NULL,
+    balance UBIGINT NOT NULL,
+    first_seen TIMESTAMPTZ,
+    held_since TIMESTAMPTZ,
+    token_count UBIGINT NOT NULL,
+    labels VARCHAR
+);
+CREATE OR REPLACE TABLE snapshots (
+    id VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    block UBIGINT,
+    total_holders UBIGINT NOT NULL,
+    active BOOLEAN NOT NULL,
+    incomplete BOOLEAN NOT NULL,
+    inconsistent BOOLEAN NOT NULL
+);
+CREATE OR REPLACE TABLE snapshot_balances (
+    snapshot_id VARCHAR NOT NULL,
+    address VARCHAR NOT NULL,
+    balance UBIGINT NOT NULL
+);
+";
+
+/// Holder changes between each active snapshot and the one before it, with
+/// the kinds the `diff` command reports.
+const DIFFS_VIEW: &str = "
+CREATE OR REPLACE VIEW diffs AS
+WITH pairs AS (
+    SELECT * FROM (SELECT LAG(id) OVER (ORDER BY created_at) AS previous, id FROM snapshots WHERE active)
+    WHERE previous IS NOT NULL
+),
+old AS (
+    SELECT pairs.id AS to_snapshot, b.address, b.balance
+    FROM pairs JOIN snapshot_balances b ON b.snapshot_id = pairs.previous
+),
+new AS (
+    SELECT pairs.id AS to_snapshot, b.address, b.balance
+    FROM pairs JOIN snapshot_balances b ON b.snapshot_id = pairs.id
+),
+changes AS (
+    SELECT
+        COALESCE(new.to_snapshot, old.to_snapshot) AS to_snapshot,
+        COALESCE(new.address, old.address) AS address,
+        CASE WHEN old.address IS NULL THEN 'added' WHEN new.address IS NULL THEN 'removed' ELSE 'balance_changed' END
+            AS kind,
+        COALESCE(old.balance, 0) AS old_balance,
+        COALESCE(new.balance, 0) AS new_balance
+    FROM old FULL OUTER JOIN new ON old.to_snapshot = new.to_snapshot AND old.address = new.address
+)
+SELECT pairs.previous AS from_snapshot, changes.*
+FROM changes JOIN pairs ON pairs.id = changes.to_snapshot
+WHERE kind <> 'balance_changed' OR old_balance <> new_balance;
+";
+
+/// Canned queries: name, description and SQL.
+pub const QUERIES: &[(&str, &str, &str)] = &[
+    (
+        "top-holders",
+        "The 25 largest holders",
+        "SELECT address, balance, token_count, held_since FROM holders ORDER BY balance DESC, address LIMIT 25",
+    ),
+    (
+        "distribution",
+        "Holders and tokens by balance bracket",
+        "SELECT CASE WHEN balance < 2 THEN '1' WHEN balance < 10 THEN '2-9' WHEN balance < 100 THEN '10-99' \
+         ELSE '100+' END AS bracket, COUNT(*) AS holders, SUM(balance) AS tokens FROM holders GROUP BY bracket \
+         ORDER BY MIN(balance)",
+    ),
+    (
+        "new-holders",
+        "Current holders first seen in the last 7 days",
+        "SELECT address, balance, first_seen FROM holders WHERE first_seen >= now() - INTERVAL 7 DAY \
+         ORDER BY first_seen DESC",
+    ),
+    (
+        "history",
+        "Holders and tokens held in each active snapshot",
+        "SELECT s.id, s.created_at, COUNT(b.address) AS holders, SUM(b.balance) AS tokens FROM snapshots s \
+         LEFT JOIN snapshot_balances b ON b.snapshot_id = s.id WHERE s.active GROUP BY s.id, s.created_at \
+         ORDER BY s.created_at",
+    ),
+    (
+        "churn",
+        "Added, removed and changed holders between consecutive snapshots",
+        "SELECT from_snapshot, to_snapshot, COUNT(*) FILTER (kind = 'added') AS added, \
+         COUNT(*) FILTER (kind = 'removed') AS removed, COUNT(*) FILTER (kind = 'balance_changed') AS changed \
+         FROM diffs GROUP BY from_snapshot, to_snapshot ORDER BY to_snapshot",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum QueryFormat {
+    Table,
+    Csv,
+    /// One JSON object per row
+    Json,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct QueryOptions {
+    /// SQL over the holders, snapshots, snapshot_balances and diffs tables, or the name of a canned query
+    #[arg(required_unless_present_any = ["list", "database"])]
+    pub sql: Option<String>,
+    /// List the tables and canned queries
+    #[arg(long)]
+    pub list: bool,
+    /// State whose holders make up the holders table (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Keep the tables in this DuckDB file, for the duckdb CLI or a notebook, instead of in memory
+    #[arg(long)]
+    pub database: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = QueryFormat::Table)]
+    pub format: QueryFormat,
+    /// Write the result to this file instead of stdout
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+pub fn run_query(options: &QueryOptions) -> Result<()> {
+    if options.list {
+        println!("Tables:");
+        println!("  holders(address, balance, first_seen, held_since, token_count, labels)");
+        println!("  snapshots(id, created_at, block, total_holders, active, incomplete, inconsistent)");
+        println!("  snapshot_balances(snapshot_id, address, balance)");
+        println!("  diffs(from_snapshot, to_snapshot, address, kind, old_balance, new_balance)");
+        println!("\nCanned queries:");
+        for (name, description, _) in QUERIES {
+            println!("  {:<14} {}", name, description);
+        }
+        return Ok(());
+    }
+
+    let sql = options.sql.as_deref().map(|sql| match QUERIES.iter().find(|(name, _, _)| *name == sql) {
+        Some((_, _, canned)) => canned.to_string(),
+        None => sql.trim().trim_end_matches(';').to_string(),
+    });
+    let connection = match &options.database {
+        Some(path) => Connection::open(path).with_context(|| format!("opening {}", path.display()))?,
+        None => Connection::open_in_memory()?,
+    };
+    // Archived balances are the expensive part, so they're only read when the query can use them
+    let reads_archive = |sql: &str| ["snapshot_balances", "diffs"].iter().any(|t| sql.to_lowercase().contains(t));
+    let archived = options.database.is_some() || sql.as_deref().is_some_and(reads_archive);
+    load(&connection, options, archived)?;
+    if let Some(path) = &options.database {
+        info!(database = %path.display(), "Wrote the holder tables");
+    }
+    let Some(sql) = sql else {
+        return Ok(());
+    };
+
+    let mut out: Box<dyn Write> = match &options.output {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("writing {}", path.display()))?;
+            Box::new(BufWriter::new(file))
+        }
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let rows = match options.format {
+        QueryFormat::Json => {
+            let mut statement = connection.prepare(&format!("SELECT to_json(q)::VARCHAR FROM ({}) q", sql))?;
+            let mut rows = statement.query([])?;
+            let mut count = 0;
+            while let Some(row) = rows.next()? {
+                writeln!(out, "{}", row.get::<_, String>(0)?)?;
+                count += 1;
+            }
+            count
+        }
+        format => {
+            let mut statement = connection.prepare(&format!("SELECT * FROM ({}) q LIMIT 0", sql))?;
+            let columns = {
+                let rows = statement.query([])?;
+                rows.as_ref().map(|statement| statement.column_names()).unwrap_or_default()
+            };
+            // Every column as text, so any type DuckDB returns can be printed
+            let mut statement = connection.prepare(&format!("SELECT COLUMNS(*)::VARCHAR FROM ({}) q", sql))?;
+            let mut rows = statement.query([])?;
+            let mut table = Vec::new();
+            while let Some(row) = rows.next()? {
+                let values = (0..columns.len()).map(|i| row.get::<_, Option<String>>(i));
+                table.push(values.map(|value| Ok(value?.unwrap_or_default())).collect::<Result<Vec<String>>>()?);
+            }
+            match format {
+                QueryFormat::Csv => {
+                    let mut writer = csv::Writer::from_writer(&mut out);
+                    writer.write_record(&columns)?;
+                    for row in &table {
+                        writer.write_record(row)?;
+                    }
+                    writer.flush()?;
+                }
+                _ => print_table(&mut out, &columns, &table)?,
+            }
+            table.len()
+        }
+    };
+    out.flush()?;
+    if let Some(path) = &options.output {
+        info!(rows, output = %path.display(), "Wrote the query result");
+    }
+    Ok(())
+}
+
+fn print_table(out: &mut impl Write, columns: &[String], rows: &[Vec<String>]) -> Result<()> {
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| rows.iter().map(|row| row[i].chars().count()).chain([column.len()]).max().unwrap_or(0))
+        .collect();
+    let line = |values: &[String]| {
+        let cells: Vec<String> = values.iter().zip(&widths).map(|(v, w)| format!("{:<width$}", v, width = w)).collect();
+        cells.join("  ").trim_end().to_string()
+    };
+    writeln!(out, "{}", line(columns))?;
+    writeln!(out, "{}", widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("  "))?;
+    for row in rows {
+        writeln!(out, "{}", line(row))?;
+    }
+    writeln!(out, "({} rows)", rows.len())?;
+    Ok(())
+}
+
+/// Creates the tables and fills them from the live state and the archive.
+/// States are regrouped by `HOLDER_KEY`, as for `diff`.
+fn load(connection: &Connection, options: &QueryOptions, archived: bool) -> Result<()> {
+    connection.execute_batch(SCHEMA)?;
+    connection.execute_batch(DIFFS_VIEW)?;
+    let identity = HolderIdentity::from_env()?;
+
+    let path = options.state.clone().unwrap_or_else(|| PathBuf::from(STATE_PATH));
+    if path.exists() {
+        let state = state::load_state_from(&path)?;
+        let state = identity.regroup(&state);
+        let mut appender = connection.appender("holders")?;
+        for holder in &state.holders {
+            let first_seen = state.first_seen.get(holder).copied();
+            let since = state.acquisitions.get(holder).map(|a| a.held_since).or(first_seen);
+            appender.append_row(params![
+                holder,
+                state.balances.get(holder).copied().unwrap_or(0),
+                first_seen,
+                since,
+                state.tokens.get(holder).map_or(0, |tokens| tokens.len() as u64),
+                state.labels.get(holder).map(|labels| labels.join(",")),
+            ])?;
+        }
+        appender.flush()?;
+    } else if options.state.is_some() {
+        bail!("{} does not exist", path.display());
+    } else {
+        warn!("No scan state at {}, the holders table is empty", STATE_PATH);
+    }
+
+    let snapshots = archive::load_index(CONTRACT_ADDRESS)?.snapshots;
+    let mut appender = connection.appender("snapshots")?;
+    for snapshot in &snapshots {
+        appender.append_row(params![
+            snapshot.id,
+            snapshot.created_at,
+            snapshot.block,
+            snapshot.total_holders,
+            snapshot.is_active(),
+            snapshot.incomplete,
+            snapshot.inconsistent,
+        ])?;
+    }
+    appender.flush()?;
+    if !archived {
+        return Ok(());
+    }
+
+    let mut appender = connection.appender("snapshot_balances")?;
+    for snapshot in &snapshots {
+        let state: ScanState = match state::load_state_from(&snapshot.path.join("state.json")) {
+            Ok(state) => state,
+            Err(e) => {
+                warn!(snapshot = %snapshot.id, error = %format!("{:#}", e), "Skipping an unreadable snapshot");
+                continue;
+            }
+        };
+        let state = identity.regroup(&state);
+        for holder in &state.holders {
+            appender.append_row(params![snapshot.id, holder, state.balances.get(holder).copied().unwrap_or(0)])?;
+        }
+    }
+    appender.flush()?;
+    Ok(())
+}
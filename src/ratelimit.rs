@@ -0,0 +1,233 @@
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::collections::BTreeMap;
+use std::env;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::cost::CostConfig;
+
+// Alchemy compute-unit costs per NFT API method, from the published pricing
+// table. `[cost] compute_units` overrides them per method, unlisted methods are
+// charged `DEFAULT_CU_COST`, and `ALCHEMY_CU_COST` overrides the cost of every request.
+const CU_COSTS: [(&str, f64); 1] = [("getOwnersForContract", 350.0)];
+const DEFAULT_CU_COST: f64 = 100.0;
+// Adaptive pacing: the rate halves on throttling and climbs by a tenth of the
+// starting rate per successful response, between a tenth of it and the ceiling
+const DECREASE: f64 = 0.5;
+const INCREASE_FRACTION: f64 = 0.1;
+const FLOOR_FRACTION: f64 = 0.1;
+const DEFAULT_CEILING_FACTOR: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    /// Every request costs one token
+    Requests,
+    /// Requests cost their documented Alchemy compute units
+    ComputeUnits { override_cost: Option<f64> },
+}
+
+/// Token bucket: `rate` tokens are added per second up to `capacity`, and each
+/// request waits until its cost is available.
+#[derive(Debug)]
+pub struct RateLimiter {
+    budget: Budget,
+    /// Per-method compute units from `[cost] compute_units`, over `CU_COSTS`
+    compute_units: BTreeMap<String, f64>,
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+    adaptive: Option<Adaptive>,
+    /// Set when the provider reported its quota used up until then
+    blocked_until: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Adaptive {
+    floor: f64,
+    ceiling: f64,
+    step: f64,
+}
+
+impl RateLimiter {
+    pub fn new(budget: Budget, rate: f64, capacity: f64) -> Self {
+        let capacity = capacity.max(f64::EPSILON);
+        RateLimiter {
+            budget,
+            compute_units: BTreeMap::new(),
+            rate,
+            capacity,
+            tokens: capacity,
+            last_refill: Instant::now(),
+            adaptive: None,
+            blocked_until: None,
+        }
+    }
+
+    /// Lets the rate move with the provider's responses, up to `ceiling`.
+    pub fn adaptive(mut self, ceiling: f64) -> Self {
+        let ceiling = ceiling.max(self.rate);
+        let (floor, step) = (self.rate * FLOOR_FRACTION, self.rate * INCREASE_FRACTION);
+        self.adaptive = Some(Adaptive { floor, ceiling, step });
+        self
+    }
+
+    /// `ALCHEMY_CU_PER_SECOND` switches to compute-unit mode; otherwise
+    /// `RATE_LIMIT_RPS` (default 1) requests per second with a burst of
+    /// `RATE_LIMIT_BURST` (default 1). `RATE_LIMIT_ADAPTIVE=true` lets the
+    /// rate adapt up to `ALCHEMY_MAX_CU_PER_SECOND` or `RATE_LIMIT_MAX_RPS`
+    /// (default ten times the starting rate). Compute units per method come
+    /// from `cost`, the same table the cost report uses.
+    pub fn from_env(cost: &CostConfig) -> Self {
+        let var = |name: &str| env::var(name).ok().and_then(|v| v.parse::<f64>().ok()).filter(|v| *v > 0.0);
+        let (limiter, ceiling) = match var("ALCHEMY_CU_PER_SECOND") {
+            Some(cu_per_second) => {
+                let override_cost = var("ALCHEMY_CU_COST");
+                // Allow a full second of budget, but never less than one request of the priciest kind
+                let largest = override_cost.unwrap_or_else(|| {
+                    CU_COSTS
+                        .iter()
+                        .map(|(_, c)| *c)
+                        .chain(cost.compute_units.values().copied())
+                        .fold(DEFAULT_CU_COST, f64::max)
+                });
+                let mut limiter =
+                    RateLimiter::new(Budget::ComputeUnits { override_cost }, cu_per_second, cu_per_second.max(largest));
+                limiter.compute_units = cost.compute_units.clone();
+                (limiter, var("ALCHEMY_MAX_CU_PER_SECOND"))
+            }
+            None => {
+                let rps = var("RATE_LIMIT_RPS").unwrap_or(1.0);
+                let limiter = RateLimiter::new(Budget::Requests, rps, var("RATE_LIMIT_BURST").unwrap_or(1.0));
+                (limiter, var("RATE_LIMIT_MAX_RPS"))
+            }
+        };
+        let adaptive = env::var("RATE_LIMIT_ADAPTIVE").is_ok_and(|v| matches!(v.trim(), "1" | "true" | "yes"));
+        match adaptive {
+            true => {
+                let ceiling = ceiling.unwrap_or(limiter.rate * DEFAULT_CEILING_FACTOR);
+                limiter.adaptive(ceiling)
+            }
+            false => limiter,
+        }
+    }
+
+    /// Current rate in budget units per second.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn cost(&self, method: &str) -> f64 {
+        match self.budget {
+            Budget::Requests => 1.0,
+            Budget::ComputeUnits { override_cost: Some(cost) } => cost,
+            Budget::ComputeUnits { override_cost: None } => {
+                self.compute_units.get(method).copied().unwrap_or_else(|| compute_units(method))
+            }
+        }
+    }
+
+    /// Budget units for `requests` calls to `method` and the shortest time
+    /// the limiter allows them to complete in.
+    pub fn plan(&self, requests: u64, method: &str) -> (f64, Duration) {
+        let units = requests as f64 * self.cost(method);
+        let seconds = ((units - self.capacity) / self.rate).max(0.0);
+        (units, Duration::from_secs_f64(seconds))
+    }
+
+    pub fn unit_name(&self) -> &'static str {
+        match self.budget {
+            Budget::Requests => "requests",
+            Budget::ComputeUnits { .. } => "compute units",
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Adjusts the pacing to a response to `method` when adaptive: throttling
+    /// halves the rate, success raises it a step, and `RateLimit-Remaining`
+    /// and `RateLimit-Reset` headers (or their `X-` forms) cap it at what the
+    /// provider says is left. A quota used up pauses requests until it resets.
+    pub fn observe(&mut self, method: &str, status: StatusCode, headers: &HeaderMap) {
+        let Some(adaptive) = self.adaptive else {
+            return;
+        };
+        let previous = self.rate;
+        let quota = quota(headers);
+        let throttled = status == StatusCode::TOO_MANY_REQUESTS;
+        if throttled {
+            self.rate = (self.rate * DECREASE).max(adaptive.floor);
+            self.tokens = 0.0;
+        } else if status.is_success() {
+            self.rate = (self.rate + adaptive.step).min(adaptive.ceiling);
+        }
+        if let Some((remaining, reset)) = quota {
+            if remaining == 0.0 {
+                self.blocked_until = Some(Instant::now() + reset);
+            } else if !reset.is_zero() {
+                // Spread what is left evenly over the time until the quota resets
+                let allowed = remaining * self.cost(method) / reset.as_secs_f64();
+                self.rate = self.rate.min(allowed.max(adaptive.floor));
+            }
+        }
+        let rate = format!("{:.2}", self.rate);
+        if throttled {
+            info!(%rate, unit = self.unit_name(), "Provider is throttling, slowing down");
+        } else if self.rate != previous {
+            debug!(%rate, unit = self.unit_name(), "Adjusted request rate");
+        }
+    }
+
+    /// Waits until the request for `method` fits in the budget and consumes it.
+    pub async fn acquire(&mut self, method: &str) {
+        if let Some(until) = self.blocked_until.take() {
+            if until > Instant::now() {
+                debug!(wait = ?(until - Instant::now()), "Waiting for the provider's quota to reset");
+                tokio::time::sleep_until(until.into()).await;
+            }
+        }
+        let cost = self.cost(method).min(self.capacity);
+        loop {
+            self.refill();
+            if self.tokens >= cost {
+                self.tokens -= cost;
+                return;
+            }
+            let wait = Duration::from_secs_f64((cost - self.tokens) / self.rate);
+            debug!(?wait, method, "Rate limited");
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Documented Alchemy compute units of one call to `method`.
+pub fn compute_units(method: &str) -> f64 {
+    CU_COSTS.iter().find(|(name, _)| *name == method).map_or(DEFAULT_CU_COST, |(_, cost)| *cost)
+}
+
+/// Requests left and time until the quota resets, from `RateLimit-*` or
+/// `X-RateLimit-*` headers. Resets above a billion seconds are Unix times.
+fn quota(headers: &HeaderMap) -> Option<(f64, Duration)> {
+    let header = |name: &str| {
+        [name.to_string(), format!("x-{}", name)]
+            .iter()
+            .find_map(|name| headers.get(name.as_str())?.to_str().ok()?.trim().parse::<f64>().ok())
+    };
+    let remaining = header("ratelimit-remaining")?;
+    let reset = header("ratelimit-reset")?;
+    let seconds = if reset > 1e9 { reset - chrono::Utc::now().timestamp() as f64 } else { reset };
+    Some((remaining.max(0.0), Duration::from_secs_f64(seconds.max(0.0))))
+}
+
+/// Extracts the API method from a request path such as
+/// `/getOwnersForContract?contractAddress=...`.
+pub fn method_from_path(path: &str) -> &str {
+    let path = path.split('?').next().unwrap_or(path);
+    path.rsplit('/').next().unwrap_or(path)
+}
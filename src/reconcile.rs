@@ -0,0 +1,258 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::address;
+use crate::archive;
+use crate::output;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, ScanState};
+
+// Header names recognised in the external file, compared case-insensitively
+pub const ADDRESS_COLUMNS: [&str; 5] = ["address", "holder", "owner", "owner_address", "wallet"];
+const BALANCE_COLUMNS: [&str; 5] = ["balance", "amount", "quantity", "count", "token_count"];
+
+#[derive(Debug, clap::Args)]
+pub struct ReconcileOptions {
+    /// Externally produced holder list (CSV with an address column and an optional balance column)
+    #[arg(long)]
+    pub against: PathBuf,
+    /// Snapshot to check (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// When the external list was taken (RFC 3339); holders we first saw later are explained by it
+    #[arg(long)]
+    pub as_of: Option<DateTime<Utc>>,
+    #[arg(long, default_value = "data/reconcile.csv")]
+    pub output: PathBuf,
+    /// Exit with an error if there is any discrepancy
+    #[arg(long)]
+    pub strict: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// In our snapshot, missing from the external list
+    OnlyOurs,
+    /// In the external list, missing from our snapshot
+    OnlyTheirs,
+    /// In both with different balances
+    BalanceMismatch,
+}
+
+impl Discrepancy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Discrepancy::OnlyOurs => "only_ours",
+            Discrepancy::OnlyTheirs => "only_theirs",
+            Discrepancy::BalanceMismatch => "balance_mismatch",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ReconcileRow {
+    pub address: String,
+    pub kind: Discrepancy,
+    pub our_balance: u64,
+    pub their_balance: Option<u64>,
+    pub reason: String,
+    /// Where our row came from, see `Provenance::label`
+    pub our_source: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ExternalEntry {
+    /// `None` when the file has no balance column
+    pub balance: Option<u64>,
+    /// Times the address appears in the file; balances of repeated rows are summed
+    pub occurrences: u32,
+}
+
+/// Reads an external holder list keyed by normalized address.
+pub fn load_external(path: &Path) -> Result<BTreeMap<String, ExternalEntry>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    let headers = reader.headers()?.clone();
+    let column = |names: &[&str]| headers.iter().position(|h| names.iter().any(|n| h.eq_ignore_ascii_case(n)));
+    let Some(address_column) = column(&ADDRESS_COLUMNS) else {
+        bail!("{} has no address column (expected one of: {})", path.display(), ADDRESS_COLUMNS.join(", "));
+    };
+    let balance_column = column(&BALANCE_COLUMNS);
+
+    let mut entries: BTreeMap<String, ExternalEntry> = BTreeMap::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+        let Some(address) = record.get(address_column).filter(|a| !a.is_empty()) else {
+            continue;
+        };
+        // Entries that aren't addresses are kept as given and reported as invalid
+        let key = address::normalize(address).unwrap_or_else(|_| address.trim().to_string());
+        let entry = entries.entry(key).or_default();
+        entry.occurrences += 1;
+        if let Some(column) = balance_column {
+            let value = record.get(column).unwrap_or("");
+            let balance: u64 = if value.is_empty() {
+                0
+            } else {
+                value.parse().with_context(|| format!("{} line {}: bad balance '{}'", path.display(), line + 2, value))?
+            };
+            entry.balance = Some(entry.balance.unwrap_or(0) + balance);
+        }
+    }
+    Ok(entries)
+}
+
+/// Compares our snapshot with an external list. `archived` holds the
+/// normalized holders of earlier snapshots, newest first; they are only
+/// consulted to explain addresses the external list has and we don't.
+pub fn reconcile(
+    ours: &ScanState,
+    theirs: &BTreeMap<String, ExternalEntry>,
+    as_of: Option<DateTime<Utc>>,
+    archived: &[(archive::SnapshotEntry, HashSet<String>)],
+) -> Result<Vec<ReconcileRow>> {
+    let our_balances: BTreeMap<String, u64> = ours
+        .holders
+        .iter()
+        .map(|h| Ok((address::normalize(h)?, ours.balances.get(h).copied().unwrap_or(0))))
+        .collect::<Result<_>>()?;
+    let first_seen: HashMap<String, DateTime<Utc>> =
+        ours.first_seen.iter().map(|(h, t)| Ok((address::normalize(h)?, *t))).collect::<Result<_>>()?;
+    let sources: HashMap<String, String> =
+        ours.provenance.iter().map(|(h, p)| Ok((address::normalize(h)?, p.label()))).collect::<Result<_>>()?;
+    let duplicated = |entry: &ExternalEntry| match entry.occurrences {
+        0 | 1 => None,
+        n => Some(format!("listed {} times in the external file", n)),
+    };
+
+    let mut rows = Vec::new();
+    for (address, &our_balance) in &our_balances {
+        match theirs.get(address) {
+            None => {
+                let seen = first_seen.get(address);
+                let reason = match (seen, as_of) {
+                    (Some(seen), Some(as_of)) if *seen > as_of => {
+                        format!("first seen {}, after the external list was taken", seen.to_rfc3339())
+                    }
+                    _ => "not in the external list".to_string(),
+                };
+                rows.push(ReconcileRow {
+                    address: address.clone(),
+                    kind: Discrepancy::OnlyOurs,
+                    our_balance,
+                    their_balance: None,
+                    reason,
+                    our_source: sources.get(address).cloned(),
+                });
+            }
+            Some(entry) => {
+                let Some(their_balance) = entry.balance.filter(|b| *b != our_balance) else {
+                    continue;
+                };
+                let mut reasons = vec![format!("differs by {}", their_balance as i128 - our_balance as i128)];
+                reasons.extend(duplicated(entry));
+                rows.push(ReconcileRow {
+                    address: address.clone(),
+                    kind: Discrepancy::BalanceMismatch,
+                    our_balance,
+                    their_balance: Some(their_balance),
+                    reason: reasons.join("; "),
+                    our_source: sources.get(address).cloned(),
+                });
+            }
+        }
+    }
+
+    for (address, entry) in theirs {
+        if our_balances.contains_key(address) {
+            continue;
+        }
+        let mut reasons = Vec::new();
+        if !address::is_valid(address) {
+            reasons.push("not a valid address".to_string());
+        } else if entry.balance == Some(0) {
+            reasons.push("listed with a zero balance".to_string());
+        } else if let Some((snapshot, _)) = archived.iter().find(|(_, holders)| holders.contains(address)) {
+            let created_at = snapshot.created_at.to_rfc3339();
+            reasons.push(format!("held in snapshot {} ({}), exited since", snapshot.id, created_at));
+        } else if !ours.complete() {
+            reasons.push("our scan is incomplete".to_string());
+        } else {
+            reasons.push("not in our snapshot".to_string());
+        }
+        reasons.extend(duplicated(entry));
+        rows.push(ReconcileRow {
+            address: address.clone(),
+            kind: Discrepancy::OnlyTheirs,
+            our_balance: 0,
+            their_balance: entry.balance,
+            reason: reasons.join("; "),
+            our_source: None,
+        });
+    }
+
+    rows.sort_by(|a, b| a.address.cmp(&b.address));
+    Ok(rows)
+}
+
+pub fn save_reconcile(rows: &[ReconcileRow], path: &Path) -> Result<()> {
+    let mut writer = output::csv_writer(path)?;
+    writer.write_record(["address", "status", "our_balance", "their_balance", "reason", "our_source"])?;
+    for row in rows {
+        writer.write_record([
+            row.address.clone(),
+            row.kind.as_str().to_string(),
+            row.our_balance.to_string(),
+            row.their_balance.map(|b| b.to_string()).unwrap_or_default(),
+            row.reason.clone(),
+            row.our_source.clone().unwrap_or_default(),
+        ])?;
+    }
+    output::finish_csv(writer)
+}
+
+pub fn run_reconcile(options: &ReconcileOptions) -> Result<()> {
+    let ours = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    let theirs = load_external(&options.against)?;
+
+    // Archived snapshots are only loaded when something needs explaining, newest first
+    let our_holders: HashSet<String> = ours.holders.iter().map(|h| address::normalize(h)).collect::<Result<_>>()?;
+    let mut archived = Vec::new();
+    if theirs.keys().any(|address| !our_holders.contains(address)) {
+        for snapshot in archive::active_snapshots(CONTRACT_ADDRESS)?.into_iter().rev() {
+            let state = state::load_state_from(&snapshot.path.join("state.json"))?;
+            let holders: HashSet<String> = state
+                .holders
+                .iter()
+                .map(|h| address::normalize(h))
+                .collect::<Result<_>>()
+                .with_context(|| format!("snapshot {}", snapshot.id))?;
+            archived.push((snapshot, holders));
+        }
+    }
+
+    let rows = reconcile(&ours, &theirs, options.as_of, &archived)?;
+    save_reconcile(&rows, &options.output)?;
+
+    let count = |kind: Discrepancy| rows.iter().filter(|r| r.kind == kind).count();
+    println!("Our holders: {}", ours.holders.len());
+    println!("External holders: {}", theirs.len());
+    println!("Only in our snapshot: {}", count(Discrepancy::OnlyOurs));
+    println!("Only in the external list: {}", count(Discrepancy::OnlyTheirs));
+    println!("Balance mismatches: {}", count(Discrepancy::BalanceMismatch));
+    info!(discrepancies = rows.len(), output = %options.output.display(), "Reconciliation report written");
+
+    if options.strict && !rows.is_empty() {
+        bail!("{} discrepancies against {}", rows.len(), options.against.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::fetcher::FetchedPage;
+
+/// A provider response as `scan --record-responses` keeps it.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    provider: String,
+    /// Page key the request continued from; `None` for the first page of a pass
+    page_key: Option<String>,
+    fetched_at: DateTime<Utc>,
+    endpoint: String,
+    status: u16,
+    body: String,
+}
+
+/// A directory of raw provider responses, numbered in the order they were
+/// fetched: `000001.json`, `000002.json`, ... Recording clears the responses
+/// of an earlier recording, and replaying hands them back in the same order.
+pub struct ResponseCache {
+    dir: PathBuf,
+    provider: &'static str,
+    next: u64,
+}
+
+impl ResponseCache {
+    pub fn record(dir: &Path, provider: &'static str) -> Result<ResponseCache> {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if is_response(&path) {
+                fs::remove_file(path)?;
+            }
+        }
+        info!(dir = %dir.display(), "Recording provider responses");
+        Ok(ResponseCache { dir: dir.to_path_buf(), provider, next: 1 })
+    }
+
+    pub fn replay(dir: &Path, provider: &'static str) -> Result<ResponseCache> {
+        let cache = ResponseCache { dir: dir.to_path_buf(), provider, next: 1 };
+        if !cache.path(1).exists() {
+            bail!("no recorded responses in {}", dir.display());
+        }
+        info!(dir = %dir.display(), "Replaying recorded provider responses");
+        Ok(cache)
+    }
+
+    fn path(&self, sequence: u64) -> PathBuf {
+        self.dir.join(format!("{:06}.json", sequence))
+    }
+
+    pub fn save(&mut self, page_key: Option<&str>, fetched: &FetchedPage) -> Result<()> {
+        let response = CachedResponse {
+            provider: self.provider.to_string(),
+            page_key: page_key.map(str::to_string),
+            fetched_at: Utc::now(),
+            endpoint: fetched.endpoint.clone(),
+            status: fetched.status.as_u16(),
+            body: fetched.body.clone(),
+        };
+        serde_json::to_writer(File::create(self.path(self.next))?, &response)?;
+        self.next += 1;
+        Ok(())
+    }
+
+    /// The next recorded response, which must have been fetched for `page_key`.
+    pub fn next(&mut self, page_key: Option<&str>) -> Result<FetchedPage> {
+        let path = self.path(self.next);
+        if !path.exists() {
+            bail!("the recording in {} ends before the scan did", self.dir.display());
+        }
+        let response: CachedResponse = serde_json::from_reader(File::open(&path)?)
+            .with_context(|| format!("reading recorded response {}", path.display()))?;
+        if response.provider != self.provider {
+            bail!("{} was recorded from {}, not {}", path.display(), response.provider, self.provider);
+        }
+        if response.page_key.as_deref() != page_key {
+            match &response.page_key {
+                Some(key) if self.next == 1 => {
+                    bail!("the recording starts partway through a pass, at page key {}; record with --fresh", key)
+                }
+                _ => {
+                    bail!("{} answers another request than the scan makes; the recording is incomplete", path.display())
+                }
+            }
+        }
+        self.next += 1;
+        Ok(FetchedPage {
+            status: reqwest::StatusCode::from_u16(response.status)?,
+            body: response.body,
+            attempts: 1,
+            latency_ms: 0,
+            endpoint: response.endpoint,
+        })
+    }
+}
+
+fn is_response(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    name.len() == "000001.json".len() && name.ends_with(".json") && name[..6].chars().all(|c| c.is_ascii_digit())
+}
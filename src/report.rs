@@ -0,0 +1,325 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::archive;
+use crate::diff::{self, ChangeKind, HolderChange};
+use crate::histogram::{self, HistogramConfig};
+use crate::identity::HolderIdentity;
+use crate::leaderboard::{self, LeaderboardRow, SortBy};
+use crate::output;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, ScanState};
+use crate::stats::{self, HolderStats};
+
+pub const AGE_DISTRIBUTION_PATH: &str = "data/age_distribution.csv";
+
+const REPORT_HTML: &str = include_str!("templates/report.html");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// The age distribution and balance histogram CSVs a scan writes
+    Csv,
+    /// One standalone HTML page with charts, changes and top holders
+    Html,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ReportOptions {
+    #[arg(long, value_enum, default_value = "csv")]
+    pub format: ReportFormat,
+    /// Snapshot to report on (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Snapshot to compare with (defaults to the latest archived snapshot taken before it)
+    #[arg(long)]
+    pub previous: Option<PathBuf>,
+    /// Holders in the top-holder table
+    #[arg(long, default_value_t = 25)]
+    pub top: usize,
+    /// HTML file to write
+    #[arg(long, default_value = "data/report.html")]
+    pub output: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct AgeBucket {
+    pub label: &'static str,
+    pub holders: u64,
+    pub supply: u128,
+}
+
+// Upper bound (exclusive) of each bucket in days; the last bucket is open-ended
+const AGE_BUCKETS: [(&str, Option<i64>); 4] = [
+    ("<1w", Some(7)),
+    ("1w-1m", Some(30)),
+    ("1m-6m", Some(182)),
+    (">6m", None),
+];
+
+pub fn age_distribution(state: &ScanState, now: DateTime<Utc>) -> Vec<AgeBucket> {
+    let mut buckets: Vec<AgeBucket> = AGE_BUCKETS
+        .iter()
+        .map(|(label, _)| AgeBucket { label, holders: 0, supply: 0 })
+        .collect();
+    // Holders carried over from state files written before first-seen tracking
+    let mut unknown = AgeBucket { label: "unknown", holders: 0, supply: 0 };
+
+    for holder in &state.holders {
+        let balance = state.balances.get(holder).copied().unwrap_or(0);
+        let bucket = match state.first_seen.get(holder) {
+            Some(first_seen) => {
+                let age = now.signed_duration_since(*first_seen);
+                let index = AGE_BUCKETS
+                    .iter()
+                    .position(|(_, max_days)| match max_days {
+                        Some(days) => age < Duration::days(*days),
+                        None => true,
+                    })
+                    .unwrap_or(AGE_BUCKETS.len() - 1);
+                &mut buckets[index]
+            }
+            None => &mut unknown,
+        };
+        bucket.holders += 1;
+        bucket.supply += u128::from(balance);
+    }
+
+    if unknown.holders > 0 {
+        buckets.push(unknown);
+    }
+    buckets
+}
+
+/// Each bucket's share of the total supply, in percent.
+fn supply_shares(buckets: &[AgeBucket]) -> Vec<f64> {
+    let total_supply: u128 = buckets.iter().map(|b| b.supply).sum();
+    buckets
+        .iter()
+        .map(|b| if total_supply > 0 { b.supply as f64 / total_supply as f64 * 100.0 } else { 0.0 })
+        .collect()
+}
+
+pub fn save_age_distribution(buckets: &[AgeBucket]) -> Result<()> {
+    let mut writer = output::csv_writer(output::export_path(AGE_DISTRIBUTION_PATH)?)?;
+    writer.write_record(["bucket", "holders", "supply", "supply_share_pct"])?;
+    for (bucket, share) in buckets.iter().zip(supply_shares(buckets)) {
+        writer.write_record([
+            bucket.label.to_string(),
+            bucket.holders.to_string(),
+            bucket.supply.to_string(),
+            format!("{:.2}", share),
+        ])?;
+    }
+    output::finish_csv(writer)
+}
+
+/// Regenerates the snapshot's reports: the CSV tables, or a single HTML page
+/// that can be mailed around without any other tooling.
+pub fn run_report(options: &ReportOptions) -> Result<()> {
+    let state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    let identity = HolderIdentity::from_env()?;
+    let view = identity.regroup(&state);
+    let histogram_config = HistogramConfig::from_env();
+    if options.format == ReportFormat::Csv {
+        save_age_distribution(&age_distribution(&view, Utc::now()))?;
+        histogram::save_histogram_table(&histogram::balance_histogram(&view, &histogram_config))?;
+        info!("Age distribution and balance histogram written");
+        return Ok(());
+    }
+
+    let previous = match &options.previous {
+        Some(path) => Some((path.display().to_string(), state::load_state_from(path)?)),
+        None => previous_snapshot(&state)?,
+    };
+    let changes = previous.as_ref().map(|(_, old)| diff::diff_states(&identity.regroup(old), &view));
+    let stats = stats::holder_stats(&view, &histogram_config);
+    let rows = leaderboard::leaderboard(&view, options.top, SortBy::Balance);
+
+    let title = match &state.metadata {
+        Some(metadata) => format!("{} holder report", metadata.label()),
+        None => "Holder report".to_string(),
+    };
+    let subtitle = format!(
+        "Contract {} · snapshot of {} · generated {}",
+        CONTRACT_ADDRESS,
+        state.last_save_time.format("%Y-%m-%d %H:%M UTC"),
+        Utc::now().format("%Y-%m-%d %H:%M UTC")
+    );
+    let previous_holders = previous.as_ref().map(|(_, old)| identity.regroup(old).holders.len() as u64);
+    let html = REPORT_HTML
+        .replace("{{title}}", &escape(&title))
+        .replace("{{subtitle}}", &escape(&subtitle))
+        .replace("{{summary}}", &summary_html(&stats, previous_holders, &view))
+        .replace("{{charts}}", &charts_html(&stats, &age_distribution(&view, Utc::now())))
+        .replace("{{changes}}", &changes_html(previous.as_ref().map(|(name, _)| name.as_str()), changes.as_deref()))
+        .replace("{{top_holders}}", &top_holders_html(&rows));
+
+    if let Some(parent) = options.output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&options.output, html)?;
+    info!(output = %options.output.display(), "HTML report written");
+    Ok(())
+}
+
+/// The latest archived snapshot taken before `state` was saved, labelled by id.
+fn previous_snapshot(state: &ScanState) -> Result<Option<(String, ScanState)>> {
+    let snapshots = archive::active_snapshots(CONTRACT_ADDRESS)?;
+    let Some(entry) = snapshots.into_iter().rev().find(|s| s.created_at < state.last_save_time) else {
+        return Ok(None);
+    };
+    let old = state::load_state_from(&entry.path.join("state.json"))?;
+    Ok(Some((format!("snapshot {}", entry.id), old)))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn summary_html(stats: &HolderStats, previous_holders: Option<u64>, state: &ScanState) -> String {
+    let mut cards = vec![
+        ("Holders", stats.total_holders.to_string()),
+        ("Total supply", stats.total_supply.to_string()),
+        ("Top-10 share", format!("{:.1}%", stats.top10_share * 100.0)),
+        ("Gini coefficient", format!("{:.3}", stats.gini)),
+    ];
+    if let Some(previous) = previous_holders {
+        let delta = stats.total_holders as i64 - previous as i64;
+        cards.insert(1, ("Change in holders", format!("{:+}", delta)));
+    }
+    if state.valuation.is_some() {
+        let total: f64 = state.holders.iter().filter_map(|h| state.usd_value(h)).sum();
+        cards.push(("Value (USD)", format!("{:.0}", total)));
+    }
+    let mut html = String::from("<div class=\"cards\">\n");
+    for (label, value) in cards {
+        let _ = writeln!(
+            html,
+            "<div class=\"card\"><div class=\"value\">{}</div><div class=\"label\">{}</div></div>",
+            escape(&value),
+            label
+        );
+    }
+    html.push_str("</div>");
+    html
+}
+
+/// Horizontal bar chart as inline SVG, so the report needs no scripts or images.
+/// Each bar is a label, its length and the text printed after it.
+fn bar_chart(title: &str, bars: &[(String, f64, String)]) -> String {
+    let max = bars.iter().map(|(_, v, _)| *v).fold(0.0, f64::max);
+    let max = if max > 0.0 { max } else { 1.0 };
+    let (label_width, bar_width, row) = (110.0, 300.0, 20.0);
+    let height = bars.len() as f64 * row + 10.0;
+    let mut svg = format!(
+        "<div class=\"chart\"><h3>{}</h3>\n<svg width=\"{}\" height=\"{}\" role=\"img\">\n",
+        escape(title),
+        label_width + bar_width + 70.0,
+        height
+    );
+    for (i, (label, value, text)) in bars.iter().enumerate() {
+        let y = i as f64 * row + 5.0;
+        let width = value / max * bar_width;
+        let _ = writeln!(
+            svg,
+            "<text x=\"0\" y=\"{:.0}\">{}</text><rect x=\"{}\" y=\"{:.0}\" width=\"{:.1}\" height=\"{}\"/>\
+             <text x=\"{:.1}\" y=\"{:.0}\">{}</text>",
+            y + 12.0,
+            escape(label),
+            label_width,
+            y,
+            width,
+            row - 6.0,
+            label_width + width + 4.0,
+            y + 12.0,
+            escape(text)
+        );
+    }
+    svg.push_str("</svg></div>");
+    svg
+}
+
+fn charts_html(stats: &HolderStats, ages: &[AgeBucket]) -> String {
+    let count = |label: String, holders: u64| (label, holders as f64, holders.to_string());
+    let balances: Vec<_> =
+        stats.histogram.iter().filter(|b| b.holders > 0).map(|b| count(b.label(), b.holders)).collect();
+    let holders: Vec<_> = ages.iter().map(|b| count(b.label.to_string(), b.holders)).collect();
+    let shares: Vec<_> = ages
+        .iter()
+        .zip(supply_shares(ages))
+        .map(|(b, share)| (b.label.to_string(), share, format!("{:.2}%", share)))
+        .collect();
+    format!(
+        "{}\n{}\n{}",
+        bar_chart("Holders by balance", &balances),
+        bar_chart("Holders by time held", &holders),
+        bar_chart("Supply share by time held", &shares)
+    )
+}
+
+fn changes_html(previous: Option<&str>, changes: Option<&[HolderChange]>) -> String {
+    let (Some(previous), Some(changes)) = (previous, changes) else {
+        return "<p class=\"muted\">No earlier snapshot to compare with.</p>".to_string();
+    };
+    let count = |kind: ChangeKind| changes.iter().filter(|c| c.kind == kind).count();
+    let mut html = format!(
+        "<p>Compared with {}: <span class=\"up\">{} new</span>, <span class=\"down\">{} exited</span>, \
+         {} changed balance.</p>\n",
+        escape(previous),
+        count(ChangeKind::Added),
+        count(ChangeKind::Removed),
+        count(ChangeKind::BalanceChanged)
+    );
+    let mut largest: Vec<&HolderChange> = changes.iter().collect();
+    largest.sort_by(|a, b| b.delta().abs().cmp(&a.delta().abs()).then_with(|| a.address.cmp(&b.address)));
+    if largest.is_empty() {
+        return html;
+    }
+    html.push_str("<table>\n<tr><th>Holder</th><th>Change</th><th>Before</th><th>After</th><th>Delta</th></tr>\n");
+    for change in largest.into_iter().take(15) {
+        let class = if change.delta() >= 0 { "up" } else { "down" };
+        let _ = writeln!(
+            html,
+            "<tr><td class=\"address\">{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"{}\">{:+}</td></tr>",
+            escape(&change.address),
+            change.kind.as_str().replace('_', " "),
+            change.old_balance,
+            change.new_balance,
+            class,
+            change.delta()
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn top_holders_html(rows: &[LeaderboardRow]) -> String {
+    let with_usd = rows.iter().any(|r| r.usd_value.is_some());
+    let mut html = String::from("<table>\n<tr><th>Rank</th><th>Holder</th><th>Balance</th><th>Share</th>");
+    if with_usd {
+        html.push_str("<th>USD</th>");
+    }
+    html.push_str("</tr>\n");
+    for row in rows {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td class=\"address\">{}</td><td>{}</td><td>{:.2}%</td>",
+            row.rank,
+            escape(&row.address),
+            row.balance,
+            row.share * 100.0
+        );
+        if with_usd {
+            let _ = write!(html, "<td>{}</td>", row.usd_value.map(|v| format!("{:.2}", v)).unwrap_or_default());
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>");
+    html
+}
@@ -0,0 +1,62 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::env;
+use web3::transports::Http;
+use web3::types::{Address, BlockId, BlockNumber, Bytes, CallRequest, U256};
+use web3::Web3;
+
+use crate::http;
+
+/// Connects to the JSON-RPC endpoint in `OPTIMISM_RPC_URL`.
+pub fn connect() -> Result<Web3<Http>> {
+    let url = env::var("OPTIMISM_RPC_URL").context("OPTIMISM_RPC_URL must be set for on-chain calls")?;
+    Ok(Web3::new(http::transport(&url)?))
+}
+
+pub async fn call(web3: &Web3<Http>, contract: Address, data: Vec<u8>) -> Result<Vec<u8>> {
+    call_at(web3, contract, data, None).await
+}
+
+/// `eth_call` against the state at `block`, or the latest block when `None`.
+/// Pinned calls need an archive node once the block is older than the node's pruning window.
+pub async fn call_at(web3: &Web3<Http>, contract: Address, data: Vec<u8>, block: Option<u64>) -> Result<Vec<u8>> {
+    let request = CallRequest::builder().to(contract).data(Bytes(data)).build();
+    let block = block.map(|b| BlockId::Number(BlockNumber::Number(b.into())));
+    Ok(web3.eth().call(request, block).await?.0)
+}
+
+/// Timestamp of a block.
+pub async fn block_time(web3: &Web3<Http>, block: u64) -> Result<DateTime<Utc>> {
+    let header = web3
+        .eth()
+        .block(BlockId::Number(BlockNumber::Number(block.into())))
+        .await?
+        .with_context(|| format!("block {} not found", block))?;
+    DateTime::from_timestamp(header.timestamp.as_u64() as i64, 0)
+        .with_context(|| format!("block {} has an invalid timestamp", block))
+}
+
+/// `totalSupply()` of an ERC-20 or ERC-721 Enumerable contract.
+pub async fn total_supply(web3: &Web3<Http>, contract: Address) -> Result<U256> {
+    total_supply_at(web3, contract, None).await
+}
+
+/// `totalSupply()` as of `block`, or the latest block when `None`.
+pub async fn total_supply_at(web3: &Web3<Http>, contract: Address, block: Option<u64>) -> Result<U256> {
+    let output = call_at(web3, contract, vec![0x18, 0x16, 0x0d, 0xdd], block).await?;
+    anyhow::ensure!(output.len() >= 32, "totalSupply() returned {} bytes", output.len());
+    Ok(U256::from_big_endian(&output[..32]))
+}
+
+/// Connects to `<CHAIN>_RPC_URL`, e.g. `BASE_RPC_URL` for `base`.
+pub fn connect_chain(chain: &str) -> Result<Web3<Http>> {
+    let var = format!("{}_RPC_URL", chain.trim().to_uppercase().replace('-', "_"));
+    let url = env::var(&var).with_context(|| format!("{} must be set to read {}", var, chain))?;
+    Ok(Web3::new(http::transport(&url)?))
+}
+
+/// Connects to the Ethereum mainnet endpoint in `ETHEREUM_RPC_URL`, used for ENS lookups.
+pub fn connect_ethereum() -> Result<Web3<Http>> {
+    let url = env::var("ETHEREUM_RPC_URL").context("ETHEREUM_RPC_URL must be set for ENS lookups")?;
+    Ok(Web3::new(http::transport(&url)?))
+}
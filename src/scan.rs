@@ -0,0 +1,1220 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, info_span, trace, warn, Instrument};
+
+use crate::address;
+use crate::alchemy::{self, OwnersResponse};
+use crate::archive::{self, RetentionPolicy};
+use crate::bundle;
+use crate::compare;
+use crate::completeness::{self, Expected, Verdict};
+use crate::config;
+use crate::cost::{BudgetTracker, OnExceeded};
+use crate::endpoints::EndpointPool;
+use crate::error::ScannerError;
+use crate::etherscan::{self, EtherscanSource};
+use crate::export::{HolderRow, JsonlStream};
+use crate::fetcher::{FetchedPage, Fetcher};
+use crate::heartbeat::HeartbeatWriter;
+use crate::histogram;
+use crate::holder_store::HolderStore;
+use crate::http;
+use crate::http_cache::HttpCache;
+use crate::identity::HolderIdentity;
+use crate::journal::{self, Journal, JournalEvent};
+use crate::keys::KeyPool;
+use crate::metrics::{self, ProviderMetrics};
+use crate::notify::{self, Notifiers, ScanSummary};
+use crate::output;
+use crate::plan;
+use crate::progress::ScanProgress;
+use crate::ratelimit::RateLimiter;
+use crate::replay::ResponseCache;
+use crate::report;
+use crate::sheets;
+use crate::state::{
+    self, load_state, save_holders_to_file, save_state, save_state_to, Provenance, QuarantinedPage, ScanState,
+    SHADOW_STATE_PATH,
+};
+use crate::subgraph::{self, SubgraphSource};
+use crate::watch::parse_interval;
+
+pub const CONTRACT_ADDRESS: &str = "0xC36442b4a4522E871399CD717aBDD847Ab11FE88";
+
+// Gives a provider node that served a bad page a moment before it is asked again
+const QUARANTINE_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Where a scan reads holders from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Provider {
+    /// Alchemy's getOwnersForContract
+    #[default]
+    Alchemy,
+    /// The GraphQL endpoint in `[subgraph]`
+    Subgraph,
+    /// The Etherscan-family explorer API in `[etherscan]`
+    Etherscan,
+}
+
+impl Provider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Provider::Alchemy => "alchemy",
+            Provider::Subgraph => "subgraph",
+            Provider::Etherscan => "etherscan",
+        }
+    }
+}
+
+/// Builds a provider's page requests and turns its responses into owners pages.
+enum PageSource {
+    Alchemy,
+    Subgraph(SubgraphSource),
+    Etherscan(EtherscanSource),
+}
+
+impl PageSource {
+    fn new(provider: Provider) -> Result<PageSource> {
+        Ok(match provider {
+            Provider::Alchemy => PageSource::Alchemy,
+            Provider::Subgraph => PageSource::Subgraph(subgraph::from_config(&config::load_config()?)?),
+            Provider::Etherscan => {
+                PageSource::Etherscan(etherscan::from_config(&config::load_config()?, CONTRACT_ADDRESS)?)
+            }
+        })
+    }
+
+    /// The other sources keep the whole pass in memory, so an interrupted pass restarts
+    fn resumes(&self) -> bool {
+        matches!(self, PageSource::Alchemy)
+    }
+
+    fn endpoints_and_keys(&self) -> Result<(EndpointPool, KeyPool)> {
+        Ok(match self {
+            PageSource::Alchemy => (EndpointPool::from_env(), KeyPool::from_env()?),
+            PageSource::Subgraph(source) => (source.endpoints(), SubgraphSource::keys()?),
+            PageSource::Etherscan(source) => (source.endpoints(), EtherscanSource::keys()?),
+        })
+    }
+
+    async fn fetch(
+        &mut self,
+        fetcher: &mut Fetcher,
+        page_key: Option<&str>,
+        page: u64,
+    ) -> Result<FetchedPage, ScannerError> {
+        match self {
+            PageSource::Alchemy => {
+                let mut path =
+                    format!("/getOwnersForContract?contractAddress={}&withTokenBalances=true", CONTRACT_ADDRESS);
+                if let Some(key) = page_key {
+                    path.push_str(&format!("&pageKey={}", key));
+                }
+                trace!(%path, "Requesting");
+                fetcher.fetch(&path, page).await
+            }
+            PageSource::Subgraph(source) => fetcher.post(&source.query(page_key), page).await,
+            PageSource::Etherscan(source) => fetcher.query(&source.query(page_key)?, page).await,
+        }
+    }
+
+    /// Builds the request for `page_key` without sending it, so the sources
+    /// that track their pass as requests are made follow a replay too.
+    fn skip_request(&mut self, page_key: Option<&str>) -> Result<(), ScannerError> {
+        match self {
+            PageSource::Alchemy => {}
+            PageSource::Subgraph(source) => {
+                source.query(page_key);
+            }
+            PageSource::Etherscan(source) => {
+                source.query(page_key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses an owners page. Owners whose address isn't a valid address are
+    /// dropped with a warning, so provider garbage never reaches the holder set.
+    fn parse(&mut self, fetched: &FetchedPage) -> Result<OwnersResponse, ScannerError> {
+        let mut response = match self {
+            PageSource::Alchemy => alchemy::parse_owners_page(fetched.status, &fetched.body),
+            PageSource::Subgraph(source) => source.parse_page(fetched.status, &fetched.body),
+            PageSource::Etherscan(source) => source.parse_page(fetched.status, &fetched.body),
+        }?;
+        response.owners.retain_mut(|owner| match address::normalize(&owner.owner_address) {
+            Ok(normalized) => {
+                owner.owner_address = normalized;
+                true
+            }
+            Err(e) => {
+                warn!(error = %e, endpoint = %fetched.endpoint, "Dropping an owner that isn't a valid address");
+                false
+            }
+        });
+        Ok(response)
+    }
+
+    /// Block the holders were read at, when the source knows it
+    fn block(&self) -> Option<u64> {
+        match self {
+            PageSource::Alchemy => None,
+            PageSource::Subgraph(source) => source.block,
+            PageSource::Etherscan(source) => source.block,
+        }
+    }
+
+    /// The page key after `page_key` when the source knows it without the
+    /// response, so the pass can go on past a page that fails to parse
+    fn page_after(&self, page_key: Option<&str>) -> Option<String> {
+        match self {
+            PageSource::Etherscan(source) => source.page_after(page_key),
+            _ => None,
+        }
+    }
+
+    /// Block requests are pinned to, which has passed by the time they're
+    /// made: a subgraph pass after its first page reads the block that page saw
+    fn pinned_block(&self) -> Option<u64> {
+        match self {
+            PageSource::Subgraph(source) => source.block,
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ScanOptions {
+    /// Archive the completed scan under data/snapshots/<contract>/<timestamp>/
+    #[arg(long)]
+    pub archive: bool,
+    /// Retention: keep only the N most recent archived snapshots
+    #[arg(long, requires = "archive")]
+    pub keep_last: Option<usize>,
+    /// Retention: keep the newest archived snapshot from each of the last N weeks
+    #[arg(long, requires = "archive")]
+    pub keep_weekly: Option<usize>,
+    /// Also write the holder list sharded by the first N hex characters of the address
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=4))]
+    pub shard_by_prefix: Option<u8>,
+    /// Disable the progress bar
+    #[arg(long)]
+    pub no_progress: bool,
+    /// Print the estimated pages, requests and budget for the scan, then exit without
+    /// fetching or writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Token supply to base the dry-run estimate on instead of calling totalSupply()
+    #[arg(long, requires = "dry_run")]
+    pub total_supply: Option<u64>,
+    /// Owners per provider page assumed by the dry-run estimate
+    #[arg(long, default_value_t = plan::DEFAULT_OWNERS_PER_PAGE)]
+    pub owners_per_page: u64,
+    /// Discard the saved checkpoint and start a new pass from the first page
+    #[arg(long)]
+    pub fresh: bool,
+    /// How often to rewrite data/heartbeat.json (e.g. 30s)
+    #[arg(long, value_parser = parse_interval, default_value = "30s")]
+    pub heartbeat_interval: std::time::Duration,
+    /// Abort with the checkpoint saved if no page completes within this long (e.g. 10m)
+    #[arg(long, value_parser = parse_interval)]
+    pub watchdog: Option<std::time::Duration>,
+    /// How many times to retry each page that failed to parse once the pass is over
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub quarantine_retries: u32,
+    /// Flag the snapshot when the tokens held or holders found differ from totalSupply() or the
+    /// provider's holder count by more than this percentage
+    #[arg(long, value_name = "PCT", default_value_t = completeness::DEFAULT_TOLERANCE_PCT)]
+    pub supply_tolerance: f64,
+    /// Skip comparing the finished scan with totalSupply() and the provider's holder count
+    #[arg(long, conflicts_with = "supply_tolerance")]
+    pub no_supply_check: bool,
+    /// Keep holders in sorted runs on disk instead of in memory, for very large holder
+    /// sets. Age and balance reports are skipped since they need the full set in memory
+    #[arg(long, conflicts_with_all = ["archive", "shard_by_prefix"])]
+    pub streaming: bool,
+    /// Scan a preset's contracts instead, e.g. `btb` for the BTB Finance ecosystem
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["archive", "streaming", "jsonl_stream", "dry_run"])]
+    pub preset: Option<String>,
+    /// Where to read holders from
+    #[arg(long, value_enum, default_value_t = Provider::Alchemy, conflicts_with = "dry_run")]
+    pub provider: Provider,
+    /// Read the holders again from a second provider once the scan completes and report
+    /// where the two disagree
+    #[arg(long, value_enum, value_name = "PROVIDER", conflicts_with_all = ["dry_run", "streaming"])]
+    pub verify_with: Option<Provider>,
+    /// Keep every raw provider response in this directory, for `--replay`
+    #[arg(long, value_name = "DIR", conflicts_with = "dry_run")]
+    pub record_responses: Option<PathBuf>,
+    /// Re-run the scan from responses kept by `--record-responses` instead of
+    /// calling the provider. Starts a fresh pass
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["dry_run", "record_responses", "verify_with"])]
+    pub replay: Option<PathBuf>,
+    /// Reuse provider responses from the HTTP cache that are younger than this (e.g. 30m, 1d) and cache new
+    /// ones. Defaults to HTTP_CACHE_TTL; without either the cache is off
+    #[arg(long, value_name = "AGE", value_parser = parse_interval)]
+    pub cache_ttl: Option<std::time::Duration>,
+    /// Neither read nor write the HTTP cache, even with HTTP_CACHE_TTL set
+    #[arg(long, conflicts_with_all = ["cache_ttl", "offline"])]
+    pub no_cache: bool,
+    /// Answer every request from the HTTP cache, however old, and fail on a miss instead of calling the provider
+    #[arg(long, conflicts_with_all = ["dry_run", "replay"])]
+    pub offline: bool,
+    /// Seed the scan from a previous snapshot instead of data/state.json: a run id,
+    /// snapshot id, snapshot directory or state file
+    #[arg(long, value_name = "RUN|SNAPSHOT|PATH", conflicts_with_all = ["dry_run", "fresh", "replay", "streaming"])]
+    pub resume_from: Option<String>,
+    /// Append each page's holders to this file as JSON Lines as soon as the page
+    /// arrives; `-` writes them to stdout
+    #[arg(long, value_name = "PATH")]
+    pub jsonl_stream: Option<PathBuf>,
+}
+
+pub async fn run_scan(options: ScanOptions) -> Result<ScanState> {
+    if options.preset.is_some() {
+        bail!("presets produce their own snapshot; run them with `scan --preset`");
+    }
+    if options.dry_run {
+        let state = load_state().unwrap_or_default();
+        let limiter = RateLimiter::from_env(&config::load_config()?.cost);
+        let scan_plan =
+            plan::estimate(CONTRACT_ADDRESS, &state, options.total_supply, options.owners_per_page, &limiter).await?;
+        plan::print_plan(&scan_plan, &limiter);
+        return Ok(state);
+    }
+
+    let config = config::load_config()?;
+    // A replay reproduces an earlier scan; nobody needs to hear about it again
+    let replaying = options.replay.is_some();
+    let notifiers = if replaying { None } else { Notifiers::new(&config)? };
+    // Read before this run adds its own finish entry to the journals
+    let previous_holders = match &notifiers {
+        Some(_) => journal::last_finished_holders().unwrap_or_else(|e| {
+            warn!(error = %e, "Could not read previous run journals");
+            None
+        }),
+        None => None,
+    };
+
+    let started_at = Utc::now();
+    #[cfg(feature = "cloud")]
+    let files_since = std::time::SystemTime::now();
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    let event_sink = crate::sink::EventSink::from_env().await?;
+    let mut journal = Journal::start()?;
+    info!(run = %journal.id, "Journaling run to {}", journal::RUNS_DIR);
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    let run_id = journal.id.clone();
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    let scan_event = |kind, pages, holders, error| crate::sink::ScanEvent {
+        kind,
+        run: run_id.clone(),
+        contract: CONTRACT_ADDRESS.to_string(),
+        at: Utc::now(),
+        pages,
+        holders,
+        error,
+    };
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    crate::sink::publish_scan(event_sink.as_ref(), scan_event("started", 0, 0, None)).await;
+    let result = scan_pages(options, &mut journal).await;
+    let (status, holders, total_balance, top_new_holders) = match &result {
+        Ok(state) => (
+            completeness::run_status(state),
+            state.total_holders,
+            state.balances.values().map(|balance| u128::from(*balance)).sum(),
+            notify::top_new_holders(state, started_at, notify::TOP_NEW_HOLDERS),
+        ),
+        Err(e) => {
+            journal.record(JournalEvent::Error { page: None, message: format!("{:#}", e) });
+            let saved = load_state().unwrap_or_default();
+            let total_balance = saved.balances.values().map(|balance| u128::from(*balance)).sum();
+            ("failed", saved.total_holders, total_balance, Vec::new())
+        }
+    };
+    journal.record(JournalEvent::RunFinished { status: status.to_string(), pages: journal.pages(), holders });
+    // A finished scan whose output never reached the bucket would be lost with the container
+    #[cfg(feature = "cloud")]
+    let result = match crate::storage::push_run(files_since).await {
+        Ok(()) => result,
+        Err(e) if result.is_ok() => Err(e.context("uploading data/ to OUTPUT_URL")),
+        Err(e) => {
+            warn!(error = %format!("{:#}", e), "Could not upload data/ to OUTPUT_URL");
+            result
+        }
+    };
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    {
+        let kind = if result.is_ok() { "finished" } else { "failed" };
+        let error = result.as_ref().err().map(|e| format!("{:#}", e));
+        crate::sink::publish_scan(event_sink.as_ref(), scan_event(kind, journal.pages(), holders, error)).await;
+    }
+
+    if let Some(notifiers) = notifiers {
+        let summary = ScanSummary {
+            contract: CONTRACT_ADDRESS.to_string(),
+            run_id: journal.id.clone(),
+            started_at,
+            finished_at: Utc::now(),
+            pages: journal.pages(),
+            holders,
+            previous_holders,
+            holder_delta: previous_holders.map(|previous| holders as i64 - previous as i64),
+            total_balance,
+            top_new_holders,
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        };
+        notifiers.notify_scan(&summary).await;
+    }
+    if let (Ok(state), false) = (&result, replaying) {
+        sheets::push_after_scan(&config, state).await;
+    }
+    result
+}
+
+// Spans: `scan` for the run, then `fetch_page`, `parse_page`, `enrich_page` and `persist_page` per page, and
+// `reports` and `archive` at the end, exported over OTLP when tracing is on
+#[tracing::instrument(name = "scan", skip_all, fields(run = %journal.id, provider = options.provider.as_str()))]
+async fn scan_pages(options: ScanOptions, journal: &mut Journal) -> Result<ScanState> {
+    #[cfg(feature = "cloud")]
+    let mut output_store = crate::storage::OutputStore::from_env()?;
+    #[cfg(feature = "cloud")]
+    if let Some(output_store) = &output_store {
+        output_store.restore_state().await?;
+    }
+
+    // Load existing state or create new one
+    let mut state = match &options.resume_from {
+        Some(reference) => {
+            let path = archive::resolve_state(reference)?;
+            info!(state = %path.display(), "Seeding the scan from an earlier state");
+            state::load_state_from(&path).with_context(|| format!("reading {}", path.display()))?
+        }
+        // A fresh pass only carries over first-seen times and the like, so a newer state file can be replaced
+        None if options.fresh || options.replay.is_some() => load_state().unwrap_or_default(),
+        None => state::load_state_or_default()?,
+    };
+
+    let started_at = Utc::now();
+
+    // Initialize HTTP client with longer timeout
+    let client = http::client(std::time::Duration::from_secs(30))?;
+
+    if options.fresh || options.replay.is_some() {
+        info!("Discarding checkpoint, starting a fresh scan");
+        state.holders.clear();
+        state.balances.clear();
+        state.tokens.clear();
+        state.total_holders = 0;
+        state.last_page_key = None;
+        state.last_page_key_at = None;
+        state.provenance.clear();
+        state.look_through.clear();
+    }
+    // Only this run's check describes the holders it ends with
+    state.completeness = None;
+    let mut store = match options.streaming {
+        true => Some(HolderStore::from_env()?),
+        false => None,
+    };
+    if let (true, Some(store)) = (options.fresh, store.as_mut()) {
+        store.clear()?;
+    }
+    let mut source = PageSource::new(options.provider)?;
+    if options.streaming && options.provider == Provider::Etherscan {
+        bail!("--streaming can't drop holders, which a transfer replay needs; scan Etherscan without it");
+    }
+    if options.verify_with == Some(options.provider) {
+        bail!("--verify-with needs a provider other than --provider {}", options.provider.as_str());
+    }
+    let mut page_key = validated_page_key(&state);
+    if !source.resumes() && page_key.take().is_some() {
+        info!(provider = options.provider.as_str(), "Restarting the pass from the first page");
+    }
+    // A new pass reads every page again; a resumed one still owes the pages it skipped
+    match page_key {
+        None => state.quarantine.clear(),
+        Some(_) => state.quarantine.retain(|page| page.skipped),
+    }
+    // Set while the first request still uses a key carried over from a previous run
+    let mut resuming = page_key.is_some();
+    let contract_address = CONTRACT_ADDRESS;
+    let max_retries: u32 = env::var("MAX_RETRIES").ok().and_then(|v| v.parse().ok()).unwrap_or(3);
+    let mut replay = match &options.replay {
+        Some(dir) => Some(ResponseCache::replay(dir, options.provider.as_str())?),
+        None => None,
+    };
+    let mut recording = match &options.record_responses {
+        Some(dir) => Some(ResponseCache::record(dir, options.provider.as_str())?),
+        None => None,
+    };
+    // A replay sends nothing, so it needs neither endpoints nor keys
+    let (endpoints, keys) = match &options.replay {
+        Some(dir) => (
+            EndpointPool::with_urls(&[dir.display().to_string()]),
+            KeyPool::new(vec![String::new()], std::time::Duration::ZERO)?,
+        ),
+        None => source.endpoints_and_keys()?,
+    };
+    let prices = config::load_config()?.cost;
+    if let Some(max_usd) = prices.budget.as_ref().and_then(|budget| budget.max_usd) {
+        let priced = match options.provider {
+            Provider::Alchemy => prices.usd_per_million_cu.is_some(),
+            _ => prices.usd_per_1000_requests.is_some(),
+        };
+        if !priced {
+            bail!("[cost.budget] max_usd = {} needs a price for {} in [cost]", max_usd, options.provider.as_str());
+        }
+    }
+    let mut budget = prices.budget.clone().map(BudgetTracker::new);
+    let limiter = RateLimiter::from_env(&prices);
+    let mut fetcher = Fetcher::new(
+        client,
+        endpoints,
+        keys,
+        ProviderMetrics::new(options.provider.as_str(), prices),
+        limiter,
+        max_retries,
+    );
+    if options.replay.is_none() {
+        let provider = options.provider.as_str();
+        fetcher.cache = HttpCache::for_scan(provider, options.cache_ttl, options.offline, options.no_cache)?;
+    }
+
+    info!(holders = state.holders.len(), page_key = ?page_key, "Starting scan");
+    journal.record(JournalEvent::RunStarted {
+        contract: contract_address.to_string(),
+        holders: state.total_holders,
+        resume_page_key: page_key.clone(),
+        provider: Some(options.provider.as_str().to_string()),
+        arguments: env::args().skip(1).collect(),
+    });
+
+    let mut jsonl_stream = match &options.jsonl_stream {
+        Some(path) => Some(JsonlStream::open(path, resuming)?),
+        None => None,
+    };
+
+    let mut page_count = 0;
+    let mut progress = ScanProgress::new(!options.no_progress, state.holders.len() as u64);
+    let heartbeat = HeartbeatWriter::start(options.heartbeat_interval, state.holders.len() as u64);
+
+    // When pagination gets stuck, the original state is parked here and a fresh
+    // pass fills a shadow state that is reconciled once it completes
+    let mut parked: Option<ScanState> = None;
+    let mut parked_store: Option<HolderStore> = None;
+    let mut pass_page_keys: HashSet<String> = HashSet::new();
+    let mut reached_end = false;
+    // Pages that fail to parse are quarantined in the state. Once the pass is over, or
+    // can't go past such a page, they are retried one at a time
+    let mut pass_over = false;
+    let mut retrying: Option<QuarantinedPage> = None;
+    let mut retries: HashMap<Option<String>, u32> = HashMap::new();
+    // Set while the pass goes on past a skipped page, whose successor may not exist
+    let mut after_skip = false;
+    // Holder count the provider last reported, for the completeness check
+    let mut reported_holders: Option<u64> = None;
+
+    // Fetch all pages of owners
+    loop {
+        if pass_over {
+            let Some(page) = next_retry(&mut state.quarantine, &mut retries, options.quarantine_retries) else {
+                break;
+            };
+            info!(page = page.page, attempts = page.attempts, "Retrying quarantined page");
+            if replay.is_none() {
+                tokio::time::sleep(QUARANTINE_RETRY_DELAY).await;
+            }
+            page_key = page.page_key.clone();
+            retrying = Some(page);
+        }
+        page_count += 1;
+        debug!(page = page_count, "Fetching page");
+
+        let fetched = if let Some(replay) = replay.as_mut() {
+            source.skip_request(page_key.as_deref())?;
+            Ok(replay.next(page_key.as_deref())?)
+        } else {
+            fetcher.block = source.pinned_block();
+            fetcher.refresh = retrying.is_some();
+            let span = info_span!("fetch_page", page = page_count);
+            let fetch = source.fetch(&mut fetcher, page_key.as_deref(), page_count).instrument(span);
+            match options.watchdog {
+                Some(limit) => match tokio::time::timeout(limit, fetch).await {
+                    Ok(fetched) => fetched,
+                    Err(_) => {
+                        // State was saved after the last completed page, so the next run resumes from it
+                        progress.finish();
+                        heartbeat.stop("stalled")?;
+                        bail!(
+                            "watchdog: no progress for {:?} on page {}, aborting with checkpoint saved",
+                            limit,
+                            page_count
+                        );
+                    }
+                },
+                None => fetch.await,
+            }
+        };
+        let fetched = match fetched {
+            Ok(fetched) => fetched,
+            Err(e) if retrying.is_some() => {
+                warn!(error = %format!("{:#}", e), page = page_count, "Quarantined page failed again");
+                if let Some(mut page) = retrying.take() {
+                    page.attempts += 1;
+                    page.error = format!("{:#}", e);
+                    page.failed_at = Utc::now();
+                    state.quarantine.push(page);
+                }
+                continue;
+            }
+            Err(e) if page_key.is_some() && parked.is_none() => {
+                warn!(error = %format!("{:#}", e), page = page_count, "Page keeps failing, restarting pagination into a shadow state");
+                journal.record(JournalEvent::Warning {
+                    page: Some(page_count),
+                    message: format!("page keeps failing ({:#}), restarting pagination into a shadow state", e),
+                });
+                parked = Some(start_shadow_pass(&mut state));
+                parked_store = start_shadow_store(&mut store)?;
+                page_key = None;
+                pass_page_keys.clear();
+                continue;
+            }
+            Err(e) => {
+                heartbeat.stop("failed")?;
+                return Err(e.into());
+            }
+        };
+        // Kept before parsing, so responses that fail to parse can be replayed too
+        if let Some(recording) = recording.as_mut() {
+            recording.save(page_key.as_deref(), &fetched)?;
+        }
+
+        let parsed = info_span!("parse_page", page = page_count).in_scope(|| source.parse(&fetched));
+        let response = match parsed {
+            Ok(response) => response,
+            Err(e @ ScannerError::Provider { status: 400..=499, .. }) if resuming => {
+                warn!(error = %e, "Provider rejected the saved page key, restarting from the first page");
+                journal.record(JournalEvent::Warning {
+                    page: Some(page_count),
+                    message: format!("provider rejected the saved page key ({}), restarting from the first page", e),
+                });
+                resuming = false;
+                page_key = None;
+                continue;
+            }
+            Err(ScannerError::MalformedResponse { reason, body }) => {
+                fetcher.metrics.record_parse_error();
+                warn!(%reason, "Response does not match the owners schema");
+                journal.record(JournalEvent::Error {
+                    page: Some(page_count),
+                    message: format!("response does not match the owners schema: {}", reason),
+                });
+                debug!(%body, "Malformed response");
+                match bundle::save_failed_response(&journal.id, page_count, &body) {
+                    Ok(path) => debug!(path = %path.display(), "Kept malformed response for diagnostics"),
+                    Err(e) => warn!(error = %e, "Could not keep malformed response"),
+                }
+                // Only a first failure can skip ahead; a retried page's pass has already moved on or stopped
+                let skip_to = match retrying {
+                    Some(_) => None,
+                    None => source.page_after(page_key.as_deref()),
+                };
+                let mut page = retrying.take().unwrap_or_else(|| QuarantinedPage {
+                    page: page_count,
+                    page_key: page_key.clone(),
+                    error: String::new(),
+                    failed_at: Utc::now(),
+                    attempts: 0,
+                    skipped: skip_to.is_some(),
+                });
+                page.attempts += 1;
+                page.error = reason;
+                page.failed_at = Utc::now();
+                info!(page = page.page, attempts = page.attempts, skipped = page.skipped, "Page quarantined");
+                journal.record(JournalEvent::Warning {
+                    page: Some(page_count),
+                    message: match &skip_to {
+                        Some(_) => "page quarantined, skipping ahead".to_string(),
+                        None if page.attempts == 1 => "page quarantined, the pass can't go on past it".to_string(),
+                        None => format!("quarantined page {} failed again", page.page),
+                    },
+                });
+                state.quarantine.push(page);
+                match skip_to {
+                    Some(next) => {
+                        page_key = Some(next);
+                        state.last_page_key = page_key.clone();
+                        state.last_page_key_at = Some(Utc::now());
+                        after_skip = true;
+                    }
+                    None => pass_over = true,
+                }
+                if parked.is_some() {
+                    save_state_to(&state, Path::new(SHADOW_STATE_PATH))?;
+                } else {
+                    save_state(&state)?;
+                }
+                continue;
+            }
+            Err(e) => {
+                heartbeat.stop("failed")?;
+                return Err(e.into());
+            }
+        };
+        debug!(count = response.owners.len(), "Found owners in response");
+        resuming = false;
+        let retried = retrying.take();
+        if let Some(page) = &retried {
+            info!(page = page.page, attempts = page.attempts + 1, "Read quarantined page");
+        }
+        // The pass already went on past a skipped page; only its owners were missing
+        let retried_skipped = retried.as_ref().is_some_and(|page| page.skipped);
+
+        let enrich = info_span!("enrich_page", page = page_count, owners = response.owners.len()).entered();
+        let mut new_owners = Vec::with_capacity(response.owners.len());
+        let mut page_balances = HashMap::with_capacity(response.owners.len());
+        let mut page_tokens = HashMap::with_capacity(response.owners.len());
+        // Replaying sources report owners whose last token just left them
+        let mut emptied = Vec::new();
+        for owner in &response.owners {
+            if owner.balance() == 0 {
+                emptied.push(owner.owner_address.clone());
+                continue;
+            }
+            new_owners.push(owner.owner_address.clone());
+            page_balances.insert(owner.owner_address.clone(), owner.balance());
+            // Sources that only report totals have no token ids
+            page_tokens.insert(
+                owner.owner_address.clone(),
+                owner.token_balances.iter().filter(|b| !b.token_id.is_empty()).map(|b| b.token_id.clone()).collect(),
+            );
+        }
+
+        // Past a skipped page, an empty last page means the skipped one was the last
+        let skipped_last = after_skip && response.page_key.is_none();
+        if new_owners.is_empty() && emptied.is_empty() && retried.is_none() && !skipped_last {
+            warn!("No owners found in response");
+            pass_over = true;
+            continue;
+        }
+        if retried.is_none() {
+            after_skip = false;
+        }
+
+        let seen_at = Utc::now();
+        let provenance = Provenance {
+            source: options.provider.as_str().to_string(),
+            endpoint: Some(fetched.endpoint.clone()),
+            run: Some(journal.id.clone()),
+            page: Some(page_count),
+            block: source.block(),
+        };
+        if let Some(stream) = jsonl_stream.as_mut() {
+            stream.write_page(new_owners.iter().map(|owner| {
+                let balance = page_balances.get(owner).copied().unwrap_or(0);
+                HolderRow {
+                    address: owner,
+                    balance,
+                    first_seen: Some(state.first_seen.get(owner).copied().unwrap_or(seen_at)),
+                    first_acquired: state.acquisitions.get(owner).map(|a| a.first_acquired),
+                    held_since: state.acquisitions.get(owner).map(|a| a.held_since),
+                    usd_value: state.valuation.as_ref().map(|v| v.usd(balance)),
+                    provenance: Some(&provenance),
+                    labels: state.labels.get(owner).map(Vec::as_slice).unwrap_or_default(),
+                }
+            }))?;
+        }
+        if let Some(store) = store.as_mut() {
+            for (owner, balance) in &page_balances {
+                store.insert(owner, *balance, seen_at)?;
+            }
+            store.checkpoint()?;
+            state.total_holders = store.holder_count();
+            debug!(parsed = new_owners.len(), total = state.total_holders, "Page processed");
+        } else {
+            for owner in &emptied {
+                state.holders.remove(owner);
+                state.balances.remove(owner);
+                state.tokens.remove(owner);
+                state.provenance.remove(owner);
+            }
+            // Add the new owners to our state
+            let initial_count = state.holders.len();
+            for owner in &new_owners {
+                state.holders.insert(owner.clone());
+                state.first_seen.entry(owner.clone()).or_insert(seen_at);
+            }
+            for owner in page_balances.keys() {
+                state.provenance.insert(owner.clone(), provenance.clone());
+            }
+            state.balances.extend(page_balances);
+            state.tokens.extend(page_tokens);
+            let new_count = state.holders.len();
+            debug!(parsed = new_owners.len(), added = new_count - initial_count, total = new_count, "Page processed");
+            state.total_holders = state.holders.len() as u64;
+        }
+        drop(enrich);
+
+        // Update state
+        state.last_save_time = Utc::now();
+
+        // Not every provider response carries a total; when it does we get an ETA
+        if let Some(total) = response.total_count {
+            progress.set_total(total);
+            reported_holders = Some(total);
+        }
+        progress.page_done(state.total_holders);
+        journal.record(JournalEvent::Page {
+            page: page_count,
+            endpoint: fetched.endpoint.clone(),
+            latency_ms: fetched.latency_ms,
+            attempts: fetched.attempts,
+            owners: new_owners.len(),
+            holders: state.total_holders,
+        });
+        heartbeat.progress(page_count, state.total_holders);
+
+        if !retried_skipped {
+            page_key = response.page_key;
+            // A page the pass stopped at has been read, so it goes on from there
+            pass_over = false;
+
+            // Save the page key in state
+            state.last_page_key = page_key.clone();
+            state.last_page_key_at = page_key.as_ref().map(|_| Utc::now());
+        }
+
+        // A page key we've already followed in this pass means the provider is looping
+        if let (Some(key), false) = (&page_key, retried_skipped) {
+            if !pass_page_keys.insert(key.clone()) {
+                if parked.is_some() {
+                    heartbeat.stop("failed")?;
+                    bail!("pagination is stuck on a repeated page key even after restarting from scratch");
+                }
+                warn!(
+                    page = page_count,
+                    "Provider returned a page key seen earlier in this pass, restarting pagination into a shadow state"
+                );
+                journal.record(JournalEvent::Warning {
+                    page: Some(page_count),
+                    message: "provider returned a page key seen earlier in this pass, restarting pagination into a shadow state".to_string(),
+                });
+                parked = Some(start_shadow_pass(&mut state));
+                parked_store = start_shadow_store(&mut store)?;
+                page_key = None;
+                pass_page_keys.clear();
+                continue;
+            }
+        }
+
+        // Save progress after each page; a shadow pass leaves the real checkpoint untouched
+        let persist = info_span!("persist_page", page = page_count, holders = state.total_holders);
+        if parked.is_some() {
+            persist.in_scope(|| save_state_to(&state, Path::new(SHADOW_STATE_PATH)))?;
+        } else {
+            persist.in_scope(|| {
+                save_state(&state)?;
+                if store.is_none() {
+                    save_holders_to_file(&state)?;
+                }
+                anyhow::Ok(())
+            })?;
+            journal.record(JournalEvent::Checkpoint {
+                page: page_count,
+                holders: state.total_holders,
+                page_key: state.last_page_key.clone(),
+            });
+            // Uploaded checkpoints are best effort; the end of the run uploads everything
+            #[cfg(feature = "cloud")]
+            if let Some(output_store) = &mut output_store {
+                if let Err(e) = output_store.checkpoint(&journal.path()).instrument(persist).await {
+                    warn!(error = %format!("{:#}", e), "Could not upload checkpoint to OUTPUT_URL");
+                }
+            }
+        }
+
+        if retried_skipped {
+            continue;
+        }
+        if page_key.is_none() {
+            info!("No more pages to fetch");
+            reached_end = true;
+            pass_over = true;
+            continue;
+        }
+
+        if let Some(budget) = budget.as_mut() {
+            if let Some(exceeded) = budget.exceeded(fetcher.metrics.cost()) {
+                journal.record(JournalEvent::Warning {
+                    page: Some(page_count),
+                    message: format!("cost budget exceeded: {}", exceeded),
+                });
+                if budget.on_exceeded() == OnExceeded::Abort {
+                    progress.finish();
+                    heartbeat.stop("failed")?;
+                    bail!("cost budget exceeded ({}); the checkpoint is saved, so a rerun resumes the scan", exceeded);
+                }
+                warn!(%exceeded, pause = ?budget.pause(), "Cost budget exceeded, pausing the scan");
+                // A paused scan isn't a stalled one, so keep the heartbeat's progress time current
+                let resume_at = tokio::time::Instant::now() + budget.pause();
+                let step = std::time::Duration::from_secs(60);
+                while tokio::time::Instant::now() < resume_at {
+                    tokio::time::sleep_until(resume_at.min(tokio::time::Instant::now() + step)).await;
+                    heartbeat.progress(page_count, state.total_holders);
+                }
+                budget.renew(fetcher.metrics.cost());
+                info!("Resuming the scan with a fresh cost budget");
+            }
+        }
+    }
+
+    let completed = reached_end && state.quarantine.is_empty();
+    if !state.quarantine.is_empty() {
+        let pages: Vec<String> = state.quarantine.iter().map(|page| page.page.to_string()).collect();
+        warn!(
+            pages = %pages.join(", "),
+            "Pages still quarantined after {} retries; the snapshot is incomplete",
+            options.quarantine_retries
+        );
+        journal.record(JournalEvent::Warning {
+            page: None,
+            message: format!("{} page(s) still quarantined, snapshot incomplete: {}", pages.len(), pages.join(", ")),
+        });
+    }
+    if let Some(original) = parked {
+        if completed {
+            if let (Some(shadow), Some(original_store)) = (store.take(), parked_store.take()) {
+                store = Some(shadow.replace(original_store)?);
+            }
+            reconcile_shadow_pass(&original, &mut state)?;
+        } else {
+            warn!("Shadow pass did not complete, keeping the original checkpoint");
+            if let (Some(shadow), Some(original_store)) = (store.take(), parked_store.take()) {
+                shadow.discard()?;
+                store = Some(original_store);
+            }
+            state = original;
+        }
+    }
+
+    progress.finish();
+    heartbeat.stop("finished")?;
+
+    if completed && !options.no_supply_check {
+        let expected = Expected {
+            contract: contract_address,
+            block: source.block(),
+            reported_holders,
+            read_supply: options.replay.is_none() && !options.offline,
+            tolerance_pct: options.supply_tolerance,
+        };
+        let checked = completeness::check(&state, store.is_some(), expected).await;
+        match checked.verdict {
+            Verdict::Consistent => info!(
+                holders = checked.recorded_holders,
+                total_supply = ?checked.total_supply,
+                reported_holders = ?checked.reported_holders,
+                "Holdings match the reported totals"
+            ),
+            Verdict::Unchecked => info!("No totals to check the holdings against"),
+            verdict => {
+                let findings = checked.findings.join("; ");
+                warn!(verdict = verdict.as_str(), %findings, "Holdings diverge from the reported totals");
+                journal.record(JournalEvent::Warning {
+                    page: None,
+                    message: format!("snapshot {}: {}", verdict.as_str(), findings),
+                });
+            }
+        }
+        state.completeness = Some(checked);
+        save_state(&state)?;
+    }
+
+    let histogram_config = histogram::HistogramConfig::from_env();
+    let reports = info_span!("reports", holders = state.total_holders).entered();
+    if let Some(store) = store.as_mut() {
+        state.total_holders = store.export(&output::export_path(state::HOLDERS_PATH)?)?;
+        save_state(&state)?;
+        info!("Streaming scan: holder list written from {}, age and balance reports skipped", store.dir().display());
+    } else {
+        // Reports count holders by the configured identity rather than raw address
+        let identity = HolderIdentity::from_env()?;
+        let view = identity.regroup(&state);
+        if view.total_holders != state.total_holders {
+            info!(addresses = state.total_holders, holders = view.total_holders, key = ?identity.key, "Grouped addresses by holder identity");
+        }
+
+        let age_buckets = report::age_distribution(&view, Utc::now());
+        report::save_age_distribution(&age_buckets)?;
+
+        let histogram_buckets = histogram::balance_histogram(&view, &histogram_config);
+        histogram::save_histogram_table(&histogram_buckets)?;
+        #[cfg(feature = "charts")]
+        histogram::save_histogram_chart(&histogram_buckets, &histogram_config.chart_path)?;
+    }
+
+    if let Some(prefix_len) = options.shard_by_prefix {
+        let shards = state::save_holders_sharded(&state.holders, prefix_len as usize)?;
+        info!("Holder list sharded into {} files under {}", shards.len(), state::SHARDS_DIR);
+    }
+    drop(reports);
+
+    if let Some(cache) = &fetcher.cache {
+        info!(hits = cache.hits, misses = cache.misses, "HTTP cache");
+    }
+    // A replay made no requests, so it leaves the recorded run's report alone
+    if options.replay.is_none() {
+        let performance = fetcher.metrics.save("data/provider_report.json")?;
+        // Keep stdout to the holder records when they are streamed there
+        if options.jsonl_stream.as_deref() != Some(Path::new("-")) {
+            metrics::print_report(&performance);
+        }
+        journal.record(JournalEvent::Performance {
+            provider: performance.provider.clone(),
+            requests: performance.requests,
+            retries: performance.retries,
+            total_bytes: performance.total_bytes,
+            error_rate: performance.error_rate,
+            compute_units: performance.cost.compute_units,
+            estimated_usd: performance.cost.estimated_usd,
+        });
+    }
+
+    if let Some(provider) = options.verify_with {
+        if completed {
+            verify_holders(&state, options.provider, provider, journal).await?;
+        } else {
+            warn!("Scan did not complete, skipping verification");
+        }
+    }
+
+    info!(
+        holders = state.total_holders,
+        pages = page_count,
+        chart = %histogram_config.chart_path,
+        "Scan complete"
+    );
+
+    #[cfg(feature = "postgres")]
+    if let Ok(url) = env::var("DATABASE_URL") {
+        let mut client = crate::db::connect(&url).await?;
+        let run_id = crate::db::record_scan(
+            &mut client,
+            contract_address,
+            "optimism",
+            started_at,
+            &state,
+            &journal.id,
+            options.provider.as_str(),
+        )
+        .await?;
+        info!(run_id, "Scan run recorded in Postgres");
+    }
+    #[cfg(not(feature = "postgres"))]
+    let _ = started_at;
+
+    if options.archive {
+        let snapshot = info_span!("archive").in_scope(|| archive::archive_snapshot(contract_address, &state))?;
+        info!("Snapshot archived to {}", snapshot.path.display());
+        journal.record(JournalEvent::Snapshot {
+            id: snapshot.id.clone(),
+            path: snapshot.path.clone(),
+            hash: snapshot.hash.clone(),
+        });
+        let policy = RetentionPolicy { keep_last: options.keep_last, keep_weekly: options.keep_weekly };
+        for removed in archive::apply_retention(contract_address, policy)? {
+            info!("Pruned snapshot {}", removed.id);
+            #[cfg(feature = "cloud")]
+            if let Some(output_store) = &output_store {
+                output_store.remove_dir(&removed.path).await?;
+            }
+        }
+    }
+
+    Ok(state)
+}
+
+/// Report written by `--verify-with`, next to the discrepancies CSV.
+#[derive(Debug, Serialize)]
+struct Verification<'a> {
+    provider: &'static str,
+    verified_with: &'static str,
+    checked_at: chrono::DateTime<Utc>,
+    /// Block each provider read at, when it reports one
+    provider_block: Option<u64>,
+    verified_block: Option<u64>,
+    discrepancies_path: &'a str,
+    #[serde(flatten)]
+    found: compare::Discrepancies,
+}
+
+/// Reads every holder from `verify_with` into a throwaway state, without
+/// checkpoints or reports, and writes where it disagrees with the scan's
+/// `state` to `data/verify_<provider>.csv` and `data/verification.json`.
+async fn verify_holders(
+    state: &ScanState,
+    provider: Provider,
+    verify_with: Provider,
+    journal: &mut Journal,
+) -> Result<()> {
+    info!(provider = verify_with.as_str(), "Verifying holders against a second provider");
+    let mut source = PageSource::new(verify_with)?;
+    let (endpoints, keys) = source.endpoints_and_keys()?;
+    let client = http::client(std::time::Duration::from_secs(30))?;
+    let max_retries: u32 = env::var("MAX_RETRIES").ok().and_then(|v| v.parse().ok()).unwrap_or(3);
+    let prices = config::load_config()?.cost;
+    let limiter = RateLimiter::from_env(&prices);
+    let metrics = ProviderMetrics::new(verify_with.as_str(), prices);
+    let mut fetcher = Fetcher::new(client, endpoints, keys, metrics, limiter, max_retries);
+
+    let mut other = ScanState::default();
+    let mut page_key: Option<String> = None;
+    let mut page_keys = HashSet::new();
+    let mut page = 0;
+    loop {
+        page += 1;
+        let fetched = source.fetch(&mut fetcher, page_key.as_deref(), page).await?;
+        let response = source.parse(&fetched)?;
+        for owner in &response.owners {
+            let address = owner.owner_address.clone();
+            match owner.balance() {
+                0 => {
+                    other.holders.remove(&address);
+                    other.balances.remove(&address);
+                }
+                balance => {
+                    other.holders.insert(address.clone());
+                    other.balances.insert(address, balance);
+                }
+            }
+        }
+        page_key = response.page_key;
+        match &page_key {
+            Some(key) if !page_keys.insert(key.clone()) => {
+                bail!("{} returned a page key it had already returned; verification stopped", verify_with.as_str())
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    other.total_holders = other.holders.len() as u64;
+
+    let path = format!("data/verify_{}.csv", verify_with.as_str());
+    let found = compare::write_discrepancies(state, &other, Path::new(&path))?;
+    let report = Verification {
+        provider: provider.as_str(),
+        verified_with: verify_with.as_str(),
+        checked_at: Utc::now(),
+        provider_block: state.provenance.values().find_map(|p| p.block),
+        verified_block: source.block(),
+        discrepancies_path: &path,
+        found,
+    };
+    serde_json::to_writer_pretty(std::fs::File::create("data/verification.json")?, &report)?;
+
+    if found.is_empty() {
+        info!(holders = found.holders_a, provider = verify_with.as_str(), "Both providers agree on every holder");
+        return Ok(());
+    }
+    warn!(
+        provider = verify_with.as_str(),
+        only_scan = found.only_a,
+        only_verifier = found.only_b,
+        balance_mismatches = found.balance_mismatches,
+        discrepancies = %path,
+        "Providers disagree on holders"
+    );
+    journal.record(JournalEvent::Warning {
+        page: None,
+        message: format!(
+            "{} disagrees with {}: {} holders only in the scan, {} only in {}, {} balance mismatches",
+            verify_with.as_str(),
+            provider.as_str(),
+            found.only_a,
+            found.only_b,
+            verify_with.as_str(),
+            found.balance_mismatches
+        ),
+    });
+    Ok(())
+}
+
+/// Swaps in an empty shadow state for a fresh pass and returns the original.
+/// First-seen times carry over so holder ages survive the restart.
+fn start_shadow_pass(state: &mut ScanState) -> ScanState {
+    let shadow = ScanState {
+        first_seen: state.first_seen.clone(),
+        acquisitions: state.acquisitions.clone(),
+        valuation: state.valuation.clone(),
+        metadata: state.metadata.clone(),
+        delegations: state.delegations.clone(),
+        labels: state.labels.clone(),
+        ..ScanState::default()
+    };
+    std::mem::replace(state, shadow)
+}
+
+/// Moves a streaming holder store aside for a shadow pass, like
+/// `start_shadow_pass`, and returns the original store.
+fn start_shadow_store(store: &mut Option<HolderStore>) -> Result<Option<HolderStore>> {
+    match store.take() {
+        Some(active) => {
+            *store = Some(active.shadow()?);
+            Ok(Some(active))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The completed shadow pass is authoritative; log how it differs from the
+/// stuck checkpoint and persist it as the real state.
+fn reconcile_shadow_pass(original: &ScanState, shadow: &mut ScanState) -> Result<()> {
+    let only_original = original.holders.difference(&shadow.holders).count();
+    let only_shadow = shadow.holders.difference(&original.holders).count();
+    info!(
+        holders = shadow.holders.len(),
+        dropped = only_original,
+        added = only_shadow,
+        "Reconciled shadow pass with the original checkpoint"
+    );
+    shadow.first_seen.retain(|holder, _| shadow.holders.contains(holder));
+    shadow.acquisitions.retain(|holder, _| shadow.holders.contains(holder));
+    shadow.provenance.retain(|holder, _| shadow.holders.contains(holder));
+    save_state(shadow)?;
+    save_holders_to_file(shadow)?;
+    let shadow_path = Path::new(SHADOW_STATE_PATH);
+    if shadow_path.exists() {
+        std::fs::remove_file(shadow_path)?;
+    }
+    Ok(())
+}
+
+/// Takes the oldest quarantined page this run has retried fewer than `limit` times.
+fn next_retry(
+    quarantine: &mut Vec<QuarantinedPage>,
+    retries: &mut HashMap<Option<String>, u32>,
+    limit: u32,
+) -> Option<QuarantinedPage> {
+    let index = quarantine.iter().position(|page| retries.get(&page.page_key).copied().unwrap_or(0) < limit)?;
+    let page = quarantine.remove(index);
+    *retries.entry(page.page_key.clone()).or_default() += 1;
+    Some(page)
+}
+
+/// Returns the checkpointed page key if it is still young enough to be
+/// accepted by the provider (`PAGE_KEY_MAX_AGE_SECS`, default one hour).
+fn validated_page_key(state: &ScanState) -> Option<String> {
+    let key = state.last_page_key.clone()?;
+    let max_age_secs: i64 = env::var("PAGE_KEY_MAX_AGE_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(3600);
+    match state.last_page_key_at {
+        Some(issued_at) if Utc::now() - issued_at <= chrono::Duration::seconds(max_age_secs) => Some(key),
+        issued_at => {
+            warn!(?issued_at, max_age_secs, "Saved page key is too old to resume from, restarting from the first page");
+            None
+        }
+    }
+}
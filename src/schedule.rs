@@ -0,0 +1,348 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveDate, TimeZone, Timelike, Utc};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::config;
+
+// How far ahead a schedule is searched for its next run. Leap days can be eight years apart;
+// an expression that finds nothing in that time, like `0 0 31 2 *`, never runs
+const SEARCH_YEARS: i64 = 8;
+
+/// `[[schedule]]` in `scanner.toml`: a scanner command run on a cron schedule
+/// by `schedule`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleTarget {
+    pub name: String,
+    /// Five-field cron expression in UTC, e.g. `0 3 * * *`, or `@hourly`,
+    /// `@daily`, `@weekly`, `@monthly`, `@yearly`
+    pub cron: CronSchedule,
+    /// Scanner arguments, e.g. `["scan", "--archive"]`
+    pub args: Vec<String>,
+    /// Working directory, for targets that keep their own `data/` and `scanner.toml`
+    pub dir: Option<PathBuf>,
+    /// Extra environment variables for the run
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Targets sharing a lock never run at the same time; one that comes due
+    /// while the lock is held waits for it. Targets without one share the
+    /// `data` lock, as most commands write `data/state.json`
+    #[serde(default = "default_lock")]
+    pub lock: String,
+    /// First retry delay after a failed run, doubled on each further failure
+    #[serde(default = "default_retry_secs")]
+    pub retry_secs: u64,
+    #[serde(default = "default_max_retry_secs")]
+    pub max_retry_secs: u64,
+    /// Failed runs retried before waiting for the next scheduled time
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+}
+
+fn default_lock() -> String {
+    "data".to_string()
+}
+
+fn default_retry_secs() -> u64 {
+    60
+}
+
+fn default_max_retry_secs() -> u64 {
+    3600
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+/// A parsed cron expression: minute, hour, day of month, month and day of
+/// week, each a `*`, a value, a range `a-b` or a list of those, optionally
+/// stepped with `/n`. Days of week run 0-7 with 0 and 7 both Sunday. As in
+/// Vixie cron, when both day fields are restricted a day matching either runs.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    source: String,
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(source: &str) -> Result<CronSchedule> {
+        let expanded = match source.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            bail!("'{}' is not a five-field cron expression (minute hour day month weekday)", source);
+        };
+        let field = |value: &str, name: &str, min: u32, max: u32| {
+            parse_field(value, min, max).with_context(|| format!("invalid {} field '{}' in '{}'", name, value, source))
+        };
+        let mut weekdays = field(weekday, "weekday", 0, 7)?;
+        weekdays[0] |= weekdays[7];
+        weekdays.truncate(7);
+        Ok(CronSchedule {
+            source: source.trim().to_string(),
+            minutes: field(minute, "minute", 0, 59)?,
+            hours: field(hour, "hour", 0, 23)?,
+            days: field(day, "day", 1, 31)?,
+            months: field(month, "month", 1, 12)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+
+    /// The first time strictly after `after` the schedule fires, to the minute.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = (after + ChronoDuration::minutes(1)).with_second(0)?.with_nanosecond(0)?;
+        let start_day = start.date_naive();
+        let last_day = start_day + ChronoDuration::days(366 * SEARCH_YEARS);
+        let mut date = start_day;
+        while date <= last_day {
+            if self.runs_on(date) {
+                for hour in (0..24).filter(|h| self.hours[*h as usize]) {
+                    for minute in (0..60).filter(|m| self.minutes[*m as usize]) {
+                        let time = Utc.from_utc_datetime(&date.and_hms_opt(hour, minute, 0)?);
+                        if time >= start {
+                            return Some(time);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    fn runs_on(&self, date: NaiveDate) -> bool {
+        if !self.months[date.month() as usize] {
+            return false;
+        }
+        let day = self.days[date.day() as usize];
+        let weekday = self.weekdays[date.weekday().num_days_from_sunday() as usize];
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for CronSchedule {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<CronSchedule, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        CronSchedule::parse(&source).map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+/// Which of `0..=max` a cron field selects, indexed by value.
+fn parse_field(value: &str, min: u32, max: u32) -> Result<Vec<bool>> {
+    let mut selected = vec![false; max as usize + 1];
+    for part in value.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().context("invalid step")?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("step must be greater than zero");
+        }
+        let (low, high) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((low, high)) => (low.parse()?, high.parse()?),
+                // `5/15` means from 5 to the end in steps of 15
+                None if part.contains('/') => (range.parse()?, max),
+                None => {
+                    let value = range.parse()?;
+                    (value, value)
+                }
+            },
+        };
+        if low < min || high > max || low > high {
+            bail!("{}-{} is outside {}-{}", low, high, min, max);
+        }
+        for value in (low..=high).step_by(step as usize) {
+            selected[value as usize] = true;
+        }
+    }
+    Ok(selected)
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ScheduleOptions {
+    /// Print each target's next run times and exit
+    #[arg(long)]
+    pub list: bool,
+    /// Run times printed per target with --list
+    #[arg(long, default_value_t = 3, requires = "list")]
+    pub count: usize,
+}
+
+/// Where a target stands between runs.
+struct Slot {
+    target: ScheduleTarget,
+    /// When it next comes due; a due target waits here while its lock is held
+    due: DateTime<Utc>,
+    running: bool,
+    /// Consecutive failed runs
+    failures: u32,
+}
+
+/// Runs every `[[schedule]]` target as a child scanner process at its cron
+/// times, until Ctrl-C. A target never overlaps itself: a time that comes
+/// while its previous run is still going is skipped. Failed runs are retried
+/// with a doubling delay, up to `max_retries` times before the next scheduled
+/// time.
+pub async fn run_schedule(options: &ScheduleOptions) -> Result<()> {
+    let targets = config::load_config()?.schedules;
+    if targets.is_empty() {
+        bail!("no [[schedule]] entries in {}", config::config_path().display());
+    }
+    let mut names = HashSet::new();
+    for target in &targets {
+        if !names.insert(target.name.as_str()) {
+            bail!("two [[schedule]] entries are named '{}'", target.name);
+        }
+        if target.args.is_empty() {
+            bail!("[[schedule]] '{}' has no args", target.name);
+        }
+    }
+    let now = Utc::now();
+    if options.list {
+        for target in &targets {
+            println!("{} ({}): scanner {}", target.name, target.cron, target.args.join(" "));
+            let mut time = now;
+            for _ in 0..options.count {
+                let Some(next) = target.cron.next_after(time) else {
+                    println!("  never");
+                    break;
+                };
+                println!("  {}", next.to_rfc3339());
+                time = next;
+            }
+        }
+        return Ok(());
+    }
+
+    let exe = std::env::current_exe().context("locating the scanner executable")?;
+    let mut slots = Vec::new();
+    for target in targets {
+        let due = target.cron.next_after(now).with_context(|| format!("'{}' never runs", target.cron))?;
+        info!(target = %target.name, cron = %target.cron, next = %due.to_rfc3339(), "Scheduled");
+        slots.push(Slot { target, due, running: false, failures: 0 });
+    }
+    let (finished_tx, mut finished) = mpsc::unbounded_channel::<(usize, Result<ExitStatus>)>();
+    let mut held: HashSet<String> = HashSet::new();
+    let mut stopping = false;
+    loop {
+        let now = Utc::now();
+        if !stopping {
+            for (index, slot) in slots.iter_mut().enumerate() {
+                if slot.due > now || slot.running || held.contains(&slot.target.lock) {
+                    continue;
+                }
+                held.insert(slot.target.lock.clone());
+                slot.running = true;
+                info!(target = %slot.target.name, args = %slot.target.args.join(" "), "Starting run");
+                let mut command = Command::new(&exe);
+                command.args(&slot.target.args).envs(&slot.target.env);
+                if let Some(dir) = &slot.target.dir {
+                    command.current_dir(dir);
+                }
+                let finished_tx = finished_tx.clone();
+                tokio::spawn(async move {
+                    let status = match command.spawn() {
+                        Ok(mut child) => child.wait().await.map_err(Into::into),
+                        Err(e) => Err(anyhow::Error::from(e).context("starting the run")),
+                    };
+                    let _ = finished_tx.send((index, status));
+                });
+            }
+        }
+        if stopping && slots.iter().all(|slot| !slot.running) {
+            info!("Scheduler stopped");
+            return Ok(());
+        }
+
+        // Sleep until the earliest due time, a run finishing, or Ctrl-C. Targets waiting for a lock
+        // are woken by the run holding it finishing
+        let next_due = slots.iter().filter(|s| !s.running && !held.contains(&s.target.lock)).map(|s| s.due).min();
+        let wait = next_due.map_or(Duration::from_secs(60), |due| (due - now).to_std().unwrap_or(Duration::ZERO));
+        tokio::select! {
+            _ = tokio::time::sleep(wait.max(Duration::from_secs(1))) => {}
+            Some((index, status)) = finished.recv() => {
+                let slot = &mut slots[index];
+                slot.running = false;
+                held.remove(&slot.target.lock);
+                finish(slot, status);
+            }
+            _ = tokio::signal::ctrl_c(), if !stopping => {
+                info!("Stopping; waiting for running targets to finish");
+                stopping = true;
+            }
+        }
+    }
+}
+
+/// Records a finished run and sets when the target next comes due.
+fn finish(slot: &mut Slot, status: Result<ExitStatus>) {
+    let now = Utc::now();
+    let target = &slot.target;
+    // Scheduled times that passed during the run are skipped rather than run back to back
+    let scheduled = target.cron.next_after(now).unwrap_or(now + ChronoDuration::days(366 * SEARCH_YEARS));
+    let failure = match status {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(format!("exited with {}", status)),
+        Err(e) => Some(format!("{:#}", e)),
+    };
+    let Some(failure) = failure else {
+        slot.failures = 0;
+        slot.due = scheduled;
+        info!(target = %target.name, next = %scheduled.to_rfc3339(), "Run succeeded");
+        return;
+    };
+    slot.failures += 1;
+    if slot.failures > target.max_retries {
+        let next = scheduled.to_rfc3339();
+        error!(target = %target.name, failures = slot.failures, error = %failure, %next, "Run failed, giving up");
+        slot.failures = 0;
+        slot.due = scheduled;
+        return;
+    }
+    let delay = target.retry_secs.saturating_mul(1 << (slot.failures - 1).min(20)).min(target.max_retry_secs);
+    // A delay too large for chrono is past the next scheduled run anyway
+    let retry = i64::try_from(delay)
+        .ok()
+        .and_then(ChronoDuration::try_seconds)
+        .and_then(|delay| now.checked_add_signed(delay))
+        .unwrap_or(scheduled);
+    slot.due = retry.min(scheduled);
+    let retry_at = slot.due.to_rfc3339();
+    warn!(target = %target.name, failures = slot.failures, error = %failure, %retry_at, "Run failed, retrying");
+}
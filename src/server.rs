@@ -0,0 +1,603 @@
+use anyhow::{anyhow, Result};
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use async_graphql_axum::GraphQL;
+use axum::{Json, Router};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::info;
+
+use crate::address;
+use crate::archive::{self, SnapshotEntry};
+use crate::cache::{self, PageResponseCache};
+use crate::claims::{self, HolderClaim};
+use crate::config;
+use crate::diff::{self, ChangeKind};
+use crate::eligibility::{Engine, Verdict};
+use crate::histogram::{self, HistogramConfig};
+use crate::graphql;
+use crate::health::{self, Health};
+use crate::identity::HolderIdentity;
+use crate::live::{ActiveSnapshot, SnapshotStore, Source};
+use crate::report;
+use crate::rpc;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, ScanState, TokenMetadata};
+
+#[derive(Debug, clap::Args)]
+pub struct ServeOptions {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+    /// Claim files written by `claims`, used for the proofs in `/eligibility/batch`
+    #[arg(long, default_value = "data/claims")]
+    pub claims_dir: PathBuf,
+    /// Most addresses accepted by one `/eligibility/batch` request
+    #[arg(long, default_value_t = 500)]
+    pub batch_max: usize,
+    /// Seconds between checks for a new snapshot to swap in
+    #[arg(long, default_value_t = 5)]
+    pub refresh_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+struct AppState {
+    store: Arc<SnapshotStore>,
+    /// Bearer token for `/admin` routes, from `SCANNER_ADMIN_TOKEN`; they are disabled without one
+    admin_token: Option<String>,
+    claims_dir: PathBuf,
+    batch_max: usize,
+    eligibility: Arc<Mutex<Option<EligibilityCache>>>,
+}
+
+/// Handler errors rendered as `{"error": "..."}` with a matching status.
+#[derive(Debug)]
+enum ApiError {
+    NotFound(String),
+    BadRequest(String),
+    Unauthorized,
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError::Internal(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, "missing or wrong admin token".to_string()),
+            ApiError::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)),
+        };
+        (status, Json(serde_json::json!({ "error": message }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+pub async fn run_serve(options: &ServeOptions) -> Result<()> {
+    let store = SnapshotStore::open(PathBuf::from(state::STATE_PATH)).await?;
+    store.spawn_refresh(Duration::from_secs(options.refresh_secs.max(1)));
+    // Cached responses are dropped when the active snapshot, the snapshot index or the config changes
+    let cache_sources = vec![archive::contract_dir(CONTRACT_ADDRESS).join("index.json"), config::config_path()];
+    let response_cache = PageResponseCache::new(config::load_config()?.cache, cache_sources, store.clone()).await?;
+    let health = Health::serve(store.clone());
+    let app_state = AppState {
+        store: store.clone(),
+        admin_token: std::env::var("SCANNER_ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+        claims_dir: options.claims_dir.clone(),
+        batch_max: options.batch_max,
+        eligibility: Arc::new(Mutex::new(None)),
+    };
+    let app = Router::new()
+        .route("/contracts/:address/holders", get(contract_holders))
+        .route("/holders/:address/tokens", get(holder_tokens))
+        .route("/stats", get(stats))
+        .route("/diff", get(snapshot_diff))
+        .route("/eligibility/:address", get(eligibility))
+        .route("/eligibility/batch", post(eligibility_batch))
+        .route("/graphql", get(graphql::graphiql).post_service(GraphQL::new(graphql::schema(store))))
+        .route_layer(axum::middleware::from_fn_with_state(response_cache, cache::cache_responses))
+        // Added after the cache layer so admin responses are never cached
+        .route("/admin/snapshot", get(admin_snapshot))
+        .route("/admin/snapshot/pin", post(admin_pin))
+        .route("/admin/snapshot/unpin", post(admin_unpin))
+        .route("/admin/snapshot/rollback", post(admin_rollback))
+        .with_state(app_state)
+        .merge(health::router(health));
+
+    let listener = tokio::net::TcpListener::bind(options.listen).await?;
+    info!(listen = %options.listen, "Serving holder data");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+// State files are replaced atomically by the scanner, so reading one while a
+// scan is running always sees a complete checkpoint
+pub(crate) async fn read_state(path: PathBuf) -> Result<ScanState> {
+    tokio::task::spawn_blocking(move || {
+        if path.exists() {
+            state::load_state_from(&path)
+        } else {
+            Ok(ScanState::default())
+        }
+    })
+    .await
+    .map_err(|e| anyhow!(e))?
+}
+
+/// Path of the state file of an archived snapshot, or `None` for an unknown id.
+pub(crate) fn snapshot_state_path(id: &str) -> Result<Option<PathBuf>> {
+    let index = archive::load_index(CONTRACT_ADDRESS)?;
+    Ok(index.snapshots.iter().find(|s| s.id == id).map(|s| s.path.join("state.json")))
+}
+
+async fn load(path: PathBuf) -> Result<ScanState, ApiError> {
+    read_state(path).await.map_err(ApiError::from)
+}
+
+/// Resolves `?at=` to an archived snapshot. Accepts a snapshot id, an RFC 3339
+/// time or a `YYYY-MM-DD` date (the end of that day, UTC), or a block number
+/// (the block's timestamp, looked up through `OPTIMISM_RPC_URL`). Times pick
+/// the latest snapshot taken at or before them.
+async fn snapshot_at(at: &str) -> Result<SnapshotEntry, ApiError> {
+    let index = archive::load_index(CONTRACT_ADDRESS)?;
+    if let Some(entry) = index.snapshots.into_iter().find(|s| s.id == at) {
+        return Ok(entry);
+    }
+    let time = if let Ok(time) = DateTime::parse_from_rfc3339(at) {
+        time.with_timezone(&Utc)
+    } else if let Ok(date) = NaiveDate::parse_from_str(at, "%Y-%m-%d") {
+        date.and_hms_opt(23, 59, 59).expect("valid time").and_utc()
+    } else if let Ok(block) = at.parse::<u64>() {
+        rpc::block_time(&rpc::connect()?, block).await?
+    } else {
+        return Err(ApiError::BadRequest(format!("`at` must be a snapshot id, date, time or block number, got {}", at)));
+    };
+    archive::snapshot_at(CONTRACT_ADDRESS, time)?
+        .ok_or_else(|| ApiError::NotFound(format!("no snapshot taken at or before {}", at)))
+}
+
+/// The archived snapshot `at` refers to, or the active one when it is `None`.
+/// Returns the snapshot id alongside the state.
+async fn state_at(app: &AppState, at: Option<&str>) -> Result<(Option<String>, Arc<ScanState>), ApiError> {
+    let Some(at) = at else {
+        return Ok((None, app.store.current().state.clone()));
+    };
+    let entry = snapshot_at(at).await?;
+    let state = load(entry.path.join("state.json")).await?;
+    Ok((Some(entry.id), Arc::new(state)))
+}
+
+#[derive(Debug, Deserialize)]
+struct AtQuery {
+    at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    #[serde(default)]
+    offset: usize,
+    #[serde(default = "default_limit")]
+    limit: usize,
+    at: Option<String>,
+}
+
+fn default_limit() -> usize {
+    1000
+}
+
+#[derive(Debug, Serialize)]
+struct HolderEntry {
+    address: String,
+    balance: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct HoldersPage {
+    contract: String,
+    /// Archived snapshot the page comes from, when `at` was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot: Option<String>,
+    total: usize,
+    offset: usize,
+    holders: Vec<HolderEntry>,
+}
+
+async fn contract_holders(
+    State(app): State<AppState>,
+    UrlPath(address): UrlPath<String>,
+    Query(page): Query<PageQuery>,
+) -> ApiResult<HoldersPage> {
+    if !address.eq_ignore_ascii_case(CONTRACT_ADDRESS) {
+        return Err(ApiError::NotFound(format!("contract {} is not scanned by this instance", address)));
+    }
+    let (snapshot, state) = state_at(&app, page.at.as_deref()).await?;
+    let mut holders: Vec<&String> = state.holders.iter().collect();
+    holders.sort();
+    let entries = holders
+        .iter()
+        .skip(page.offset)
+        .take(page.limit.min(10_000))
+        .map(|h| HolderEntry { address: (*h).clone(), balance: state.balances.get(*h).copied().unwrap_or(0) })
+        .collect();
+    Ok(Json(HoldersPage {
+        contract: CONTRACT_ADDRESS.to_string(),
+        snapshot,
+        total: holders.len(),
+        offset: page.offset,
+        holders: entries,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct HolderTokens {
+    address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot: Option<String>,
+    balance: u64,
+    first_seen: Option<chrono::DateTime<Utc>>,
+    token_ids: Vec<String>,
+}
+
+async fn holder_tokens(
+    State(app): State<AppState>,
+    UrlPath(address): UrlPath<String>,
+    Query(query): Query<AtQuery>,
+) -> ApiResult<HolderTokens> {
+    let address = address::normalize(&address).map_err(|e| ApiError::BadRequest(format!("{:#}", e)))?;
+    let (snapshot, state) = state_at(&app, query.at.as_deref()).await?;
+    let holder = state
+        .holders
+        .get(&address)
+        .ok_or_else(|| ApiError::NotFound(format!("{} is not a holder", address)))?;
+    Ok(Json(HolderTokens {
+        address: holder.clone(),
+        snapshot,
+        balance: state.balances.get(holder).copied().unwrap_or(0),
+        first_seen: state.first_seen.get(holder).copied(),
+        token_ids: state.tokens.get(holder).cloned().unwrap_or_default(),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct Bucket {
+    label: String,
+    holders: u64,
+    #[serde(serialize_with = "as_string")]
+    supply: u128,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    contract: String,
+    /// Contract metadata recorded by `metadata`, without the token URIs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<TokenMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot: Option<String>,
+    holders: u64,
+    #[serde(serialize_with = "as_string")]
+    supply: u128,
+    last_save_time: chrono::DateTime<Utc>,
+    scan_in_progress: bool,
+    age_distribution: Vec<Bucket>,
+    balance_histogram: Vec<Bucket>,
+}
+
+// Totals can pass what JSON clients read exactly as a number
+fn as_string<S: serde::Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+async fn stats(State(app): State<AppState>, Query(query): Query<AtQuery>) -> ApiResult<Stats> {
+    let (snapshot, state) = state_at(&app, query.at.as_deref()).await?;
+    let identity = HolderIdentity::from_env()?;
+    let view = identity.regroup(&state);
+    let age_distribution = report::age_distribution(&view, Utc::now())
+        .into_iter()
+        .map(|b| Bucket { label: b.label.to_string(), holders: b.holders, supply: b.supply })
+        .collect();
+    let balance_histogram = histogram::balance_histogram(&view, &HistogramConfig::from_env())
+        .into_iter()
+        .map(|b| Bucket { label: b.label(), holders: b.holders, supply: u128::from(b.supply) })
+        .collect();
+    Ok(Json(Stats {
+        contract: CONTRACT_ADDRESS.to_string(),
+        token: state.metadata.clone().map(|m| TokenMetadata {
+            token_uris: Default::default(),
+            token_attributes: Default::default(),
+            ..m
+        }),
+        snapshot,
+        holders: view.holders.len() as u64,
+        supply: view.balances.values().map(|b| *b as u128).sum(),
+        last_save_time: state.last_save_time,
+        scan_in_progress: state.last_page_key.is_some(),
+        age_distribution,
+        balance_histogram,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct DiffQuery {
+    from: String,
+    to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChangeEntry {
+    address: String,
+    change: &'static str,
+    old_balance: u64,
+    new_balance: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct DiffResponse {
+    from: String,
+    to: String,
+    added: usize,
+    removed: usize,
+    balance_changed: usize,
+    changes: Vec<ChangeEntry>,
+}
+
+/// Diffs two archived snapshots, each given in any form `?at=` accepts;
+/// `to` defaults to the active snapshot.
+async fn snapshot_diff(State(app): State<AppState>, Query(query): Query<DiffQuery>) -> ApiResult<DiffResponse> {
+    let (from, old) = state_at(&app, Some(&query.from)).await?;
+    let (to, new) = state_at(&app, query.to.as_deref()).await?;
+
+    let identity = HolderIdentity::from_env()?;
+    let changes = diff::diff_states(&identity.regroup(&old), &identity.regroup(&new));
+    let count = |kind: ChangeKind| changes.iter().filter(|c| c.kind == kind).count();
+    Ok(Json(DiffResponse {
+        from: from.unwrap_or_default(),
+        to: to.unwrap_or_else(|| "live".to_string()),
+        added: count(ChangeKind::Added),
+        removed: count(ChangeKind::Removed),
+        balance_changed: count(ChangeKind::BalanceChanged),
+        changes: changes
+            .iter()
+            .map(|c| ChangeEntry {
+                address: c.address.clone(),
+                change: c.kind.as_str(),
+                old_balance: c.old_balance,
+                new_balance: c.new_balance,
+            })
+            .collect(),
+    }))
+}
+
+// Cached lookups are rebuilt at least this often so `held_for` rules see time pass
+const ELIGIBILITY_CACHE_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// Eligibility engine and per-address results, valid while the active
+/// snapshot, the claim index and `scanner.toml` are unchanged.
+#[derive(Debug)]
+struct EligibilityCache {
+    version: u64,
+    fingerprint: Vec<Option<SystemTime>>,
+    built_at: Instant,
+    engine: Engine,
+    merkle_root: Option<String>,
+    results: HashMap<String, BatchEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BatchEntry {
+    address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eligibility: Option<Verdict>,
+    /// Amount, leaf and merkle proof from the claim files, when the address has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    claim: Option<HolderClaim>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Looks addresses up through the cache, rebuilding it first if any input changed.
+fn lookup_eligibility(app: &AppState, addresses: &[String]) -> Result<(Option<String>, Vec<BatchEntry>)> {
+    let claims_index = app.claims_dir.join("index.json");
+    let fingerprint = vec![modified(&claims_index), modified(&config::config_path())];
+    let active = app.store.current();
+    let mut cache = app.eligibility.lock().unwrap_or_else(|e| e.into_inner());
+    let stale = cache.as_ref().map_or(true, |c| {
+        c.version != active.version || c.fingerprint != fingerprint || c.built_at.elapsed() > ELIGIBILITY_CACHE_MAX_AGE
+    });
+    if stale {
+        let engine = Engine::new(&config::load_config()?.eligibility, &active.state, Utc::now())?;
+        let merkle_root = std::fs::read(&claims_index)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+            .and_then(|index| index.get("merkle_root")?.as_str().map(str::to_string));
+        *cache = Some(EligibilityCache {
+            version: active.version,
+            fingerprint,
+            built_at: Instant::now(),
+            engine,
+            merkle_root,
+            results: HashMap::new(),
+        });
+    }
+    let cache = cache.as_mut().expect("cache was just built");
+
+    let mut entries = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        let normalized = address::normalize(address).ok();
+        let key = normalized.clone().unwrap_or_else(|| address.to_lowercase());
+        if let Some(entry) = cache.results.get(&key) {
+            entries.push(entry.clone());
+            continue;
+        }
+        let entry = if normalized.is_some() {
+            let claim_path = claims::holder_path(&app.claims_dir, &key);
+            let claim = match std::fs::read(&claim_path) {
+                Ok(bytes) => Some(serde_json::from_slice(&bytes)?),
+                Err(_) => None,
+            };
+            BatchEntry { address: key.clone(), error: None, eligibility: Some(cache.engine.evaluate(&key)?), claim }
+        } else {
+            BatchEntry {
+                address: address.clone(),
+                error: Some("not an address".to_string()),
+                eligibility: None,
+                claim: None,
+            }
+        };
+        cache.results.insert(key, entry.clone());
+        entries.push(entry);
+    }
+    Ok((cache.merkle_root.clone(), entries))
+}
+
+async fn lookup(app: AppState, addresses: Vec<String>) -> Result<(Option<String>, Vec<BatchEntry>), ApiError> {
+    tokio::task::spawn_blocking(move || lookup_eligibility(&app, &addresses))
+        .await
+        .map_err(|e| anyhow!(e))?
+        .map_err(ApiError::from)
+}
+
+/// Eligibility of one address under the rules in `scanner.toml`. Only the
+/// requested address is evaluated, so the endpoint can't be used to list holders.
+async fn eligibility(State(app): State<AppState>, UrlPath(address): UrlPath<String>) -> ApiResult<Verdict> {
+    if let Err(e) = address::normalize(&address) {
+        return Err(ApiError::BadRequest(format!("{:#}", e)));
+    }
+    let (_, mut entries) = lookup(app, vec![address]).await?;
+    let verdict = entries.pop().and_then(|e| e.eligibility).ok_or_else(|| anyhow!("no verdict returned"))?;
+    Ok(Json(verdict))
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchRequest {
+    addresses: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchResponse {
+    /// Root the claim proofs verify against, when claim files exist
+    merkle_root: Option<String>,
+    results: Vec<BatchEntry>,
+}
+
+/// Eligibility and claim proofs for many addresses in one round trip, in
+/// request order. Malformed addresses get an `error` entry instead of failing
+/// the whole batch.
+async fn eligibility_batch(State(app): State<AppState>, Json(request): Json<BatchRequest>) -> ApiResult<BatchResponse> {
+    if request.addresses.len() > app.batch_max {
+        return Err(ApiError::BadRequest(format!(
+            "at most {} addresses per batch, got {}",
+            app.batch_max,
+            request.addresses.len()
+        )));
+    }
+    let (merkle_root, results) = lookup(app, request.addresses).await?;
+    Ok(Json(BatchResponse { merkle_root, results }))
+}
+
+#[derive(Debug, Serialize)]
+struct SnapshotInfo {
+    source: Source,
+    version: u64,
+    loaded_at: chrono::DateTime<Utc>,
+    holders: usize,
+    last_save_time: chrono::DateTime<Utc>,
+}
+
+impl From<&ActiveSnapshot> for SnapshotInfo {
+    fn from(active: &ActiveSnapshot) -> Self {
+        SnapshotInfo {
+            source: active.source.clone(),
+            version: active.version,
+            loaded_at: active.loaded_at,
+            holders: active.state.holders.len(),
+            last_save_time: active.state.last_save_time,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AdminSnapshot {
+    pinned: bool,
+    active: SnapshotInfo,
+    previous: Option<SnapshotInfo>,
+}
+
+fn admin_snapshot_info(store: &SnapshotStore) -> AdminSnapshot {
+    AdminSnapshot {
+        pinned: store.is_pinned(),
+        active: SnapshotInfo::from(&*store.current()),
+        previous: store.previous().map(|p| SnapshotInfo::from(&*p)),
+    }
+}
+
+/// Checks `Authorization: Bearer <SCANNER_ADMIN_TOKEN>`. Without a configured
+/// token the admin routes don't exist as far as clients can tell.
+fn authorize(app: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(token) = &app.admin_token else {
+        return Err(ApiError::NotFound("admin endpoints are disabled".to_string()));
+    };
+    let given = headers.get(axum::http::header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+    match given.and_then(|v| v.strip_prefix("Bearer ")) {
+        Some(given) if given == token => Ok(()),
+        _ => Err(ApiError::Unauthorized),
+    }
+}
+
+async fn admin_snapshot(State(app): State<AppState>, headers: HeaderMap) -> ApiResult<AdminSnapshot> {
+    authorize(&app, &headers)?;
+    Ok(Json(admin_snapshot_info(&app.store)))
+}
+
+#[derive(Debug, Deserialize)]
+struct PinRequest {
+    id: String,
+}
+
+/// Serves an archived snapshot until unpinned, e.g. to hold a known-good
+/// snapshot while a bad scan is investigated.
+async fn admin_pin(
+    State(app): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<PinRequest>,
+) -> ApiResult<AdminSnapshot> {
+    authorize(&app, &headers)?;
+    if snapshot_state_path(&request.id)?.is_none() {
+        return Err(ApiError::BadRequest(format!("unknown snapshot id {}", request.id)));
+    }
+    app.store.pin(&request.id).await?;
+    Ok(Json(admin_snapshot_info(&app.store)))
+}
+
+async fn admin_unpin(State(app): State<AppState>, headers: HeaderMap) -> ApiResult<AdminSnapshot> {
+    authorize(&app, &headers)?;
+    app.store.unpin().await?;
+    Ok(Json(admin_snapshot_info(&app.store)))
+}
+
+/// Swaps the previously active snapshot back in and pins it.
+async fn admin_rollback(State(app): State<AppState>, headers: HeaderMap) -> ApiResult<AdminSnapshot> {
+    authorize(&app, &headers)?;
+    if app.store.previous().is_none() {
+        return Err(ApiError::BadRequest("no previous snapshot to roll back to".to_string()));
+    }
+    app.store.rollback()?;
+    Ok(Json(admin_snapshot_info(&app.store)))
+}
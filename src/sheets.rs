@@ -0,0 +1,239 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::{self, Config};
+use crate::histogram::HistogramConfig;
+use crate::http;
+use crate::identity::HolderIdentity;
+use crate::leaderboard::{self, SortBy};
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, ScanState};
+use crate::stats;
+
+const SHEETS_API_URL: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+const SHEETS_SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+
+/// `[sheets]` in `scanner.toml`. The named tabs must exist in the spreadsheet,
+/// which must be shared with the service account's email address.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SheetsConfig {
+    /// The id in the spreadsheet's URL, between `/d/` and `/edit`
+    pub spreadsheet_id: String,
+    /// Service account key file; defaults to `GOOGLE_APPLICATION_CREDENTIALS`
+    pub credentials: Option<PathBuf>,
+    /// Rows in the top-holders tab
+    #[serde(default = "default_top")]
+    pub top: usize,
+    #[serde(default = "default_holders_sheet")]
+    pub holders_sheet: String,
+    #[serde(default = "default_summary_sheet")]
+    pub summary_sheet: String,
+    /// Tab that gets one summary row appended per push, for trends
+    pub history_sheet: Option<String>,
+}
+
+fn default_top() -> usize {
+    100
+}
+
+fn default_holders_sheet() -> String {
+    "Top holders".to_string()
+}
+
+fn default_summary_sheet() -> String {
+    "Summary".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct ServiceAccount {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SheetsOptions {
+    /// Snapshot to push (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+}
+
+/// Writes a snapshot's summary and top holders to a Google Sheet.
+pub struct SheetsClient {
+    client: reqwest::Client,
+    config: SheetsConfig,
+    token: String,
+}
+
+impl SheetsClient {
+    /// Exchanges a JWT signed with the service account key for an access token.
+    pub async fn connect(config: &SheetsConfig) -> Result<SheetsClient> {
+        let path = match &config.credentials {
+            Some(path) => path.clone(),
+            None => PathBuf::from(
+                env::var("GOOGLE_APPLICATION_CREDENTIALS")
+                    .context("[sheets] needs `credentials` or GOOGLE_APPLICATION_CREDENTIALS")?,
+            ),
+        };
+        let text = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        let account: ServiceAccount =
+            serde_json::from_str(&text).with_context(|| format!("{} is not a service account key", path.display()))?;
+
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            iss: &account.client_email,
+            scope: SHEETS_SCOPE,
+            aud: &account.token_uri,
+            iat: now,
+            exp: now + 3600,
+        };
+        let key = jsonwebtoken::EncodingKey::from_rsa_pem(account.private_key.as_bytes())
+            .context("invalid private key in the service account file")?;
+        let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+        let assertion = jsonwebtoken::encode(&header, &claims, &key)?;
+
+        let client = http::client(Duration::from_secs(30))?;
+        let response = client
+            .post(&account.token_uri)
+            .form(&[("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"), ("assertion", &assertion)])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            bail!("Google token request failed: {} {}", response.status(), response.text().await.unwrap_or_default());
+        }
+        let token: TokenResponse = response.json().await?;
+        Ok(SheetsClient { client, config: config.clone(), token: token.access_token })
+    }
+
+    /// `https://.../spreadsheets/<id>/values/<range><suffix>`, with the range escaped.
+    fn values_url(&self, range: &str, suffix: &str) -> Result<reqwest::Url> {
+        let mut url = reqwest::Url::parse(SHEETS_API_URL)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid Sheets API URL"))?
+            .push(&self.config.spreadsheet_id)
+            .push("values")
+            .push(&format!("{}{}", range, suffix));
+        Ok(url)
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder, what: &str) -> Result<()> {
+        let response = request.bearer_auth(&self.token).send().await?;
+        if !response.status().is_success() {
+            bail!("Sheets {} failed: {} {}", what, response.status(), response.text().await.unwrap_or_default());
+        }
+        Ok(())
+    }
+
+    /// Replaces the contents of `sheet` with `rows`.
+    async fn replace(&self, sheet: &str, rows: Vec<Vec<Value>>) -> Result<()> {
+        let range = format!("'{}'", sheet.replace('\'', "''"));
+        self.send(self.client.post(self.values_url(&range, ":clear")?), "clear").await?;
+        let mut url = self.values_url(&format!("{}!A1", range), "")?;
+        url.query_pairs_mut().append_pair("valueInputOption", "RAW");
+        self.send(self.client.put(url).json(&json!({ "values": rows })), "update").await
+    }
+
+    async fn append(&self, sheet: &str, row: Vec<Value>) -> Result<()> {
+        let range = format!("'{}'!A1", sheet.replace('\'', "''"));
+        let mut url = self.values_url(&range, ":append")?;
+        url.query_pairs_mut().append_pair("valueInputOption", "RAW").append_pair("insertDataOption", "INSERT_ROWS");
+        self.send(self.client.post(url).json(&json!({ "values": [row] })), "append").await
+    }
+
+    pub async fn push(&self, state: &ScanState) -> Result<()> {
+        let identity = HolderIdentity::from_env()?;
+        let view = identity.regroup(state);
+        let stats = stats::holder_stats(&view, &HistogramConfig::from_env());
+        let updated_at = Utc::now().to_rfc3339();
+        let usd: Option<f64> =
+            view.valuation.as_ref().map(|_| view.holders.iter().filter_map(|h| view.usd_value(h)).sum());
+
+        let summary = vec![
+            vec![json!("metric"), json!("value")],
+            vec![json!("contract"), json!(CONTRACT_ADDRESS)],
+            vec![json!("token"), json!(stats.token.clone().unwrap_or_default())],
+            vec![json!("snapshot_time"), json!(state.last_save_time.to_rfc3339())],
+            vec![json!("holders"), json!(stats.total_holders)],
+            vec![json!("total_supply"), json!(stats.total_supply as f64)],
+            vec![json!("median_balance"), json!(stats.median_balance)],
+            vec![json!("top10_share_pct"), json!(stats.top10_share * 100.0)],
+            vec![json!("top100_share_pct"), json!(stats.top100_share * 100.0)],
+            vec![json!("gini"), json!(stats.gini)],
+            vec![json!("usd_value"), json!(usd)],
+            vec![json!("updated_at"), json!(updated_at)],
+        ];
+        self.replace(&self.config.summary_sheet, summary).await?;
+
+        let header = ["rank", "address", "balance", "share_pct", "first_seen", "usd_value"];
+        let mut rows = vec![header.map(|h| json!(h)).to_vec()];
+        for row in leaderboard::leaderboard(&view, self.config.top, SortBy::Balance) {
+            rows.push(vec![
+                json!(row.rank),
+                json!(row.address),
+                json!(row.balance),
+                json!(row.share * 100.0),
+                json!(row.first_seen.map(|t| t.to_rfc3339()).unwrap_or_default()),
+                json!(row.usd_value),
+            ]);
+        }
+        self.replace(&self.config.holders_sheet, rows).await?;
+
+        if let Some(history) = &self.config.history_sheet {
+            let row = vec![
+                json!(updated_at),
+                json!(state.last_save_time.to_rfc3339()),
+                json!(stats.total_holders),
+                json!(stats.total_supply as f64),
+                json!(stats.top10_share * 100.0),
+                json!(stats.gini),
+            ];
+            self.append(history, row).await?;
+        }
+        info!(spreadsheet = %self.config.spreadsheet_id, "Pushed snapshot to Google Sheets");
+        Ok(())
+    }
+}
+
+/// Pushes a finished scan when `[sheets]` is configured. Failures are logged
+/// rather than failing the scan, like notifications.
+pub async fn push_after_scan(config: &Config, state: &ScanState) {
+    let Some(sheets) = &config.sheets else {
+        return;
+    };
+    let pushed = async { SheetsClient::connect(sheets).await?.push(state).await };
+    if let Err(e) = pushed.await {
+        warn!(error = %format!("{:#}", e), "Could not push to Google Sheets");
+    }
+}
+
+pub async fn run_sheets(options: &SheetsOptions) -> Result<()> {
+    let config = config::load_config()?;
+    let sheets =
+        config.sheets.with_context(|| format!("no [sheets] section in {}", config::config_path().display()))?;
+    let state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    SheetsClient::connect(&sheets).await?.push(&state).await
+}
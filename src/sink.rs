@@ -0,0 +1,155 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt};
+use serde::Serialize;
+use std::env;
+use tracing::{debug, info, warn};
+
+use crate::diff::{HolderChange, HolderEvent};
+
+// Holder events awaiting broker acknowledgement at once
+const MAX_IN_FLIGHT: usize = 1_000;
+
+/// A scan run starting, finishing or failing, published to `<prefix>.scans`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanEvent {
+    /// `started`, `finished` or `failed`
+    pub kind: &'static str,
+    pub run: String,
+    pub contract: String,
+    pub at: DateTime<Utc>,
+    pub pages: u64,
+    pub holders: u64,
+    pub error: Option<String>,
+}
+
+enum Backend {
+    #[cfg(feature = "kafka")]
+    Kafka(rdkafka::producer::FutureProducer),
+    #[cfg(feature = "nats")]
+    Nats(async_nats::Client),
+}
+
+/// Publishes holder changes to `<prefix>.holders` and scan lifecycle events to
+/// `<prefix>.scans` on the broker in `EVENT_SINK_URL`: `kafka://host:9092[,host:9092]`
+/// or `nats://host:4222`. `EVENT_SINK_PREFIX` defaults to `scanner`. Payloads are
+/// JSON; Kafka messages are keyed by holder address or contract, so each one's
+/// events stay ordered within a partition.
+pub struct EventSink {
+    backend: Backend,
+    prefix: String,
+}
+
+impl EventSink {
+    /// `None` when `EVENT_SINK_URL` isn't set.
+    pub async fn from_env() -> Result<Option<EventSink>> {
+        let Ok(url) = env::var("EVENT_SINK_URL") else {
+            return Ok(None);
+        };
+        let prefix = env::var("EVENT_SINK_PREFIX").unwrap_or_else(|_| "scanner".to_string());
+        let (scheme, _) = url.split_once("://").context("EVENT_SINK_URL must be kafka://... or nats://...")?;
+        let backend = match scheme {
+            #[cfg(feature = "kafka")]
+            "kafka" => Backend::Kafka(kafka_producer(&url["kafka://".len()..])?),
+            #[cfg(feature = "nats")]
+            "nats" | "tls" => Backend::Nats(nats_client(&url).await?),
+            _ => bail!("EVENT_SINK_URL scheme {}:// isn't supported by this build (features `kafka`, `nats`)", scheme),
+        };
+        info!(sink = scheme, prefix = %prefix, "Publishing events");
+        Ok(Some(EventSink { backend, prefix }))
+    }
+
+    async fn send(&self, topic: &str, key: &str, payload: &[u8]) -> Result<()> {
+        let topic = format!("{}.{}", self.prefix, topic);
+        match &self.backend {
+            #[cfg(feature = "kafka")]
+            Backend::Kafka(producer) => {
+                let record = rdkafka::producer::FutureRecord::to(&topic).key(key).payload(payload);
+                producer
+                    .send(record, rdkafka::util::Timeout::Never)
+                    .await
+                    .map_err(|(e, _)| e)
+                    .with_context(|| format!("publishing to Kafka topic {}", topic))?;
+            }
+            #[cfg(feature = "nats")]
+            Backend::Nats(client) => {
+                // NATS has no message keys; one connection already keeps publish order
+                let _ = key;
+                client
+                    .publish(topic.clone(), payload.to_vec().into())
+                    .await
+                    .with_context(|| format!("publishing to NATS subject {}", topic))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits until everything sent so far reached the broker.
+    async fn flush(&self) -> Result<()> {
+        match &self.backend {
+            // Kafka sends already waited for delivery
+            #[cfg(feature = "kafka")]
+            Backend::Kafka(_) => Ok(()),
+            #[cfg(feature = "nats")]
+            Backend::Nats(client) => Ok(client.flush().await?),
+        }
+    }
+
+    pub async fn publish_changes(&self, cycle: u64, changes: &[HolderChange]) -> Result<()> {
+        let at = Utc::now();
+        let events = changes
+            .iter()
+            .map(|change| {
+                let event = HolderEvent::new(cycle, at, change);
+                Ok((serde_json::to_vec(&event)?, event.address))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        futures::stream::iter(events.iter().map(|(payload, address)| self.send("holders", address, payload)))
+            .buffer_unordered(MAX_IN_FLIGHT)
+            .try_collect::<Vec<()>>()
+            .await?;
+        self.flush().await?;
+        debug!(cycle, events = changes.len(), "Published holder changes to the event sink");
+        Ok(())
+    }
+
+    pub async fn publish_scan(&self, event: &ScanEvent) -> Result<()> {
+        self.send("scans", &event.contract, &serde_json::to_vec(event)?).await?;
+        self.flush().await
+    }
+}
+
+/// Publishes a lifecycle event if a sink is configured. A failed publish only
+/// logs a warning; it never fails the scan.
+pub async fn publish_scan(sink: Option<&EventSink>, event: ScanEvent) {
+    if let Some(sink) = sink {
+        if let Err(e) = sink.publish_scan(&event).await {
+            warn!(error = %format!("{:#}", e), kind = event.kind, "Could not publish scan event");
+        }
+    }
+}
+
+/// Producer for a comma-separated broker list. Other librdkafka settings come
+/// from `KAFKA_*` variables: `KAFKA_SECURITY_PROTOCOL=sasl_ssl` sets
+/// `security.protocol`.
+#[cfg(feature = "kafka")]
+fn kafka_producer(brokers: &str) -> Result<rdkafka::producer::FutureProducer> {
+    let mut config = rdkafka::ClientConfig::new();
+    config.set("bootstrap.servers", brokers.trim_end_matches('/')).set("message.timeout.ms", "30000");
+    for (name, value) in env::vars() {
+        if let Some(setting) = name.strip_prefix("KAFKA_") {
+            config.set(setting.to_lowercase().replace('_', "."), value);
+        }
+    }
+    Ok(config.create()?)
+}
+
+/// Connects with credentials from the URL, or from the `NATS_CREDS` file.
+#[cfg(feature = "nats")]
+async fn nats_client(url: &str) -> Result<async_nats::Client> {
+    let options = match env::var("NATS_CREDS") {
+        Ok(path) => async_nats::ConnectOptions::with_credentials_file(path).await?,
+        Err(_) => async_nats::ConnectOptions::new(),
+    };
+    options.connect(url).await.with_context(|| format!("connecting to NATS at {}", url))
+}
@@ -0,0 +1,485 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::address;
+use crate::allocate::{self, AllocationPolicy, Strategy};
+use crate::diff;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::{self, ScanState, StateLoadError};
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    #[error("reading snapshot: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("parsing snapshot: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("snapshot state version {found} is newer than this build reads ({supported})")]
+    UnsupportedVersion { found: u32, supported: u32 },
+    #[error("invalid address: {0}")]
+    InvalidAddress(String),
+    #[error("invalid allocation policy: {0}")]
+    InvalidPolicy(String),
+}
+
+fn normalize(raw: &str) -> Result<String, SnapshotError> {
+    address::normalize(raw).map_err(|e| SnapshotError::InvalidAddress(format!("{:#}", e)))
+}
+
+impl From<StateLoadError> for SnapshotError {
+    fn from(error: StateLoadError) -> Self {
+        match error {
+            StateLoadError::Newer { found, supported } => SnapshotError::UnsupportedVersion { found, supported },
+            StateLoadError::Parse(e) => SnapshotError::Parse(e),
+        }
+    }
+}
+
+/// One holder in a [`Snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HolderRecord {
+    address: String,
+    balance: u64,
+    first_seen: Option<DateTime<Utc>>,
+    token_ids: Vec<String>,
+}
+
+impl HolderRecord {
+    pub fn builder(address: impl Into<String>) -> HolderRecordBuilder {
+        HolderRecordBuilder {
+            record: HolderRecord { address: address.into(), balance: 0, first_seen: None, token_ids: Vec::new() },
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Number of tokens held.
+    pub fn balance(&self) -> u64 {
+        self.balance
+    }
+
+    /// When the scanner first saw this address holding, if known.
+    pub fn first_seen(&self) -> Option<DateTime<Utc>> {
+        self.first_seen
+    }
+
+    pub fn token_ids(&self) -> impl Iterator<Item = &str> {
+        self.token_ids.iter().map(String::as_str)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HolderRecordBuilder {
+    record: HolderRecord,
+}
+
+impl HolderRecordBuilder {
+    pub fn balance(mut self, balance: u64) -> Self {
+        self.record.balance = balance;
+        self
+    }
+
+    pub fn first_seen(mut self, first_seen: DateTime<Utc>) -> Self {
+        self.record.first_seen = Some(first_seen);
+        self
+    }
+
+    pub fn token_id(mut self, token_id: impl Into<String>) -> Self {
+        self.record.token_ids.push(token_id.into());
+        self
+    }
+
+    pub fn build(self) -> HolderRecord {
+        self.record
+    }
+}
+
+/// The holders of a contract at one point in time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    contract: String,
+    taken_at: DateTime<Utc>,
+    // Keyed by normalized address so lookups are case-insensitive
+    holders: BTreeMap<String, HolderRecord>,
+}
+
+impl Snapshot {
+    pub fn builder(contract: impl Into<String>) -> SnapshotBuilder {
+        SnapshotBuilder {
+            snapshot: Snapshot { contract: contract.into(), taken_at: Utc::now(), holders: BTreeMap::new() },
+            error: None,
+        }
+    }
+
+    /// Reads a state file written by the scanner (`data/state.json` or a
+    /// `state.json` inside an archived snapshot directory).
+    pub fn load(path: impl AsRef<Path>) -> Result<Snapshot, SnapshotError> {
+        Snapshot::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader(mut reader: impl Read) -> Result<Snapshot, SnapshotError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let state = state::parse_state(&bytes)?;
+        Snapshot::from_state(CONTRACT_ADDRESS, &state)
+    }
+
+    pub(crate) fn from_state(contract: &str, state: &ScanState) -> Result<Snapshot, SnapshotError> {
+        let holders = state
+            .holders
+            .iter()
+            .map(|address| {
+                let record = HolderRecord {
+                    address: address.clone(),
+                    balance: state.balances.get(address).copied().unwrap_or(0),
+                    first_seen: state.first_seen.get(address).copied(),
+                    token_ids: state.tokens.get(address).cloned().unwrap_or_default(),
+                };
+                Ok((normalize(address)?, record))
+            })
+            .collect::<Result<_, SnapshotError>>()?;
+        Ok(Snapshot { contract: contract.to_string(), taken_at: state.last_save_time, holders })
+    }
+
+    pub(crate) fn to_state(&self) -> ScanState {
+        let mut state = ScanState { last_save_time: self.taken_at, ..ScanState::default() };
+        for (address, record) in &self.holders {
+            state.holders.insert(address.clone());
+            state.balances.insert(address.clone(), record.balance);
+            if let Some(first_seen) = record.first_seen {
+                state.first_seen.insert(address.clone(), first_seen);
+            }
+            if !record.token_ids.is_empty() {
+                state.tokens.insert(address.clone(), record.token_ids.clone());
+            }
+        }
+        state.total_holders = state.holders.len() as u64;
+        state
+    }
+
+    pub fn contract(&self) -> &str {
+        &self.contract
+    }
+
+    pub fn taken_at(&self) -> DateTime<Utc> {
+        self.taken_at
+    }
+
+    pub fn len(&self) -> usize {
+        self.holders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.holders.is_empty()
+    }
+
+    /// Looks up a holder by address, ignoring case. `None` for anything
+    /// that isn't a valid address.
+    pub fn holder(&self, address: &str) -> Option<&HolderRecord> {
+        self.holders.get(&address::normalize(address).ok()?)
+    }
+
+    /// Holders in address order.
+    pub fn holders(&self) -> impl Iterator<Item = &HolderRecord> {
+        self.holders.values()
+    }
+
+    /// Tokens held across all holders.
+    pub fn total_balance(&self) -> u128 {
+        self.holders.values().map(|h| h.balance as u128).sum()
+    }
+
+    /// Changes from this snapshot to a `newer` one.
+    pub fn diff(&self, newer: &Snapshot) -> Diff {
+        let changes = diff::diff_states(&self.to_state(), &newer.to_state())
+            .into_iter()
+            .map(|change| HolderChange {
+                address: change.address,
+                kind: match change.kind {
+                    diff::ChangeKind::Added => ChangeKind::Added,
+                    diff::ChangeKind::Removed => ChangeKind::Removed,
+                    diff::ChangeKind::BalanceChanged => ChangeKind::BalanceChanged,
+                },
+                old_balance: change.old_balance,
+                new_balance: change.new_balance,
+            })
+            .collect();
+        Diff { changes }
+    }
+}
+
+#[derive(Debug)]
+pub struct SnapshotBuilder {
+    snapshot: Snapshot,
+    /// The first invalid holder address, reported by `build`
+    error: Option<SnapshotError>,
+}
+
+impl SnapshotBuilder {
+    pub fn taken_at(mut self, taken_at: DateTime<Utc>) -> Self {
+        self.snapshot.taken_at = taken_at;
+        self
+    }
+
+    /// Adds a holder, replacing any earlier record for the same address.
+    pub fn holder(mut self, record: HolderRecord) -> Self {
+        match normalize(&record.address) {
+            Ok(key) => {
+                self.snapshot.holders.insert(key, record);
+            }
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    pub fn holders(mut self, records: impl IntoIterator<Item = HolderRecord>) -> Self {
+        for record in records {
+            self = self.holder(record);
+        }
+        self
+    }
+
+    /// Fails with the first holder whose address isn't valid.
+    pub fn build(self) -> Result<Snapshot, SnapshotError> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.snapshot),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    BalanceChanged,
+}
+
+/// How one holder differs between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HolderChange {
+    address: String,
+    kind: ChangeKind,
+    old_balance: u64,
+    new_balance: u64,
+}
+
+impl HolderChange {
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    pub fn old_balance(&self) -> u64 {
+        self.old_balance
+    }
+
+    pub fn new_balance(&self) -> u64 {
+        self.new_balance
+    }
+
+    pub fn delta(&self) -> i128 {
+        self.new_balance as i128 - self.old_balance as i128
+    }
+}
+
+/// The changes between two snapshots, in address order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diff {
+    changes: Vec<HolderChange>,
+}
+
+impl Diff {
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HolderChange> {
+        self.changes.iter()
+    }
+
+    pub fn of_kind(&self, kind: ChangeKind) -> impl Iterator<Item = &HolderChange> {
+        self.changes.iter().filter(move |c| c.kind == kind)
+    }
+
+    pub fn added(&self) -> impl Iterator<Item = &HolderChange> {
+        self.of_kind(ChangeKind::Added)
+    }
+
+    pub fn removed(&self) -> impl Iterator<Item = &HolderChange> {
+        self.of_kind(ChangeKind::Removed)
+    }
+
+    pub fn balance_changed(&self) -> impl Iterator<Item = &HolderChange> {
+        self.of_kind(ChangeKind::BalanceChanged)
+    }
+}
+
+impl<'a> IntoIterator for &'a Diff {
+    type Item = &'a HolderChange;
+    type IntoIter = std::slice::Iter<'a, HolderChange>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.iter()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum AllocationStrategy {
+    /// Amount proportional to tokens held
+    #[default]
+    Proportional,
+    /// Amount proportional to the square root of tokens held
+    Quadratic,
+}
+
+/// One recipient's amount in an [`Allocation`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllocationEntry {
+    address: String,
+    balance: u64,
+    amount: u128,
+}
+
+impl AllocationEntry {
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Tokens the recipient held in the snapshot.
+    pub fn balance(&self) -> u64 {
+        self.balance
+    }
+
+    /// Amount in the reward token's base units.
+    pub fn amount(&self) -> u128 {
+        self.amount
+    }
+}
+
+/// A reward budget split across the holders of a snapshot. Amounts always
+/// add up to the budget unless nobody is eligible.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Allocation {
+    total: u128,
+    entries: Vec<AllocationEntry>,
+}
+
+impl Allocation {
+    pub fn builder(total: u128) -> AllocationBuilder {
+        AllocationBuilder {
+            total,
+            policy: AllocationPolicy {
+                name: String::new(),
+                strategy: Strategy::Proportional,
+                cap_share: None,
+                min_balance: 1,
+                min_amount: 0,
+                exclude: Vec::new(),
+                tiers: Vec::new(),
+                round_to: 1,
+                min_usd: None,
+            },
+        }
+    }
+
+    pub fn total(&self) -> u128 {
+        self.total
+    }
+
+    pub fn recipients(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn amount_for(&self, address: &str) -> Option<u128> {
+        let address = address::normalize(address).ok()?;
+        self.entries.iter().find(|e| e.address == address).map(|e| e.amount)
+    }
+
+    /// Recipients in address order.
+    pub fn iter(&self) -> impl Iterator<Item = &AllocationEntry> {
+        self.entries.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Allocation {
+    type Item = &'a AllocationEntry;
+    type IntoIter = std::slice::Iter<'a, AllocationEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocationBuilder {
+    total: u128,
+    policy: AllocationPolicy,
+}
+
+impl AllocationBuilder {
+    pub fn strategy(mut self, strategy: AllocationStrategy) -> Self {
+        self.policy.strategy = match strategy {
+            AllocationStrategy::Proportional => Strategy::Proportional,
+            AllocationStrategy::Quadratic => Strategy::Quadratic,
+        };
+        self
+    }
+
+    /// Largest share of the total one address may receive (e.g. `0.01`).
+    pub fn cap_share(mut self, cap_share: f64) -> Self {
+        self.policy.cap_share = Some(cap_share);
+        self
+    }
+
+    pub fn min_balance(mut self, min_balance: u64) -> Self {
+        self.policy.min_balance = min_balance;
+        self
+    }
+
+    /// Drops recipients whose amount would fall below `min_amount`.
+    pub fn min_amount(mut self, min_amount: u128) -> Self {
+        self.policy.min_amount = min_amount;
+        self
+    }
+
+    pub fn exclude(mut self, address: impl Into<String>) -> Self {
+        self.policy.exclude.push(address.into());
+        self
+    }
+
+    /// Fails when an excluded address isn't valid.
+    pub fn build(self, snapshot: &Snapshot) -> Result<Allocation, SnapshotError> {
+        self.policy.validate().map_err(|e| SnapshotError::InvalidPolicy(format!("{:#}", e)))?;
+        for address in &self.policy.exclude {
+            normalize(address)?;
+        }
+        // Proportional and quadratic weights are positive for every holder
+        // with a balance, so the split cannot fail
+        let entries = allocate::allocate(&snapshot.to_state(), &self.policy, self.total)
+            .expect("holders with a balance have positive weight")
+            .into_iter()
+            .map(|row| AllocationEntry { address: row.address, balance: row.balance, amount: row.amount })
+            .collect();
+        Ok(Allocation { total: self.total, entries })
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::{bail, Context, Result};
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{debug, info};
+use web3::ethabi::{self, ParamType, Token};
+use web3::signing::keccak256;
+use web3::transports::Http;
+use web3::types::{Address, BlockNumber, FilterBuilder, Log, H256, U256};
+use web3::Web3;
+
+/// Standards every contract can be scanned with. `transfer` is the default:
+/// the shared ERC-20/ERC-721 `Transfer` event, telling the two apart by
+/// whether the third argument is indexed.
+const BUILT_IN: [&str; 4] = ["transfer", "erc20", "erc721", "erc1155"];
+
+/// `[events]` in `scanner.toml`: which transfer events to replay for which
+/// contract, and event layouts for tokens that don't follow the standard ABI.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventsConfig {
+    /// Standard name by contract address; contracts not listed use `transfer`
+    #[serde(default)]
+    pub contracts: HashMap<String, String>,
+    /// Custom standards by name, usable in `contracts`
+    #[serde(default)]
+    pub custom: HashMap<String, Vec<CustomEvent>>,
+}
+
+/// A transfer-like event. Fields are `topicN` (an indexed argument, N from 1
+/// to 3) or `dataN` (the Nth 32-byte word of the data); `amount` may also be
+/// `one` for events that move a single NFT.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomEvent {
+    /// e.g. `Moved(address,address,uint256)`
+    pub signature: String,
+    pub from: String,
+    pub to: String,
+    pub amount: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Topic(usize),
+    Data(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Amount {
+    /// One token per event
+    One,
+    Word(Field),
+    /// `Transfer`: one token when the third argument is indexed, else the first data word
+    Auto,
+    /// ERC-1155 `TransferBatch`: the sum of the `values` array
+    BatchSum,
+}
+
+/// One event the scanner decodes into `(from, to, amount)`.
+#[derive(Debug, Clone)]
+pub struct TransferEvent {
+    pub signature: String,
+    pub topic: H256,
+    from: Field,
+    to: Field,
+    amount: Amount,
+}
+
+impl TransferEvent {
+    fn new(signature: &str, from: Field, to: Field, amount: Amount) -> TransferEvent {
+        TransferEvent {
+            signature: signature.to_string(),
+            topic: H256::from(keccak256(signature.as_bytes())),
+            from,
+            to,
+            amount,
+        }
+    }
+
+    pub(crate) fn custom(event: &CustomEvent) -> Result<TransferEvent> {
+        let signature: String = event.signature.chars().filter(|c| !c.is_whitespace()).collect();
+        if !signature.contains('(') || !signature.ends_with(')') {
+            bail!("'{}' is not an event signature like Name(type,...)", event.signature);
+        }
+        let amount = match event.amount.trim() {
+            "one" => Amount::One,
+            other => Amount::Word(parse_field(other)?),
+        };
+        Ok(TransferEvent::new(&signature, parse_field(&event.from)?, parse_field(&event.to)?, amount))
+    }
+
+    /// Sender, recipient and amount of `log`, or `None` when the log isn't
+    /// this event or is shorter than its layout says.
+    pub fn decode(&self, log: &Log) -> Option<(Address, Address, U256)> {
+        if log.topics.first() != Some(&self.topic) {
+            return None;
+        }
+        let from = Address::from_slice(&word(log, self.from)?[12..]);
+        let to = Address::from_slice(&word(log, self.to)?[12..]);
+        let amount = match self.amount {
+            Amount::One => U256::one(),
+            Amount::Word(field) => U256::from_big_endian(&word(log, field)?),
+            Amount::Auto if log.topics.len() == 4 => U256::one(),
+            Amount::Auto => U256::from_big_endian(&word(log, Field::Data(0))?),
+            Amount::BatchSum => {
+                let arrays = ParamType::Array(Box::new(ParamType::Uint(256)));
+                let decoded = ethabi::decode(&[arrays.clone(), arrays], &log.data.0).ok()?;
+                let Some(Token::Array(values)) = decoded.into_iter().nth(1) else {
+                    return None;
+                };
+                values.iter().filter_map(|v| v.clone().into_uint()).fold(U256::zero(), |a, b| a.saturating_add(b))
+            }
+        };
+        Some((from, to, amount))
+    }
+
+    /// The id of the NFT `log` moved, for ERC-721 `Transfer` events that
+    /// index it as the third argument.
+    pub fn token_id(&self, log: &Log) -> Option<U256> {
+        if log.topics.first() != Some(&self.topic) || log.topics.len() != 4 {
+            return None;
+        }
+        matches!(self.amount, Amount::One | Amount::Auto).then(|| U256::from_big_endian(log.topics[3].as_bytes()))
+    }
+}
+
+fn parse_field(value: &str) -> Result<Field> {
+    let value = value.trim();
+    if let Some(n) = value.strip_prefix("topic") {
+        let n: usize = n.parse().with_context(|| format!("invalid field '{}'", value))?;
+        if !(1..=3).contains(&n) {
+            bail!("'{}': indexed arguments are topic1 to topic3", value);
+        }
+        return Ok(Field::Topic(n));
+    }
+    if let Some(n) = value.strip_prefix("data") {
+        return Ok(Field::Data(n.parse().with_context(|| format!("invalid field '{}'", value))?));
+    }
+    bail!("invalid field '{}' (use topicN or dataN)", value)
+}
+
+fn word(log: &Log, field: Field) -> Option<[u8; 32]> {
+    let bytes = match field {
+        Field::Topic(n) => log.topics.get(n)?.as_bytes(),
+        Field::Data(n) => log.data.0.get(n * 32..(n + 1) * 32)?,
+    };
+    bytes.try_into().ok()
+}
+
+fn built_in(name: &str) -> Option<Vec<TransferEvent>> {
+    let transfer = "Transfer(address,address,uint256)";
+    let (from, to) = (Field::Topic(1), Field::Topic(2));
+    Some(match name {
+        "transfer" => vec![TransferEvent::new(transfer, from, to, Amount::Auto)],
+        "erc20" => vec![TransferEvent::new(transfer, from, to, Amount::Word(Field::Data(0)))],
+        "erc721" => vec![TransferEvent::new(transfer, from, to, Amount::One)],
+        // TransferSingle(operator, from, to, id, value) and TransferBatch(operator, from, to, ids, values);
+        // balances add up across token ids
+        "erc1155" => vec![
+            TransferEvent::new(
+                "TransferSingle(address,address,address,uint256,uint256)",
+                Field::Topic(2),
+                Field::Topic(3),
+                Amount::Word(Field::Data(1)),
+            ),
+            TransferEvent::new(
+                "TransferBatch(address,address,address,uint256[],uint256[])",
+                Field::Topic(2),
+                Field::Topic(3),
+                Amount::BatchSum,
+            ),
+        ],
+        _ => return None,
+    })
+}
+
+impl EventsConfig {
+    /// Name of the standard configured for `contract`.
+    pub fn standard_for(&self, contract: &str) -> String {
+        self.contracts
+            .iter()
+            .find(|(address, _)| address.eq_ignore_ascii_case(contract))
+            .map(|(_, name)| name.trim().to_lowercase())
+            .unwrap_or_else(|| "transfer".to_string())
+    }
+
+    /// Events to replay for `contract`. Custom standards take precedence over
+    /// built-in ones of the same name.
+    pub fn events_for(&self, contract: &str) -> Result<Vec<TransferEvent>> {
+        self.events_named(&self.standard_for(contract), contract)
+    }
+
+    /// Events of the standard called `name`, for a contract that names its
+    /// standard somewhere other than `[events.contracts]`.
+    pub fn events_named(&self, name: &str, contract: &str) -> Result<Vec<TransferEvent>> {
+        let name = name.trim().to_lowercase();
+        if let Some((_, events)) = self.custom.iter().find(|(custom, _)| custom.eq_ignore_ascii_case(&name)) {
+            if events.is_empty() {
+                bail!("custom standard '{}' has no events", name);
+            }
+            return events
+                .iter()
+                .map(|e| TransferEvent::custom(e).with_context(|| format!("in custom standard '{}'", name)))
+                .collect();
+        }
+        built_in(&name).with_context(|| {
+            let mut known: Vec<&str> = BUILT_IN.to_vec();
+            known.extend(self.custom.keys().map(String::as_str));
+            format!("unknown token standard '{}' for {} (known: {})", name, contract, known.join(", "))
+        })
+    }
+}
+
+/// Fetches `contract`'s logs for `events` over `(from_block, to_block,
+/// chunk_size)` and hands each decoded transfer to `on_transfer` as
+/// `(block, from, to, amount)`, in chain order.
+///
+/// Up to `LOG_CONCURRENCY` chunks (default 4) are fetched at once. A chunk the
+/// node refuses as too large is split in half, and the chunks after it start
+/// at the smaller size.
+pub async fn replay_transfers(
+    web3: &Web3<Http>,
+    contract: Address,
+    events: &[TransferEvent],
+    range: (u64, u64, u64),
+    mut on_transfer: impl FnMut(u64, Address, Address, U256),
+) -> Result<()> {
+    replay_transfer_logs(web3, contract, events, range, |log, from, to, amount| {
+        if let Some(block) = log.block_number {
+            on_transfer(block.as_u64(), from, to, amount);
+        }
+    })
+    .await
+}
+
+/// Like `replay_transfers`, but hands over each transfer's log too, for
+/// callers that need its position or token id. Logs without a block number
+/// are skipped.
+pub async fn replay_transfer_logs(
+    web3: &Web3<Http>,
+    contract: Address,
+    events: &[TransferEvent],
+    (from_block, to_block, chunk_size): (u64, u64, u64),
+    mut on_transfer: impl FnMut(&Log, Address, Address, U256),
+) -> Result<()> {
+    let topics: Vec<H256> = events.iter().map(|e| e.topic).collect();
+    debug!(events = ?events.iter().map(|e| &e.signature).collect::<Vec<_>>(), "Replaying transfer events");
+    let concurrency: usize = env::var("LOG_CONCURRENCY").ok().and_then(|v| v.parse().ok()).unwrap_or(4);
+    let chunk_size = AtomicU64::new(chunk_size.max(1));
+    let chunks = stream::unfold(from_block, |start| {
+        let size = chunk_size.load(Ordering::Relaxed);
+        async move {
+            let end = start.checked_add(size - 1)?.min(to_block);
+            (start <= to_block).then_some(((start, end), end + 1))
+        }
+    });
+    // `buffered` yields chunks in order however they finish, so balances replay chronologically
+    let mut fetched = std::pin::pin!(chunks
+        .map(|(start, end)| fetch_logs(web3, contract, &topics, (start, end), &chunk_size))
+        .buffered(concurrency.max(1)));
+    while let Some(logs) = fetched.next().await {
+        for log in &logs? {
+            if log.block_number.is_none() {
+                continue;
+            }
+            if let Some((from, to, amount)) = events.iter().find_map(|event| event.decode(log)) {
+                on_transfer(log, from, to, amount);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Logs of one chunk, in block and log-index order. Halves of a chunk the
+/// node refuses as too large are fetched one after the other.
+async fn fetch_logs(
+    web3: &Web3<Http>,
+    contract: Address,
+    topics: &[H256],
+    (start, end): (u64, u64),
+    chunk_size: &AtomicU64,
+) -> Result<Vec<Log>> {
+    let mut logs = Vec::new();
+    let mut pending = vec![(start, end)];
+    while let Some((start, end)) = pending.pop() {
+        let filter = FilterBuilder::default()
+            .address(vec![contract])
+            .topics(Some(topics.to_vec()), None, None, None)
+            .from_block(BlockNumber::Number(start.into()))
+            .to_block(BlockNumber::Number(end.into()))
+            .build();
+        match web3.eth().logs(filter).await {
+            Ok(found) => {
+                debug!(start, end, logs = found.len(), "Fetched transfer logs");
+                logs.extend(found);
+            }
+            Err(e) if end > start && is_too_large(&e) => {
+                let middle = start + (end - start) / 2;
+                let previous = chunk_size.fetch_min(middle - start + 1, Ordering::Relaxed);
+                if previous > middle - start + 1 {
+                    info!(blocks = middle - start + 1, "Node refused a log range as too large, shrinking chunks");
+                }
+                pending.push((middle + 1, end));
+                pending.push((start, middle));
+            }
+            Err(e) => return Err(e).with_context(|| format!("eth_getLogs for blocks {}..={}", start, end)),
+        }
+    }
+    Ok(logs)
+}
+
+/// Nodes word "too many logs in this range" differently; these cover the
+/// common ones (geth, Alchemy, Infura, QuickNode, Ankr).
+fn is_too_large(error: &web3::Error) -> bool {
+    let message = error.to_string().to_lowercase();
+    ["too large", "response size", "returned more than", "too many", "block range", "limit exceeded"]
+        .iter()
+        .any(|phrase| message.contains(phrase))
+}
@@ -0,0 +1,385 @@
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tracing::info;
+
+use crate::completeness::Completeness;
+use crate::export;
+use crate::output::{self, ExportWriter};
+
+pub const STATE_PATH: &str = "data/state.json";
+pub const HOLDERS_PATH: &str = "data/uniswap_v3_holders.txt";
+pub const SHADOW_STATE_PATH: &str = "data/state.shadow.json";
+
+/// Version of the state file layout this build writes.
+pub const STATE_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// `MIGRATIONS[n]` upgrades a version `n` state file to version `n + 1`. A
+/// change that `#[serde(default)]` can't absorb, such as a renamed field or a
+/// new meaning for an old one, gets a migration here.
+const MIGRATIONS: [fn(&mut Value); 1] = [
+    // Files from before versioning. Every field added until then has a serde default
+    |_| {},
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum StateLoadError {
+    #[error("state file version {found} was written by a newer scanner; this build reads up to version {supported}")]
+    Newer { found: u32, supported: u32 },
+    #[error(transparent)]
+    Parse(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanState {
+    /// Layout version; files without one are version 0
+    #[serde(default)]
+    pub version: u32,
+    pub last_processed_block: u64,
+    pub last_save_time: chrono::DateTime<Utc>,
+    pub total_holders: u64,
+    pub holders: HashSet<String>,
+    pub last_page_key: Option<String>,
+    /// When `last_page_key` was issued; provider page keys expire
+    #[serde(default)]
+    pub last_page_key_at: Option<chrono::DateTime<Utc>>,
+    #[serde(default)]
+    pub first_seen: HashMap<String, chrono::DateTime<Utc>>,
+    #[serde(default)]
+    pub balances: HashMap<String, u64>,
+    /// Token ids held by each holder
+    #[serde(default)]
+    pub tokens: HashMap<String, Vec<String>>,
+    /// Acquisition times replayed from Transfer logs by the `acquisition` command
+    #[serde(default)]
+    pub acquisitions: HashMap<String, Acquisition>,
+    /// Unit price recorded by the `price` command
+    #[serde(default)]
+    pub valuation: Option<Valuation>,
+    /// Where each holder's current row came from
+    #[serde(default)]
+    pub provenance: HashMap<String, Provenance>,
+    /// Contract and token metadata recorded by the `metadata` command
+    #[serde(default)]
+    pub metadata: Option<TokenMetadata>,
+    /// Hot wallets holders have delegated to, recorded by the `delegations` command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegations: Option<Delegations>,
+    /// Wrapper contracts the `look-through` command replaced with their depositors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub look_through: Vec<WrappedPosition>,
+    /// Labels from the `[labels]` datasets of each labeled holder, recorded by the `labels` command
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, Vec<String>>,
+    /// Pages of the current pass that failed to parse and haven't been read since
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quarantine: Vec<QuarantinedPage>,
+    /// The last scan's totals against `totalSupply()` and the provider's holder count
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completeness: Option<Completeness>,
+}
+
+/// A page the scan couldn't read, kept until a retry reads it. Its holders
+/// are missing from the state meanwhile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantinedPage {
+    /// Page number in the run that first failed on it
+    pub page: u64,
+    /// Page key it was requested with; `None` for the first page
+    pub page_key: Option<String>,
+    pub error: String,
+    pub failed_at: chrono::DateTime<Utc>,
+    pub attempts: u32,
+    /// Whether the pass went on past the page, which only sources that know the next page
+    /// key without the response can do. Otherwise the pass stopped here
+    pub skipped: bool,
+}
+
+/// The provider or strategy that last reported a holder, and where in its
+/// output, so a row in a merged or cross-checked dataset can be traced back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// `alchemy` (owners API), `enumerate` (ownerOf), `balance_of`, `webhook` (`ingest`), `subscribe`
+    /// or `look_through` (a wrapper's depositor)
+    pub source: String,
+    /// Provider base URL the page came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Journal id of the scan run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block: Option<u64>,
+}
+
+impl Provenance {
+    /// One-line description, e.g. `alchemy run 20240101T000000Z page 12` or `enumerate block 117000000`.
+    pub fn label(&self) -> String {
+        let mut label = self.source.clone();
+        if let Some(run) = &self.run {
+            label.push_str(&format!(" run {}", run));
+        }
+        if let Some(page) = self.page {
+            label.push_str(&format!(" page {}", page));
+        }
+        if let Some(block) = self.block {
+            label.push_str(&format!(" block {}", block));
+        }
+        label
+    }
+}
+
+impl ScanState {
+    /// USD value of a holder's balance, when the state has been priced.
+    pub fn usd_value(&self, holder: &str) -> Option<f64> {
+        let valuation = self.valuation.as_ref()?;
+        Some(valuation.usd(self.balances.get(holder).copied().unwrap_or(0)))
+    }
+
+    /// Whether the pass reached its last page with no page left in quarantine.
+    pub fn complete(&self) -> bool {
+        self.last_page_key.is_none() && self.quarantine.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Valuation {
+    /// Where the price came from: `coingecko` or `chainlink`
+    pub source: String,
+    /// USD per whole token (per NFT for collections)
+    pub price_usd: f64,
+    /// Balances are divided by 10^decimals to get whole tokens
+    pub decimals: u32,
+    pub priced_at: chrono::DateTime<Utc>,
+    /// Block a Chainlink price was read at, when pinned
+    #[serde(default)]
+    pub block: Option<u64>,
+}
+
+impl Valuation {
+    pub fn usd(&self, balance: u64) -> f64 {
+        balance as f64 / 10f64.powi(self.decimals as i32) * self.price_usd
+    }
+}
+
+/// What the scanned contract calls itself. Calls the contract doesn't
+/// implement are left empty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub contract: String,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+    /// Decimal string; supplies can exceed u64
+    pub total_supply: Option<String>,
+    pub fetched_at: chrono::DateTime<Utc>,
+    /// `tokenURI(id)` by token id, when fetched with `--token-uris`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub token_uris: HashMap<String, String>,
+    /// `attributes` of each token's metadata document as trait type → value,
+    /// by token id, when fetched with `--token-attributes`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub token_attributes: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl TokenMetadata {
+    /// Short name for reports: the symbol, else the name, else the address.
+    pub fn label(&self) -> &str {
+        self.symbol.as_deref().or(self.name.as_deref()).unwrap_or(&self.contract)
+    }
+}
+
+/// A staking or vault contract's holding, as credited to its depositors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedPosition {
+    pub wrapper: String,
+    pub name: Option<String>,
+    /// `shares` or `deposits`
+    pub kind: String,
+    pub block: u64,
+    /// What the wrapper held before it was looked through
+    pub balance: u64,
+    pub tokens: Vec<String>,
+    /// Balance credited to each depositor
+    pub credited: BTreeMap<String, u64>,
+    /// Share token balance of each depositor, for `shares` wrappers
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub shares: BTreeMap<String, String>,
+}
+
+/// Delegations read from a delegate.xyz registry for the scanned contract.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delegations {
+    pub registry: String,
+    /// Block the registry was read at, latest when `None`
+    pub block: Option<u64>,
+    pub resolved_at: chrono::DateTime<Utc>,
+    /// Delegates of each holder that delegated, both lowercase
+    pub delegates: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Acquisition {
+    /// Block the holder first received the token
+    pub first_block: u64,
+    pub first_acquired: chrono::DateTime<Utc>,
+    /// Block the current unbroken holding started; later than `first_block`
+    /// if the holder sold out and bought back in
+    pub held_since_block: u64,
+    pub held_since: chrono::DateTime<Utc>,
+}
+
+impl Acquisition {
+    pub fn holding_days(&self, now: chrono::DateTime<Utc>) -> i64 {
+        (now - self.held_since).num_days()
+    }
+}
+
+impl Default for ScanState {
+    fn default() -> Self {
+        ScanState {
+            version: STATE_VERSION,
+            last_processed_block: 0,
+            last_save_time: Utc::now(),
+            total_holders: 0,
+            holders: HashSet::new(),
+            last_page_key: None,
+            last_page_key_at: None,
+            first_seen: HashMap::new(),
+            balances: HashMap::new(),
+            tokens: HashMap::new(),
+            acquisitions: HashMap::new(),
+            valuation: None,
+            provenance: HashMap::new(),
+            metadata: None,
+            delegations: None,
+            look_through: Vec::new(),
+            labels: HashMap::new(),
+            quarantine: Vec::new(),
+            completeness: None,
+        }
+    }
+}
+
+pub fn save_state(state: &ScanState) -> Result<()> {
+    save_state_to(state, Path::new(STATE_PATH))
+}
+
+pub fn save_state_to(state: &ScanState, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Write to a temporary file and rename it over the old one, so readers such
+    // as `serve` never see a half-written checkpoint
+    let tmp_path = path.with_extension("json.tmp");
+    let mut state_file = BufWriter::new(File::create(&tmp_path)?);
+    serde_json::to_writer_pretty(&mut state_file, &state)?;
+    state_file.flush()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Writes the holder list in each format configured in `EXPORT_FORMATS`.
+pub fn save_holders_to_file(state: &ScanState) -> Result<()> {
+    export::export_holders(state)
+}
+
+pub fn load_state() -> Result<ScanState> {
+    let state_path = Path::new(STATE_PATH);
+    if state_path.exists() {
+        load_state_from(state_path)
+    } else {
+        Ok(ScanState::default())
+    }
+}
+
+/// Like `load_state`, but a checkpoint that can't be read starts the scan
+/// over. One from a newer scanner is still an error, so it isn't overwritten.
+pub fn load_state_or_default() -> Result<ScanState> {
+    match load_state() {
+        Err(e) if e.downcast_ref::<StateLoadError>().is_some_and(|e| matches!(e, StateLoadError::Newer { .. })) => {
+            Err(e)
+        }
+        result => Ok(result.unwrap_or_default()),
+    }
+}
+
+pub fn load_state_from(path: &Path) -> Result<ScanState> {
+    let bytes = std::fs::read(path)?;
+    let state = parse_state(&bytes)?;
+    Ok(state)
+}
+
+/// Parses a state file, upgrading it from older versions.
+pub fn parse_state(bytes: &[u8]) -> Result<ScanState, StateLoadError> {
+    #[derive(Deserialize)]
+    struct Versioned {
+        #[serde(default)]
+        version: u32,
+    }
+    let found = serde_json::from_slice::<Versioned>(bytes)?.version;
+    if found > STATE_VERSION {
+        return Err(StateLoadError::Newer { found, supported: STATE_VERSION });
+    }
+    if found == STATE_VERSION {
+        return Ok(serde_json::from_slice(bytes)?);
+    }
+    let mut value: Value = serde_json::from_slice(bytes)?;
+    for migrate in &MIGRATIONS[found as usize..] {
+        migrate(&mut value);
+    }
+    let mut state: ScanState = serde_json::from_value(value)?;
+    state.version = STATE_VERSION;
+    info!(from = found, to = STATE_VERSION, "Upgraded state file");
+    Ok(state)
+}
+
+pub const SHARDS_DIR: &str = "data/holders_shards";
+
+/// Writes the holder list split into one file per lowercase hex address prefix
+/// (`prefix_len` 2 gives `00.txt` through `ff.txt`) plus a `manifest.json` of counts.
+pub fn save_holders_sharded(holders: &HashSet<String>, prefix_len: usize) -> Result<BTreeMap<String, usize>> {
+    let mut shards: BTreeMap<String, Vec<&String>> = BTreeMap::new();
+    for holder in holders {
+        let hex = holder.trim_start_matches("0x").to_lowercase();
+        let prefix = match hex.get(..prefix_len) {
+            Some(prefix) if prefix.chars().all(|c| c.is_ascii_hexdigit()) => prefix.to_string(),
+            _ => "invalid".to_string(),
+        };
+        shards.entry(prefix).or_default().push(holder);
+    }
+
+    let dir = Path::new(SHARDS_DIR);
+    if dir.exists() {
+        // Drop shards from a previous run that used a different prefix length
+        std::fs::remove_dir_all(dir)?;
+    }
+    std::fs::create_dir_all(dir)?;
+
+    let mut counts = BTreeMap::new();
+    for (prefix, mut members) in shards {
+        members.sort();
+        let path = output::export_path(&dir.join(format!("{}.txt", prefix)).to_string_lossy())?;
+        let mut shard_file = ExportWriter::create(path)?;
+        for holder in &members {
+            writeln!(shard_file, "{}", holder)?;
+        }
+        shard_file.finish()?;
+        counts.insert(prefix, members.len());
+    }
+
+    let manifest = File::create(dir.join("manifest.json"))?;
+    serde_json::to_writer_pretty(manifest, &serde_json::json!({
+        "prefix_length": prefix_len,
+        "total_holders": holders.len(),
+        "shards": &counts,
+    }))?;
+    Ok(counts)
+}
@@ -0,0 +1,128 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fs::File;
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::histogram::{self, HistogramBucket, HistogramConfig};
+use crate::identity::HolderIdentity;
+use crate::state::{self, ScanState};
+
+pub const STATS_PATH: &str = "data/holder_stats.json";
+
+const PERCENTILES: [u32; 6] = [10, 25, 50, 75, 90, 99];
+
+#[derive(Debug, clap::Args)]
+pub struct StatsOptions {
+    /// Snapshot to analyze (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Print the JSON report to stdout instead of a table
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Percentile {
+    pub percentile: u32,
+    pub balance: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HolderStats {
+    /// Symbol, name or address of the token, when `metadata` has run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    pub total_holders: u64,
+    pub total_supply: u128,
+    pub mean_balance: f64,
+    pub median_balance: u64,
+    pub max_balance: u64,
+    pub top10_share: f64,
+    pub top100_share: f64,
+    /// 0 when every holder has the same balance, approaching 1 when one holder has everything
+    pub gini: f64,
+    pub percentiles: Vec<Percentile>,
+    pub histogram: Vec<HistogramBucket>,
+}
+
+pub fn holder_stats(state: &ScanState, config: &HistogramConfig) -> HolderStats {
+    let mut balances: Vec<u64> =
+        state.holders.iter().map(|h| state.balances.get(h).copied().unwrap_or(0)).collect();
+    balances.sort_unstable();
+    let n = balances.len();
+    let total: u128 = balances.iter().map(|b| *b as u128).sum();
+
+    let top_share = |count: usize| {
+        let top: u128 = balances.iter().rev().take(count).map(|b| *b as u128).sum();
+        if total > 0 { top as f64 / total as f64 } else { 0.0 }
+    };
+    // Nearest-rank percentile over the ascending balances
+    let percentile = |p: u32| match n {
+        0 => 0,
+        _ => balances[((p as usize * n).div_ceil(100)).clamp(1, n) - 1],
+    };
+    let gini = if n > 0 && total > 0 {
+        let weighted: f64 = balances.iter().enumerate().map(|(i, b)| (i + 1) as f64 * *b as f64).sum();
+        2.0 * weighted / (n as f64 * total as f64) - (n as f64 + 1.0) / n as f64
+    } else {
+        0.0
+    };
+
+    HolderStats {
+        token: state.metadata.as_ref().map(|m| m.label().to_string()),
+        total_holders: n as u64,
+        total_supply: total,
+        mean_balance: if n > 0 { total as f64 / n as f64 } else { 0.0 },
+        median_balance: percentile(50),
+        max_balance: balances.last().copied().unwrap_or(0),
+        top10_share: top_share(10),
+        top100_share: top_share(100),
+        gini,
+        percentiles: PERCENTILES.iter().map(|&p| Percentile { percentile: p, balance: percentile(p) }).collect(),
+        histogram: histogram::balance_histogram(state, config),
+    }
+}
+
+pub fn print_stats(stats: &HolderStats) {
+    if let Some(token) = &stats.token {
+        println!("Token            {:>14}", token);
+    }
+    println!("Holders          {:>14}", stats.total_holders);
+    println!("Total supply     {:>14}", stats.total_supply);
+    println!("Mean balance     {:>14.2}", stats.mean_balance);
+    println!("Median balance   {:>14}", stats.median_balance);
+    println!("Max balance      {:>14}", stats.max_balance);
+    println!("Top-10 share     {:>13.2}%", stats.top10_share * 100.0);
+    println!("Top-100 share    {:>13.2}%", stats.top100_share * 100.0);
+    println!("Gini coefficient {:>14.4}", stats.gini);
+    println!();
+    println!("{:<12} {:>10}", "percentile", "balance");
+    for p in &stats.percentiles {
+        println!("{:<12} {:>10}", format!("p{}", p.percentile), p.balance);
+    }
+    println!();
+    println!("{:<16} {:>10} {:>12}", "balance", "holders", "supply");
+    for bucket in stats.histogram.iter().filter(|b| b.holders > 0) {
+        println!("{:<16} {:>10} {:>12}", bucket.label(), bucket.holders, bucket.supply);
+    }
+}
+
+pub fn run_stats(options: &StatsOptions) -> Result<()> {
+    let state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    let identity = HolderIdentity::from_env()?;
+    let stats = holder_stats(&identity.regroup(&state), &HistogramConfig::from_env());
+
+    std::fs::create_dir_all("data")?;
+    serde_json::to_writer_pretty(File::create(STATS_PATH)?, &stats)?;
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        print_stats(&stats);
+        info!("Holder statistics saved to {}", STATS_PATH);
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use anyhow::{bail, Context, Result};
+use futures::TryStreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload, WriteMultipart};
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, info};
+
+use crate::state::STATE_PATH;
+
+// Local directory mirrored to the bucket
+const DATA_DIR: &str = "data";
+// Files above this are uploaded in parts of this size
+const PART_SIZE: usize = 8 * 1024 * 1024;
+// Parts in flight per multipart upload
+const MAX_CONCURRENT_PARTS: usize = 4;
+
+/// Mirrors `data/` to `OUTPUT_URL` (`s3://bucket/prefix/` or
+/// `gs://bucket/prefix/`), so scans in ephemeral containers keep their state,
+/// snapshots and exports. Credentials come from the usual environment:
+/// `AWS_ACCESS_KEY_ID`/`AWS_SECRET_ACCESS_KEY`/`AWS_REGION` (and
+/// `AWS_ENDPOINT` for S3-compatible stores), or `GOOGLE_SERVICE_ACCOUNT`.
+pub struct OutputStore {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+    url: String,
+    checkpoint_every: Duration,
+    last_checkpoint: Option<Instant>,
+}
+
+impl OutputStore {
+    /// `None` when `OUTPUT_URL` isn't set.
+    pub fn from_env() -> Result<Option<OutputStore>> {
+        let Ok(url) = env::var("OUTPUT_URL") else {
+            return Ok(None);
+        };
+        let parsed = reqwest::Url::parse(&url).with_context(|| format!("invalid OUTPUT_URL {}", url))?;
+        let bucket = parsed.host_str().context("OUTPUT_URL has no bucket")?;
+        let store: Arc<dyn ObjectStore> = match parsed.scheme() {
+            "s3" => Arc::new(AmazonS3Builder::from_env().with_bucket_name(bucket).build()?),
+            "gs" => Arc::new(GoogleCloudStorageBuilder::from_env().with_bucket_name(bucket).build()?),
+            scheme => bail!("OUTPUT_URL must start with s3:// or gs://, got {}://", scheme),
+        };
+        let checkpoint_every =
+            env::var("OUTPUT_CHECKPOINT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(300);
+        Ok(Some(OutputStore {
+            store,
+            prefix: parsed.path().trim_matches('/').to_string(),
+            url,
+            checkpoint_every: Duration::from_secs(checkpoint_every),
+            last_checkpoint: None,
+        }))
+    }
+
+    /// Object key of a file under `data/`.
+    fn key(&self, local: &Path) -> Result<ObjectPath> {
+        let relative = local.strip_prefix(DATA_DIR).with_context(|| format!("{} is outside data/", local.display()))?;
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        Ok(match self.prefix.is_empty() {
+            true => ObjectPath::from(relative),
+            false => ObjectPath::from(format!("{}/{}", self.prefix, relative)),
+        })
+    }
+
+    pub async fn upload(&self, local: &Path) -> Result<()> {
+        let key = self.key(local)?;
+        let mut file = File::open(local).with_context(|| format!("reading {}", local.display()))?;
+        let size = file.metadata()?.len() as usize;
+        if size <= PART_SIZE {
+            let mut bytes = Vec::with_capacity(size);
+            file.read_to_end(&mut bytes)?;
+            self.store.put(&key, PutPayload::from(bytes)).await?;
+        } else {
+            let upload = self.store.put_multipart(&key).await?;
+            let mut writer = WriteMultipart::new_with_chunk_size(upload, PART_SIZE);
+            let mut buffer = vec![0; PART_SIZE];
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                writer.wait_for_capacity(MAX_CONCURRENT_PARTS).await?;
+                writer.write(&buffer[..read]);
+            }
+            writer.finish().await?;
+        }
+        debug!(file = %local.display(), key = %key, size, "Uploaded");
+        Ok(())
+    }
+
+    /// Downloads one file; `false` when the bucket doesn't have it.
+    pub async fn download(&self, local: &Path) -> Result<bool> {
+        let key = self.key(local)?;
+        let bytes = match self.store.get(&key).await {
+            Ok(result) => result.bytes().await?,
+            Err(object_store::Error::NotFound { .. }) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(parent) = local.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(local, &bytes)?;
+        Ok(true)
+    }
+
+    /// Fetches the checkpoint when the container starts without one.
+    pub async fn restore_state(&self) -> Result<()> {
+        let path = Path::new(STATE_PATH);
+        if !path.exists() && self.download(path).await? {
+            info!(from = %self.url, "Restored checkpoint from object storage");
+        }
+        Ok(())
+    }
+
+    /// Uploads the checkpoint and the run journal if `OUTPUT_CHECKPOINT_SECS`
+    /// (default 300) have passed since the last upload.
+    pub async fn checkpoint(&mut self, journal: &Path) -> Result<()> {
+        if self.last_checkpoint.is_some_and(|at| at.elapsed() < self.checkpoint_every) {
+            return Ok(());
+        }
+        self.upload(Path::new(STATE_PATH)).await?;
+        self.upload(journal).await?;
+        self.last_checkpoint = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Uploads every file under `data/` changed since `since`, or all of them.
+    pub async fn push(&self, since: Option<SystemTime>) -> Result<usize> {
+        let mut uploaded = 0;
+        for file in local_files(Path::new(DATA_DIR))? {
+            let modified = fs::metadata(&file)?.modified()?;
+            if since.is_some_and(|since| modified < since) {
+                continue;
+            }
+            self.upload(&file).await?;
+            uploaded += 1;
+        }
+        info!(files = uploaded, to = %self.url, "Pushed data/ to object storage");
+        Ok(uploaded)
+    }
+
+    /// Downloads everything under the prefix into `data/`.
+    pub async fn pull(&self) -> Result<usize> {
+        let prefix = (!self.prefix.is_empty()).then(|| ObjectPath::from(self.prefix.as_str()));
+        let objects: Vec<_> = self.store.list(prefix.as_ref()).try_collect().await?;
+        for object in &objects {
+            let key = object.location.as_ref();
+            let relative = key.strip_prefix(&self.prefix).unwrap_or(key).trim_start_matches('/');
+            self.download(&Path::new(DATA_DIR).join(relative)).await?;
+        }
+        info!(files = objects.len(), from = %self.url, "Pulled data/ from object storage");
+        Ok(objects.len())
+    }
+
+    /// Deletes the objects under a local directory, e.g. a pruned snapshot.
+    pub async fn remove_dir(&self, local: &Path) -> Result<()> {
+        let prefix = self.key(local)?;
+        let objects: Vec<_> = self.store.list(Some(&prefix)).try_collect().await?;
+        for object in objects {
+            self.store.delete(&object.location).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Uploads what a scan run wrote under `data/` when `OUTPUT_URL` is set.
+pub async fn push_run(since: SystemTime) -> Result<()> {
+    if let Some(store) = OutputStore::from_env()? {
+        store.push(Some(since)).await?;
+    }
+    Ok(())
+}
+
+fn local_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(local_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum SyncCommand {
+    /// Upload data/ to OUTPUT_URL
+    Push {
+        /// Only files changed within this long (e.g. 1h); default everything
+        #[arg(long, value_parser = crate::watch::parse_interval)]
+        changed_within: Option<Duration>,
+    },
+    /// Download everything under OUTPUT_URL into data/, e.g. before `export` or `serve`
+    Pull,
+}
+
+pub async fn run_sync(command: &SyncCommand) -> Result<()> {
+    let store = OutputStore::from_env()?.context("OUTPUT_URL must be set, e.g. s3://bucket/scanner/")?;
+    match command {
+        SyncCommand::Push { changed_within } => {
+            let since = changed_within.map(|within| SystemTime::now() - within);
+            println!("{} file(s) uploaded", store.push(since).await?);
+        }
+        SyncCommand::Pull => println!("{} file(s) downloaded", store.pull().await?),
+    }
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::time::Duration;
+use tracing::info;
+use web3::types::U256;
+
+use crate::alchemy::{Owner, OwnersResponse, TokenBalance};
+use crate::config::{self, Config};
+use crate::endpoints::EndpointPool;
+use crate::error::ScannerError;
+use crate::holder_updates::checked_amount;
+use crate::keys::KeyPool;
+
+/// `[subgraph]` in `scanner.toml`: where `scan --provider subgraph` reads
+/// holders from. The defaults fit the Uniswap v3 subgraph's `positions`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubgraphConfig {
+    /// GraphQL endpoint, e.g. `https://gateway.thegraph.com/api/subgraphs/id/<ID>`;
+    /// several comma-separated URLs are load-balanced like `ALCHEMY_ENDPOINTS`
+    pub url: String,
+    /// Entity collection with one row per token (NFTs) or per holder
+    #[serde(default = "default_entity")]
+    pub entity: String,
+    /// Field holding the owner's address; `owner.id` selects a nested entity's id
+    #[serde(default = "default_owner_field")]
+    pub owner_field: String,
+    /// Whole-number balance field for per-holder entities; without it every row
+    /// counts as one token
+    pub balance_field: Option<String>,
+    /// Extra `where` conditions, e.g. `liquidity_gt: "0"`
+    #[serde(rename = "where")]
+    pub filter: Option<String>,
+    /// Rows per query; The Graph allows at most 1000
+    #[serde(default = "default_page_size")]
+    pub page_size: usize,
+}
+
+fn default_entity() -> String {
+    "positions".to_string()
+}
+
+fn default_owner_field() -> String {
+    "owner".to_string()
+}
+
+fn default_page_size() -> usize {
+    1000
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryResponse {
+    data: Option<QueryData>,
+    #[serde(default)]
+    errors: Vec<QueryError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryData {
+    rows: Vec<Value>,
+    #[serde(rename = "_meta")]
+    meta: Option<Meta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Meta {
+    block: MetaBlock,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetaBlock {
+    number: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryError {
+    message: String,
+}
+
+/// Pages through a subgraph entity by `id`, the cursor pagination subgraphs
+/// support without the `skip` limit. Every query of a pass is pinned to the
+/// block the first one saw, so the pass is one consistent snapshot.
+///
+/// An owner's rows are spread over many pages, so each page reports the
+/// owners it touched with everything seen for them so far in the pass.
+pub struct SubgraphSource {
+    config: SubgraphConfig,
+    /// Block the current pass is pinned to
+    pub block: Option<u64>,
+    pass: HashMap<String, Vec<TokenBalance>>,
+}
+
+impl SubgraphSource {
+    pub fn new(config: SubgraphConfig) -> SubgraphSource {
+        SubgraphSource { config, block: None, pass: HashMap::new() }
+    }
+
+    /// Endpoints from `url`, with the reevaluation settings of the Alchemy pool.
+    pub fn endpoints(&self) -> EndpointPool {
+        let urls: Vec<String> =
+            self.config.url.split(',').map(|url| url.trim().to_string()).filter(|url| !url.is_empty()).collect();
+        EndpointPool::with_urls(&urls)
+    }
+
+    /// Keys from `SUBGRAPH_API_KEYS` or `SUBGRAPH_API_KEY`, sent as bearer
+    /// tokens. Without either, queries go out unauthenticated.
+    pub fn keys() -> Result<KeyPool> {
+        let raw = env::var("SUBGRAPH_API_KEYS").or_else(|_| env::var("SUBGRAPH_API_KEY")).unwrap_or_default();
+        let mut keys: Vec<String> =
+            raw.split(',').map(|key| key.trim().to_string()).filter(|key| !key.is_empty()).collect();
+        if keys.is_empty() {
+            keys.push(String::new());
+        }
+        let cooldown = env::var("API_KEY_COOLDOWN_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(120);
+        KeyPool::new(keys, Duration::from_secs(cooldown))
+    }
+
+    /// The GraphQL request for the page after `cursor`, or the first page of a
+    /// new pass. One row more than a page is asked for, to tell whether
+    /// another page follows.
+    pub fn query(&mut self, cursor: Option<&str>) -> Value {
+        if cursor.is_none() {
+            self.block = None;
+            self.pass.clear();
+        }
+        let mut conditions: Vec<String> = Vec::new();
+        if let Some(cursor) = cursor {
+            conditions.push(format!("id_gt: {}", Value::from(cursor)));
+        }
+        if let Some(filter) = &self.config.filter {
+            conditions.push(filter.clone());
+        }
+        let block = self.block.map(|number| format!("block: {{ number: {} }}, ", number)).unwrap_or_default();
+        let fields = [Some(self.config.owner_field.as_str()), self.config.balance_field.as_deref()]
+            .into_iter()
+            .flatten()
+            .map(selection)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let query = format!(
+            "{{ rows: {}({}first: {}, orderBy: id, orderDirection: asc, where: {{ {} }}) {{ id {} }} \
+             _meta {{ block {{ number }} }} }}",
+            self.config.entity,
+            block,
+            self.config.page_size + 1,
+            conditions.join(", "),
+            fields
+        );
+        json!({ "query": query })
+    }
+
+    /// Turns a query response into an owners page whose `page_key` is the id
+    /// to continue after.
+    pub fn parse_page(&mut self, status: reqwest::StatusCode, body: &str) -> Result<OwnersResponse, ScannerError> {
+        if !status.is_success() {
+            return Err(ScannerError::from_status(status, None, body));
+        }
+        let malformed = |reason: String| ScannerError::MalformedResponse { reason, body: body.to_string() };
+        let response: QueryResponse = serde_json::from_str(body).map_err(|e| malformed(e.to_string()))?;
+        if !response.errors.is_empty() {
+            let messages: Vec<&str> = response.errors.iter().map(|e| e.message.as_str()).collect();
+            return Err(ScannerError::Provider { status: status.as_u16(), message: messages.join("; ") });
+        }
+        let mut data = response.data.ok_or_else(|| malformed("no data in the response".to_string()))?;
+        if self.block.is_none() {
+            self.block = data.meta.map(|meta| meta.block.number);
+            if let Some(block) = self.block {
+                info!(block, "Subgraph pass pinned to block");
+            }
+        }
+
+        let more = data.rows.len() > self.config.page_size;
+        data.rows.truncate(self.config.page_size);
+        // Every row is read before any is added to the pass, so a bad page can be retried
+        let mut rows = Vec::with_capacity(data.rows.len());
+        for row in &data.rows {
+            let id = field(row, "id").ok_or_else(|| malformed("row without an id".to_string()))?;
+            let owner = field(row, &self.config.owner_field)
+                .ok_or_else(|| malformed(format!("row {} has no {}", id, self.config.owner_field)))?;
+            let balance = match &self.config.balance_field {
+                Some(name) => {
+                    let value = field(row, name)
+                        .and_then(|value| U256::from_dec_str(&value).ok())
+                        .ok_or_else(|| malformed(format!("row {} has no whole-number {}", id, name)))?;
+                    checked_amount(value)
+                        .ok_or_else(|| malformed(format!("row {}: {} {} exceeds u64", id, name, value)))?
+                }
+                None => 1,
+            };
+            rows.push((id, owner, balance));
+        }
+        let mut touched = BTreeSet::new();
+        let mut last_id = None;
+        for (id, owner, balance) in rows {
+            self.pass.entry(owner.clone()).or_default().push(TokenBalance { token_id: id.clone(), balance });
+            touched.insert(owner);
+            last_id = Some(id);
+        }
+
+        let owners = touched
+            .into_iter()
+            .map(|owner| Owner { token_balances: self.pass[&owner].clone(), owner_address: owner })
+            .collect();
+        Ok(OwnersResponse { owners, page_key: if more { last_id } else { None }, total_count: None })
+    }
+}
+
+/// `owner.id` -> `owner { id }`
+fn selection(path: &str) -> String {
+    match path.split_once('.') {
+        Some((head, rest)) => format!("{} {{ {} }}", head, selection(rest)),
+        None => path.to_string(),
+    }
+}
+
+/// A scalar at a dotted path, as text; addresses are lowercased by subgraphs already.
+fn field(row: &Value, path: &str) -> Option<String> {
+    let value = path.split('.').try_fold(row, |value, name| value.get(name))?;
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+/// The `[subgraph]` section, required by `--provider subgraph`.
+pub fn from_config(config: &Config) -> Result<SubgraphSource> {
+    let subgraph = config
+        .subgraph
+        .clone()
+        .with_context(|| format!("--provider subgraph needs [subgraph] in {}", config::config_path().display()))?;
+    Ok(SubgraphSource::new(subgraph))
+}
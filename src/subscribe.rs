@@ -0,0 +1,319 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::File;
+#[cfg(feature = "server")]
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+use web3::transports::{Http, WebSocket};
+use web3::types::{Address, FilterBuilder, Log};
+use web3::Web3;
+
+use crate::config;
+use crate::diff::HolderChange;
+#[cfg(feature = "server")]
+use crate::events::{self, EventSender};
+use crate::holder_updates::{checked_amount, HolderUpdates};
+use crate::rpc;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::standards::{self, TransferEvent};
+use crate::state::{self, ScanState};
+
+pub const CURSOR_PATH: &str = "data/subscribe_cursor.json";
+
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, clap::Args)]
+pub struct SubscribeOptions {
+    /// WebSocket RPC endpoint to subscribe on (defaults to OPTIMISM_WS_URL)
+    #[arg(long)]
+    pub ws_url: Option<String>,
+    /// Block to start from. Defaults to where the last `subscribe` left off for
+    /// this state, else the next block
+    #[arg(long)]
+    pub from_block: Option<u64>,
+    /// Blocks per eth_getLogs request when backfilling after a disconnect
+    #[arg(long, default_value_t = 2_000)]
+    pub chunk_size: u64,
+    /// Seconds between writes of the updated holder set to data/state.json
+    #[arg(long, default_value_t = 5)]
+    pub save_secs: u64,
+    /// Push holder added/removed/balance-changed events to WebSocket clients at
+    /// ws://<ADDR>/events as transfers are applied
+    #[cfg(feature = "server")]
+    #[arg(long, value_name = "ADDR")]
+    pub events_listen: Option<SocketAddr>,
+}
+
+/// Where in the chain a log sits; logs are applied in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct Position {
+    block: u64,
+    log_index: u64,
+}
+
+impl Position {
+    fn of(log: &Log) -> Option<Position> {
+        Some(Position { block: log.block_number?.as_u64(), log_index: log.log_index?.as_u64() })
+    }
+}
+
+/// `data/subscribe_cursor.json`: the first log not applied to the state yet.
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    next: Position,
+    /// `last_save_time` of the state this cursor belongs to. A scan that
+    /// rewrites the state since invalidates the cursor.
+    state_saved_at: DateTime<Utc>,
+}
+
+struct Follower {
+    http: Web3<Http>,
+    contract: Address,
+    events: Vec<TransferEvent>,
+    chunk_size: u64,
+    state: ScanState,
+    next: Position,
+    /// Just past the furthest log applied. A reorg moves `next` back, but not this
+    applied: Position,
+    /// `next` as of the last save, and whether the holders changed since
+    saved_next: Position,
+    dirty: bool,
+    backoff: Duration,
+    batches: u64,
+    #[cfg(feature = "server")]
+    sender: Option<EventSender>,
+}
+
+/// Follows the contract's transfer logs over a WebSocket subscription and
+/// applies them to the holder set. After every (re)connect the logs since the
+/// last applied one are backfilled over `OPTIMISM_RPC_URL` first.
+pub async fn run_subscribe(options: &SubscribeOptions) -> Result<()> {
+    let ws_url = match &options.ws_url {
+        Some(url) => url.clone(),
+        None => env::var("OPTIMISM_WS_URL").context("pass --ws-url or set OPTIMISM_WS_URL")?,
+    };
+    let http = rpc::connect()?;
+    let contract: Address = CONTRACT_ADDRESS.parse().context("invalid contract address")?;
+    let events = config::load_config()?.events.events_for(CONTRACT_ADDRESS)?;
+    let state = state::load_state_or_default()?;
+    if state.last_page_key.is_some() {
+        // Transfers applied to a partial holder set would be lost or wrong once the pass continues
+        bail!("data/state.json holds an unfinished scan pass; finish the scan before subscribing");
+    }
+    let next = match (options.from_block, load_cursor(&state)?) {
+        (Some(block), _) => Position { block, log_index: 0 },
+        (None, Some(next)) => {
+            info!(block = next.block, "Resuming where the last subscription left off");
+            next
+        }
+        (None, None) => {
+            let block = http.eth().block_number().await?.as_u64() + 1;
+            if !state.holders.is_empty() {
+                warn!(block, "No cursor for this state, following from the next block without a backfill");
+            }
+            Position { block, log_index: 0 }
+        }
+    };
+    info!(holders = state.holders.len(), "Loaded holder set");
+
+    let mut follower = Follower {
+        http,
+        contract,
+        events,
+        chunk_size: options.chunk_size,
+        state,
+        next,
+        applied: next,
+        saved_next: next,
+        dirty: false,
+        backoff: Duration::from_secs(1),
+        batches: 0,
+        #[cfg(feature = "server")]
+        sender: match options.events_listen {
+            Some(listen) => Some(events::start(listen).await?),
+            None => None,
+        },
+    };
+    let (stop, mut stopped) = watch::channel(false);
+    tokio::spawn(async move {
+        let _ = tokio::signal::ctrl_c().await;
+        let _ = stop.send(true);
+    });
+
+    let save_every = Duration::from_secs(options.save_secs.max(1));
+    while !*stopped.borrow() {
+        if let Err(e) = follower.follow(&ws_url, save_every, &mut stopped).await {
+            warn!(error = %format!("{:#}", e), retry_in = ?follower.backoff, "Subscription lost, reconnecting");
+            follower.save()?;
+            tokio::select! {
+                _ = tokio::time::sleep(follower.backoff) => {}
+                _ = stopped.changed() => {}
+            }
+            follower.backoff = (follower.backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+    follower.save()?;
+    info!("Holder set saved, stopping");
+    Ok(())
+}
+
+fn load_cursor(state: &ScanState) -> Result<Option<Position>> {
+    if !Path::new(CURSOR_PATH).exists() {
+        return Ok(None);
+    }
+    let cursor: Cursor = serde_json::from_reader(File::open(CURSOR_PATH)?).context("reading the subscription cursor")?;
+    if cursor.state_saved_at != state.last_save_time {
+        info!("data/state.json was rewritten since the last subscription, not resuming from its cursor");
+        return Ok(None);
+    }
+    Ok(Some(cursor.next))
+}
+
+impl Follower {
+    /// Connects, backfills and applies live logs until the subscription
+    /// drops, which is an error, or `stopped` is set.
+    async fn follow(&mut self, ws_url: &str, save_every: Duration, stopped: &mut watch::Receiver<bool>) -> Result<()> {
+        let web3 = Web3::new(WebSocket::new(ws_url).await.context("connecting to the WebSocket endpoint")?);
+        let topics = self.events.iter().map(|e| e.topic).collect();
+        let filter =
+            FilterBuilder::default().address(vec![self.contract]).topics(Some(topics), None, None, None).build();
+        // Subscribed before the backfill, so no log falls between the two
+        let mut logs = web3.eth_subscribe().subscribe_logs(filter).await.context("eth_subscribe to logs")?;
+        self.backoff = Duration::from_secs(1);
+        tokio::select! {
+            backfilled = self.backfill() => backfilled?,
+            _ = stopped.changed() => return Ok(()),
+        }
+        info!(block = self.next.block, "Following transfer logs");
+        let mut ticks = tokio::time::interval(save_every);
+        loop {
+            tokio::select! {
+                log = logs.next() => match log {
+                    Some(Ok(log)) => self.apply_live(&log)?,
+                    Some(Err(e)) => return Err(e).context("log subscription failed"),
+                    None => bail!("the node closed the log subscription"),
+                },
+                _ = ticks.tick() => self.save()?,
+                _ = stopped.changed() => return Ok(()),
+            }
+        }
+    }
+
+    /// Replays the logs from `next` up to the latest block over HTTP. A
+    /// transfer too large for a balance stops the replay just before it.
+    async fn backfill(&mut self) -> Result<()> {
+        let head = self.http.eth().block_number().await?.as_u64();
+        if self.next.block > head {
+            return Ok(());
+        }
+        info!(from = self.next.block, to = head, "Backfilling transfer logs");
+        // A backfill cut short by Ctrl-C has still moved holders along with `next`
+        self.dirty = true;
+        let mut updates = HolderUpdates::new(&mut self.state, "subscribe");
+        let (events, next, applied) = (&self.events, &mut self.next, &mut self.applied);
+        let range = (next.block, head, self.chunk_size);
+        let mut oversized = None;
+        standards::replay_transfer_logs(&self.http, self.contract, events, range, |log, from, to, amount| {
+            let Some(position) = Position::of(log).filter(|position| *position >= *next) else {
+                return;
+            };
+            if oversized.is_some() {
+                return;
+            }
+            let Some(amount) = checked_amount(amount) else {
+                oversized = Some((position, amount));
+                return;
+            };
+            let token = events.iter().find_map(|e| e.token_id(log)).map(|id| id.to_string());
+            let (from, to) = (format!("{:?}", from), format!("{:?}", to));
+            updates.transfer(&from, &to, token.as_deref(), amount, Some(position.block));
+            *next = Position { block: position.block, log_index: position.log_index + 1 };
+            *applied = (*applied).max(*next);
+        })
+        .await?;
+        let changes = updates.finish();
+        self.publish(changes);
+        if let Some((position, amount)) = oversized {
+            bail!("transfer of {} at block {} log {} exceeds u64", amount, position.block, position.log_index);
+        }
+        // Live logs up to `head` were part of the backfill
+        self.next = self.next.max(Position { block: head + 1, log_index: 0 });
+        self.applied = self.applied.max(self.next);
+        Ok(())
+    }
+
+    /// Applies one subscribed log. A transfer too large for a balance is an
+    /// error, so the follower reconnects and backfills from it.
+    fn apply_live(&mut self, log: &Log) -> Result<()> {
+        let Some(position) = Position::of(log) else {
+            return Ok(());
+        };
+        let Some((from, to, amount)) = self.events.iter().find_map(|event| event.decode(log)) else {
+            return Ok(());
+        };
+        let amount = checked_amount(amount).with_context(|| {
+            format!("transfer of {} at block {} log {} exceeds u64", amount, position.block, position.log_index)
+        });
+        let token = self.events.iter().find_map(|e| e.token_id(log)).map(|id| id.to_string());
+        let (mut from, mut to) = (format!("{:?}", from), format!("{:?}", to));
+        let mut updates = HolderUpdates::new(&mut self.state, "subscribe");
+        if log.removed == Some(true) {
+            if position >= self.applied {
+                // Never applied, so there is nothing to undo
+                return Ok(());
+            }
+            debug!(block = position.block, "Undoing a transfer dropped by a reorg");
+            std::mem::swap(&mut from, &mut to);
+            updates.transfer(&from, &to, token.as_deref(), amount?, None);
+            // The reorged block's replacement logs start at or after this one
+            self.next = self.next.min(position);
+        } else {
+            if position < self.next {
+                return Ok(());
+            }
+            updates.transfer(&from, &to, token.as_deref(), amount?, Some(position.block));
+            self.next = Position { block: position.block, log_index: position.log_index + 1 };
+            self.applied = self.applied.max(self.next);
+        }
+        let changes = updates.finish();
+        self.publish(changes);
+        Ok(())
+    }
+
+    fn publish(&mut self, changes: Vec<HolderChange>) {
+        if changes.is_empty() {
+            return;
+        }
+        self.dirty = true;
+        self.batches += 1;
+        debug!(changes = changes.len(), block = self.next.block, "Applied transfer logs");
+        #[cfg(feature = "server")]
+        if let Some(sender) = &self.sender {
+            events::publish(sender, self.batches, &changes);
+        }
+    }
+
+    /// Writes the state if the holders changed, and the cursor if it moved.
+    fn save(&mut self) -> Result<()> {
+        if !self.dirty && self.next == self.saved_next {
+            return Ok(());
+        }
+        if self.dirty {
+            self.state.last_save_time = Utc::now();
+            state::save_state(&self.state)?;
+            self.dirty = false;
+        }
+        let cursor = Cursor { next: self.next, state_saved_at: self.state.last_save_time };
+        let tmp_path = Path::new(CURSOR_PATH).with_extension("json.tmp");
+        serde_json::to_writer(File::create(&tmp_path)?, &cursor)?;
+        std::fs::rename(&tmp_path, CURSOR_PATH)?;
+        self.saved_next = self.next;
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use chrono::Duration;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use tracing::{info, warn};
+use web3::types::Address;
+
+use crate::address;
+use crate::config;
+use crate::funding::FundingLookup;
+use crate::output;
+use crate::rpc;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::standards;
+use crate::state;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum Check {
+    /// First funded by a wallet that also funded many other holders
+    SharedFunder,
+    /// First funded shortly before the snapshot
+    Fresh,
+    /// Sent the token around a loop of addresses that ends where it started
+    Circular,
+}
+
+impl Check {
+    fn name(self) -> &'static str {
+        match self {
+            Check::SharedFunder => "shared_funder",
+            Check::Fresh => "fresh",
+            Check::Circular => "circular",
+        }
+    }
+
+    /// Share of the score a positive check contributes when every check runs
+    fn weight(self) -> f64 {
+        match self {
+            Check::SharedFunder => 0.4,
+            Check::Fresh => 0.3,
+            Check::Circular => 0.3,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SybilOptions {
+    /// Snapshot to check (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Heuristics to run, comma-separated
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "shared-funder,fresh,circular")]
+    pub checks: Vec<Check>,
+    /// Holders sharing a first funder before it counts as suspicious
+    #[arg(long, default_value_t = 5)]
+    pub shared_funder_min: usize,
+    /// Funders never counted as shared, on top of `[funding] exchanges` (repeatable)
+    #[arg(long = "exclude-funder", value_name = "ADDRESS")]
+    pub exclude_funders: Vec<String>,
+    /// Days before the snapshot within which a first funding counts as fresh
+    #[arg(long, default_value_t = 7)]
+    pub fresh_days: i64,
+    /// First block of Transfer logs to search for cycles
+    #[arg(long, default_value_t = 0)]
+    pub from_block: u64,
+    /// Last block of Transfer logs to search for cycles (defaults to the latest block)
+    #[arg(long)]
+    pub to_block: Option<u64>,
+    /// Blocks per eth_getLogs request
+    #[arg(long, default_value_t = 10_000)]
+    pub chunk_size: u64,
+    #[arg(long, default_value = "data/sybil.csv")]
+    pub output: PathBuf,
+}
+
+/// Flags holders that look like one operator's wallets and gives each a
+/// `sybil_score` from 0 to 1: the weighted share of the selected checks it
+/// fails. The flags are prompts for review, not verdicts.
+pub async fn run_sybil(options: &SybilOptions) -> Result<()> {
+    let state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    let config = config::load_config()?;
+    let checks: HashSet<Check> = options.checks.iter().copied().collect();
+    let web3 = rpc::connect()?;
+
+    let mut holders: Vec<String> = state.holders.iter().filter(|h| address::is_valid(h)).cloned().collect();
+    holders.sort();
+    if holders.len() < state.holders.len() {
+        warn!(holders = state.holders.len() - holders.len(), "Skipping holders that aren't addresses");
+    }
+    let normalized: Vec<String> = holders.iter().map(|h| address::normalize(h)).collect::<Result<_>>()?;
+
+    let fundings = if checks.contains(&Check::SharedFunder) || checks.contains(&Check::Fresh) {
+        FundingLookup::open(web3.clone()).first_fundings(&normalized).await?
+    } else {
+        HashMap::new()
+    };
+    let excluded = config.funding.exchanges_with(&options.exclude_funders)?;
+    let mut funded: HashMap<&str, usize> = HashMap::new();
+    for funding in fundings.values().flatten() {
+        *funded.entry(funding.funder.as_str()).or_default() += 1;
+    }
+
+    let on_cycle = if checks.contains(&Check::Circular) {
+        let events = config.events.events_for(CONTRACT_ADDRESS)?;
+        let contract: Address = CONTRACT_ADDRESS.parse().context("invalid contract address")?;
+        let to_block = match options.to_block {
+            Some(block) => block,
+            None => web3.eth().block_number().await?.as_u64(),
+        };
+        let mut edges: HashSet<(Address, Address)> = HashSet::new();
+        let range = (options.from_block, to_block, options.chunk_size);
+        standards::replay_transfers(&web3, contract, &events, range, |_, from, to, _| {
+            if !from.is_zero() && !to.is_zero() && from != to {
+                edges.insert((from, to));
+            }
+        })
+        .await?;
+        on_cycles(&edges)
+    } else {
+        HashSet::new()
+    };
+
+    let fresh_after = state.last_save_time - Duration::days(options.fresh_days);
+    let total_weight: f64 = checks.iter().map(|c| c.weight()).sum();
+    let mut writer = output::csv_writer(&options.output)?;
+    writer.write_record([
+        "address",
+        "balance",
+        "sybil_score",
+        "flags",
+        "funder",
+        "funder_holders",
+        "first_funded",
+        "on_cycle",
+    ])?;
+    let mut flagged: HashMap<Check, usize> = HashMap::new();
+    for (holder, address) in holders.iter().zip(&normalized) {
+        let funding = fundings.get(address).cloned().flatten();
+        let funder_holders = funding.as_ref().map(|f| funded[f.funder.as_str()]).unwrap_or(0);
+        let mut failed = Vec::new();
+        let shared = funding.as_ref().is_some_and(|f| !excluded.contains(&f.funder));
+        if shared && funder_holders >= options.shared_funder_min {
+            failed.push(Check::SharedFunder);
+        }
+        if funding.as_ref().and_then(|f| f.time).is_some_and(|t| t >= fresh_after) {
+            failed.push(Check::Fresh);
+        }
+        let cycle = address.parse::<Address>().is_ok_and(|a| on_cycle.contains(&a));
+        if cycle {
+            failed.push(Check::Circular);
+        }
+        failed.retain(|c| checks.contains(c));
+        for check in &failed {
+            *flagged.entry(*check).or_default() += 1;
+        }
+        let weight: f64 = failed.iter().map(|c| c.weight()).sum();
+        let score = if total_weight > 0.0 { weight / total_weight } else { 0.0 };
+        writer.write_record([
+            holder.clone(),
+            state.balances.get(holder).copied().unwrap_or(0).to_string(),
+            format!("{:.2}", score),
+            failed.iter().map(|c| c.name()).collect::<Vec<_>>().join(";"),
+            funding.as_ref().map(|f| f.funder.clone()).unwrap_or_default(),
+            funder_holders.to_string(),
+            funding.and_then(|f| f.time).map(|t| t.to_rfc3339()).unwrap_or_default(),
+            cycle.to_string(),
+        ])?;
+    }
+    output::finish_csv(writer)?;
+    for check in &options.checks {
+        println!("{:<14} {:>8} holders flagged", check.name(), flagged.get(check).copied().unwrap_or(0));
+    }
+    info!(holders = holders.len(), output = %options.output.display(), "Sybil flags written");
+    Ok(())
+}
+
+/// Addresses on a transfer cycle: members of a strongly connected component
+/// of the transfer graph with more than one address (Kosaraju, iteratively,
+/// since the graph can be deep enough to overflow the stack).
+fn on_cycles(edges: &HashSet<(Address, Address)>) -> HashSet<Address> {
+    let mut index: HashMap<Address, usize> = HashMap::new();
+    let mut nodes: Vec<Address> = Vec::new();
+    let mut id = |address: Address, nodes: &mut Vec<Address>| {
+        *index.entry(address).or_insert_with(|| {
+            nodes.push(address);
+            nodes.len() - 1
+        })
+    };
+    let pairs: Vec<(usize, usize)> = edges.iter().map(|(a, b)| (id(*a, &mut nodes), id(*b, &mut nodes))).collect();
+    let mut forward = vec![Vec::new(); nodes.len()];
+    let mut reverse = vec![Vec::new(); nodes.len()];
+    for (a, b) in pairs {
+        forward[a].push(b);
+        reverse[b].push(a);
+    }
+
+    // Finishing order of a depth-first search over the forward edges
+    let mut visited = vec![false; nodes.len()];
+    let mut order = Vec::with_capacity(nodes.len());
+    for root in 0..nodes.len() {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        let mut stack = vec![(root, 0)];
+        while let Some(&(node, next)) = stack.last() {
+            if let Some(&child) = forward[node].get(next) {
+                let top = stack.len() - 1;
+                stack[top].1 += 1;
+                if !visited[child] {
+                    visited[child] = true;
+                    stack.push((child, 0));
+                }
+            } else {
+                order.push(node);
+                stack.pop();
+            }
+        }
+    }
+
+    // Components over the reverse edges, in reverse finishing order
+    let mut component = vec![usize::MAX; nodes.len()];
+    let mut sizes = Vec::new();
+    for &root in order.iter().rev() {
+        if component[root] != usize::MAX {
+            continue;
+        }
+        let current = sizes.len();
+        component[root] = current;
+        let mut size = 0;
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            size += 1;
+            for &parent in &reverse[node] {
+                if component[parent] == usize::MAX {
+                    component[parent] = current;
+                    stack.push(parent);
+                }
+            }
+        }
+        sizes.push(size);
+    }
+    nodes.iter().enumerate().filter(|(i, _)| sizes[component[*i]] > 1).map(|(_, a)| *a).collect()
+}
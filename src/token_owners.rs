@@ -0,0 +1,128 @@
+use anyhow::{bail, Context, Result};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+use tracing::{info, warn};
+use web3::types::{Address, U256};
+
+use crate::config;
+use crate::holder_updates;
+use crate::output::{self, ExportWriter};
+use crate::rpc;
+use crate::scan::CONTRACT_ADDRESS;
+use crate::standards;
+use crate::state::ScanState;
+
+pub const TOKEN_OWNERS_CSV: &str = "data/token_owners.csv";
+pub const TOKEN_OWNERS_JSON: &str = "data/token_owners.json";
+
+/// Current owner of every token id in the snapshot, in id order: the reverse
+/// of the holder → tokens view the scan records. Ids are read as the source
+/// stored them, hex from the owners API or decimal from `enumerate`. An id
+/// listed under several holders, from a snapshot taken while it moved, keeps
+/// the first holder in address order.
+pub fn token_owners(state: &ScanState) -> BTreeMap<U256, &str> {
+    let mut holders: Vec<&String> = state.tokens.keys().filter(|h| state.holders.contains(*h)).collect();
+    holders.sort();
+    let mut owners = BTreeMap::new();
+    let mut conflicts = 0;
+    for holder in holders {
+        for id in &state.tokens[holder] {
+            let Some(id) = holder_updates::parse_token_id(id) else {
+                warn!(holder = %holder, id = %id, "Skipping a token id that isn't a number");
+                continue;
+            };
+            match owners.entry(id) {
+                Entry::Vacant(entry) => {
+                    entry.insert(holder.as_str());
+                }
+                Entry::Occupied(_) => conflicts += 1,
+            }
+        }
+    }
+    if conflicts > 0 {
+        warn!(conflicts, "Some token ids are listed under more than one holder; kept the first in address order");
+    }
+    owners
+}
+
+/// Block each token id last moved in, from the contract's ERC-721 `Transfer`
+/// logs between `from_block` and the latest block. Ids whose last transfer
+/// went to someone other than their owner in the snapshot get no block, as
+/// the snapshot and the logs disagree.
+pub async fn acquisition_blocks(
+    owners: &BTreeMap<U256, &str>,
+    from_block: u64,
+    chunk_size: u64,
+) -> Result<HashMap<U256, u64>> {
+    let web3 = rpc::connect()?;
+    let contract: Address = CONTRACT_ADDRESS.parse().context("invalid contract address")?;
+    let events = config::load_config()?.events.events_for(CONTRACT_ADDRESS)?;
+    let to_block = web3.eth().block_number().await?.as_u64();
+    info!(from_block, to_block, "Replaying Transfer logs for token acquisition blocks");
+
+    let mut last_transfer: HashMap<U256, (u64, Address)> = HashMap::new();
+    let range = (from_block, to_block, chunk_size);
+    standards::replay_transfer_logs(&web3, contract, &events, range, |log, _, to, _| {
+        let Some(id) = events.iter().find_map(|event| event.token_id(log)) else {
+            return;
+        };
+        if let Some(block) = log.block_number {
+            last_transfer.insert(id, (block.as_u64(), to));
+        }
+    })
+    .await?;
+    if last_transfer.is_empty() && !owners.is_empty() {
+        bail!("no ERC-721 Transfer logs with a token id found; is {} an ERC-721 contract?", CONTRACT_ADDRESS);
+    }
+
+    let mut blocks = HashMap::new();
+    let mut mismatched = 0;
+    for (id, owner) in owners {
+        match last_transfer.get(id) {
+            Some((block, to)) if format!("{:?}", to).eq_ignore_ascii_case(owner) => {
+                blocks.insert(*id, *block);
+            }
+            Some(_) => mismatched += 1,
+            None => {}
+        }
+    }
+    if mismatched > 0 {
+        warn!(mismatched, "Some tokens last moved to another owner than the snapshot lists; is the snapshot stale?");
+    }
+    Ok(blocks)
+}
+
+/// Writes the map as `token_id,owner[,acquired_block]` CSV and as a JSON
+/// object keyed by token id, both in id order. Ids are written in decimal.
+pub fn save_token_owners(
+    owners: &BTreeMap<U256, &str>,
+    blocks: Option<&HashMap<U256, u64>>,
+    csv_path: &Path,
+    json_path: &Path,
+) -> Result<()> {
+    let mut writer = output::csv_writer(csv_path)?;
+    match blocks {
+        Some(_) => writer.write_record(["token_id", "owner", "acquired_block"])?,
+        None => writer.write_record(["token_id", "owner"])?,
+    }
+    // Streamed rather than built as a JSON object, which would sort the ids as strings
+    let mut json = ExportWriter::create(json_path)?;
+    write!(json, "{{")?;
+    for (i, (id, owner)) in owners.iter().enumerate() {
+        let mut record = vec![id.to_string(), owner.to_string()];
+        let mut entry = serde_json::json!({ "owner": owner });
+        if let Some(blocks) = blocks {
+            let block = blocks.get(id);
+            record.push(block.map(|b| b.to_string()).unwrap_or_default());
+            entry["acquired_block"] = serde_json::json!(block);
+        }
+        writer.write_record(&record)?;
+        write!(json, "{}\n  \"{}\": {}", if i > 0 { "," } else { "" }, id, entry)?;
+    }
+    writeln!(json, "\n}}")?;
+    output::finish_csv(writer)?;
+    json.finish()?;
+    Ok(())
+}
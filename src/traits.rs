@@ -0,0 +1,247 @@
+use anyhow::{anyhow, bail, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A condition on an NFT's attributes, as written in `scanner.toml`:
+///
+/// ```text
+/// tier == "gold" and (level >= 3 or 'Background Color' != "red")
+/// ```
+///
+/// Comparisons are `==`, `!=`, `<`, `<=`, `>` and `>=`, combined with `and`,
+/// `or`, `not` and parentheses. Trait names are bare words or single-quoted,
+/// values double-quoted strings or numbers. Names and string values compare
+/// case-insensitively; `<` and friends need numbers on both sides. A
+/// comparison on a trait the token doesn't have is false.
+#[derive(Debug, Clone)]
+pub struct TraitFilter {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Compare { name: String, op: Op, value: Value },
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    Number(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Text(String),
+    Number(f64),
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl TraitFilter {
+    pub fn parse(source: &str) -> Result<TraitFilter> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            bail!("unexpected {:?} in trait filter '{}'", token, source);
+        }
+        Ok(TraitFilter { source: source.to_string(), expr })
+    }
+
+    /// Whether a token with `attributes` (trait type → value) passes.
+    pub fn matches(&self, attributes: &BTreeMap<String, String>) -> bool {
+        self.expr.matches(attributes)
+    }
+}
+
+impl fmt::Display for TraitFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for TraitFilter {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<TraitFilter, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        TraitFilter::parse(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Expr {
+    fn matches(&self, attributes: &BTreeMap<String, String>) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(attributes) && b.matches(attributes),
+            Expr::Or(a, b) => a.matches(attributes) || b.matches(attributes),
+            Expr::Not(inner) => !inner.matches(attributes),
+            Expr::Compare { name, op, value } => {
+                let Some((_, actual)) = attributes.iter().find(|(trait_type, _)| trait_type.eq_ignore_ascii_case(name))
+                else {
+                    return false;
+                };
+                match value {
+                    Value::Number(expected) => match actual.trim().parse::<f64>() {
+                        Ok(actual) => op.holds(actual.partial_cmp(expected)),
+                        Err(_) => false,
+                    },
+                    Value::Text(expected) => match op {
+                        Op::Eq => actual.eq_ignore_ascii_case(expected),
+                        Op::Ne => !actual.eq_ignore_ascii_case(expected),
+                        _ => false,
+                    },
+                }
+            }
+        }
+    }
+}
+
+impl Op {
+    fn holds(self, ordering: Option<std::cmp::Ordering>) -> bool {
+        use std::cmp::Ordering::*;
+        let Some(ordering) = ordering else {
+            return false;
+        };
+        match self {
+            Op::Eq => ordering == Equal,
+            Op::Ne => ordering != Equal,
+            Op::Lt => ordering == Less,
+            Op::Le => ordering != Greater,
+            Op::Gt => ordering == Greater,
+            Op::Ge => ordering != Less,
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' | '\'' => {
+                chars.next();
+                let text: String = chars.by_ref().take_while(|&next| next != c).collect();
+                tokens.push(if c == '"' { Token::Text(text) } else { Token::Quoted(text) });
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let equals = chars.next_if_eq(&'=').is_some();
+                let op = match (c, equals) {
+                    ('=', true) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    _ => bail!("unknown operator '{}' in trait filter '{}'; use == or !=", c, source),
+                };
+                tokens.push(Token::Op(op));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(next) = chars.next_if(|n| n.is_alphanumeric() || matches!(n, '_' | '-' | '.')) {
+                    word.push(next);
+                }
+                if word.is_empty() {
+                    bail!("unexpected '{}' in trait filter '{}'", c, source);
+                }
+                match word.parse::<f64>() {
+                    Ok(number) => tokens.push(Token::Number(number)),
+                    Err(_) => tokens.push(Token::Word(word)),
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => bail!("missing ')' in trait filter"),
+                }
+            }
+            Some(Token::Word(name)) | Some(Token::Quoted(name)) => {
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    other => bail!("expected a comparison after '{}', found {:?}", name, other),
+                };
+                let value = match self.next() {
+                    Some(Token::Text(text)) => Value::Text(text),
+                    Some(Token::Number(number)) => Value::Number(number),
+                    // A bare word on the right is a string, e.g. tier == gold
+                    Some(Token::Word(word)) => Value::Text(word),
+                    other => return Err(anyhow!("expected a value after '{}', found {:?}", name, other)),
+                };
+                Ok(Expr::Compare { name, op, value })
+            }
+            other => bail!("expected a trait name, found {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+#[cfg(feature = "server")]
+use std::net::SocketAddr;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::chains;
+use crate::diff::{self, ChangeKind};
+#[cfg(feature = "server")]
+use crate::events;
+#[cfg(feature = "server")]
+use crate::health::{self, Health};
+#[cfg(any(feature = "kafka", feature = "nats"))]
+use crate::sink::EventSink;
+use crate::identity::HolderIdentity;
+use crate::scan::{self, ScanOptions};
+use crate::state::{load_state, save_state};
+
+pub const HISTORY_PATH: &str = "data/watch_history.jsonl";
+
+#[derive(Debug, clap::Args)]
+pub struct WatchOptions {
+    /// Chain being scanned; its block time sets the default cadence and staleness alarm
+    #[arg(long, default_value = "optimism")]
+    pub chain: String,
+    /// Time between the end of one rescan and the start of the next (e.g. 30m, 1h, 1d).
+    /// Defaults to --blocks-per-cycle blocks of the chain's block time
+    #[arg(long, value_parser = parse_interval)]
+    pub interval: Option<Duration>,
+    /// Blocks between rescans when --interval is not given
+    #[arg(long, default_value_t = 1800)]
+    pub blocks_per_cycle: u64,
+    /// Raise a staleness alarm when no cycle has succeeded for this many blocks
+    /// (defaults to three cycles)
+    #[arg(long)]
+    pub stale_after_blocks: Option<u64>,
+    /// Push holder added/removed/balance-changed events to WebSocket clients at
+    /// ws://<ADDR>/events as each cycle detects them
+    #[cfg(feature = "server")]
+    #[arg(long, value_name = "ADDR")]
+    pub events_listen: Option<SocketAddr>,
+    /// Serve liveness and readiness checks at http://<ADDR>/healthz and /readyz
+    #[cfg(feature = "server")]
+    #[arg(long, value_name = "ADDR")]
+    pub health_listen: Option<SocketAddr>,
+    /// Report the scanner as not live on /healthz when a scan completes no page for this long
+    #[cfg(feature = "server")]
+    #[arg(long, default_value = "15m", value_parser = parse_interval)]
+    pub health_stall_after: Duration,
+    #[command(flatten)]
+    pub scan: ScanOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct CycleRecord {
+    cycle: u64,
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    total_holders: u64,
+    added: usize,
+    removed: usize,
+    balance_changed: usize,
+    error: Option<String>,
+}
+
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| format!("invalid interval: {}", value))?;
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown interval unit '{}' (use s, m, h or d)", unit)),
+    };
+    let seconds = amount.checked_mul(scale).ok_or_else(|| format!("interval too large: {}", value))?;
+    if seconds == 0 {
+        return Err("interval must be greater than zero".to_string());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+pub async fn run_watch(options: WatchOptions) -> Result<()> {
+    if options.scan.streaming {
+        // Cycle deltas are computed from the in-memory holder set
+        bail!("--streaming is not supported in watch mode");
+    }
+    let chain = chains::chain_config(&options.chain)?;
+    let interval = options.interval.unwrap_or_else(|| chain.blocks(options.blocks_per_cycle));
+    let stale_after = match options.stale_after_blocks {
+        Some(blocks) => chain.blocks(blocks),
+        None => interval * 3,
+    };
+    info!(chain = %chain.name, block_time = ?chain.block_time, ?interval, ?stale_after, "Watching");
+    let identity = HolderIdentity::from_env()?;
+    #[cfg(feature = "server")]
+    let events = match options.events_listen {
+        Some(listen) => Some(events::start(listen).await?),
+        None => None,
+    };
+    #[cfg(feature = "server")]
+    let health = Health::watch(stale_after, options.health_stall_after);
+    #[cfg(feature = "server")]
+    if let Some(listen) = options.health_listen {
+        health::start(listen, health.clone()).await?;
+    }
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    let sink = EventSink::from_env().await?;
+    let mut cycle = 0;
+    let mut last_success = Utc::now();
+    let mut last_complete = load_state().ok().filter(|state| state.complete());
+
+    loop {
+        cycle += 1;
+        let started_at = Utc::now();
+        info!(cycle, "Watch cycle started");
+        #[cfg(feature = "server")]
+        health.cycle_started();
+
+        let saved = load_state().unwrap_or_default();
+        // A finished pass leaves no page key behind; the next one starts over in memory so
+        // holders who exited since then drop out, and nothing is written until it succeeds.
+        // An interrupted pass is resumed from its checkpoint.
+        let mut scan_options = options.scan.clone();
+        scan_options.fresh |= saved.last_page_key.is_none();
+        // Deltas are taken against the last completed pass, never a checkpoint
+        let previous = match &last_complete {
+            Some(state) => state.clone(),
+            None => saved,
+        };
+
+        let record = match scan::run_scan(scan_options).await {
+            Ok(mut current) => {
+                last_success = Utc::now();
+                #[cfg(feature = "server")]
+                health.cycle_succeeded();
+                // Forget first-seen times for holders that left so a return counts as a new holding
+                current.first_seen.retain(|holder, _| current.holders.contains(holder));
+                current.acquisitions.retain(|holder, _| current.holders.contains(holder));
+                current.provenance.retain(|holder, _| current.holders.contains(holder));
+                save_state(&current)?;
+                if current.complete() {
+                    last_complete = Some(current.clone());
+                }
+
+                let changes = diff::diff_states(&identity.regroup(&previous), &identity.regroup(&current));
+                #[cfg(feature = "server")]
+                if let Some(events) = &events {
+                    events::publish(events, cycle, &changes);
+                }
+                #[cfg(any(feature = "kafka", feature = "nats"))]
+                if let Some(sink) = &sink {
+                    if let Err(e) = sink.publish_changes(cycle, &changes).await {
+                        warn!(cycle, error = %format!("{:#}", e), "Could not publish holder changes");
+                    }
+                }
+                let count = |kind: ChangeKind| changes.iter().filter(|c| c.kind == kind).count();
+                CycleRecord {
+                    cycle,
+                    started_at,
+                    finished_at: Utc::now(),
+                    total_holders: current.holders.len() as u64,
+                    added: count(ChangeKind::Added),
+                    removed: count(ChangeKind::Removed),
+                    balance_changed: count(ChangeKind::BalanceChanged),
+                    error: None,
+                }
+            }
+            Err(e) => {
+                error!(cycle, error = %format!("{:#}", e), "Watch cycle failed");
+                #[cfg(feature = "server")]
+                health.cycle_failed(&e);
+                CycleRecord {
+                    cycle,
+                    started_at,
+                    finished_at: Utc::now(),
+                    total_holders: previous.holders.len() as u64,
+                    added: 0,
+                    removed: 0,
+                    balance_changed: 0,
+                    error: Some(format!("{:#}", e)),
+                }
+            }
+        };
+
+        info!(
+            cycle = record.cycle,
+            holders = record.total_holders,
+            added = record.added,
+            removed = record.removed,
+            balance_changed = record.balance_changed,
+            "Watch cycle finished"
+        );
+        append_history(&record)?;
+
+        let since_success = (Utc::now() - last_success).to_std().unwrap_or_default();
+        if since_success > stale_after {
+            let blocks = since_success.as_secs_f64() / chain.block_time.as_secs_f64();
+            warn!(
+                chain = %chain.name,
+                stale_for = ?since_success,
+                blocks_behind = blocks as u64,
+                "Holder data is stale: no successful cycle within the staleness window"
+            );
+        }
+
+        info!(?interval, "Waiting for next rescan");
+        tokio::time::sleep(interval).await;
+    }
+}
+
+fn append_history(record: &CycleRecord) -> Result<()> {
+    std::fs::create_dir_all("data")?;
+    let mut file = OpenOptions::new().create(true).append(true).open(HISTORY_PATH)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
@@ -0,0 +1,292 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use tracing::{info, warn};
+use web3::ethabi::{self, Token};
+use web3::signing::keccak256;
+use web3::types::{Address, U256};
+
+use crate::config;
+use crate::holder_updates::parse_token_id;
+use crate::multicall::Multicall;
+use crate::output;
+use crate::rpc;
+use crate::standards;
+use crate::state::{self, Provenance, ScanState, WrappedPosition};
+
+pub const LOOK_THROUGH_PATH: &str = "data/look_through.csv";
+
+/// `[[wrapper]]` in `scanner.toml`: a staking or vault contract that holds
+/// positions on behalf of its depositors.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WrapperConfig {
+    /// Shown in logs and the look-through report
+    pub name: Option<String>,
+    pub address: String,
+    #[serde(flatten)]
+    pub kind: WrapperKind,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WrapperKind {
+    /// The wrapper issues ERC-20 shares of everything it holds (Gamma
+    /// hypervisors, Arrakis vaults). Its balance is split across the share
+    /// holders pro rata, replayed from the share token's `Transfer` logs
+    /// starting at `from_block`, ideally the wrapper's deployment block
+    Shares {
+        #[serde(default)]
+        from_block: u64,
+    },
+    /// The wrapper records who deposited each token (Uniswap V3 staker style).
+    /// `function(tokenId)` returns the depositor as its first value
+    Deposits {
+        #[serde(default = "default_deposits_function")]
+        function: String,
+    },
+}
+
+fn default_deposits_function() -> String {
+    "deposits(uint256)".to_string()
+}
+
+impl WrapperKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            WrapperKind::Shares { .. } => "shares",
+            WrapperKind::Deposits { .. } => "deposits",
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct LookThroughOptions {
+    /// Snapshot to resolve (defaults to data/state.json)
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Block to resolve depositors at (defaults to the latest block); older blocks need an archive node
+    #[arg(long)]
+    pub block: Option<u64>,
+    /// Blocks per eth_getLogs request when replaying share transfers
+    #[arg(long, default_value_t = 10_000)]
+    pub chunk_size: u64,
+    /// Per-depositor report
+    #[arg(long, default_value = LOOK_THROUGH_PATH)]
+    pub output: PathBuf,
+}
+
+/// Replaces every `[[wrapper]]` contract in the holder set with the
+/// depositors it holds for, so the snapshot credits them rather than the
+/// wrapper. Wrappers that aren't holders are skipped.
+pub async fn run_look_through(options: &LookThroughOptions) -> Result<()> {
+    let config = config::load_config()?;
+    if config.wrappers.is_empty() {
+        bail!("no [[wrapper]] entries in {}", config::config_path().display());
+    }
+    let mut state = match &options.state {
+        Some(path) => state::load_state_from(path)?,
+        None => state::load_state()?,
+    };
+    if state.last_page_key.is_some() {
+        bail!("the snapshot holds an unfinished scan pass; finish the scan before looking through wrappers");
+    }
+    let web3 = rpc::connect()?;
+    let block = match options.block {
+        Some(block) => block,
+        None => web3.eth().block_number().await?.as_u64(),
+    };
+
+    let mut resolved = Vec::new();
+    for wrapper in &config.wrappers {
+        let address: Address = wrapper.address.parse().with_context(|| format!("invalid wrapper {}", wrapper.address))?;
+        let key = format!("{:?}", address);
+        let label = wrapper.name.clone().unwrap_or_else(|| key.clone());
+        if !state.holders.contains(&key) {
+            info!(wrapper = %label, "Wrapper holds nothing in this snapshot, skipping");
+            continue;
+        }
+        let balance = state.balances.get(&key).copied().unwrap_or(0);
+        let tokens = state.tokens.get(&key).cloned().unwrap_or_default();
+        let (credited, shares) = match &wrapper.kind {
+            WrapperKind::Shares { from_block } => {
+                let shares = share_balances(&web3, &config, address, (*from_block, block, options.chunk_size)).await?;
+                (apportion(balance, &shares), shares)
+            }
+            WrapperKind::Deposits { .. } if tokens.is_empty() => {
+                warn!(wrapper = %label, "The snapshot has no token ids for the wrapper to look up depositors for");
+                continue;
+            }
+            WrapperKind::Deposits { function } => {
+                let depositors = depositors(&web3, address, function, &tokens, block).await?;
+                let mut credited: BTreeMap<String, Vec<String>> = BTreeMap::new();
+                for (token, depositor) in tokens.iter().zip(depositors) {
+                    match depositor {
+                        Some(depositor) => credited.entry(depositor).or_default().push(token.clone()),
+                        None => warn!(wrapper = %label, token = %token, "No depositor, token stays with the wrapper"),
+                    }
+                }
+                (credited.into_iter().map(|(d, t)| (d, (t.len() as u64, t))).collect(), BTreeMap::new())
+            }
+        };
+        if credited.is_empty() {
+            warn!(wrapper = %label, "Found no depositors; the wrapper stays a holder");
+            continue;
+        }
+        credit(&mut state, &key, &credited, block);
+        info!(wrapper = %label, depositors = credited.len(), balance, "Looked through wrapper");
+        resolved.push(WrappedPosition {
+            wrapper: key,
+            name: wrapper.name.clone(),
+            kind: wrapper.kind.as_str().to_string(),
+            block,
+            balance,
+            tokens,
+            credited: credited.into_iter().map(|(depositor, (balance, _))| (depositor, balance)).collect(),
+            shares: shares.into_iter().map(|(depositor, shares)| (depositor, shares.to_string())).collect(),
+        });
+    }
+
+    let mut writer = output::csv_writer(&options.output)?;
+    writer.write_record(["wrapper", "name", "depositor", "shares", "credited_balance"])?;
+    for position in &resolved {
+        for (depositor, balance) in &position.credited {
+            writer.write_record([
+                position.wrapper.as_str(),
+                position.name.as_deref().unwrap_or_default(),
+                depositor,
+                position.shares.get(depositor).map(String::as_str).unwrap_or_default(),
+                &balance.to_string(),
+            ])?;
+        }
+    }
+    output::finish_csv(writer)?;
+
+    state.total_holders = state.holders.len() as u64;
+    state.look_through.extend(resolved);
+    match &options.state {
+        Some(path) => state::save_state_to(&state, path)?,
+        None => {
+            state::save_state(&state)?;
+            state::save_holders_to_file(&state)?;
+        }
+    }
+    info!(output = %options.output.display(), holders = state.holders.len(), "Look-through report written");
+    Ok(())
+}
+
+/// Balances of the wrapper's ERC-20 share token at the end of `range`,
+/// replayed from its `Transfer` logs. The zero address and the wrapper itself
+/// are left out.
+async fn share_balances(
+    web3: &web3::Web3<web3::transports::Http>,
+    config: &config::Config,
+    wrapper: Address,
+    range: (u64, u64, u64),
+) -> Result<BTreeMap<String, U256>> {
+    let events = config.events.events_named("erc20", &format!("{:?}", wrapper))?;
+    let mut balances: HashMap<Address, U256> = HashMap::new();
+    standards::replay_transfers(web3, wrapper, &events, range, |_, from, to, amount| {
+        let sent = balances.entry(from).or_default();
+        *sent = sent.saturating_sub(amount);
+        let received = balances.entry(to).or_default();
+        *received = received.saturating_add(amount);
+    })
+    .await?;
+    Ok(balances
+        .into_iter()
+        .filter(|(holder, shares)| !holder.is_zero() && *holder != wrapper && !shares.is_zero())
+        .map(|(holder, shares)| (format!("{:?}", holder), shares))
+        .collect())
+}
+
+/// Splits `balance` across `shares` with the largest-remainder method, so the
+/// parts add up to `balance` exactly. Ties go to the lower address. Holders
+/// whose part rounds to zero still get an entry.
+fn apportion(balance: u64, shares: &BTreeMap<String, U256>) -> BTreeMap<String, (u64, Vec<String>)> {
+    let total = shares.values().fold(U256::zero(), |a, b| a.saturating_add(*b));
+    if total.is_zero() {
+        return BTreeMap::new();
+    }
+    let mut parts: Vec<(&String, u64, U256)> = shares
+        .iter()
+        .map(|(holder, held)| {
+            // Share supplies stay far below 2^192, so this doesn't overflow
+            let scaled = held.saturating_mul(U256::from(balance));
+            (holder, (scaled / total).low_u64(), scaled % total)
+        })
+        .collect();
+    let mut left = balance - parts.iter().map(|(_, part, _)| part).sum::<u64>();
+    let mut by_remainder: Vec<usize> = (0..parts.len()).collect();
+    by_remainder.sort_by(|a, b| parts[*b].2.cmp(&parts[*a].2).then(parts[*a].0.cmp(parts[*b].0)));
+    for index in by_remainder {
+        if left == 0 {
+            break;
+        }
+        parts[index].1 += 1;
+        left -= 1;
+    }
+    parts.into_iter().map(|(holder, part, _)| (holder.clone(), (part, Vec::new()))).collect()
+}
+
+/// The depositor of each token, from `function(tokenId)` on the wrapper.
+/// `None` where the call fails or names the zero address.
+async fn depositors(
+    web3: &web3::Web3<web3::transports::Http>,
+    wrapper: Address,
+    function: &str,
+    tokens: &[String],
+    block: u64,
+) -> Result<Vec<Option<String>>> {
+    let selector = &keccak256(function.trim().as_bytes())[..4];
+    let mut calls = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        let id = parse_token_id(token).with_context(|| format!("token id {} isn't a number", token))?;
+        let mut data = selector.to_vec();
+        data.extend(ethabi::encode(&[Token::Uint(id)]));
+        calls.push((wrapper, data));
+    }
+    let results = Multicall::from_env()?.aggregate(web3, &calls, Some(block)).await?;
+    Ok(results
+        .into_iter()
+        .map(|data| {
+            let depositor = Address::from_slice(data?.get(12..32)?);
+            (!depositor.is_zero()).then(|| format!("{:?}", depositor))
+        })
+        .collect())
+}
+
+/// Moves the wrapper's holding to its depositors: each gets its balance and
+/// token ids added to any it holds directly. Whatever balance nobody was
+/// credited with stays with the wrapper, along with its uncredited tokens.
+fn credit(state: &mut ScanState, wrapper: &str, credited: &BTreeMap<String, (u64, Vec<String>)>, block: u64) {
+    let since = state.first_seen.get(wrapper).copied().unwrap_or_else(Utc::now);
+    let moved: u64 = credited.values().map(|(balance, _)| balance).sum();
+    for (depositor, (balance, tokens)) in credited {
+        if state.holders.insert(depositor.clone()) {
+            state.first_seen.insert(depositor.clone(), since);
+            let source = "look_through".to_string();
+            let provenance = Provenance { source, endpoint: None, run: None, page: None, block: Some(block) };
+            state.provenance.insert(depositor.clone(), provenance);
+        }
+        *state.balances.entry(depositor.clone()).or_insert(0) += balance;
+        if !tokens.is_empty() {
+            state.tokens.entry(depositor.clone()).or_default().extend(tokens.iter().cloned());
+        }
+    }
+    let remaining = state.balances.get(wrapper).copied().unwrap_or(0).saturating_sub(moved);
+    if remaining == 0 {
+        state.holders.remove(wrapper);
+        state.balances.remove(wrapper);
+        state.tokens.remove(wrapper);
+        state.first_seen.remove(wrapper);
+        state.acquisitions.remove(wrapper);
+        state.provenance.remove(wrapper);
+    } else {
+        state.balances.insert(wrapper.to_string(), remaining);
+        if let Some(tokens) = state.tokens.get_mut(wrapper) {
+            tokens.retain(|token| !credited.values().any(|(_, moved)| moved.contains(token)));
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+use std::path::Path;
+
+use crate::histogram::HistogramConfig;
+use crate::identity::HolderIdentity;
+use crate::leaderboard::{self, SortBy};
+use crate::scan::CONTRACT_ADDRESS;
+use crate::state::ScanState;
+use crate::stats;
+
+// Excel's row limit, header included
+const MAX_ROWS: usize = 1_048_576;
+
+struct Formats {
+    header: Format,
+    time: Format,
+    percent: Format,
+    usd: Format,
+}
+
+impl Formats {
+    fn new() -> Formats {
+        Formats {
+            header: Format::new().set_bold(),
+            time: Format::new().set_num_format("yyyy-mm-dd hh:mm:ss"),
+            percent: Format::new().set_num_format("0.00%"),
+            usd: Format::new().set_num_format("#,##0.00"),
+        }
+    }
+}
+
+fn write_header(sheet: &mut Worksheet, formats: &Formats, columns: &[&str]) -> Result<()> {
+    for (col, name) in columns.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *name, &formats.header)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    Ok(())
+}
+
+fn write_time(sheet: &mut Worksheet, row: u32, col: u16, time: Option<DateTime<Utc>>, formats: &Formats) -> Result<()> {
+    if let Some(time) = time {
+        sheet.write_datetime_with_format(row, col, time.naive_utc(), &formats.time)?;
+    }
+    Ok(())
+}
+
+fn write_usd(sheet: &mut Worksheet, row: u32, col: u16, value: Option<f64>, formats: &Formats) -> Result<()> {
+    if let Some(value) = value {
+        sheet.write_number_with_format(row, col, value, &formats.usd)?;
+    }
+    Ok(())
+}
+
+/// Every holder, in address order, with the columns of the csv export.
+fn holders_sheet(sheet: &mut Worksheet, state: &ScanState, formats: &Formats) -> Result<()> {
+    sheet.set_name("Holders")?;
+    let columns =
+        ["address", "balance", "first_seen", "first_acquired", "held_since", "holding_days", "usd_value", "source"];
+    write_header(sheet, formats, &columns)?;
+    let mut holders: Vec<&String> = state.holders.iter().collect();
+    holders.sort();
+    for (i, address) in holders.iter().enumerate() {
+        let row = i as u32 + 1;
+        let acquisition = state.acquisitions.get(*address);
+        sheet.write_string(row, 0, address.as_str())?;
+        sheet.write_number(row, 1, state.balances.get(*address).copied().unwrap_or(0) as f64)?;
+        write_time(sheet, row, 2, state.first_seen.get(*address).copied(), formats)?;
+        write_time(sheet, row, 3, acquisition.map(|a| a.first_acquired), formats)?;
+        write_time(sheet, row, 4, acquisition.map(|a| a.held_since), formats)?;
+        if let Some(acquisition) = acquisition {
+            sheet.write_number(row, 5, (Utc::now() - acquisition.held_since).num_days() as f64)?;
+        }
+        write_usd(sheet, row, 6, state.usd_value(address), formats)?;
+        if let Some(provenance) = state.provenance.get(*address) {
+            sheet.write_string(row, 7, &provenance.source)?;
+        }
+    }
+    sheet.autofilter(0, 0, holders.len() as u32, columns.len() as u16 - 1)?;
+    sheet.set_column_width(0, 44)?;
+    sheet.set_column_range_width(2, 4, 20)?;
+    Ok(())
+}
+
+/// The `top` largest holders, counted by `HOLDER_KEY`.
+fn top_holders_sheet(sheet: &mut Worksheet, view: &ScanState, top: usize, formats: &Formats) -> Result<()> {
+    sheet.set_name("Top holders")?;
+    let columns = ["rank", "address", "balance", "share", "first_seen", "held_since", "usd_value", "source"];
+    write_header(sheet, formats, &columns)?;
+    for (i, entry) in leaderboard::leaderboard(view, top, SortBy::Balance).iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_number(row, 0, entry.rank as f64)?;
+        sheet.write_string(row, 1, &entry.address)?;
+        sheet.write_number(row, 2, entry.balance as f64)?;
+        sheet.write_number_with_format(row, 3, entry.share, &formats.percent)?;
+        write_time(sheet, row, 4, entry.first_seen, formats)?;
+        write_time(sheet, row, 5, entry.held_since, formats)?;
+        write_usd(sheet, row, 6, entry.usd_value, formats)?;
+        if let Some(source) = &entry.source {
+            sheet.write_string(row, 7, source)?;
+        }
+    }
+    sheet.set_column_width(1, 44)?;
+    sheet.set_column_range_width(4, 5, 20)?;
+    Ok(())
+}
+
+/// Concentration figures, then the percentile and histogram tables side by side.
+fn statistics_sheet(sheet: &mut Worksheet, view: &ScanState, formats: &Formats) -> Result<()> {
+    sheet.set_name("Statistics")?;
+    let stats = stats::holder_stats(view, &HistogramConfig::from_env());
+    write_header(sheet, formats, &["metric", "value"])?;
+    let figures = [
+        ("holders", stats.total_holders as f64, None),
+        ("total_supply", stats.total_supply as f64, None),
+        ("mean_balance", stats.mean_balance, None),
+        ("median_balance", stats.median_balance as f64, None),
+        ("max_balance", stats.max_balance as f64, None),
+        ("top10_share", stats.top10_share, Some(&formats.percent)),
+        ("top100_share", stats.top100_share, Some(&formats.percent)),
+        ("gini", stats.gini, None),
+    ];
+    for (i, (name, value, format)) in figures.iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_string(row, 0, *name)?;
+        match format {
+            Some(format) => sheet.write_number_with_format(row, 1, *value, format)?,
+            None => sheet.write_number(row, 1, *value)?,
+        };
+    }
+
+    for (col, name) in ["percentile", "balance"].iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16 + 3, *name, &formats.header)?;
+    }
+    for (i, percentile) in stats.percentiles.iter().enumerate() {
+        sheet.write_number(i as u32 + 1, 3, percentile.percentile as f64)?;
+        sheet.write_number(i as u32 + 1, 4, percentile.balance as f64)?;
+    }
+
+    for (col, name) in ["balance", "holders", "supply"].iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16 + 6, *name, &formats.header)?;
+    }
+    for (i, bucket) in stats.histogram.iter().enumerate() {
+        sheet.write_string(i as u32 + 1, 6, bucket.label())?;
+        sheet.write_number(i as u32 + 1, 7, bucket.holders as f64)?;
+        sheet.write_number(i as u32 + 1, 8, bucket.supply as f64)?;
+    }
+    sheet.set_column_width(0, 16)?;
+    Ok(())
+}
+
+/// What the workbook was built from, so a forwarded file still says which
+/// token, block and price it reflects.
+fn run_sheet(sheet: &mut Worksheet, state: &ScanState, formats: &Formats) -> Result<()> {
+    sheet.set_name("Run")?;
+    write_header(sheet, formats, &["field", "value"])?;
+    let metadata = state.metadata.as_ref();
+    let valuation = state.valuation.as_ref();
+    let mut fields: Vec<(&str, String)> = vec![
+        ("contract", metadata.map(|m| m.contract.clone()).unwrap_or_else(|| CONTRACT_ADDRESS.to_string())),
+        ("token_name", metadata.and_then(|m| m.name.clone()).unwrap_or_default()),
+        ("token_symbol", metadata.and_then(|m| m.symbol.clone()).unwrap_or_default()),
+        ("snapshot_time", state.last_save_time.to_rfc3339()),
+        ("last_processed_block", state.last_processed_block.to_string()),
+        ("holders", state.holders.len().to_string()),
+        ("price_usd", valuation.map(|v| v.price_usd.to_string()).unwrap_or_default()),
+        ("price_source", valuation.map(|v| v.source.clone()).unwrap_or_default()),
+        ("priced_at", valuation.map(|v| v.priced_at.to_rfc3339()).unwrap_or_default()),
+        ("holder_key", std::env::var("HOLDER_KEY").unwrap_or_else(|_| "address".to_string())),
+        ("exported_at", Utc::now().to_rfc3339()),
+        ("scanner_version", env!("CARGO_PKG_VERSION").to_string()),
+    ];
+    // Scan runs that reported the current holders
+    let mut runs: Vec<&str> = state.provenance.values().filter_map(|p| p.run.as_deref()).collect();
+    runs.sort_unstable();
+    runs.dedup();
+    fields.push(("scan_runs", runs.join(", ")));
+    for (i, (name, value)) in fields.iter().enumerate() {
+        sheet.write_string(i as u32 + 1, 0, *name)?;
+        sheet.write_string(i as u32 + 1, 1, value)?;
+    }
+    sheet.set_column_width(0, 22)?;
+    sheet.set_column_width(1, 48)?;
+    Ok(())
+}
+
+/// Writes a workbook with Holders, Top holders, Statistics and Run sheets.
+pub fn write_workbook(state: &ScanState, top: usize, path: &Path) -> Result<()> {
+    if state.holders.len() >= MAX_ROWS {
+        bail!("{} holders don't fit in one Excel sheet; export csv instead", state.holders.len());
+    }
+    let identity = HolderIdentity::from_env()?;
+    let view = identity.regroup(state);
+    let formats = Formats::new();
+
+    let mut workbook = Workbook::new();
+    holders_sheet(workbook.add_worksheet(), state, &formats)?;
+    top_holders_sheet(workbook.add_worksheet(), &view, top, &formats)?;
+    statistics_sheet(workbook.add_worksheet(), &view, &formats)?;
+    run_sheet(workbook.add_worksheet(), state, &formats)?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    workbook.save(path)?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use uniswap_nft_holders::address::{self, AddressFormat};
+
+const CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+const LOWER: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+
+#[test]
+fn every_spelling_of_an_address_normalizes_to_lowercase() {
+    assert_eq!(address::normalize(LOWER).unwrap(), LOWER);
+    assert_eq!(address::normalize(CHECKSUMMED).unwrap(), LOWER);
+    assert_eq!(address::normalize(&format!("  {}\n", CHECKSUMMED)).unwrap(), LOWER);
+    assert_eq!(address::normalize("0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED").unwrap(), LOWER);
+    assert_eq!(address::normalize("0X5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap(), LOWER);
+}
+
+#[test]
+fn mixed_case_must_be_a_valid_checksum() {
+    let error = address::normalize("0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed").unwrap_err();
+    assert!(error.to_string().contains("EIP-55"), "{}", error);
+    assert!(!address::is_valid("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD"));
+}
+
+#[test]
+fn malformed_addresses_are_rejected() {
+    for raw in [
+        "",
+        "5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+        "0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea",
+        "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed00",
+        "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beazz",
+    ] {
+        assert!(address::normalize(raw).is_err(), "{:?} was accepted", raw);
+    }
+}
+
+#[test]
+fn checksummed_round_trips_through_normalize() {
+    for raw in [
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ] {
+        let lower = address::normalize(raw).unwrap();
+        assert_eq!(address::checksummed(&lower).unwrap(), raw);
+    }
+}
+
+#[test]
+fn non_evm_keys_never_merge_with_real_addresses() {
+    assert_eq!(AddressFormat::Evm.key(&format!(" {} ", CHECKSUMMED)), LOWER);
+    assert_eq!(AddressFormat::Evm.key(" not-an-address "), "not-an-address");
+}
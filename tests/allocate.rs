@@ -0,0 +1,93 @@
+use uniswap_nft_holders::allocate::{self, AllocationPolicy, AllocationRow, Strategy, Tier};
+use uniswap_nft_holders::state::ScanState;
+
+fn state(holders: &[(&str, u64)]) -> ScanState {
+    let mut state = ScanState::default();
+    for (address, balance) in holders {
+        state.holders.insert(address.to_string());
+        state.balances.insert(address.to_string(), *balance);
+    }
+    state
+}
+
+fn policy(strategy: Strategy) -> AllocationPolicy {
+    AllocationPolicy {
+        name: "test".to_string(),
+        strategy,
+        cap_share: None,
+        min_balance: 1,
+        min_amount: 0,
+        exclude: Vec::new(),
+        tiers: Vec::new(),
+        round_to: 1,
+        min_usd: None,
+    }
+}
+
+#[test]
+fn eighteen_decimal_totals_are_split_exactly() {
+    // Balances with awkward ratios; the total is far above 2^53
+    let holders: Vec<(String, u64)> = (1..=97u64).map(|i| (format!("0x{:040x}", i), i * i * 7_919 + 13)).collect();
+    let holders: Vec<(&str, u64)> = holders.iter().map(|(a, b)| (a.as_str(), *b)).collect();
+    let state = state(&holders);
+
+    for total in [1_000_000_000_000_000_003u128, 123_456_789_123_456_789_123, u128::MAX / 3] {
+        for strategy in [Strategy::Proportional, Strategy::Quadratic] {
+            let policy = policy(strategy);
+            let rows = allocate::allocate(&state, &policy, total).unwrap();
+            assert_eq!(rows.iter().map(|r| r.amount).sum::<u128>(), total);
+            assert_eq!(allocate::verify(&rows, &policy, total).unwrap(), 0);
+        }
+    }
+}
+
+#[test]
+fn leftover_units_go_to_the_largest_remainders() {
+    let state = state(&[("0xaa", 1), ("0xbb", 1), ("0xcc", 1)]);
+    let mut policy = policy(Strategy::Proportional);
+    policy.round_to = 10;
+
+    let rows = allocate::allocate(&state, &policy, 105).unwrap();
+
+    // 10 units of 10 over three holders; the first in address order gets the spare unit
+    let amounts: Vec<u128> = rows.iter().map(|r| r.amount).collect();
+    assert_eq!(amounts, [40, 30, 30]);
+    assert_eq!(allocate::verify(&rows, &policy, 105).unwrap(), 5);
+}
+
+#[test]
+fn verify_rejects_amounts_that_do_not_add_up() {
+    let policy = policy(Strategy::Proportional);
+    let rows = vec![
+        AllocationRow { address: "0xaa".to_string(), balance: 1, amount: 60 },
+        AllocationRow { address: "0xbb".to_string(), balance: 1, amount: 50 },
+    ];
+
+    let error = allocate::verify(&rows, &policy, 100).unwrap_err();
+
+    assert!(error.to_string().contains("sum to 110 but 100"), "{}", error);
+}
+
+#[test]
+fn all_zero_weights_fail_instead_of_spinning() {
+    let state = state(&[("0xaa", 1), ("0xbb", 5)]);
+    let mut policy = policy(Strategy::Tiered);
+    policy.tiers = vec![Tier { min_balance: 10, weight: 1.0 }];
+
+    let error = allocate::allocate(&state, &policy, 1_000_000_000_000_000_000_000).unwrap_err();
+
+    assert!(error.to_string().contains("zero weight"), "{}", error);
+}
+
+#[test]
+fn cap_shares_outside_zero_to_one_are_rejected() {
+    let mut policy = policy(Strategy::Capped);
+    for share in [-0.5, 0.0, 1.5, f64::NAN] {
+        policy.cap_share = Some(share);
+        let error = policy.validate().unwrap_err();
+        assert!(error.to_string().contains("cap_share must be above 0"), "{}", error);
+    }
+
+    policy.cap_share = Some(1.0);
+    policy.validate().unwrap();
+}
@@ -0,0 +1,48 @@
+use uniswap_nft_holders::claims::{self, ClaimOptions, HolderClaim};
+use uniswap_nft_holders::merkle;
+use uniswap_nft_holders::state::ScanState;
+
+fn state(holders: &[(&str, u64)]) -> ScanState {
+    let mut state = ScanState::default();
+    for (address, balance) in holders {
+        state.holders.insert(address.to_string());
+        state.balances.insert(address.to_string(), *balance);
+    }
+    state
+}
+
+fn options(output_dir: &std::path::Path, amount_per_token: u128) -> ClaimOptions {
+    ClaimOptions { output_dir: output_dir.to_path_buf(), amount_per_token, min_balance: 2, with_checker: false }
+}
+
+#[test]
+fn claim_files_hold_normalized_addresses_and_their_leaves() {
+    let dir = tempfile::tempdir().unwrap();
+    let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    let state = state(&[(checksummed, 3), (" 0x00000000000000000000000000000000000000b2 ", 5), ("0xcc", 9)]);
+
+    let index = claims::export_claims(&state, &options(dir.path(), 10)).unwrap();
+
+    assert_eq!(index.recipients, 2);
+    assert_eq!(index.total_amount, "80");
+    let address = checksummed.to_lowercase();
+    let path = claims::holder_path(dir.path(), &address);
+    let claim: HolderClaim = serde_json::from_reader(std::fs::File::open(path).unwrap()).unwrap();
+    assert_eq!(claim.address, address);
+    assert_eq!(claim.amount, "30");
+    let leaf = merkle::leaf_hash(&merkle::parse_address(&address).unwrap(), 30);
+    assert_eq!(claim.leaf, merkle::to_hex(&leaf));
+    assert_eq!(claim.proof.len(), 1);
+}
+
+#[test]
+fn amounts_that_overflow_u128_fail() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = state(&[("0x00000000000000000000000000000000000000a1", u64::MAX)]);
+
+    let Err(error) = claims::eligible_recipients(&state, &options(dir.path(), u128::MAX / 2)) else {
+        panic!("the amount should overflow");
+    };
+
+    assert!(error.to_string().contains("overflows u128"), "{}", error);
+}
@@ -0,0 +1,56 @@
+use uniswap_nft_holders::digest;
+use uniswap_nft_holders::state::ScanState;
+
+// The web3.js documentation's example key and its address
+const KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+const SIGNER: &str = "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23";
+
+fn state(holders: &[(&str, u64)]) -> ScanState {
+    let mut state = ScanState::default();
+    for (address, balance) in holders {
+        state.holders.insert(address.to_string());
+        state.balances.insert(address.to_string(), *balance);
+    }
+    state
+}
+
+#[test]
+fn signatures_recover_to_the_signing_key() {
+    let hash = digest::snapshot_hash("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed,3\n");
+    std::env::set_var("SNAPSHOT_SIGNING_KEY", KEY);
+
+    let signature = digest::sign_from_env(&hash).unwrap().unwrap();
+
+    assert_eq!(signature.signer, SIGNER);
+    let recovered = digest::recover_signer(&hash, &signature.signature).unwrap();
+    assert_eq!(format!("{:?}", recovered), SIGNER.to_lowercase());
+
+    let other = digest::snapshot_hash("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed,4\n");
+    let recovered = digest::recover_signer(&other, &signature.signature).unwrap();
+    assert_ne!(format!("{:?}", recovered), SIGNER.to_lowercase());
+}
+
+#[test]
+fn canonical_list_is_checksummed_sorted_and_spelling_independent() {
+    let a =
+        state(&[("0xfb6916095ca1df60bb79ce92ce3ea74c37c5d359", 2), ("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed", 3)]);
+    let b =
+        state(&[(" 0xFB6916095CA1DF60BB79CE92CE3EA74C37C5D359", 2), ("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed", 3)]);
+
+    let canonical = digest::canonical_list(&a);
+
+    assert_eq!(
+        canonical,
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed,3\n0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359,2\n"
+    );
+    assert_eq!(digest::canonical_list(&b), canonical);
+    assert_eq!(digest::snapshot_hash(&canonical), digest::snapshot_hash(&digest::canonical_list(&b)));
+}
+
+#[test]
+fn malformed_signatures_are_rejected() {
+    let hash = digest::snapshot_hash("");
+    assert!(digest::recover_signer(&hash, "0x1234").is_err());
+    assert!(digest::recover_signer(&hash, &format!("0x{}", "11".repeat(64) + "05")).is_err());
+    assert!(digest::recover_signer("0x1234", &format!("0x{}", "11".repeat(65))).is_err());
+}
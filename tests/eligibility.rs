@@ -0,0 +1,101 @@
+use chrono::{DateTime, Duration, Utc};
+use uniswap_nft_holders::eligibility::{EligibilityConfig, Engine};
+use uniswap_nft_holders::state::ScanState;
+
+const WHALE: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+const NEWCOMER: &str = "0xfb6916095ca1df60bb79ce92ce3ea74c37c5d359";
+const EXCHANGE: &str = "0xdbf03b407c01e7cd3cbea99509d93f8dddc8c6fb";
+
+fn as_of() -> DateTime<Utc> {
+    "2026-06-01T00:00:00Z".parse().unwrap()
+}
+
+fn state() -> ScanState {
+    let mut state = ScanState::default();
+    for (address, balance, days_held) in [(WHALE, 10, 400), (NEWCOMER, 2, 3), (EXCHANGE, 50, 900)] {
+        state.holders.insert(address.to_string());
+        state.balances.insert(address.to_string(), balance);
+        state.first_seen.insert(address.to_string(), as_of() - Duration::days(days_held));
+    }
+    state.labels.insert(EXCHANGE.to_string(), vec!["Exchange".to_string()]);
+    state
+}
+
+fn engine(rules: &str) -> Engine {
+    let config: EligibilityConfig = toml::from_str(rules).unwrap();
+    Engine::new(&config, &state(), as_of()).unwrap()
+}
+
+const RULES: &str = r#"
+[[rule]]
+type = "min_balance"
+min = 5
+
+[[rule]]
+type = "held_for"
+days = 365
+
+[[rule]]
+name = "no exchanges"
+type = "not_labeled"
+labels = ["exchange"]
+"#;
+
+#[test]
+fn holders_must_pass_every_rule() {
+    let engine = engine(RULES);
+
+    let whale = engine.evaluate(WHALE).unwrap();
+    assert!(whale.eligible);
+    assert_eq!(whale.balance, 10);
+
+    let newcomer = engine.evaluate(NEWCOMER).unwrap();
+    assert!(!newcomer.eligible);
+    let failed: Vec<_> = newcomer.failed().map(|o| (o.rule.as_str(), o.detail.as_str())).collect();
+    assert_eq!(
+        failed,
+        [("balance >= 5", "holds 2, needs 5"), ("held for 365 days", "holding since 2026-05-29 (3 days), needs 365")]
+    );
+
+    let exchange = engine.evaluate(EXCHANGE).unwrap();
+    assert!(!exchange.eligible);
+    let failed: Vec<_> = exchange.failed().map(|o| (o.rule.as_str(), o.detail.as_str())).collect();
+    assert_eq!(failed, [("no exchanges", "labeled exchange")]);
+}
+
+#[test]
+fn addresses_are_looked_up_in_any_spelling() {
+    let engine = engine(RULES);
+
+    let verdict = engine.evaluate(" 0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").unwrap();
+
+    assert_eq!(verdict.address, WHALE);
+    assert!(verdict.eligible);
+    assert!(engine.evaluate("0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_err());
+}
+
+#[test]
+fn non_holders_fail_balance_and_holding_rules() {
+    let engine = engine(RULES);
+
+    let verdict = engine.evaluate("0xd1220a0cf47c7b9be7a2e6ba89f429762e7b9adb").unwrap();
+
+    assert!(!verdict.eligible);
+    assert_eq!(verdict.balance, 0);
+    assert_eq!(verdict.failed().count(), 2);
+}
+
+#[test]
+fn out_of_range_holding_periods_are_errors() {
+    let engine = engine("[[rule]]\ntype = \"held_for\"\ndays = 9223372036854775807\n");
+
+    let error = engine.evaluate(WHALE).unwrap_err();
+
+    assert!(error.to_string().contains("out of range"), "{}", error);
+}
+
+#[test]
+fn a_config_without_rules_is_rejected() {
+    let config = EligibilityConfig::default();
+    assert!(Engine::new(&config, &state(), as_of()).is_err());
+}
@@ -0,0 +1,13 @@
+use uniswap_nft_holders::error::ScannerError;
+
+#[tokio::test]
+async fn http_failures_do_not_reveal_the_api_key() {
+    // Nothing listens on port 1, so the request fails before any response
+    let url = "http://127.0.0.1:1/secret-api-key/getOwnersForContract?apikey=secret-api-key";
+    let error: ScannerError = reqwest::get(url).await.unwrap_err().into();
+
+    let message = format!("{:#}", anyhow::Error::from(error));
+
+    assert!(message.starts_with("request failed"), "{}", message);
+    assert!(!message.contains("secret-api-key"), "{}", message);
+}
@@ -0,0 +1,60 @@
+use uniswap_nft_holders::merkle::{self, Hash, MerkleTree};
+use web3::signing::keccak256;
+
+fn leaf(address: &str, amount: u128) -> Hash {
+    merkle::leaf_hash(&merkle::parse_address(address).unwrap(), amount)
+}
+
+// What OpenZeppelin's `MerkleProof.verify` computes: sorted pairs up to the root
+fn verify(proof: &[Hash], root: &Hash, leaf: &Hash) -> bool {
+    let computed = proof.iter().fold(*leaf, |node, sibling| {
+        let (low, high) = if node <= *sibling { (node, *sibling) } else { (*sibling, node) };
+        keccak256(&[low, high].concat())
+    });
+    computed == *root
+}
+
+#[test]
+fn root_matches_openzeppelin_standard_merkle_tree() {
+    // The example from the @openzeppelin/merkle-tree README
+    let leaves = vec![
+        leaf("0x1111111111111111111111111111111111111111", 5_000_000_000_000_000_000),
+        leaf("0x2222222222222222222222222222222222222222", 2_500_000_000_000_000_000),
+    ];
+
+    let tree = MerkleTree::new(leaves);
+
+    assert_eq!(merkle::to_hex(&tree.root()), "0xd4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77");
+}
+
+#[test]
+fn every_proof_verifies_against_the_root() {
+    for count in 1..=9u128 {
+        let leaves: Vec<Hash> = (1..=count).map(|i| leaf(&format!("0x{:040x}", i), i * 1_000)).collect();
+        let tree = MerkleTree::new(leaves.clone());
+
+        for leaf in &leaves {
+            let proof = tree.proof(tree.leaf_index(leaf).unwrap());
+            assert!(verify(&proof, &tree.root(), leaf), "leaf {} of {}", merkle::to_hex(leaf), count);
+        }
+    }
+}
+
+#[test]
+fn proofs_do_not_verify_other_amounts() {
+    let leaves: Vec<Hash> = (1..=5u128).map(|i| leaf(&format!("0x{:040x}", i), i)).collect();
+    let tree = MerkleTree::new(leaves.clone());
+
+    let proof = tree.proof(tree.leaf_index(&leaves[2]).unwrap());
+
+    assert!(!verify(&proof, &tree.root(), &leaf(&format!("0x{:040x}", 3), 4)));
+}
+
+#[test]
+fn single_leaf_is_its_own_root() {
+    let only = leaf("0x1111111111111111111111111111111111111111", 1);
+    let tree = MerkleTree::new(vec![only]);
+
+    assert_eq!(tree.root(), only);
+    assert!(tree.proof(0).is_empty());
+}
@@ -0,0 +1,63 @@
+use uniswap_nft_holders::{Allocation, AllocationStrategy, ChangeKind, HolderRecord, Snapshot, SnapshotError};
+
+/// A holder address ending in `suffix`, e.g. `address("aa")`.
+fn address(suffix: &str) -> String {
+    format!("0x{:0>40}", suffix)
+}
+
+fn snapshot(holders: &[(&str, u64)]) -> Snapshot {
+    Snapshot::builder("0xC36442b4a4522E871399CD717aBDD847Ab11FE88")
+        .holders(
+            holders.iter().map(|(suffix, balance)| HolderRecord::builder(address(suffix)).balance(*balance).build()),
+        )
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn holders_are_looked_up_case_insensitively_in_address_order() {
+    let snapshot = snapshot(&[("BB", 2), ("aa", 1)]);
+
+    assert_eq!(snapshot.len(), 2);
+    assert_eq!(snapshot.total_balance(), 3);
+    assert_eq!(snapshot.holder(&address("bb")).map(|h| h.balance()), Some(2));
+    assert!(snapshot.holder("0xbb").is_none());
+    let addresses: Vec<&str> = snapshot.holders().map(|h| h.address()).collect();
+    assert_eq!(addresses, [address("aa"), address("BB")]);
+}
+
+#[test]
+fn snapshots_reject_invalid_holder_addresses() {
+    let result = Snapshot::builder("0xC36442b4a4522E871399CD717aBDD847Ab11FE88")
+        .holder(HolderRecord::builder("0xaa").balance(1).build())
+        .build();
+
+    assert!(matches!(result, Err(SnapshotError::InvalidAddress(_))));
+}
+
+#[test]
+fn diff_reports_added_removed_and_changed_holders() {
+    let old = snapshot(&[("aa", 1), ("bb", 2)]);
+    let new = snapshot(&[("bb", 5), ("cc", 1)]);
+
+    let diff = old.diff(&new);
+
+    assert_eq!(diff.len(), 3);
+    assert_eq!(diff.added().map(|c| c.address()).collect::<Vec<_>>(), [address("cc")]);
+    assert_eq!(diff.removed().map(|c| c.address()).collect::<Vec<_>>(), [address("aa")]);
+    let changed: Vec<_> = diff.of_kind(ChangeKind::BalanceChanged).collect();
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0].delta(), 3);
+}
+
+#[test]
+fn allocation_spends_the_whole_budget() {
+    let snapshot = snapshot(&[("aa", 1), ("bb", 4), ("cc", 9), ("dd", 0)]);
+
+    let allocation = Allocation::builder(1_000).strategy(AllocationStrategy::Quadratic).build(&snapshot).unwrap();
+
+    assert_eq!(allocation.recipients(), 3);
+    assert_eq!(allocation.iter().map(|e| e.amount()).sum::<u128>(), 1_000);
+    assert_eq!(allocation.amount_for(&address("aa")), Some(167));
+    assert_eq!(allocation.amount_for(&address("dd")), None);
+}
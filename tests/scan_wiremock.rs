@@ -0,0 +1,158 @@
+use serde_json::Value;
+use std::path::Path;
+use std::process::Output;
+use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const OWNERS_PATH: &str = "/test-key/getOwnersForContract";
+
+fn fixture(name: &str) -> Value {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+async fn run_scanner(dir: &Path, server: &MockServer, args: &[&str]) -> Output {
+    tokio::process::Command::new(env!("CARGO_BIN_EXE_scanner"))
+        .args(args)
+        .arg("--no-progress")
+        .current_dir(dir)
+        .env("ALCHEMY_API_KEY", "test-key")
+        .env_remove("ALCHEMY_API_KEYS")
+        .env("ALCHEMY_ENDPOINTS", server.uri())
+        .env("RATE_LIMIT_RPS", "100")
+        .env("RATE_LIMIT_BURST", "100")
+        .env("MAX_RETRIES", "2")
+        .output()
+        .await
+        .unwrap()
+}
+
+fn read_state(dir: &Path) -> Value {
+    serde_json::from_str(&std::fs::read_to_string(dir.join("data/state.json")).unwrap()).unwrap()
+}
+
+async fn mount_pages(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path(OWNERS_PATH))
+        .and(query_param_is_missing("pageKey"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("owners_page1.json")))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(OWNERS_PATH))
+        .and(query_param("pageKey", "page-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("owners_page2.json")))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn follows_page_keys_and_persists_state() {
+    let server = MockServer::start().await;
+    mount_pages(&server).await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = run_scanner(dir.path(), &server, &["scan"]).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let state = read_state(dir.path());
+    assert_eq!(state["total_holders"], 3);
+    assert_eq!(state["last_page_key"], Value::Null);
+    assert_eq!(state["balances"]["0x00000000000000000000000000000000000000b2"], 2);
+
+    let holders = std::fs::read_to_string(dir.path().join("data/uniswap_v3_holders.txt")).unwrap();
+    assert_eq!(
+        holders.lines().collect::<Vec<_>>(),
+        [
+            "0x00000000000000000000000000000000000000a1",
+            "0x00000000000000000000000000000000000000b2",
+            "0x00000000000000000000000000000000000000c3",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(OWNERS_PATH))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount_pages(&server).await;
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = run_scanner(dir.path(), &server, &["scan"]).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(read_state(dir.path())["total_holders"], 3);
+}
+
+#[tokio::test]
+async fn resumes_from_saved_page_key() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(OWNERS_PATH))
+        .and(query_param_is_missing("pageKey"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("owners_page1.json")))
+        .expect(0)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(OWNERS_PATH))
+        .and(query_param("pageKey", "page-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture("owners_page2.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("data")).unwrap();
+    let checkpoint = serde_json::json!({
+        "last_processed_block": 0,
+        "last_save_time": chrono::Utc::now(),
+        "total_holders": 1,
+        "holders": ["0x00000000000000000000000000000000000000a1"],
+        "last_page_key": "page-2",
+        "last_page_key_at": chrono::Utc::now(),
+    });
+    std::fs::write(dir.path().join("data/state.json"), checkpoint.to_string()).unwrap();
+
+    let output = run_scanner(dir.path(), &server, &["scan"]).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(read_state(dir.path())["total_holders"], 2);
+}
+
+#[tokio::test]
+async fn failed_scans_do_not_publish_the_api_key() {
+    let webhook = MockServer::start().await;
+    Mock::given(method("POST")).and(path("/hook")).respond_with(ResponseTemplate::new(200)).mount(&webhook).await;
+    let dir = tempfile::tempdir().unwrap();
+
+    // Nothing listens on port 1, so every provider request fails with a transport error
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_scanner"))
+        .args(["scan", "--no-progress"])
+        .current_dir(dir.path())
+        .env("ALCHEMY_API_KEY", "test-key")
+        .env_remove("ALCHEMY_API_KEYS")
+        .env("ALCHEMY_ENDPOINTS", "http://127.0.0.1:1")
+        .env("WEBHOOK_URLS", format!("{}/hook", webhook.uri()))
+        .env("MAX_RETRIES", "0")
+        .output()
+        .await
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("test-key"));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("test-key"));
+
+    let requests = webhook.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    let payload: Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(payload["event"], "scan.failed");
+    assert!(!payload["summary"]["error"].as_str().unwrap().contains("test-key"));
+
+    for journal in std::fs::read_dir(dir.path().join("data/runs")).unwrap() {
+        assert!(!std::fs::read_to_string(journal.unwrap().path()).unwrap().contains("test-key"));
+    }
+}
@@ -0,0 +1,68 @@
+use chrono::{DateTime, TimeZone, Utc};
+use uniswap_nft_holders::schedule::CronSchedule;
+
+fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+}
+
+fn next(expression: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    CronSchedule::parse(expression).unwrap().next_after(after)
+}
+
+#[test]
+fn steps_fire_on_every_multiple() {
+    let mut time = at(2024, 1, 1, 10, 7);
+    let mut fired = Vec::new();
+    for _ in 0..5 {
+        time = next("*/15 * * * *", time).unwrap();
+        fired.push(time);
+    }
+
+    assert_eq!(
+        fired,
+        [
+            at(2024, 1, 1, 10, 15),
+            at(2024, 1, 1, 10, 30),
+            at(2024, 1, 1, 10, 45),
+            at(2024, 1, 1, 11, 0),
+            at(2024, 1, 1, 11, 15)
+        ]
+    );
+}
+
+#[test]
+fn next_run_is_strictly_after() {
+    assert_eq!(next("0 * * * *", at(2024, 1, 1, 10, 0)), Some(at(2024, 1, 1, 11, 0)));
+}
+
+#[test]
+fn impossible_dates_never_fire() {
+    assert_eq!(next("0 0 31 2 *", at(2024, 1, 1, 0, 0)), None);
+}
+
+#[test]
+fn leap_days_wait_for_a_leap_year() {
+    assert_eq!(next("0 0 29 2 *", at(2024, 3, 1, 0, 0)), Some(at(2028, 2, 29, 0, 0)));
+}
+
+#[test]
+fn day_of_week_seven_is_sunday() {
+    // 2024-01-07 was a Sunday
+    let after = at(2024, 1, 3, 12, 0);
+    assert_eq!(next("30 6 * * 7", after), Some(at(2024, 1, 7, 6, 30)));
+    assert_eq!(next("30 6 * * 7", after), next("30 6 * * 0", after));
+}
+
+#[test]
+fn restricted_day_and_weekday_fire_on_either() {
+    // The 15th, or any Monday: 2024-01-08 is the first after the 3rd
+    assert_eq!(next("0 0 15 * 1", at(2024, 1, 3, 0, 0)), Some(at(2024, 1, 8, 0, 0)));
+    assert_eq!(next("0 0 15 * 1", at(2024, 1, 13, 0, 0)), Some(at(2024, 1, 15, 0, 0)));
+}
+
+#[test]
+fn invalid_expressions_are_rejected() {
+    for expression in ["* * * *", "60 * * * *", "*/0 * * * *", "0 0 0 * *", "0 0 * 13 *", "0 0 * * 8", "5-1 * * * *"] {
+        assert!(CronSchedule::parse(expression).is_err(), "{}", expression);
+    }
+}